@@ -0,0 +1,57 @@
+//! Steady-state frame pipeline benchmark: per-frame allocation vs pooled buffers
+//!
+//! Run with `cargo bench --bench pipeline`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use opc_server::pixel_format::transform_pixels;
+use opc_server::pool::BufferPool;
+use opc_server::protocol::{build_awa_frame, build_awa_frame_into};
+
+const OUTPUTS: usize = 8;
+const LEDS_PER_OUTPUT: usize = 300;
+const FRAMES: usize = 60 * 60; // One minute at 60fps
+
+/// Time `FRAMES` iterations of `frame`, returning the mean time per frame
+fn measure<F: FnMut()>(mut frame: F) -> Duration {
+    // Warm up caches and the allocator
+    for _ in 0..FRAMES / 10 {
+        frame();
+    }
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    start.elapsed() / FRAMES as u32
+}
+
+fn main() {
+    let opc_data: Vec<u8> = (0..OUTPUTS * LEDS_PER_OUTPUT * 3).map(|i| i as u8).collect();
+    let slice_len = LEDS_PER_OUTPUT * 3;
+
+    let allocating = measure(|| {
+        for chunk in opc_data.chunks_exact(slice_len) {
+            let sliced = chunk.to_vec();
+            let transformed = transform_pixels(sliced, Some("GRB"));
+            black_box(build_awa_frame(&transformed, 3));
+        }
+    });
+
+    let pools: Vec<BufferPool> = (0..OUTPUTS).map(|_| BufferPool::new()).collect();
+    let mut frames: Vec<Vec<u8>> = vec![Vec::new(); OUTPUTS];
+    let pooled = measure(|| {
+        for ((chunk, pool), frame) in opc_data.chunks_exact(slice_len).zip(&pools).zip(frames.iter_mut()) {
+            let sliced = pool.copy_from(chunk);
+            let transformed = transform_pixels(sliced, Some("GRB"));
+            build_awa_frame_into(&transformed, 3, frame);
+            pool.put(transformed);
+            black_box(&frame);
+        }
+    });
+
+    println!("{} outputs x {} LEDs, {} frames", OUTPUTS, LEDS_PER_OUTPUT, FRAMES);
+    println!("  allocating per frame: {:>10.2?} / frame", allocating);
+    println!("  pooled buffers:       {:>10.2?} / frame", pooled);
+}
@@ -0,0 +1,69 @@
+//! Hot-path benchmarks for pixel transforms and protocol frame builders
+//!
+//! Run with `cargo bench --bench transforms`. Uses a small std-only harness:
+//! each case is timed over a fixed number of iterations after a warm-up, and the
+//! mean time per call is reported alongside the throughput in LEDs per second.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use opc_server::pixel_format::transform_pixels;
+use opc_server::protocol::{build_adalight_frame, build_awa_frame};
+
+/// Realistic strip lengths
+const LED_COUNTS: [usize; 3] = [150, 300, 1000];
+
+const ITERATIONS: u32 = 20_000;
+
+/// Mean time per call of `f`
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn report(name: &str, leds: usize, per_call: Duration) {
+    let leds_per_sec = leds as f64 / per_call.as_secs_f64();
+    println!("{:<28} {:>5} LEDs  {:>10.2?} / call  {:>8.1} M LEDs/s",
+             name, leds, per_call, leds_per_sec / 1e6);
+}
+
+/// Deterministic, non-uniform RGB test pattern
+fn pattern(leds: usize) -> Vec<u8> {
+    (0..leds * 3).map(|i| (i * 37 % 251) as u8).collect()
+}
+
+fn main() {
+    for leds in LED_COUNTS {
+        let data = pattern(leds);
+
+        for format in ["GRB", "BGR", "RGBW", "GRBW"] {
+            // Cloning the input is part of every case, as in the output worker
+            let per_call = measure(|| {
+                black_box(transform_pixels(black_box(data.clone()), Some(format)));
+            });
+            report(&format!("transform_pixels {}", format), leds, per_call);
+        }
+
+        let rgbw = transform_pixels(data.clone(), Some("RGBW"));
+        for (name, pixels, stride) in [("RGB", &data, 3), ("RGBW", &rgbw, 4)] {
+            let per_call = measure(|| {
+                black_box(build_awa_frame(black_box(pixels), stride));
+            });
+            report(&format!("build_awa_frame {}", name), leds, per_call);
+
+            let per_call = measure(|| {
+                black_box(build_adalight_frame(black_box(pixels), stride));
+            });
+            report(&format!("build_adalight_frame {}", name), leds, per_call);
+        }
+
+        println!();
+    }
+}
@@ -0,0 +1,30 @@
+//! Arbitrary pixel buffers through the AWA and Adalight frame builders
+//!
+//! The first byte picks the stride (3 for RGB, 4 for RGBW); the frames must always
+//! carry the whole buffer behind a well-formed header.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opc_server::protocol::{build_adalight_frame, build_awa_frame};
+
+fuzz_target!(|input: &[u8]| {
+    let [selector, pixels @ ..] = input else {
+        return;
+    };
+    let stride = if selector & 1 == 0 { 3 } else { 4 };
+    let count_minus_one = (pixels.len() / stride).saturating_sub(1);
+    let (count_hi, count_lo) = ((count_minus_one >> 8) as u8, count_minus_one as u8);
+
+    let awa = build_awa_frame(pixels, stride);
+    assert_eq!(awa.len(), 6 + pixels.len() + 3);
+    assert_eq!(&awa[..6], &[b'A', b'w', b'a', count_hi, count_lo, count_hi ^ count_lo ^ 0x55]);
+    assert_eq!(&awa[6..6 + pixels.len()], pixels);
+    // 'A' is never sent as the last checksum byte, so it can't be mistaken for a header
+    assert_ne!(awa[awa.len() - 1], b'A');
+
+    let adalight = build_adalight_frame(pixels, stride);
+    assert_eq!(adalight.len(), 6 + pixels.len());
+    assert_eq!(&adalight[..6], &[b'A', b'd', b'a', count_hi, count_lo, count_hi ^ count_lo ^ 0x55]);
+    assert_eq!(&adalight[6..], pixels);
+});
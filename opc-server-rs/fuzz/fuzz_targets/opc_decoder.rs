@@ -0,0 +1,38 @@
+//! Arbitrary bytes through the streaming OPC decoder, split into arbitrary reads
+//!
+//! The first byte picks the read size and the second the max_message_size, so the
+//! fuzzer covers headers split across reads, coalesced messages and oversized lengths.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opc_server::opc_protocol::{OpcDecoder, HEADER_SIZE};
+
+fuzz_target!(|input: &[u8]| {
+    let [chunk, limit, stream @ ..] = input else {
+        return;
+    };
+    let chunk_size = *chunk as usize + 1;
+    let max_message_size = *limit as usize * 257;
+
+    let mut decoder = OpcDecoder::new(max_message_size);
+    let mut decoded = 0;
+    for read in stream.chunks(chunk_size) {
+        decoder.push(read);
+        loop {
+            match decoder.next_message() {
+                Ok(Some(message)) => {
+                    assert!(message.data.len() <= max_message_size);
+                    decoded += HEADER_SIZE + message.data.len();
+                }
+                Ok(None) => break,
+                // The connection would be dropped here
+                Err(_) => return,
+            }
+        }
+    }
+
+    // Every byte is either part of a returned message or still buffered
+    assert_eq!(decoded + decoder.buffered(), stream.len());
+    assert!(decoder.buffered() < HEADER_SIZE + max_message_size);
+});
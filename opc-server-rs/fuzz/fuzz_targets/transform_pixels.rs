@@ -0,0 +1,41 @@
+//! Arbitrary pixel buffers through every pixel format transform
+//!
+//! Buffers need not be a whole number of pixels, as with messages from a client that
+//! sends partial pixels.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opc_server::pixel_format::transform_pixels;
+
+const FORMATS: [Option<&str>; 7] = [None, Some("RGB"), Some("GRB"), Some("BGR"), Some("RGBW"), Some("GRBW"), Some("XYZ")];
+
+fuzz_target!(|input: &[u8]| {
+    let [selector, pixels @ ..] = input else {
+        return;
+    };
+    let format = FORMATS[*selector as usize % FORMATS.len()];
+    let output = transform_pixels(pixels.to_vec(), format);
+
+    match format {
+        Some("RGBW") | Some("GRBW") => {
+            assert_eq!(output.len(), pixels.len() / 3 * 4);
+            for (out, pixel) in output.chunks_exact(4).zip(pixels.chunks_exact(3)) {
+                // White is taken out of the colors, never added
+                let w = pixel[0].min(pixel[1]).min(pixel[2]);
+                assert_eq!(out[3], w);
+                assert_eq!(out.iter().map(|&c| c as u32).sum::<u32>(),
+                           pixel.iter().map(|&c| c as u32).sum::<u32>() - 2 * w as u32);
+            }
+        }
+        _ => {
+            assert_eq!(output.len(), pixels.len());
+            let mut sorted_in: Vec<u8> = pixels.to_vec();
+            let mut sorted_out = output.clone();
+            sorted_in.sort_unstable();
+            sorted_out.sort_unstable();
+            // Reordering channels only moves bytes around
+            assert_eq!(sorted_in, sorted_out);
+        }
+    }
+});
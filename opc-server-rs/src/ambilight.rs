@@ -0,0 +1,152 @@
+use anyhow::Result;
+
+use crate::arbiter::Source;
+use crate::child_input;
+use crate::config::{AmbilightConfig, EdgeLayout};
+use crate::opc_server::SharedState;
+
+/// Size the screen is scaled to before sampling, unless configured
+const DEFAULT_SIZE: (usize, usize) = (64, 36);
+
+const DEFAULT_FPS: u32 = 25;
+
+/// Share of the screen averaged into each LED, unless `layout.depth` is set
+const DEFAULT_DEPTH: f32 = 0.1;
+
+/// Start the screen-capture input on a background thread
+///
+/// The grabber (ffmpeg by default) writes raw RGB24 frames scaled to `width`x`height`
+/// to its stdout. Each frame's edges are averaged into one color per LED and
+/// delivered to `opc_channel` like an OPC message. A grabber that exits is restarted;
+/// when the server stops, the grabber ends on the closed pipe.
+pub fn spawn(config: AmbilightConfig, state: SharedState, debug: bool) -> Result<()> {
+    let (width, height) = (config.width.unwrap_or(DEFAULT_SIZE.0), config.height.unwrap_or(DEFAULT_SIZE.1));
+    let layout = &config.layout;
+    if width == 0 || height == 0 {
+        anyhow::bail!("ambilight width and height must be at least 1");
+    }
+    if layout.top + layout.right + layout.bottom + layout.left == 0 {
+        anyhow::bail!("ambilight layout has no LEDs");
+    }
+    let command = grabber_command(&config, width, height);
+
+    if debug {
+        println!("✓ Ambilight capturing {}x{} with `{}` for {} LEDs on channel {}",
+                 width, height, command.join(" "), led_count(layout), config.opc_channel);
+    }
+
+    let mut colors = Vec::with_capacity(led_count(&config.layout) * 3);
+    let running = state.running.clone();
+    child_input::spawn("Ambilight grabber", command, width * height * 3, running, move |frame| {
+        edge_colors(frame, width, height, &config.layout, &mut colors);
+        if state.admit_message(None) {
+            state.handle_opc_message(Source::Ambilight, config.opc_channel, 0, &colors);
+        }
+    });
+
+    Ok(())
+}
+
+/// ffmpeg capturing the screen with the platform's grabber, unless `command` is set
+fn grabber_command(config: &AmbilightConfig, width: usize, height: usize) -> Vec<String> {
+    if let Some(command) = config.command.clone().filter(|c| !c.is_empty()) {
+        return command;
+    }
+
+    let (format, default_input) = if cfg!(windows) {
+        ("gdigrab", "desktop".to_string())
+    } else if cfg!(target_os = "macos") {
+        ("avfoundation", "Capture screen 0".to_string())
+    } else {
+        ("x11grab", std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string()))
+    };
+    let input = config.display.clone().unwrap_or(default_input);
+    let fps = config.fps.unwrap_or(DEFAULT_FPS).to_string();
+    let scale = format!("scale={}:{}", width, height);
+
+    ["ffmpeg", "-loglevel", "error", "-f", format, "-framerate", &fps, "-i", &input,
+     "-vf", &scale, "-pix_fmt", "rgb24", "-f", "rawvideo", "-"]
+        .iter().map(|s| s.to_string()).collect()
+}
+
+fn led_count(layout: &EdgeLayout) -> usize {
+    layout.top + layout.right + layout.bottom + layout.left
+}
+
+/// Average the screen's edges into one RGB color per LED
+///
+/// LEDs are numbered clockwise from the top-left corner: the top edge left to right,
+/// the right edge top to bottom, the bottom edge right to left and the left edge
+/// bottom to top. Each LED averages its share of the edge, `depth` deep.
+pub fn edge_colors(frame: &[u8], width: usize, height: usize, layout: &EdgeLayout, out: &mut Vec<u8>) {
+    let depth = layout.depth.unwrap_or(DEFAULT_DEPTH).clamp(0.0, 1.0);
+    let rows = ((height as f32 * depth).round() as usize).clamp(1, height);
+    let cols = ((width as f32 * depth).round() as usize).clamp(1, width);
+
+    // Start and end of part `i` of `n` along `len` pixels, at least one pixel wide
+    let span = |i: usize, n: usize, len: usize| {
+        let start = (i * len / n).min(len - 1);
+        (start, ((i + 1) * len / n).max(start + 1))
+    };
+
+    out.clear();
+    for i in 0..layout.top {
+        let (x0, x1) = span(i, layout.top, width);
+        out.extend(average(frame, width, x0..x1, 0..rows));
+    }
+    for i in 0..layout.right {
+        let (y0, y1) = span(i, layout.right, height);
+        out.extend(average(frame, width, width - cols..width, y0..y1));
+    }
+    for i in 0..layout.bottom {
+        let (x0, x1) = span(layout.bottom - 1 - i, layout.bottom, width);
+        out.extend(average(frame, width, x0..x1, height - rows..height));
+    }
+    for i in 0..layout.left {
+        let (y0, y1) = span(layout.left - 1 - i, layout.left, height);
+        out.extend(average(frame, width, 0..cols, y0..y1));
+    }
+}
+
+/// Mean color of a rectangle of a row-major RGB frame
+fn average(frame: &[u8], width: usize, xs: std::ops::Range<usize>, ys: std::ops::Range<usize>) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    let count = (xs.len() * ys.len()).max(1) as u64;
+    for y in ys {
+        let row = &frame[(y * width + xs.start) * 3..(y * width + xs.end) * 3];
+        for pixel in row.chunks_exact(3) {
+            for (sum, &value) in sums.iter_mut().zip(pixel) {
+                *sum += value as u64;
+            }
+        }
+    }
+    sums.map(|sum| (sum / count) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edge_colors() {
+        // 4x2 frame: red top row, blue bottom row, with a green top-right pixel
+        let mut frame = Vec::new();
+        for pixel in 0..8 {
+            frame.extend(match pixel {
+                3 => [0, 255, 0],
+                0..=2 => [255, 0, 0],
+                _ => [0, 0, 255],
+            });
+        }
+        let layout = EdgeLayout { top: 2, right: 1, bottom: 2, left: 1, depth: Some(0.5) };
+        let mut colors = Vec::new();
+        edge_colors(&frame, 4, 2, &layout, &mut colors);
+
+        assert_eq!(colors, vec![
+            255, 0, 0, 127, 127, 0, // top, left to right
+            63, 63, 127,            // right: red and green above blue, two columns wide
+            0, 0, 255, 0, 0, 255,   // bottom, right to left
+            127, 0, 127,            // left
+        ]);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SourcesConfig;
+
+/// Priority of sources not listed in `sources.priorities`
+pub const DEFAULT_PRIORITY: i32 = 100;
+
+/// Priority of the audio effects unless configured, so any live input overrides them
+pub const AUDIO_PRIORITY: i32 = 0;
+
+/// Time without data after which a source stops holding its channels (default)
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Kind of input a pixel message came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// OPC TCP clients
+    Opc,
+    /// Fadecandy WebSocket clients
+    Fadecandy,
+    /// Hyperion.ng flatbuffers clients
+    Hyperion,
+    /// Screen capture (`ambilight`)
+    Ambilight,
+    /// Audio-reactive effects (`audio`)
+    Audio,
+    /// `replay` of a recording
+    Replay,
+}
+
+impl Source {
+    pub const ALL: [Source; 6] = [
+        Source::Opc, Source::Fadecandy, Source::Hyperion, Source::Ambilight, Source::Audio, Source::Replay,
+    ];
+
+    /// Name used in the `sources.priorities` config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Opc => "opc",
+            Source::Fadecandy => "fadecandy",
+            Source::Hyperion => "hyperion",
+            Source::Ambilight => "ambilight",
+            Source::Audio => "audio",
+            Source::Replay => "replay",
+        }
+    }
+}
+
+/// Decides which input drives each OPC channel when several are sending
+///
+/// The active source with the highest priority wins a channel; messages from
+/// lower-priority sources on that channel are dropped until the winner has been
+/// quiet for the timeout. Sources with equal priority are all admitted.
+pub struct Arbiter {
+    priorities: HashMap<Source, i32>,
+    timeout: Duration,
+    /// Last pixel message seen per channel and source
+    last_seen: Mutex<HashMap<u8, HashMap<Source, Instant>>>,
+}
+
+impl Arbiter {
+    pub fn from_config(config: &SourcesConfig) -> Result<Self> {
+        let mut priorities = HashMap::new();
+        for (name, &priority) in &config.priorities {
+            let Some(source) = Source::ALL.into_iter().find(|s| s.name() == name) else {
+                let names: Vec<_> = Source::ALL.iter().map(|s| s.name()).collect();
+                anyhow::bail!("Unknown source '{}' in sources.priorities (expected one of: {})",
+                              name, names.join(", "));
+            };
+            priorities.insert(source, priority);
+        }
+
+        Ok(Arbiter {
+            priorities,
+            timeout: config.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT),
+            last_seen: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn priority(&self, source: Source) -> i32 {
+        let default = if source == Source::Audio { AUDIO_PRIORITY } else { DEFAULT_PRIORITY };
+        self.priorities.get(&source).copied().unwrap_or(default)
+    }
+
+    /// Record a pixel message from `source` and decide whether it is shown
+    pub fn admit(&self, source: Source, channel: u8) -> bool {
+        self.admit_at(source, channel, Instant::now())
+    }
+
+    fn admit_at(&self, source: Source, channel: u8, now: Instant) -> bool {
+        let priority = self.priority(source);
+        let mut last_seen = self.last_seen.lock().unwrap();
+        let sources = last_seen.entry(channel).or_default();
+        sources.insert(source, now);
+
+        !sources.iter().any(|(&other, &seen)| {
+            self.priority(other) > priority && now.duration_since(seen) < self.timeout
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highest_active_source_wins() {
+        let config = SourcesConfig {
+            priorities: HashMap::from([("hyperion".to_string(), 200), ("replay".to_string(), 50)]),
+            timeout_ms: Some(1000),
+        };
+        let arbiter = Arbiter::from_config(&config).unwrap();
+        let start = Instant::now();
+
+        assert!(arbiter.admit_at(Source::Opc, 0, start));
+        assert!(arbiter.admit_at(Source::Hyperion, 0, start));
+        // OPC (100) is held off while Hyperion (200) is active, on that channel only
+        assert!(!arbiter.admit_at(Source::Opc, 0, start + Duration::from_millis(500)));
+        assert!(arbiter.admit_at(Source::Opc, 1, start + Duration::from_millis(500)));
+        // Hyperion went quiet: OPC takes over again
+        assert!(arbiter.admit_at(Source::Opc, 0, start + Duration::from_millis(1500)));
+        assert!(!arbiter.admit_at(Source::Replay, 0, start + Duration::from_millis(1600)));
+        // Audio effects rank below every other input unless configured
+        assert!(!arbiter.admit_at(Source::Audio, 0, start + Duration::from_millis(1700)));
+        assert!(arbiter.admit_at(Source::Audio, 2, start + Duration::from_millis(1700)));
+    }
+
+    #[test]
+    fn test_unknown_source_rejected() {
+        let config = SourcesConfig {
+            priorities: HashMap::from([("sacn".to_string(), 1)]),
+            timeout_ms: None,
+        };
+        assert!(Arbiter::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::Result;
+use std::f32::consts::PI;
+
+use crate::arbiter::Source;
+use crate::child_input;
+use crate::config::{AudioConfig, AudioEffect, AudioEffectConfig};
+use crate::effects::hue;
+use crate::opc_server::SharedState;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+const DEFAULT_FPS: u32 = 30;
+
+/// Samples analysed per frame; about 23ms at 44.1kHz
+const FFT_SIZE: usize = 1024;
+
+/// Level shown as dark, in dB below full scale
+const FLOOR_DB: f32 = -60.0;
+
+/// Lowest frequency on a spectrum strip
+const LOWEST_HZ: f32 = 40.0;
+
+/// Share of the last level kept each frame, so peaks fall back smoothly
+const DECAY: f32 = 0.85;
+
+/// Start the audio effects on a background thread
+///
+/// The capture command (arecord or ffmpeg by default) writes mono 16-bit samples to
+/// its stdout. Every frame the latest samples are analysed and each effect is drawn
+/// across the LEDs of its channel and delivered like an OPC message from the
+/// `audio` source, which ranks below every other input: a connected client takes
+/// the channel over, and the effect returns once it has been quiet for the
+/// `sources.timeout_ms`.
+pub fn spawn(config: AudioConfig, state: SharedState, debug: bool) -> Result<()> {
+    if config.effects.is_empty() {
+        anyhow::bail!("audio has no effects");
+    }
+    let sample_rate = config.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE).max(1);
+    let command = capture_command(&config, sample_rate)?;
+
+    if debug {
+        let channels: Vec<String> = config.effects.iter().map(|e| e.opc_channel.to_string()).collect();
+        println!("✓ Audio effects on channel(s) {} from `{}`", channels.join(", "), command.join(" "));
+    }
+
+    let hop = (sample_rate / config.fps.unwrap_or(DEFAULT_FPS).max(1)).max(1) as usize;
+    let mut window = vec![0f32; FFT_SIZE];
+    let mut meters: Vec<Meter> = config.effects.iter().map(|_| Meter::default()).collect();
+    let mut frame = Vec::new();
+    let running = state.running.clone();
+    child_input::spawn("Audio capture", command, hop * 2, running, move |bytes| {
+        // Slide the newest samples into the analysis window
+        let new: Vec<f32> = bytes.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect();
+        let keep = FFT_SIZE.saturating_sub(new.len());
+        window.copy_within(FFT_SIZE - keep.., 0);
+        window[keep..].copy_from_slice(&new[new.len() - (FFT_SIZE - keep)..]);
+
+        for (effect, meter) in config.effects.iter().zip(&mut meters) {
+            let led_count = state.channel_led_count(effect.opc_channel);
+            if led_count == 0 {
+                continue;
+            }
+            meter.update(effect.effect, &window, sample_rate, led_count);
+            render(effect, meter, &mut frame);
+            if state.admit_message(None) {
+                state.handle_opc_message(Source::Audio, effect.opc_channel, 0, &frame);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Platform capture command, unless `command` is set
+fn capture_command(config: &AudioConfig, sample_rate: u32) -> Result<Vec<String>> {
+    if let Some(command) = config.command.clone().filter(|c| !c.is_empty()) {
+        return Ok(command);
+    }
+
+    let rate = sample_rate.to_string();
+    if cfg!(target_os = "linux") {
+        let mut command = vec!["arecord", "-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r", &rate];
+        if let Some(device) = &config.device {
+            command.extend(["-D", device.as_str()]);
+        }
+        return Ok(command.iter().map(|s| s.to_string()).collect());
+    }
+
+    let input = if cfg!(windows) {
+        let Some(device) = &config.device else {
+            anyhow::bail!("audio.device must name a DirectShow input on Windows (see `ffmpeg -list_devices true -f dshow -i dummy`)");
+        };
+        ["dshow".to_string(), format!("audio={}", device)]
+    } else {
+        ["avfoundation".to_string(), format!(":{}", config.device.as_deref().unwrap_or("0"))]
+    };
+    Ok(["ffmpeg", "-loglevel", "error", "-f", &input[0], "-i", &input[1], "-ac", "1", "-ar", &rate, "-f", "s16le", "-"]
+        .iter().map(|s| s.to_string()).collect())
+}
+
+/// Displayed levels of one effect, 0.0 - 1.0, falling back slowly after peaks
+#[derive(Debug, Default)]
+struct Meter {
+    /// One level per LED for `spectrum`, a single level for `vu`
+    levels: Vec<f32>,
+    led_count: usize,
+}
+
+impl Meter {
+    fn update(&mut self, effect: AudioEffect, samples: &[f32], sample_rate: u32, led_count: usize) {
+        let current = match effect {
+            AudioEffect::Spectrum => band_levels(samples, sample_rate, led_count),
+            AudioEffect::Vu => vec![rms_level(samples)],
+        };
+        self.levels.resize(current.len(), 0.0);
+        for (level, new) in self.levels.iter_mut().zip(current) {
+            *level = new.max(*level * DECAY);
+        }
+        self.led_count = led_count;
+    }
+}
+
+/// Draw an effect's levels as RGB data for its channel
+fn render(effect: &AudioEffectConfig, meter: &Meter, out: &mut Vec<u8>) {
+    out.clear();
+    let n = meter.led_count;
+    match effect.effect {
+        AudioEffect::Spectrum => {
+            for (i, &level) in meter.levels.iter().enumerate() {
+                let color = effect.color.unwrap_or_else(|| hue(i as f32 / n as f32 * 0.8));
+                out.extend(color.map(|c| (c as f32 * level) as u8));
+            }
+        }
+        AudioEffect::Vu => {
+            let lit = (meter.levels.first().copied().unwrap_or(0.0) * n as f32).round() as usize;
+            for i in 0..n {
+                // Green at the start of the strip, through yellow to red at the end
+                let color = effect.color.unwrap_or_else(|| hue((1.0 - i as f32 / n as f32) / 3.0));
+                out.extend(if i < lit { color } else { [0, 0, 0] });
+            }
+        }
+    }
+}
+
+/// Map an amplitude (1.0 = full scale) onto 0.0 - 1.0 between `FLOOR_DB` and 0dB
+fn db_level(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+    ((20.0 * amplitude.log10() - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// Loudness of the samples, 0.0 - 1.0
+pub fn rms_level(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    // A full-scale sine has an RMS of 1/sqrt(2); count it as full scale
+    db_level(mean_square.sqrt() * std::f32::consts::SQRT_2)
+}
+
+/// Level of `bands` log-spaced frequency bands from `LOWEST_HZ` up, 0.0 - 1.0
+///
+/// `samples` must be a power of two long.
+pub fn band_levels(samples: &[f32], sample_rate: u32, bands: usize) -> Vec<f32> {
+    let n = samples.len();
+    let mut re: Vec<f32> = samples.iter().enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()))
+        .collect();
+    let mut im = vec![0f32; n];
+    fft(&mut re, &mut im);
+
+    // A full-scale sine peaks at n/4 after the Hann window
+    let magnitudes: Vec<f32> = (0..n / 2)
+        .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt() / (n as f32 / 4.0))
+        .collect();
+
+    let bin_hz = sample_rate as f32 / n as f32;
+    let highest = (sample_rate as f32 / 2.0).clamp(LOWEST_HZ * 2.0, 16000.0);
+    let ratio = highest / LOWEST_HZ;
+    (0..bands).map(|band| {
+        let low = LOWEST_HZ * ratio.powf(band as f32 / bands as f32);
+        let high = LOWEST_HZ * ratio.powf((band + 1) as f32 / bands as f32);
+        let first = ((low / bin_hz) as usize).min(magnitudes.len() - 1);
+        let last = ((high / bin_hz).ceil() as usize).clamp(first + 1, magnitudes.len());
+        db_level(magnitudes[first..last].iter().copied().fold(0.0, f32::max))
+    }).collect()
+}
+
+/// In-place radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two());
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_re, w_im) = ((angle * k as f32).cos(), (angle * k as f32).sin());
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, amplitude: f32) -> Vec<f32> {
+        (0..FFT_SIZE).map(|i| amplitude * (2.0 * PI * hz * i as f32 / 44100.0).sin()).collect()
+    }
+
+    #[test]
+    fn test_band_levels_find_the_tone() {
+        let levels = band_levels(&sine(1000.0, 1.0), 44100, 10);
+        let loudest = (0..10).max_by(|&a, &b| levels[a].total_cmp(&levels[b])).unwrap();
+        // 40Hz-16kHz in 10 log bands: 1kHz falls in the sixth band
+        assert_eq!(loudest, 5);
+        assert!(levels[5] > 0.95);
+        assert!(levels[0] < 0.5 && levels[9] < 0.5);
+
+        assert_eq!(band_levels(&vec![0.0; FFT_SIZE], 44100, 4), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_rms_level_in_db() {
+        assert!((rms_level(&sine(440.0, 1.0)) - 1.0).abs() < 0.01);
+        // -30dB is halfway between the floor and full scale
+        assert!((rms_level(&sine(440.0, 0.0316)) - 0.5).abs() < 0.02);
+        assert_eq!(rms_level(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_render_vu() {
+        let effect = AudioEffectConfig { opc_channel: 0, effect: AudioEffect::Vu, color: Some([0, 0, 255]) };
+        let meter = Meter { levels: vec![0.5], led_count: 4 };
+        let mut frame = Vec::new();
+        render(&effect, &meter, &mut frame);
+        assert_eq!(frame, vec![0, 0, 255, 0, 0, 255, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use serialport::{ClearBuffer, SerialPort};
+use std::io::Write;
+use std::time::Duration;
+
+use crate::config::OutputConfig;
+use crate::output::Output;
+use crate::protocol::{wait_for_ack, AckMode, AckResult, Protocol, ADALIGHT_BAUD_RATES};
+
+/// Rates HyperSerial AWA firmwares are built for, fastest first
+pub const AWA_BAUD_RATES: &[u32] = &[2000000, 1000000, 921600, 500000, 460800, 250000, 115200];
+
+/// How long to listen at each rate for a greeting unless `wait_for_ada_ms` is set
+pub const GREETING_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// Try `probe` at each rate in turn, returning the first rate (and result) that works
+///
+/// Shared by the WLED, Adalight and `baud_rate: "auto"` handshakes.
+pub fn scan<T>(label: &str, rates: &[u32], ddebug: bool, mut probe: impl FnMut(u32) -> Result<T>) -> Result<(u32, T)> {
+    for &baud in rates {
+        if ddebug {
+            eprintln!("[DEBUG {}] Trying baud rate {}...", label, baud);
+        }
+
+        match probe(baud) {
+            Ok(found) => return Ok((baud, found)),
+            Err(e) => {
+                if ddebug {
+                    eprintln!("[DEBUG {}] No response at {} baud: {:#}", label, baud, e);
+                }
+            }
+        }
+    }
+
+    anyhow::bail!("no response at any of {} baud rates", rates.len())
+}
+
+/// How a device proves it is listening at a rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Evidence {
+    /// The Adalight sketch's `Ada` greeting
+    Greeting,
+    /// An acknowledgement of a blank frame (`ack` configured)
+    Ack(AckMode),
+}
+
+/// What `baud_rate: "auto"` can look for on this output
+fn evidence(config: &OutputConfig) -> Result<Evidence> {
+    if config.is_wled() {
+        anyhow::bail!("baud_rate \"auto\" is not supported for WLED, which is always detected; set the rate to switch to");
+    }
+    match (config.ack, config.protocol.as_str()) {
+        (Some(mode), _) => Ok(Evidence::Ack(mode)),
+        (None, "adalight") => Ok(Evidence::Greeting),
+        _ => anyhow::bail!("baud_rate \"auto\" on a {} output needs `ack`, since the device sends no banner", config.protocol),
+    }
+}
+
+/// Check that `baud_rate: "auto"` can work for this output
+pub fn check(config: &OutputConfig) -> Result<()> {
+    evidence(config).map(|_| ())
+}
+
+/// Find the rate an Adalight or AWA device listens at and open the port there
+///
+/// Adalight devices are recognized by their greeting, and devices with `ack` by
+/// acknowledging a blank frame. Returns the port and the rate found.
+pub fn detect(config: &OutputConfig, protocol: &dyn Protocol, debug: bool, ddebug: bool) -> Result<(u32, Box<dyn SerialPort>)> {
+    let evidence = evidence(config)?;
+
+    let standard = if config.protocol == "adalight" { ADALIGHT_BAUD_RATES } else { AWA_BAUD_RATES };
+    let mut rates: Vec<u32> = config.handshake_baud_rate.into_iter().collect();
+    rates.extend(standard.iter().filter(|&&rate| Some(rate) != config.handshake_baud_rate));
+
+    let stride = match config.pixel_format.as_deref() {
+        Some("RGBW") | Some("GRBW") => 4,
+        _ => 3,
+    };
+    let blank = protocol.build_frame(&vec![0u8; config.led_count * stride], stride);
+    let greeting_timeout = config.wait_for_ada_ms.map(Duration::from_millis).unwrap_or(GREETING_TIMEOUT);
+    let ack_timeout = Duration::from_millis(config.ack_timeout_ms.unwrap_or(100));
+
+    let result = scan(&config.label(), &rates, ddebug, |baud| match evidence {
+        Evidence::Greeting => open_greeted(config, baud, greeting_timeout),
+        Evidence::Ack(mode) => {
+            let mut port = open_at(config, baud)?;
+            let _ = port.clear(ClearBuffer::Input);
+            port.write_all(&blank)?;
+            port.flush()?;
+            match wait_for_ack(port.as_mut(), mode, ack_timeout)? {
+                AckResult::Ack => Ok(port),
+                other => anyhow::bail!("{:?}", other),
+            }
+        }
+    });
+
+    let (baud, port) = result.map_err(|e| anyhow::anyhow!("Failed to detect the baud rate of {}: {}", config.port, e))?;
+    if debug {
+        println!("✓ {} answered at {} baud", config.label(), baud);
+    }
+    Ok((baud, port))
+}
+
+/// Open the output's port at `baud` with its other serial settings
+fn open_at(config: &OutputConfig, baud: u32) -> Result<Box<dyn SerialPort>> {
+    let mut attempt = config.clone();
+    attempt.baud_rate = baud;
+    attempt.wait_for_ada_ms = None;
+    Output::open_standard_port(&attempt)
+}
+
+/// Open the port at `baud` and wait for an Adalight sketch's `Ada` greeting
+pub fn open_greeted(config: &OutputConfig, baud: u32, timeout: Duration) -> Result<Box<dyn SerialPort>> {
+    let mut port = open_at(config, baud)?;
+    match wait_for_ack(port.as_mut(), AckMode::Ada, timeout)? {
+        AckResult::Ack => {
+            // The sketch repeats its greeting until data arrives; don't leave it for the ack checks
+            let _ = port.clear(ClearBuffer::Input);
+            Ok(port)
+        }
+        other => anyhow::bail!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_returns_first_working_rate() {
+        let mut tried = Vec::new();
+        let found = scan("test", &[115200, 500000, 1000000], false, |baud| {
+            tried.push(baud);
+            if baud >= 500000 { Ok(baud / 100) } else { anyhow::bail!("silent") }
+        });
+        assert_eq!(found.unwrap(), (500000, 5000));
+        assert_eq!(tried, vec![115200, 500000]);
+
+        assert!(scan("test", &[9600], false, |_| -> Result<()> { anyhow::bail!("silent") }).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use crate::arbiter::Source;
+use crate::stats;
+
+/// What one OPC channel has received
+struct ChannelRecord {
+    frames: u64,
+    bytes: u64,
+    last_frame_ms: u64,
+    source: Source,
+    client: Option<SocketAddr>,
+    /// Pixel data of the most recent message, as it arrived
+    last_frame: Vec<u8>,
+}
+
+/// Counters of one channel, as reported by the control API
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelCounters {
+    pub channel: u8,
+    /// Pixel messages received, including ones a higher-priority input preempted
+    pub frames: u64,
+    pub bytes: u64,
+    /// Unix time in milliseconds of the last message
+    pub last_frame_ms: u64,
+    /// Input the last message came from (`sources.priorities` name)
+    pub source: &'static str,
+    /// Address of the client that sent it, for network inputs with connections
+    pub client: Option<String>,
+}
+
+/// Per-channel counters of incoming pixel messages, with the last message of each
+///
+/// Messages are counted as they arrive, before arbitration and the schedule, so the
+/// last frame is exactly what the client sent even when no output showed it.
+#[derive(Default)]
+pub struct ChannelStats {
+    channels: Mutex<BTreeMap<u8, ChannelRecord>>,
+}
+
+impl ChannelStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a pixel message and keep its data as the channel's last frame
+    pub fn record(&self, source: Source, client: Option<SocketAddr>, channel: u8, data: &[u8]) {
+        let mut channels = self.channels.lock().unwrap();
+        let record = channels.entry(channel).or_insert_with(|| ChannelRecord {
+            frames: 0,
+            bytes: 0,
+            last_frame_ms: 0,
+            source,
+            client,
+            last_frame: Vec::new(),
+        });
+        record.frames += 1;
+        record.bytes += data.len() as u64;
+        record.last_frame_ms = stats::now_ms();
+        record.source = source;
+        record.client = client;
+        // The buffer is reused, so steady streams don't allocate
+        record.last_frame.clear();
+        record.last_frame.extend_from_slice(data);
+    }
+
+    /// Counters of every channel that has received a message, in channel order
+    pub fn counters(&self) -> Vec<ChannelCounters> {
+        self.channels.lock().unwrap().iter()
+            .map(|(&channel, record)| counters(channel, record))
+            .collect()
+    }
+
+    /// Counters and last frame of `channel`, if it has received a message
+    pub fn last_frame(&self, channel: u8) -> Option<(ChannelCounters, Vec<u8>)> {
+        let channels = self.channels.lock().unwrap();
+        channels.get(&channel).map(|record| (counters(channel, record), record.last_frame.clone()))
+    }
+}
+
+fn counters(channel: u8, record: &ChannelRecord) -> ChannelCounters {
+    ChannelCounters {
+        channel,
+        frames: record.frames,
+        bytes: record.bytes,
+        last_frame_ms: record.last_frame_ms,
+        source: record.source.name(),
+        client: record.client.map(|addr| addr.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_keeps_last_frame_per_channel() {
+        let stats = ChannelStats::new();
+        let client: SocketAddr = "192.168.1.20:51234".parse().unwrap();
+        stats.record(Source::Opc, Some(client), 2, &[1, 2, 3, 4, 5, 6]);
+        stats.record(Source::Hyperion, None, 0, &[9; 3]);
+        stats.record(Source::Opc, Some(client), 2, &[7, 8, 9]);
+
+        let counters = stats.counters();
+        assert_eq!(counters.iter().map(|c| c.channel).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!((counters[1].frames, counters[1].bytes), (2, 9));
+        assert_eq!(counters[1].client.as_deref(), Some("192.168.1.20:51234"));
+        assert_eq!(counters[0].source, "hyperion");
+
+        let (_, frame) = stats.last_frame(2).unwrap();
+        assert_eq!(frame, vec![7, 8, 9]);
+        assert!(stats.last_frame(1).is_none());
+    }
+}
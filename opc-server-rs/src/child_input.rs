@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Wait before restarting a command that exited
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Run `command` on a background thread and hand its stdout to `on_chunk`, `chunk_len`
+/// bytes at a time (one video frame, one block of samples)
+///
+/// A command that fails to start or exits is reported as `name` and restarted after
+/// a few seconds. Once `running` is cleared the command is killed after its next chunk,
+/// or ends on the closed pipe.
+pub fn spawn(name: &'static str, command: Vec<String>, chunk_len: usize, running: Arc<AtomicBool>,
+             mut on_chunk: impl FnMut(&[u8]) + Send + 'static) {
+    thread::spawn(move || {
+        while running.load(Ordering::Relaxed) {
+            if let Err(e) = run(&command, chunk_len, &running, &mut on_chunk) {
+                eprintln!("✗ {} failed: {:#}", name, e);
+            }
+            let retry_at = Instant::now() + RESTART_DELAY;
+            while running.load(Ordering::Relaxed) && Instant::now() < retry_at {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    });
+}
+
+/// Run the command once, until it exits or `running` is cleared
+fn run(command: &[String], chunk_len: usize, running: &AtomicBool, on_chunk: &mut impl FnMut(&[u8])) -> Result<()> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to start {}", command[0]))?;
+    let mut stdout = child.stdout.take().context(format!("{} has no stdout", command[0]))?;
+
+    let mut chunk = vec![0u8; chunk_len];
+    let result = loop {
+        if !running.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        if let Err(e) = stdout.read_exact(&mut chunk) {
+            break Err(e).context(format!("{} stopped sending data", command[0]));
+        }
+        on_chunk(&chunk);
+    };
+
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_chunks_until_the_command_exits() {
+        let command = ["sh", "-c", "printf abcdefg"].map(String::from).to_vec();
+        let mut chunks = Vec::new();
+        let result = run(&command, 3, &AtomicBool::new(true), &mut |chunk: &[u8]| chunks.push(chunk.to_vec()));
+
+        // The incomplete last chunk is not handed over
+        assert_eq!(chunks, vec![b"abc".to_vec(), b"def".to_vec()]);
+        assert!(format!("{:#}", result.unwrap_err()).starts_with("sh stopped sending data"));
+        assert!(run(&["/nonexistent/grabber".to_string()], 3, &AtomicBool::new(true), &mut |_: &[u8]| {}).is_err());
+    }
+
+    #[test]
+    fn test_stops_with_the_server() {
+        let running = Arc::new(AtomicBool::new(true));
+        let chunks = Arc::new(Mutex::new(0));
+        let counted = chunks.clone();
+        let flag = running.clone();
+        spawn("Test input", ["sh", "-c", "yes"].map(String::from).to_vec(), 4, running.clone(), move |_| {
+            *counted.lock().unwrap() += 1;
+            flag.store(false, Ordering::Relaxed);
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while *chunks.lock().unwrap() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(*chunks.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Color correction parameters (same model and JSON field names as Fadecandy)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorCorrection {
+    /// Exponent applied to the nonlinear section of the curve
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// Per-channel (R, G, B) scale applied before the curve
+    #[serde(default = "default_whitepoint")]
+    pub whitepoint: [f32; 3],
+    /// Slope of the linear section near black
+    #[serde(default = "default_linear_slope")]
+    pub linear_slope: f32,
+    /// Output level below which the linear section is used
+    #[serde(default)]
+    pub linear_cutoff: f32,
+}
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+fn default_whitepoint() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_linear_slope() -> f32 {
+    1.0
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        ColorCorrection {
+            gamma: default_gamma(),
+            whitepoint: default_whitepoint(),
+            linear_slope: default_linear_slope(),
+            linear_cutoff: 0.0,
+        }
+    }
+}
+
+impl ColorCorrection {
+    /// Fadecandy's out-of-the-box correction curve
+    pub fn fadecandy_default() -> Self {
+        ColorCorrection {
+            gamma: 2.5,
+            ..Default::default()
+        }
+    }
+
+    /// Build the per-channel lookup table for this correction
+    pub fn build_lut(&self) -> ColorLut {
+        let mut table = [[0u8; 256]; 3];
+
+        for (channel, entries) in table.iter_mut().enumerate() {
+            for (i, entry) in entries.iter_mut().enumerate() {
+                let input = (i as f32 / 255.0) * self.whitepoint[channel];
+
+                let output = if input * self.linear_slope <= self.linear_cutoff {
+                    input * self.linear_slope
+                } else {
+                    // Nonlinear section, scaled so the curve stays continuous at the cutoff
+                    let scale = 1.0 - self.linear_cutoff;
+                    let nonlinear = (input - self.linear_cutoff / self.linear_slope.max(f32::EPSILON)).max(0.0);
+                    self.linear_cutoff + (nonlinear / scale.max(f32::EPSILON)).powf(self.gamma) * scale
+                };
+
+                *entry = (output.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+
+        ColorLut { table }
+    }
+}
+
+/// Precomputed per-channel color correction table for RGB pixel data
+#[derive(Debug, Clone)]
+pub struct ColorLut {
+    table: [[u8; 256]; 3],
+}
+
+impl ColorLut {
+    /// Apply the table in-place to RGB pixel data
+    pub fn apply(&self, data: &mut [u8]) {
+        for pixel in data.chunks_exact_mut(3) {
+            pixel[0] = self.table[0][pixel[0] as usize];
+            pixel[1] = self.table[1][pixel[1] as usize];
+            pixel[2] = self.table[2][pixel[2] as usize];
+        }
+    }
+}
+
+/// Precision of linear-light values in the `LinearLight` tables
+const LINEAR_BITS: u32 = 12;
+
+/// sRGB decode/encode tables for scaling pixel data in linear light (`linear_light`)
+///
+/// Scaling sRGB bytes directly darkens midtones too fast, so dimmed colors shift
+/// and fades look uneven; decoding to linear light first keeps them true.
+#[derive(Debug)]
+pub struct LinearLight {
+    decode: [u16; 256],
+    encode: Vec<u8>,
+}
+
+impl LinearLight {
+    /// The shared tables, built on first use
+    pub fn get() -> &'static LinearLight {
+        static TABLES: OnceLock<LinearLight> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let max = ((1 << LINEAR_BITS) - 1) as f32;
+            let mut decode = [0u16; 256];
+            for (i, entry) in decode.iter_mut().enumerate() {
+                *entry = (srgb_to_linear(i as f32 / 255.0) * max).round() as u16;
+            }
+            let encode = (0..1 << LINEAR_BITS)
+                .map(|i| (linear_to_srgb(i as f32 / max) * 255.0).round() as u8)
+                .collect();
+            LinearLight { decode, encode }
+        })
+    }
+
+    /// Scale sRGB pixel data in-place by brightness (255 = unchanged), in linear light
+    pub fn scale(&self, data: &mut [u8], brightness: u8) {
+        for byte in data.iter_mut() {
+            let linear = (self.decode[*byte as usize] as u32 * brightness as u32 + 127) / 255;
+            *byte = self.encode[linear as usize];
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Temperature at which `color_temperature_k` leaves colors unchanged (D65 daylight)
+pub const NEUTRAL_TEMPERATURE_K: f32 = 6500.0;
+
+/// Temperatures the black body approximation covers
+pub const TEMPERATURE_RANGE_K: std::ops::RangeInclusive<f32> = 1000.0..=40000.0;
+
+/// Color of a black body at `kelvin`, each channel 0.0 - 1.0 (Tanner Helland's fit)
+pub fn blackbody_rgb(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(*TEMPERATURE_RANGE_K.start(), *TEMPERATURE_RANGE_K.end()) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.69873 * (t - 60.0).powf(-0.13320476) };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12217 * (t - 60.0).powf(-0.07551485)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    [red, green, blue].map(|c| (c / 255.0).clamp(0.0, 1.0))
+}
+
+/// Per-channel white point correction for one output (`color_temperature_k`)
+///
+/// Scales the channels so white takes on the color of a black body at the given
+/// temperature, relative to neutral daylight; the strongest channel is left at full level.
+#[derive(Debug, Clone)]
+pub struct ColorTemperature {
+    /// Channel multipliers, 256 = unchanged
+    scale: [u16; 3],
+}
+
+impl ColorTemperature {
+    pub fn new(kelvin: f32) -> Self {
+        let target = blackbody_rgb(kelvin);
+        let neutral = blackbody_rgb(NEUTRAL_TEMPERATURE_K);
+        let ratios = [0, 1, 2].map(|c| target[c] / neutral[c].max(f32::EPSILON));
+        let max = ratios.iter().cloned().fold(f32::EPSILON, f32::max);
+        ColorTemperature {
+            scale: ratios.map(|r| (r / max * 256.0).round() as u16),
+        }
+    }
+
+    /// Apply the correction in-place to RGB pixel data
+    pub fn apply(&self, data: &mut [u8]) {
+        if self.scale == [256; 3] {
+            return;
+        }
+        for pixel in data.chunks_exact_mut(3) {
+            for (value, &scale) in pixel.iter_mut().zip(&self.scale) {
+                *value = ((*value as u32 * scale as u32) >> 8) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_correction() {
+        let lut = ColorCorrection::default().build_lut();
+        let mut data: Vec<u8> = (0..=254).collect();
+        let expected = data.clone();
+        lut.apply(&mut data);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_gamma_darkens_midtones() {
+        let lut = ColorCorrection::fadecandy_default().build_lut();
+        let mut data = vec![0, 128, 255];
+        lut.apply(&mut data);
+        assert_eq!(data[0], 0);
+        assert!(data[1] < 64);
+        assert_eq!(data[2], 255);
+    }
+
+    #[test]
+    fn test_whitepoint_scales_channels() {
+        let correction = ColorCorrection {
+            whitepoint: [1.0, 0.5, 0.0],
+            ..Default::default()
+        };
+        let mut data = vec![255, 255, 255];
+        correction.build_lut().apply(&mut data);
+        assert_eq!(data, vec![255, 128, 0]);
+    }
+
+    #[test]
+    fn test_color_temperature() {
+        let mut data = vec![255, 255, 255];
+        ColorTemperature::new(NEUTRAL_TEMPERATURE_K).apply(&mut data);
+        assert_eq!(data, vec![255, 255, 255]);
+
+        // Warm white keeps red and pulls green and (more) blue down
+        ColorTemperature::new(3000.0).apply(&mut data);
+        assert_eq!(data[0], 255);
+        assert!(data[2] < data[1] && data[1] < 255);
+    }
+
+    #[test]
+    fn test_linear_light_scaling() {
+        let tables = LinearLight::get();
+        let mut data: Vec<u8> = (0..=255).collect();
+        tables.scale(&mut data, 255);
+        assert_eq!(data, (0..=255).collect::<Vec<u8>>());
+
+        // Half the light of full white is sRGB 188, not 128
+        let mut data = vec![255, 0];
+        tables.scale(&mut data, 128);
+        assert_eq!(data, vec![188, 0]);
+    }
+}
@@ -1,21 +1,783 @@
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::autobaud;
+use crate::protocol::{self, AckMode};
+use crate::queue::QueuePolicy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::color::{self, ColorCorrection};
+use crate::health;
+use crate::config_format::Format;
+use crate::matrix;
+use crate::net;
+use crate::pending::DEFAULT_OUTPUT_RETRY;
+use crate::persist;
+use crate::sched;
+use crate::schedule::Schedule;
+use crate::serial::{self, UsbMatch};
+use crate::strip_type;
+use crate::transform;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub opc: OpcConfig,
     pub outputs: Vec<OutputConfig>,
+    /// Optional runtime control server (HTTP)
+    pub control: Option<ControlConfig>,
+    /// Optional Fadecandy server emulation
+    pub fadecandy: Option<FadecandyConfig>,
+    /// Optional Hyperion.ng flatbuffers input
+    pub hyperion: Option<HyperionConfig>,
+    /// Optional screen-capture input (needs the `ambilight` feature)
+    pub ambilight: Option<AmbilightConfig>,
+    /// Optional audio-reactive effects (needs the `audio` feature)
+    pub audio: Option<AudioConfig>,
+    /// Optional priorities between inputs that drive the same channels
+    pub sources: Option<SourcesConfig>,
+    /// Optional daily on/off windows; outside them outputs are blank and input is ignored
+    pub schedule: Option<Vec<ScheduleWindow>>,
+    /// Optional downstream OPC servers every received message is forwarded to
+    pub relay: Option<Vec<RelayConfig>>,
+    /// Optional frame release ticks shared by the servers of a multi-host installation
+    pub frame_sync: Option<FrameSyncConfig>,
+    /// Update rate for the Python server and validate tools; this server sends frames as they arrive
+    pub target_fps: Option<f64>,
+    /// Time between attempts to open outputs that failed (default: 5000ms, 0 disables retries)
+    pub output_retry_ms: Option<u64>,
+    /// Watch for serial devices being plugged in and removed (default: true; needs retries)
+    pub hotplug: Option<bool>,
+    /// Keep brightness, enabled state and color correction changed at runtime across restarts (default: true)
+    pub persist_state: Option<bool>,
+    /// File the runtime state is kept in (default: next to the config file, e.g. `config.state.json`)
+    pub state_file: Option<String>,
+    /// Optional thresholds at which outputs are reported degraded (`/health`, `health`)
+    pub health: Option<HealthConfig>,
+}
+
+impl Config {
+    /// Interval for retrying outputs that failed to open; None when retries are off
+    pub fn output_retry(&self) -> Option<Duration> {
+        match self.output_retry_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => Some(DEFAULT_OUTPUT_RETRY),
+        }
+    }
+    
+    /// Where to keep runtime adjustments for the config at `config_path`; None when not persisting
+    pub fn state_file(&self, config_path: &str) -> Option<PathBuf> {
+        if !self.persist_state.unwrap_or(true) {
+            return None;
+        }
+        match &self.state_file {
+            Some(path) => Some(PathBuf::from(path)),
+            None => persist::default_path(config_path),
+        }
+    }
+    
+    /// Whether to reopen outputs when their devices are plugged back in
+    pub fn hotplug(&self) -> bool {
+        self.hotplug.unwrap_or(true) && self.output_retry().is_some()
+    }
+    
+    /// Load configuration from a JSON, TOML (`.toml`) or YAML (`.yaml`/`.yml`) file
+    pub fn load(path: &str) -> Result<Self> {
+        Self::load_with(path, &LoadOptions::default())
+    }
+    
+    /// Load configuration, apply overrides in order (later ones win) and check for unknown keys
+    pub fn load_with(path: &str, options: &LoadOptions) -> Result<Self> {
+        let config_data = fs::read_to_string(path)
+            .context(format!("Failed to read config file {}", path))?;
+        let mut value = Format::from_path(path).parse(&config_data)
+            .context(format!("Failed to parse config file {}", path))?;
+        expand_strips(&mut value)
+            .context(format!("Invalid strips in config file {}", path))?;
+        for entry in &options.overrides {
+            entry.apply(&mut value)?;
+        }
+        let mut config: Config = serde_json::from_value(value.clone())
+            .context(format!("Failed to parse config file {}", path))?;
+        
+        for output in &mut config.outputs {
+            strip_type::apply(output).context(format!("Invalid outputs in config file {}", path))?;
+        }
+        config.check_outputs()
+            .context(format!("Invalid outputs in config file {}", path))?;
+        config.opc.check()
+            .context(format!("Invalid opc settings in config file {}", path))?;
+        if config.ambilight.is_some() && !cfg!(feature = "ambilight") {
+            anyhow::bail!("ambilight in config file {} needs a server built with `--features ambilight`", path);
+        }
+        if config.audio.is_some() && !cfg!(feature = "audio") {
+            anyhow::bail!("audio in config file {} needs a server built with `--features audio`", path);
+        }
+        if let Some(health) = &config.health {
+            health::check(health)
+                .context(format!("Invalid health in config file {}", path))?;
+        }
+        if let Some(windows) = &config.schedule {
+            Schedule::from_config(windows)
+                .context(format!("Invalid schedule in config file {}", path))?;
+        }
+        
+        let unknown = config.unknown_keys(&value);
+        if !unknown.is_empty() {
+            if !options.lenient {
+                anyhow::bail!("Unknown keys in config file {} (use --lenient to ignore):\n  {}",
+                              path, unknown.join("\n  "));
+            }
+            for key in &unknown {
+                eprintln!("Warning: {} (ignored)", key);
+            }
+        }
+        
+        // After the key check, which matches outputs to the file by position
+        if config.outputs.iter().any(|o| o.host.is_some()) {
+            let hostname = options.hostname.clone().or_else(net::hostname)
+                .context("Outputs have a `host`, but this machine's hostname is unknown (use --hostname)")?;
+            let total = config.outputs.len();
+            config.retain_host_outputs(&hostname);
+            if config.outputs.is_empty() {
+                anyhow::bail!("None of the {} outputs in config file {} are for host '{}'", total, path, hostname);
+            }
+        }
+        Ok(config)
+    }
+    
+    /// Keep the outputs without a `host` and those for `hostname`, dropping other machines' outputs
+    pub fn retain_host_outputs(&mut self, hostname: &str) {
+        self.outputs.retain(|o| o.host.as_deref().is_none_or(|host| host_matches(host, hostname)));
+    }
+    
+    /// Output names pick outputs in the control API and logs, so each must be unique
+    pub fn check_outputs(&self) -> Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for output in &self.outputs {
+            if let Some(name) = &output.name {
+                if !seen.insert(name) {
+                    anyhow::bail!("Output name '{}' is used more than once", name);
+                }
+            }
+            output.check()?;
+        }
+        Ok(())
+    }
+    
+    /// Keys in the raw config that no setting reads, with a did-you-mean hint where one is close
+    ///
+    /// Every field is written out when a config is serialized, so the raw keys are
+    /// compared against this config serialized back. Keys starting with `_` are comments.
+    pub fn unknown_keys(&self, raw: &serde_json::Value) -> Vec<String> {
+        let known = serde_json::to_value(self).unwrap_or(serde_json::Value::Null);
+        let mut unknown = Vec::new();
+        collect_unknown(raw, &known, "", &mut unknown);
+        unknown
+    }
+}
+
+/// How a config file is read
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// `--set` and environment overrides
+    pub overrides: Vec<Override>,
+    /// Warn about unknown keys instead of refusing the config
+    pub lenient: bool,
+    /// Name matched against outputs' `host` (`--hostname`; default: this machine's hostname)
+    pub hostname: Option<String>,
+}
+
+/// Hostnames compare without case, and a bare name matches the same name with a
+/// domain (`pi-2` and `pi-2.local`)
+fn host_matches(host: &str, hostname: &str) -> bool {
+    if host.eq_ignore_ascii_case(hostname) {
+        return true;
+    }
+    if host.contains('.') && hostname.contains('.') {
+        return false;
+    }
+    let short = |name: &str| name.split('.').next().unwrap_or_default().to_string();
+    short(host).eq_ignore_ascii_case(&short(hostname))
+}
+
+fn collect_unknown(raw: &serde_json::Value, known: &serde_json::Value, path: &str, unknown: &mut Vec<String>) {
+    match (raw, known) {
+        (serde_json::Value::Object(raw), serde_json::Value::Object(known)) => {
+            for (key, value) in raw {
+                let key_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known.get(key) {
+                    Some(expected) => collect_unknown(value, expected, &key_path, unknown),
+                    None if key.starts_with('_') => {}
+                    None => {
+                        let hint = known.keys()
+                            .map(|k| (edit_distance(key, k), k))
+                            .filter(|(distance, k)| *distance <= (k.len() / 3).max(2))
+                            .min();
+                        match hint {
+                            Some((_, k)) => unknown.push(format!("Unknown key `{}` (did you mean `{}`?)", key_path, k)),
+                            None => unknown.push(format!("Unknown key `{}`", key_path)),
+                        }
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(raw), serde_json::Value::Array(known)) => {
+            for (index, (value, expected)) in raw.iter().zip(known).enumerate() {
+                collect_unknown(value, expected, &format!("{}[{}]", path, index), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Levenshtein distance between two keys
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + (ca != cb) as usize).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Prefix of environment variables that override config values
+pub const ENV_PREFIX: &str = "OPC_SERVER_";
+
+/// One config value replaced from the command line (`--set`) or environment
+///
+/// Paths are dotted (`opc.port`, `outputs.0.baud_rate`); list entries are picked
+/// by index or by `name` (`outputs.desk.brightness`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub path: Vec<String>,
+    pub value: serde_json::Value,
+    /// Where the override came from, for error messages
+    pub origin: String,
+}
+
+impl Override {
+    /// Parse `key=value`; the value is read as JSON if possible, otherwise as a string
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((key, raw)) = spec.split_once('=') else {
+            anyhow::bail!("Invalid override '{}' (expected key=value)", spec);
+        };
+        Self::new(key.trim().split('.').map(String::from).collect(), raw.trim(), format!("--set {}", spec))
+    }
+    
+    /// Overrides from `OPC_SERVER_<PATH>` variables, with `__` between path parts
+    /// (`OPC_SERVER_OPC__PORT=7891`, `OPC_SERVER_OUTPUTS__0__BAUD_RATE=1000000`)
+    ///
+    /// `OPC_SERVER_DEBUG` and `OPC_SERVER_DDEBUG` are flags, not config values.
+    pub fn from_env() -> Result<Vec<Self>> {
+        let mut vars: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        vars.sort();
+        
+        vars.into_iter()
+            .filter(|(name, _)| !matches!(&name[ENV_PREFIX.len()..], "DEBUG" | "DDEBUG"))
+            .map(|(name, raw)| {
+                let path = name[ENV_PREFIX.len()..].split("__").map(|p| p.to_lowercase()).collect();
+                Self::new(path, &raw, name.clone())
+            })
+            .collect()
+    }
+    
+    fn new(path: Vec<String>, raw: &str, origin: String) -> Result<Self> {
+        if path.iter().any(|p| p.is_empty()) {
+            anyhow::bail!("Invalid config path in {}", origin);
+        }
+        let value = serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()));
+        Ok(Override { path, value, origin })
+    }
+    
+    /// Set the value in a parsed config, creating missing sections
+    pub fn apply(&self, config: &mut serde_json::Value) -> Result<()> {
+        let (last, parents) = self.path.split_last().context("Empty config path")?;
+        let mut node = config;
+        for part in parents {
+            node = child(node, part, true).context(format!("{}: no '{}' in config", self.origin, part))?;
+        }
+        match node {
+            serde_json::Value::Array(_) => {
+                let slot = child(node, last, false).context(format!("{}: no '{}' in config", self.origin, last))?;
+                *slot = self.value.clone();
+            }
+            serde_json::Value::Object(map) => {
+                map.insert(last.clone(), self.value.clone());
+            }
+            _ => anyhow::bail!("{}: '{}' is not a section", self.origin, parents.join(".")),
+        }
+        Ok(())
+    }
+}
+
+/// Step into `part` of an object (creating it when `create`) or a list (by index or `name`)
+fn child<'a>(node: &'a mut serde_json::Value, part: &str, create: bool) -> Option<&'a mut serde_json::Value> {
+    match node {
+        serde_json::Value::Object(map) => {
+            if create {
+                Some(map.entry(part.to_string()).or_insert_with(|| serde_json::json!({})))
+            } else {
+                map.get_mut(part)
+            }
+        }
+        serde_json::Value::Array(items) => {
+            if let Ok(index) = part.parse::<usize>() {
+                return items.get_mut(index);
+            }
+            items.iter_mut().find(|item| {
+                item.get("name").and_then(|n| n.as_str()).is_some_and(|n| n.eq_ignore_ascii_case(part))
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Replace the optional top-level `strips` list with the outputs it describes
+///
+/// A strip is one logical run of pixels on an OPC channel, split across several
+/// controllers: `{"name", "opc_channel", "opc_offset", "segments": [...]}`. Each
+/// segment is an output entry without `opc_channel`/`opc_offset`; those are filled
+/// in so the segments follow each other (offset of the previous segment plus its
+/// `led_count`), and unnamed segments are called `<strip>-1`, `<strip>-2`, ...
+fn expand_strips(config: &mut serde_json::Value) -> Result<()> {
+    let Some(strips) = config.as_object_mut().and_then(|c| c.remove("strips")) else {
+        return Ok(());
+    };
+    let strips = match strips {
+        serde_json::Value::Array(strips) => strips,
+        _ => anyhow::bail!("`strips` must be a list"),
+    };
+    
+    let mut expanded = Vec::new();
+    for (index, strip) in strips.into_iter().enumerate() {
+        let label = strip.get("name").and_then(|n| n.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| format!("strip {}", index + 1));
+        let opc_channel = strip.get("opc_channel").and_then(|c| c.as_u64())
+            .context(format!("{}: missing opc_channel", label))?;
+        let mut offset = strip.get("opc_offset").and_then(|o| o.as_u64()).unwrap_or(0);
+        let segments = strip.get("segments").and_then(|s| s.as_array())
+            .context(format!("{}: missing segments list", label))?;
+        
+        for (number, segment) in segments.iter().enumerate() {
+            let mut segment = segment.as_object()
+                .context(format!("{}: segment {} is not an object", label, number + 1))?
+                .clone();
+            if segment.contains_key("opc_channel") || segment.contains_key("opc_offset") {
+                anyhow::bail!("{}: set opc_channel and opc_offset on the strip, not on segment {}", label, number + 1);
+            }
+            let led_count = segment.get("led_count").and_then(|c| c.as_u64())
+                .context(format!("{}: segment {} is missing led_count", label, number + 1))?;
+            
+            segment.insert("opc_channel".into(), opc_channel.into());
+            segment.insert("opc_offset".into(), offset.into());
+            if let (Some(name), false) = (strip.get("name"), segment.contains_key("name")) {
+                if let Some(name) = name.as_str() {
+                    segment.insert("name".into(), format!("{}-{}", name, number + 1).into());
+                }
+            }
+            offset += led_count;
+            expanded.push(serde_json::Value::Object(segment));
+        }
+    }
+    
+    match config.get_mut("outputs") {
+        Some(serde_json::Value::Array(outputs)) => outputs.extend(expanded),
+        _ => {
+            config["outputs"] = serde_json::Value::Array(expanded);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OpcConfig {
     pub host: String,
     pub port: u16,
+    /// Accept OPC clients over TCP on `host`:`port`; turn off for servers fed only by
+    /// other inputs (default: true)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Disable Nagle's algorithm on client connections
+    #[serde(default)]
+    pub tcp_nodelay: bool,
+    /// Socket receive buffer size in bytes (default: OS default)
+    pub recv_buffer_size: Option<usize>,
+    /// Only accept connections on this network interface, e.g. `eth0` (Linux only)
+    pub interface: Option<String>,
+    /// Connections the kernel queues before they are accepted (default: 128, Unix only)
+    pub listen_backlog: Option<u32>,
+    /// Most OPC clients served at once; more are turned away (default: one at a time,
+    /// with the next connection waiting until the current client disconnects)
+    pub max_clients: Option<usize>,
+    /// Disconnect clients that send nothing for this long, such as ones that vanished
+    /// without closing the connection (default: never)
+    pub idle_timeout_ms: Option<u64>,
+    /// When bound to an IPv6 address, refuse IPv4 clients instead of listening dual-stack
+    #[serde(default)]
+    pub ipv6_only: bool,
+    /// Client addresses allowed to connect, in CIDR notation (default: any)
+    pub allowed_clients: Option<Vec<String>>,
+    /// Shared secret clients must send, followed by a newline, before any OPC data
+    /// (WebSocket clients pass it as `?token=` instead)
+    pub auth_token: Option<String>,
+    /// Encrypt OPC connections with TLS, through OpenSSL's `openssl` command (default: plain TCP)
+    pub tls: Option<TlsConfig>,
+    /// Also accept OPC messages over WebSocket on this port, one message per binary frame (default: off)
+    pub websocket_port: Option<u16>,
+    /// Most OPC messages per second accepted from one connection (default: unlimited)
+    pub max_messages_per_sec: Option<f64>,
+    /// Most OPC messages per second accepted from all sources together (default: unlimited)
+    pub max_total_messages_per_sec: Option<f64>,
+    /// Largest OPC message payload accepted; larger messages disconnect the client (default: 65535)
+    pub max_message_size: Option<usize>,
+    /// Drop pixel messages whose length is not a multiple of 3
+    #[serde(default)]
+    pub reject_partial_pixels: bool,
+    /// Disconnect clients that leave a message incomplete this long (default: 5000ms)
+    pub message_timeout_ms: Option<u64>,
+    /// Release the frames of each OPC message to all its outputs at the same moment
+    #[serde(default)]
+    pub sync_outputs: bool,
+    /// Accept frames that carry a wall-clock presentation time (timed-pixels SysEx), and
+    /// show them at that time
+    #[serde(default)]
+    pub timed_frames: bool,
+    /// What happens to client data while the outputs can't keep up (default: drop)
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Realtime (SCHED_FIFO) priority 1-99 of the listener and client threads (Linux only;
+    /// default: normal scheduling)
+    pub priority: Option<u8>,
+    /// CPUs the listener and client threads may run on (Linux only; default: any)
+    pub cpu_affinity: Option<Vec<usize>>,
+}
+
+impl OpcConfig {
+    /// Check the listener settings
+    pub fn check(&self) -> Result<()> {
+        if self.max_clients == Some(0) {
+            anyhow::bail!("max_clients must be at least 1 (leave it out to serve one client at a time)");
+        }
+        if let Some(tls) = &self.tls {
+            let files = [("cert", Some(&tls.cert)), ("key", Some(&tls.key)), ("client_ca", tls.client_ca.as_ref())];
+            for (name, path) in files {
+                if let Some(path) = path.filter(|path| !Path::new(path).is_file()) {
+                    anyhow::bail!("tls.{} {} is not a file", name, path);
+                }
+            }
+        }
+        sched::check(self.priority, self.cpu_affinity.as_deref())
+    }
+}
+
+/// TLS settings of the OPC listener (`opc.tls`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// PEM certificate the server presents, optionally followed by its chain
+    pub cert: String,
+    /// PEM private key of `cert`
+    pub key: String,
+    /// PEM CA certificates; when set, only clients presenting a certificate signed by
+    /// one of them can connect (default: any client)
+    pub client_ca: Option<String>,
+}
+
+/// What the OPC listener does when every output of a message has a full queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Keep reading; the outputs drop frames by their `queue_policy`
+    #[default]
+    Drop,
+    /// Stop reading the client's socket until there is room, so TCP slows the client down
+    Backpressure,
+}
+
+/// When outputs count as degraded (`health`)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HealthConfig {
+    /// Share of frames in the window that may fail (write errors, missed acknowledgements,
+    /// late frames) before an output is degraded (default: 0.01)
+    pub error_budget: Option<f64>,
+    /// Reconnects in the window an output may need before it is degraded (default: 0)
+    pub max_reconnects: Option<u32>,
+    /// Length of the window errors and reconnects are counted over (default: 60000ms)
+    pub window_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ControlConfig {
+    pub host: String,
+    pub port: u16,
+    /// Serve a live preview page at `/preview`
+    #[serde(default)]
+    pub preview: bool,
+    /// Serve the configuration page at `/ui`, and let `PUT /config` rewrite the config file
+    #[serde(default)]
+    pub ui: bool,
+}
+
+/// Arbitration between inputs (`opc`, `fadecandy`, `hyperion`, `ambilight`, `audio`, `replay`)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SourcesConfig {
+    /// Priority per source name; the highest active source wins a channel (default: 100 each, 0 for audio)
+    #[serde(default)]
+    pub priorities: HashMap<String, i32>,
+    /// Time without data after which a source stops holding its channels (default: 2000ms)
+    pub timeout_ms: Option<u64>,
+}
+
+/// A downstream OPC server that receives a copy of the incoming messages (`relay`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayConfig {
+    pub host: String,
+    #[serde(default = "default_opc_port")]
+    pub port: u16,
+    /// Channels to forward (default: all)
+    pub channels: Option<Vec<u8>>,
+    /// Shared secret the downstream server expects (its `opc.auth_token`)
+    pub auth_token: Option<String>,
+}
+
+fn default_opc_port() -> u16 {
+    7890
+}
+
+/// Whether this server sends the frame sync ticks or waits for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameSyncRole {
+    Master,
+    Follower,
+}
+
+/// Frame release ticks over UDP (`frame_sync`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FrameSyncConfig {
+    pub role: FrameSyncRole,
+    /// UDP port the ticks are sent to and received on (default: 7893)
+    pub port: Option<u16>,
+    /// Master: addresses the ticks are sent to (default: `["255.255.255.255"]`, the local network)
+    pub targets: Option<Vec<String>>,
+    /// Follower: address to receive the ticks on (default: 0.0.0.0)
+    pub host: Option<String>,
+    /// Master: ticks per second, the highest frame rate of every output (default: 60)
+    pub rate_hz: Option<f64>,
+    /// Longest an output waits for a tick before sending anyway (default: 100ms)
+    pub timeout_ms: Option<u64>,
+}
+
+/// One daily window during which the outputs are lit
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScheduleWindow {
+    /// Local time the window starts, as "HH:MM"
+    pub on: String,
+    /// Local time the window ends, as "HH:MM"; earlier than `on` runs past midnight
+    pub off: String,
+    /// Days the window starts on (default: every day)
+    pub days: Option<Vec<Weekday>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+/// Hyperion.ng flatbuffers input (for Hyperion forwarders and grabbers)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HyperionConfig {
+    /// Bind address (defaults to the OPC host)
+    pub host: Option<String>,
+    #[serde(default = "default_hyperion_port")]
+    pub port: u16,
+    /// OPC channel that receives Hyperion images and colors
+    #[serde(default)]
+    pub opc_channel: u8,
+    /// TCP port of the Hyperion JSON-RPC server for status, brightness and on/off (default: none; Hyperion uses 19444)
+    pub json_port: Option<u16>,
+}
+
+fn default_hyperion_port() -> u16 {
+    19400
+}
+
+/// Screen-capture input that lights LEDs around the display (`ambilight` feature)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AmbilightConfig {
+    /// Grabber writing raw RGB24 frames of `width`x`height` to stdout (default: ffmpeg)
+    pub command: Option<Vec<String>>,
+    /// Display or input the default grabber captures (default: `$DISPLAY`, the main screen or desktop)
+    pub display: Option<String>,
+    /// Size the screen is scaled to before sampling (default: 64x36)
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    /// Capture rate (default: 25)
+    pub fps: Option<u32>,
+    /// OPC channel that receives the edge colors
+    #[serde(default)]
+    pub opc_channel: u8,
+    /// LEDs along each edge of the screen
+    pub layout: EdgeLayout,
+}
+
+/// Audio-reactive effects, shown while no other input drives their channels (`audio` feature)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AudioConfig {
+    /// Capture program writing mono signed 16-bit little-endian samples to stdout
+    /// (default: arecord on Linux, ffmpeg elsewhere)
+    pub command: Option<Vec<String>>,
+    /// Capture device for the default command (default: the system default input)
+    pub device: Option<String>,
+    /// Sample rate requested from the capture command (default: 44100)
+    pub sample_rate: Option<u32>,
+    /// Effect frames per second (default: 30)
+    pub fps: Option<u32>,
+    /// Effects and the channels they are drawn on
+    pub effects: Vec<AudioEffectConfig>,
+}
+
+/// One audio effect drawn on an OPC channel
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AudioEffectConfig {
+    #[serde(default)]
+    pub opc_channel: u8,
+    pub effect: AudioEffect,
+    /// Color of the effect (default: a rainbow for `spectrum`, green to red for `vu`)
+    pub color: Option<[u8; 3]>,
+}
+
+/// Audio visualizations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEffect {
+    /// Frequency bands from bass to treble along the strip, brighter when louder
+    Spectrum,
+    /// A level meter growing from the start of the strip
+    Vu,
+}
+
+/// LEDs around a screen, numbered clockwise from the top-left corner
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EdgeLayout {
+    #[serde(default)]
+    pub top: usize,
+    #[serde(default)]
+    pub right: usize,
+    #[serde(default)]
+    pub bottom: usize,
+    #[serde(default)]
+    pub left: usize,
+    /// Share of the screen's width or height averaged into each LED (default: 0.1)
+    pub depth: Option<f32>,
+}
+
+/// Fadecandy (fcserver) compatibility mode
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FadecandyConfig {
+    /// Bind address for the WebSocket server (defaults to the OPC host)
+    pub host: Option<String>,
+    #[serde(default = "default_fadecandy_websocket_port")]
+    pub websocket_port: u16,
+    /// Initial color correction (defaults to Fadecandy's gamma 2.5 curve)
+    pub color: Option<ColorCorrection>,
+    /// Reported dithering default (serial strips cannot dither)
+    #[serde(default = "default_true")]
+    pub dithering: bool,
+    /// Reported interpolation default (serial strips cannot interpolate)
+    #[serde(default = "default_true")]
+    pub interpolation: bool,
+}
+
+fn default_fadecandy_websocket_port() -> u16 {
+    7891
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Layout of a 2D LED panel driven by one output
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MatrixConfig {
+    /// Pixels per row of the image sent by the client
+    pub width: usize,
+    /// Rows of the image sent by the client
+    pub height: usize,
+    /// Every other row is wired in the opposite direction (zigzag)
+    #[serde(default)]
+    pub serpentine: bool,
+    /// Clockwise rotation of the image on the panel: 0, 90, 180 or 270 degrees
+    #[serde(default)]
+    pub rotation: u16,
+    /// Panel corner the first LED is in, with rows running from it along the panel's width
+    #[serde(default)]
+    pub origin: Corner,
+}
+
+/// One step of an output's `transforms` chain
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformConfig {
+    /// Keep `count` pixels from `offset` on (default: all the rest)
+    Slice {
+        #[serde(default)]
+        offset: usize,
+        count: Option<usize>,
+    },
+    /// Pixel `i` of the result is pixel `pixels[i]` of the input
+    Map { pixels: Vec<usize> },
+    /// Last pixel first
+    Reverse,
+    /// Raise every channel to the power `gamma`
+    Gamma { gamma: f32 },
+    /// Scale every channel by `level`, 0.0 to 1.0
+    Brightness { level: f32 },
+    /// Rearrange each pixel's channels, e.g. "GRB"
+    ColorOrder { order: String },
+}
+
+/// Corner of a matrix panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OutputConfig {
+    /// Optional friendly name, usable instead of the port to reference this output
+    pub name: Option<String>,
+    /// Only the machine with this hostname opens the output, so one config can be shared
+    /// by several hosts (default: every host)
+    pub host: Option<String>,
     pub port: String,
     pub protocol: String,
+    /// Data rate, or `"auto"` (stored as `AUTO_BAUD`) to probe for the rate the device answers at
+    #[serde(with = "baud_rate")]
     pub baud_rate: u32,
     /// Optional baud rate for initial handshake/configuration (e.g., WLED JSON protocol)
     /// If specified, the port will open at this speed first, then switch to baud_rate for LED data
@@ -25,7 +787,612 @@ pub struct OutputConfig {
     pub hardware_type: Option<String>,
     pub opc_channel: u8,
     pub led_count: usize,
+    /// First pixel of the channel this output shows
     #[serde(default)]
     pub opc_offset: usize,
+    /// Read past the end of the channel's data from its start again, e.g. for rings
+    /// fed by sources with fewer pixels (default: false)
+    #[serde(default)]
+    pub wrap: bool,
+    /// Stretch the channel's pixels from `opc_offset` on to fill the strip (default: off)
+    pub resample: Option<Resample>,
+    /// Wiring of a 2D panel; the channel's pixels are taken as a row-major image
+    pub matrix: Option<MatrixConfig>,
+    /// Steps run in order on the channel's pixels before the settings above pick this
+    /// output's pixels out of them (default: none)
+    pub transforms: Option<Vec<TransformConfig>>,
+    /// Source pixels to resample, e.g. 60 for a 60-pixel source on a 144-LED strip
+    /// (default: all of the message from `opc_offset` on)
+    pub source_pixels: Option<usize>,
     pub pixel_format: Option<String>,
+    /// LED chip the strip is built from, e.g. "ws2812b" or "sk6812-rgbw"; sets the
+    /// `pixel_format`, `color` and `milliamps_per_channel` the output leaves unset (default: none)
+    pub strip_type: Option<String>,
+    /// Color correction of this output, used while no global correction is set over OPC,
+    /// Fadecandy or the control API (default: none)
+    pub color: Option<ColorCorrection>,
+    /// Channel values below this become 0, hiding capture noise that makes dark LEDs
+    /// glow (default: 0)
+    pub black_threshold: Option<u8>,
+    /// Lowest value a lit channel is sent with (default: 0)
+    pub min_brightness: Option<u8>,
+    /// Initial brightness (0.0 - 1.0), adjustable at runtime via the control API
+    pub brightness: Option<f32>,
+    /// Scale brightness and fades in linear light instead of per sRGB byte (default: false)
+    #[serde(default)]
+    pub linear_light: bool,
+    /// White point in kelvin, to match strips of different color temperatures
+    /// (default: none; 6500 leaves colors unchanged)
+    pub color_temperature_k: Option<f32>,
+    /// What to leave on the LEDs when the server stops (default: blank, or preset for WLED)
+    pub on_shutdown: Option<ShutdownPolicy>,
+    /// Fade duration for `on_shutdown: "fade"` (default: 1000ms)
+    pub shutdown_fade_ms: Option<u64>,
+    /// Pattern shown when the output opens, before any OPC data (default: none)
+    pub on_start: Option<StartPattern>,
+    /// Color for the `color` and `chase` start patterns (default: white)
+    pub start_color: Option<[u8; 3]>,
+    /// How long the start pattern runs before the strip is blanked (default: 2000ms)
+    pub start_duration_ms: Option<u64>,
+    /// Crossfade time between blank and live data (default: 0, hard cut)
+    pub transition_ms: Option<u64>,
+    /// Fade to black after this long without frames (default: hold the last frame)
+    pub idle_timeout_ms: Option<u64>,
+    /// Animation shown while no input sends to this output (default: none)
+    pub standby: Option<StandbyConfig>,
+    /// Program that may modify every frame before it is sent (default: none)
+    pub script: Option<ScriptConfig>,
+    /// Power budget for the strip; brighter frames are scaled down (default: unlimited)
+    pub max_milliamps: Option<u32>,
+    /// Current per R, G, B channel of one LED at full brightness (default: 20mA each)
+    pub milliamps_per_channel: Option<[f32; 3]>,
+    /// Shortest time the strip may take to go from black to full white (default: no limit)
+    pub soft_start_ms: Option<u64>,
+    /// Share of the previous frames kept in every frame, 0.0 to 1.0, to steady noisy
+    /// sources (default: 0, no smoothing)
+    pub smoothing: Option<f32>,
+    /// Frames buffered between the OPC server and the serial worker (default: 1)
+    pub queue_depth: Option<usize>,
+    /// What happens to frames when the queue is full (default: drop_newest)
+    #[serde(default)]
+    pub queue_policy: QueuePolicy,
+    /// Skip frames identical to the last one written (default: false)
+    #[serde(default)]
+    pub dedupe: bool,
+    /// With `dedupe`, resend an unchanged frame after this long (default: 1000ms)
+    pub dedupe_refresh_ms: Option<u64>,
+    /// Extra serial ports that receive identical frames, opened with this output's settings
+    pub mirrors: Option<Vec<String>>,
+    /// Hold every frame back this long before sending, to line up with slower outputs
+    pub delay_ms: Option<u64>,
+    /// Write to the port from a thread of its own, so the next frame is prepared while
+    /// the previous one is on the wire
+    #[serde(default)]
+    pub writer_thread: bool,
+    /// Write frames in pieces of this many bytes, for USB-serial bridges that drop bytes
+    /// of long bursts (default: whole frames)
+    pub chunk_size: Option<usize>,
+    /// Pause between the chunks of a frame, in microseconds (default: 0)
+    pub inter_chunk_delay_us: Option<u64>,
+    /// Abandon a frame whose write takes longer than this and go on with the freshest
+    /// queued one (default: wait for the port's 1s timeout, then disconnect)
+    pub write_deadline_ms: Option<u64>,
+    /// Realtime (SCHED_FIFO) priority 1-99 of the worker and writer threads (Linux only;
+    /// default: normal scheduling)
+    pub priority: Option<u8>,
+    /// CPUs the worker and writer threads may run on (Linux only; default: any)
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Response the device sends after each frame, checked by the worker (default: none)
+    pub ack: Option<AckMode>,
+    /// How long to wait for each acknowledgement (default: 100ms)
+    pub ack_timeout_ms: Option<u64>,
+    /// Bits per character, 5-8 (default: 8)
+    pub data_bits: Option<u8>,
+    /// Parity bit (default: none)
+    pub parity: Option<Parity>,
+    /// Stop bits, 1 or 2 (default: 1)
+    pub stop_bits: Option<u8>,
+    /// Flow control (default: none)
+    pub flow_control: Option<FlowControl>,
+    /// DTR level after opening; false keeps boards that reset on DTR from resetting (default: true)
+    pub dtr: Option<bool>,
+    /// RTS level after opening, unless hardware flow control drives it (default: true)
+    pub rts: Option<bool>,
+    /// Verify the device when opening: Adalight outputs wait for the `Ada` greeting,
+    /// trying other baud rates if it does not come (default: false)
+    #[serde(default)]
+    pub handshake: bool,
+    /// What the Adalight header's LED count field holds (default: "minus_one")
+    pub adalight_count: Option<AdalightCount>,
+    /// Frames between the full keyframes of a `delta` output (default: 30)
+    pub keyframe_interval: Option<u32>,
+    /// Pixelblaze Output Expander channel the output drives, 0-63 (default: 0)
+    pub expander_channel: Option<u8>,
+    /// DMX slots of `dmx` and `enttec-pro` outputs and what each one shows
+    /// (default: every pixel byte in order from address 1)
+    pub dmx_channels: Option<Vec<DmxChannel>>,
+    /// First universe of `artnet` and `sacn` outputs; more LEDs than one universe holds
+    /// continue in the next (default: 0 for Art-Net, 1 for sACN)
+    pub universe: Option<u16>,
+    /// Pixel bytes per universe, e.g. 512 for controllers that split pixels across
+    /// universes (default: as many whole pixels as fit, 510 for RGB)
+    pub universe_size: Option<u16>,
+    /// Device index on the OpenRGB server of `openrgb` outputs (default: 0)
+    pub openrgb_device: Option<u32>,
+    /// Zone of the device the output drives (default: all of its LEDs)
+    pub openrgb_zone: Option<u32>,
+    /// Bridge, credentials and lights of `hue` outputs
+    pub hue: Option<HueConfig>,
+    /// Most frames per second sent to the device; faster input waits for the next slot
+    /// (default: unlimited, 20 for LIFX, 25 for Hue)
+    pub max_fps: Option<f64>,
+    /// Pause after opening the port before the first frame, e.g. while an Arduino resets (default: 100ms)
+    pub init_delay_ms: Option<u64>,
+    /// After the init delay, wait up to this long for the Adalight `Ada` greeting (default: don't wait)
+    pub wait_for_ada_ms: Option<u64>,
+    /// What to do with OPC messages carrying fewer than `led_count` pixels (default: send)
+    pub short_frames: Option<ShortFramePolicy>,
+}
+
+/// Per-output pattern shown at startup so installers can check each strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartPattern {
+    /// Send a black frame
+    Blank,
+    /// Show `start_color` on every LED
+    Color,
+    /// Run a single lit segment of `start_color` along the strip
+    Chase,
+}
+
+/// External program each frame of an output passes through (`script`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScriptConfig {
+    /// Program and arguments; it reads raw RGB frames on stdin and writes them back on stdout
+    pub command: Vec<String>,
+    /// Time the program has to return each frame before it is restarted (default: 100ms)
+    pub timeout_ms: Option<u64>,
+}
+
+/// Per-output fallback animation while no input is sending (`standby`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StandbyConfig {
+    pub effect: StandbyEffect,
+    /// Animation speed; 2.0 runs twice as fast (default: 1.0, one cycle per 4 seconds)
+    pub speed: Option<f32>,
+    /// Colors used by `solid`, `breathe` and `twinkle` (default: warm white)
+    pub palette: Option<Vec<[u8; 3]>>,
+    /// Time without frames before the animation starts (default: 5000ms)
+    pub after_ms: Option<u64>,
+}
+
+/// Standby animations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StandbyEffect {
+    /// The palette repeated along the strip
+    Solid,
+    /// The whole strip slowly fading in and out, one palette color per breath
+    Breathe,
+    /// A rainbow moving along the strip
+    Rainbow,
+    /// LEDs sparkling independently in palette colors
+    Twinkle,
+}
+
+/// `baud_rate` of outputs configured as `"auto"`
+pub const AUTO_BAUD: u32 = 0;
+
+/// `baud_rate` as a number or `"auto"`
+mod baud_rate {
+    use super::AUTO_BAUD;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Rate(u32),
+        Named(String),
+    }
+
+    pub fn serialize<S: Serializer>(baud: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        match *baud {
+            AUTO_BAUD => Raw::Named("auto".to_string()),
+            rate => Raw::Rate(rate),
+        }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Rate(AUTO_BAUD) => Err(serde::de::Error::custom("baud_rate must be positive (or \"auto\")")),
+            Raw::Rate(rate) => Ok(rate),
+            Raw::Named(name) if name.eq_ignore_ascii_case("auto") => Ok(AUTO_BAUD),
+            Raw::Named(name) => Err(serde::de::Error::custom(format!("invalid baud_rate '{}' (expected a number or \"auto\")", name))),
+        }
+    }
+}
+
+/// Serial parity bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+/// Serial flow control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    #[default]
+    None,
+    /// XON/XOFF
+    Software,
+    /// RTS/CTS
+    Hardware,
+}
+
+/// How source pixels are mapped onto a strip of a different length
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resample {
+    /// Repeat or skip source pixels
+    Nearest,
+    /// Blend neighbouring source pixels
+    Linear,
+}
+
+/// LED count convention of an Adalight output's header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdalightCount {
+    /// The number of LEDs minus one, as Adafruit's LEDstream sketch reads it
+    #[default]
+    MinusOne,
+    /// The number of LEDs, for firmware that reads the raw count
+    Actual,
+}
+
+/// One DMX slot of an output's `dmx_channels`: a color of one of its pixels, or a fixed level
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DmxChannel {
+    /// DMX address, 1-512
+    pub address: u16,
+    /// Pixel of the output whose `color` the slot shows
+    pub pixel: Option<usize>,
+    /// Letter of the color in the output's `pixel_format`, e.g. "R" or "W"
+    pub color: Option<String>,
+    /// Level the slot always has instead, e.g. for a fixture's master dimmer or mode
+    pub value: Option<u8>,
+}
+
+/// Philips Hue Entertainment streaming of a `hue` output (`hue` feature)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HueConfig {
+    /// Bridge user, from pairing with `generateclientkey`
+    pub username: String,
+    /// Streaming key returned by the same pairing, 32 hex digits
+    pub clientkey: String,
+    /// Entertainment area (group id) to stream to
+    pub group: String,
+    /// Light ids of the area, one per LED of the output
+    pub lights: Vec<u16>,
+    /// DTLS client forwarding its stdin to the bridge; `{host}`, `{username}` and
+    /// `{clientkey}` are filled in (default: openssl s_client)
+    pub dtls_command: Option<Vec<String>>,
+}
+
+/// Per-output handling of OPC messages shorter than the strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortFramePolicy {
+    /// Send only the pixels that arrived; the firmware decides what the rest show
+    #[default]
+    Send,
+    /// Fill the missing pixels with black
+    PadBlack,
+    /// Keep the missing pixels from the last message
+    PadLast,
+    /// Skip the message
+    Drop,
+}
+
+/// Per-output behavior when the server shuts down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownPolicy {
+    /// Send a black frame
+    Blank,
+    /// Leave the last frame on the LEDs
+    Hold,
+    /// Fade the last frame to black over `shutdown_fade_ms`
+    Fade,
+    /// Let a WLED device resume its own effect/preset (blank for other devices)
+    Preset,
+}
+
+impl OutputConfig {
+    /// `usb:` ports, serial line settings and the pixel mapping options must be valid
+    pub fn check(&self) -> Result<()> {
+        UsbMatch::parse(&self.port).map_err(anyhow::Error::msg)?;
+        serial::line_settings(self).context(self.label())?;
+        if self.is_autobaud() {
+            autobaud::check(self).context(self.label())?;
+        }
+        sched::check(self.priority, self.cpu_affinity.as_deref()).context(self.label())?;
+        if self.protocol == "pixelblaze" {
+            protocol::check_pixelblaze(self).context(self.label())?;
+        }
+        if self.protocol == "delta" {
+            protocol::check_delta(self).context(self.label())?;
+        }
+        if protocol::is_dmx(&self.protocol) {
+            protocol::DmxLayout::from_config(self).context(self.label())?;
+        }
+        if protocol::is_network(&self.protocol) {
+            protocol::check_network(self).context(self.label())?;
+        }
+        if (self.protocol == "lifx" && !cfg!(feature = "lifx")) || (self.protocol == "hue" && !cfg!(feature = "hue")) {
+            anyhow::bail!("{}: protocol '{}' needs a server built with `--features {}`", self.label(), self.protocol, self.protocol);
+        }
+        if self.chunk_size.is_some() && protocol::is_network(&self.protocol) {
+            anyhow::bail!("{}: chunk_size only applies to serial outputs", self.label());
+        }
+        if self.write_deadline_ms.is_some() && self.writer_thread {
+            anyhow::bail!("{}: write_deadline_ms cannot be combined with writer_thread", self.label());
+        }
+        if self.write_deadline_ms == Some(0) {
+            anyhow::bail!("{}: write_deadline_ms must be at least 1", self.label());
+        }
+        if self.chunk_size == Some(0) {
+            anyhow::bail!("{}: chunk_size must be at least 1", self.label());
+        }
+        if self.smoothing.is_some_and(|strength| !(0.0..1.0).contains(&strength)) {
+            anyhow::bail!("{}: smoothing must be at least 0 and below 1", self.label());
+        }
+        if self.max_fps.is_some_and(|fps| !(fps > 0.0 && fps.is_finite())) {
+            anyhow::bail!("{}: max_fps must be positive", self.label());
+        }
+        if self.resample.is_some() && self.wrap {
+            anyhow::bail!("{}: wrap and resample cannot be combined", self.label());
+        }
+        if let Some(transforms) = &self.transforms {
+            transform::check(transforms).context(self.label())?;
+        }
+        if let Some(matrix) = &self.matrix {
+            matrix::check(matrix, self.led_count).context(self.label())?;
+        }
+        if let Some(kelvin) = self.color_temperature_k {
+            if !color::TEMPERATURE_RANGE_K.contains(&kelvin) {
+                anyhow::bail!("{}: color_temperature_k must be between {} and {}", self.label(),
+                              color::TEMPERATURE_RANGE_K.start(), color::TEMPERATURE_RANGE_K.end());
+            }
+        }
+        match (self.source_pixels, self.resample) {
+            (Some(0), _) => anyhow::bail!("{}: source_pixels must be at least 1", self.label()),
+            (Some(_), None) => anyhow::bail!("{}: source_pixels needs resample", self.label()),
+            _ => {}
+        }
+        Ok(())
+    }
+    
+    /// Identifier used to reference this output: the name if set, otherwise the port
+    pub fn id(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.port)
+    }
+    
+    /// How the output appears in log lines: `name (port)` when named, otherwise the port
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", name, self.port),
+            None => self.port.clone(),
+        }
+    }
+    
+    /// Baud rates to probe during a handshake, in priority order: the configured
+    /// `baud_rate`, then `handshake_baud_rate`, then the device's `standard` rates
+    pub fn baud_candidates(&self, standard: &[u32]) -> Vec<u32> {
+        let mut rates = vec![self.baud_rate];
+        for rate in self.handshake_baud_rate.into_iter().chain(standard.iter().copied()) {
+            if !rates.contains(&rate) {
+                rates.push(rate);
+            }
+        }
+        rates
+    }
+    
+    /// Whether the baud rate is detected when the port opens (`baud_rate: "auto"`)
+    pub fn is_autobaud(&self) -> bool {
+        self.baud_rate == AUTO_BAUD
+    }
+    
+    /// Check whether this output drives a WLED device
+    pub fn is_wled(&self) -> bool {
+        self.hardware_type.as_deref() == Some("WLED") || self.protocol == "wled"
+    }
+    
+    /// Effective shutdown policy, defaulting to `preset` for WLED and `blank` otherwise
+    pub fn shutdown_policy(&self) -> ShutdownPolicy {
+        self.on_shutdown.unwrap_or(if self.is_wled() {
+            ShutdownPolicy::Preset
+        } else {
+            ShutdownPolicy::Blank
+        })
+    }
+    
+    /// An AWA output of 10 LEDs on `port`, channel 0, 115200 baud and every option at
+    /// its default, for tests to adjust with struct update syntax
+    #[cfg(test)]
+    pub(crate) fn test_default(port: &str) -> Self {
+        serde_json::from_value(serde_json::json!({
+            "port": port, "protocol": "awa", "baud_rate": 115200, "opc_channel": 0, "led_count": 10,
+        })).expect("the test output is a valid entry")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_overrides_replace_values() {
+        let mut value = json!({
+            "opc": {"host": "0.0.0.0", "port": 7890},
+            "outputs": [
+                {"name": "desk", "port": "/dev/ttyUSB0", "protocol": "awa", "baud_rate": 2000000, "opc_channel": 0, "led_count": 10},
+            ],
+        });
+        for spec in ["opc.port=7891", "opc.host=127.0.0.1", "outputs.0.baud_rate=1000000", "outputs.DESK.brightness=0.5"] {
+            Override::parse(spec).unwrap().apply(&mut value).unwrap();
+        }
+        let config: Config = serde_json::from_value(value.clone()).unwrap();
+        
+        assert_eq!(config.opc.port, 7891);
+        assert_eq!(config.opc.host, "127.0.0.1");
+        assert_eq!(config.outputs[0].baud_rate, 1000000);
+        assert_eq!(config.outputs[0].brightness, Some(0.5));
+        
+        assert!(Override::parse("outputs.shelf.brightness=1").unwrap().apply(&mut value).is_err());
+        assert!(Override::parse("opc.port").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_suggest_nearest() {
+        let raw = json!({
+            "opc": {"host": "0.0.0.0", "port": 7890, "prot": 1},
+            "_comment": "wiring notes",
+            "outputs": [
+                {"port": "/dev/ttyUSB0", "protocol": "awa", "baud_rate": 2000000, "opc_channel": 0,
+                 "led_count": 10, "pixelformat": "GRB", "_comment_port": "hub 2"},
+            ],
+            "fadecandy": {"color": {"gama": 2.2}},
+            "sources": {"priorities": {"hyperion": 200}},
+            "frobnicate": true,
+        });
+        let config: Config = serde_json::from_value(raw.clone()).unwrap();
+        
+        assert_eq!(config.unknown_keys(&raw), vec![
+            "Unknown key `fadecandy.color.gama` (did you mean `gamma`?)".to_string(),
+            "Unknown key `frobnicate`".to_string(),
+            "Unknown key `opc.prot` (did you mean `port`?)".to_string(),
+            "Unknown key `outputs[0].pixelformat` (did you mean `pixel_format`?)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_baud_candidates_keep_configured_first() {
+        let output: OutputConfig = serde_json::from_value(json!({
+            "port": "/dev/ttyACM0", "protocol": "adalight", "baud_rate": 500000,
+            "handshake_baud_rate": 115200, "opc_channel": 0, "led_count": 10,
+        })).unwrap();
+        assert_eq!(output.baud_candidates(&[115200, 230400, 500000]), vec![500000, 115200, 230400]);
+    }
+
+    #[test]
+    fn test_baud_rate_auto() {
+        let output = |baud: serde_json::Value, extra: serde_json::Value| {
+            let mut entry = json!({"port": "/dev/ttyACM0", "protocol": "adalight", "opc_channel": 0, "led_count": 10});
+            entry["baud_rate"] = baud;
+            entry.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value::<OutputConfig>(entry)
+        };
+
+        let auto = output(json!("auto"), json!({})).unwrap();
+        assert!(auto.is_autobaud());
+        assert_eq!(serde_json::to_value(&auto).unwrap()["baud_rate"], "auto");
+        assert!(autobaud::check(&auto).is_ok());
+        assert!(!output(json!(115200), json!({})).unwrap().is_autobaud());
+        assert!(output(json!("fast"), json!({})).is_err());
+        assert!(output(json!(0), json!({})).is_err());
+
+        // AWA firmwares are silent, so detection needs acknowledgements
+        let awa = output(json!("auto"), json!({"protocol": "awa"})).unwrap();
+        assert!(autobaud::check(&awa).is_err());
+        assert!(autobaud::check(&output(json!("auto"), json!({"protocol": "awa", "ack": "byte"})).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_retain_host_outputs() {
+        let output = |port: &str, host: Option<&str>| {
+            json!(OutputConfig { host: host.map(str::to_string), ..OutputConfig::test_default(port) })
+        };
+        let config: Config = serde_json::from_value(json!({
+            "opc": {"host": "0.0.0.0", "port": 7890},
+            "outputs": [
+                output("/dev/ttyACM0", Some("pi-1")),
+                output("/dev/ttyACM0", Some("PI-2.local")),
+                output("/dev/ttyACM1", Some("pi-2")),
+                output("/dev/ttyUSB0", None),
+            ],
+        })).unwrap();
+
+        let ports = |hostname: &str| {
+            let mut config = config.clone();
+            config.retain_host_outputs(hostname);
+            config.outputs.iter().map(|o| (o.port.clone(), o.host.clone())).collect::<Vec<_>>()
+        };
+        assert_eq!(ports("pi-2"), vec![
+            ("/dev/ttyACM0".to_string(), Some("PI-2.local".to_string())),
+            ("/dev/ttyACM1".to_string(), Some("pi-2".to_string())),
+            ("/dev/ttyUSB0".to_string(), None),
+        ]);
+        assert_eq!(ports("pi-1.example.com").len(), 2);
+        assert_eq!(ports("pi-2.example.com").len(), 2);
+        assert_eq!(ports("pi-3").len(), 1);
+    }
+
+    #[test]
+    fn test_expand_strips_computes_offsets() {
+        let mut value = json!({
+            "opc": {"host": "0.0.0.0", "port": 7890},
+            "outputs": [],
+            "strips": [{
+                "name": "facade",
+                "opc_channel": 2,
+                "opc_offset": 10,
+                "segments": [
+                    {"port": "/dev/ttyUSB0", "protocol": "adalight", "baud_rate": 115200, "led_count": 300},
+                    {"port": "/dev/ttyUSB1", "protocol": "adalight", "baud_rate": 115200, "led_count": 250, "name": "end"},
+                ],
+            }],
+        });
+        expand_strips(&mut value).unwrap();
+        let config: Config = serde_json::from_value(value).unwrap();
+
+        let placement: Vec<_> = config.outputs.iter()
+            .map(|o| (o.id().to_string(), o.opc_channel, o.opc_offset))
+            .collect();
+        assert_eq!(placement, vec![("facade-1".to_string(), 2, 10), ("end".to_string(), 2, 310)]);
+    }
+
+    #[test]
+    fn test_opc_check() {
+        let opc = |extra: serde_json::Value| {
+            let mut value = json!({"host": "0.0.0.0", "port": 7890});
+            value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value::<OpcConfig>(value).unwrap().check()
+        };
+        opc(json!({})).unwrap();
+        opc(json!({"max_clients": 1})).unwrap();
+        let e = opc(json!({"max_clients": 0})).unwrap_err();
+        assert!(e.to_string().contains("max_clients must be at least 1"));
+        
+        // TLS files are checked when the config loads, not when the first client connects
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        opc(json!({"tls": {"cert": manifest, "key": manifest}})).unwrap();
+        let e = opc(json!({"tls": {"cert": manifest, "key": "no-such.key"}})).unwrap_err();
+        assert!(e.to_string().contains("tls.key no-such.key"));
+    }
+
+    #[test]
+    fn test_expand_strips_rejects_segment_offsets() {
+        let mut value = json!({
+            "strips": [{"opc_channel": 0, "segments": [{"led_count": 10, "opc_offset": 5}]}],
+        });
+        assert!(expand_strips(&mut value).is_err());
+    }
 }
@@ -0,0 +1,975 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Config file syntax, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// `.toml` and `.yaml`/`.yml` files are read as such; everything else is JSON
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("toml") => Format::Toml,
+            Some("yaml") | Some("yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    /// Parse config text into a JSON value, so all formats share the same structs
+    pub fn parse(&self, text: &str) -> Result<Value> {
+        match self {
+            Format::Json => Ok(serde_json::from_str(text)?),
+            Format::Toml => parse_toml(text),
+            Format::Yaml => parse_yaml(text),
+        }
+    }
+}
+
+/// Length of the run of digits at the start of `text`, allowing single underscores
+/// between digits when `underscores` is set
+fn digit_run(text: &[u8], underscores: bool) -> usize {
+    let mut len = 0;
+    while let Some(&c) = text.get(len) {
+        let separator = underscores && c == b'_' && len > 0 && text.get(len + 1).is_some_and(u8::is_ascii_digit);
+        if !c.is_ascii_digit() && !separator {
+            break;
+        }
+        len += 1;
+    }
+    len
+}
+
+/// Parse a decimal number already checked against a format's grammar
+fn decimal(text: &str, float: bool) -> Option<Value> {
+    if !float {
+        if let Ok(n) = text.parse::<i64>() {
+            return Some(Value::from(n));
+        }
+        return None;
+    }
+    text.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number)
+}
+
+/// Decode the escape `\c`; `rest` follows it. Returns the character and how many of `rest` it used.
+///
+/// Only the escapes TOML and YAML share are accepted: `\n`, `\t`, `\r`, `\"`, `\\` and `\uXXXX`.
+fn escape(c: char, rest: &[char]) -> Result<(char, usize)> {
+    Ok(match c {
+        'n' => ('\n', 0),
+        't' => ('\t', 0),
+        'r' => ('\r', 0),
+        '\\' => ('\\', 0),
+        '"' => ('"', 0),
+        'u' => {
+            let hex: String = rest.iter().take(4).collect();
+            let decoded = Some(&hex)
+                .filter(|h| h.len() == 4 && h.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .and_then(char::from_u32);
+            (decoded.context(format!("Invalid escape \\u{}", hex))?, 4)
+        }
+        other => anyhow::bail!("Unsupported escape \\{}", other),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// TOML subset: `key = value` lines with bare or dotted bare keys, `[table]` and
+// `[[array]]` headers, single-line basic and literal strings, decimal integers
+// and floats, booleans, arrays and single-line inline tables of bare keys. Each
+// table is defined once, before its sub-tables. Anything else is an error.
+// ---------------------------------------------------------------------------
+
+fn parse_toml(text: &str) -> Result<Value> {
+    let mut parser = TomlParser { chars: text.chars().collect(), pos: 0, line: 1 };
+    parser.document().map_err(|e| e.context(format!("line {}", parser.line)))
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => anyhow::bail!("Expected '{}', found '{}'", expected, c),
+            None => anyhow::bail!("Expected '{}', found end of file", expected),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skip whitespace, newlines and comments (between statements and inside arrays)
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(c) => anyhow::bail!("Unexpected '{}' after value", c),
+        }
+    }
+
+    fn document(&mut self) -> Result<Value> {
+        let mut root = Value::Object(Map::new());
+        // Path of the current table; entries of arrays of tables are addressed by index
+        let mut current: Vec<String> = Vec::new();
+        // Arrays made by `[[array]]` headers, which later headers append to
+        let mut table_arrays: HashSet<Vec<String>> = HashSet::new();
+        // Values set with `key = value`, which headers and dotted keys may not reopen
+        let mut assigned: HashSet<Vec<String>> = HashSet::new();
+
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.bump();
+                    }
+                    self.skip_spaces();
+                    let path = self.key_path()?;
+                    self.skip_spaces();
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+
+                    let (last, parent) = path.split_last().context("Empty table name")?;
+                    let mut table = resolve(&root, parent, &table_arrays, &assigned)?;
+                    let parent_table = table_at(&mut root, &table)?;
+                    table.push(last.clone());
+                    if assigned.contains(&table) {
+                        anyhow::bail!("'{}' is already set to a value", path.join("."));
+                    }
+                    if array {
+                        let items = match parent_table.entry(last.clone()).or_insert_with(|| Value::Array(Vec::new())) {
+                            Value::Array(items) => items,
+                            _ => anyhow::bail!("'{}' is not an array of tables", path.join(".")),
+                        };
+                        items.push(Value::Object(Map::new()));
+                        let index = items.len() - 1;
+                        table_arrays.insert(table.clone());
+                        table.push(index.to_string());
+                    } else if parent_table.contains_key(last) {
+                        anyhow::bail!("Table '{}' is already defined", path.join("."));
+                    } else {
+                        parent_table.insert(last.clone(), Value::Object(Map::new()));
+                    }
+                    current = table;
+                }
+                Some(_) => {
+                    let path = self.key_path()?;
+                    self.skip_spaces();
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    let value = self.value()?;
+                    self.end_of_line()?;
+
+                    let (last, parent) = path.split_last().context("Empty key")?;
+                    let mut key = current.clone();
+                    for part in parent {
+                        key.push(part.clone());
+                        if assigned.contains(&key) {
+                            anyhow::bail!("'{}' is already set to a value", path.join("."));
+                        }
+                    }
+                    let table = table_at(&mut root, &key)?;
+                    if table.contains_key(last) {
+                        anyhow::bail!("Duplicate key '{}'", path.join("."));
+                    }
+                    table.insert(last.clone(), value);
+                    key.push(last.clone());
+                    assigned.insert(key);
+                }
+            }
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>> {
+        let mut path = vec![self.key()?];
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.bump();
+            self.skip_spaces();
+            path.push(self.key()?);
+        }
+    }
+
+    fn key(&mut self) -> Result<String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.bump();
+        }
+        if self.pos == start {
+            match self.peek() {
+                Some('"') | Some('\'') => anyhow::bail!("Quoted keys are not supported"),
+                Some(c) => anyhow::bail!("Expected a key, found '{}'", c),
+                None => anyhow::bail!("Expected a key, found end of file"),
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        if self.peek() == Some('"') && self.peek_at(1) == Some('"') {
+            anyhow::bail!("Multi-line strings are not supported");
+        }
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => anyhow::bail!("Unterminated string"),
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let c = self.bump().context("Unterminated string")?;
+                    let (c, used) = escape(c, &self.chars[self.pos..])?;
+                    out.push(c);
+                    self.pos += used;
+                }
+                Some(c) if c.is_control() && c != '\t' => anyhow::bail!("Control character in string"),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.expect('\'')?;
+        if self.peek() == Some('\'') && self.peek_at(1) == Some('\'') {
+            anyhow::bail!("Multi-line strings are not supported");
+        }
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => anyhow::bail!("Unterminated string"),
+                Some('\'') => return Ok(out),
+                Some(c) if c.is_control() && c != '\t' => anyhow::bail!("Control character in string"),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.bump();
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank();
+                    match self.bump() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(items)),
+                        _ => anyhow::bail!("Expected ',' or ']' in array"),
+                    }
+                }
+            }
+            Some('{') => {
+                self.bump();
+                let mut table = Map::new();
+                self.skip_spaces();
+                if self.peek() == Some('}') {
+                    self.bump();
+                    return Ok(Value::Object(table));
+                }
+                loop {
+                    self.skip_spaces();
+                    let key = self.key()?;
+                    self.skip_spaces();
+                    if self.peek() == Some('.') {
+                        anyhow::bail!("Dotted keys are not supported in inline tables");
+                    }
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    let value = self.value()?;
+                    if table.insert(key.clone(), value).is_some() {
+                        anyhow::bail!("Duplicate key '{}'", key);
+                    }
+                    self.skip_spaces();
+                    match self.bump() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Value::Object(table)),
+                        _ => anyhow::bail!("Expected ',' or '}}' in inline table"),
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "_+-.".contains(c)) {
+                    self.bump();
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => anyhow::bail!("Expected a value"),
+                    _ => toml_number(&word).context(format!("Unsupported value '{}'", word)),
+                }
+            }
+        }
+    }
+}
+
+/// Decimal integer or float: `[+-]int[.digits][e[+-]digits]`, the integer part without
+/// leading zeros, single underscores allowed between digits
+fn toml_number(word: &str) -> Option<Value> {
+    let bytes = word.as_bytes();
+    let mut pos = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let int = digit_run(&bytes[pos..], true);
+    if int == 0 || (bytes[pos] == b'0' && int > 1) {
+        return None;
+    }
+    pos += int;
+    let mut float = false;
+    if bytes.get(pos) == Some(&b'.') {
+        let fraction = digit_run(&bytes[pos + 1..], true);
+        if fraction == 0 {
+            return None;
+        }
+        pos += 1 + fraction;
+        float = true;
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        pos += usize::from(matches!(bytes.get(pos), Some(b'+' | b'-')));
+        let exponent = digit_run(&bytes[pos..], true);
+        if exponent == 0 {
+            return None;
+        }
+        pos += exponent;
+        float = true;
+    }
+    if pos != bytes.len() {
+        return None;
+    }
+    decimal(&word.replace('_', ""), float)
+}
+
+/// Path of the table a header names, with keys of arrays of tables continuing in their
+/// last entry
+fn resolve(root: &Value, path: &[String], table_arrays: &HashSet<Vec<String>>,
+           assigned: &HashSet<Vec<String>>) -> Result<Vec<String>> {
+    let mut table = Vec::new();
+    for key in path {
+        table.push(key.clone());
+        if assigned.contains(&table) {
+            anyhow::bail!("'{}' is already set to a value", path.join("."));
+        }
+        if table_arrays.contains(&table) {
+            if let Some(Value::Array(items)) = value_at(root, &table) {
+                table.push((items.len() - 1).to_string());
+            }
+        }
+    }
+    Ok(table)
+}
+
+fn value_at<'a>(root: &'a Value, path: &[String]) -> Option<&'a Value> {
+    let mut value = root;
+    for key in path {
+        value = match value {
+            Value::Object(map) => map.get(key)?,
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Find (creating as needed) the table at `path`; entries of arrays of tables are addressed by index
+fn table_at<'a>(root: &'a mut Value, path: &[String]) -> Result<&'a mut Map<String, Value>> {
+    let mut value = root;
+    for key in path {
+        value = match value {
+            Value::Object(map) => map.entry(key.clone()).or_insert_with(|| Value::Object(Map::new())),
+            Value::Array(items) => key.parse::<usize>().ok()
+                .and_then(|i| items.get_mut(i))
+                .context(format!("'{}' is not a table", key))?,
+            _ => anyhow::bail!("'{}' is not a table", key),
+        };
+    }
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => anyhow::bail!("'{}' is not a table", path.join(".")),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// YAML subset: one document of block mappings and lists, single-line flow
+// `[...]`/`{...}` collections, and plain, single- or double-quoted scalars read
+// with the YAML 1.2 core schema (null, booleans, decimal numbers). Anchors,
+// tags, block and multi-line scalars, `?` keys, hex and octal numbers and
+// `.inf`/`.nan` are errors.
+// ---------------------------------------------------------------------------
+
+struct YamlLine {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+fn parse_yaml(text: &str) -> Result<Value> {
+    let mut lines = Vec::new();
+    let mut started = false;
+    for (i, raw) in text.lines().enumerate() {
+        let content = strip_yaml_comment(raw).trim_end();
+        let trimmed = content.trim_start_matches(' ');
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('\t') {
+            anyhow::bail!("line {}: Tabs are not allowed for indentation", i + 1);
+        }
+        match trimmed {
+            "---" if !started && lines.is_empty() => {
+                started = true;
+                continue;
+            }
+            "---" => anyhow::bail!("line {}: Only one document is supported", i + 1),
+            "..." => anyhow::bail!("line {}: Document end markers are not supported", i + 1),
+            _ => {}
+        }
+        if trimmed.starts_with('%') {
+            anyhow::bail!("line {}: Directives are not supported", i + 1);
+        }
+        lines.push(YamlLine { number: i + 1, indent: content.len() - trimmed.len(), text: trimmed.to_string() });
+    }
+
+    if lines.is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let mut pos = 0;
+    let indent = lines[0].indent;
+    let value = yaml_block(&mut lines, &mut pos, indent)?;
+    if let Some(line) = lines.get(pos) {
+        anyhow::bail!("line {}: Unexpected indentation", line.number);
+    }
+    Ok(value)
+}
+
+/// Cut a ` #` comment, unless it sits inside a quoted scalar
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            // Quotes only open a scalar (`key: 'x'`, `- "x"`, `[a, 'b']`), not in `it's`
+            None if (c == '"' || c == '\'') && " [{,".contains(prev) => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+fn yaml_block(lines: &mut [YamlLine], pos: &mut usize, indent: usize) -> Result<Value> {
+    if is_sequence_item(&lines[*pos].text) {
+        yaml_sequence(lines, pos, indent)
+    } else {
+        yaml_mapping(lines, pos, indent)
+    }
+}
+
+fn yaml_sequence(lines: &mut [YamlLine], pos: &mut usize, indent: usize) -> Result<Value> {
+    let mut items = Vec::new();
+
+    while *pos < lines.len() && lines[*pos].indent == indent && is_sequence_item(&lines[*pos].text) {
+        let line = &lines[*pos];
+        let rest = line.text[1..].trim_start().to_string();
+        let number = line.number;
+
+        if rest.is_empty() {
+            *pos += 1;
+            match lines.get(*pos) {
+                Some(next) if next.indent > indent => {
+                    let child = next.indent;
+                    items.push(yaml_block(lines, pos, child)?);
+                }
+                _ => items.push(Value::Null),
+            }
+        } else if is_sequence_item(&rest) || split_key(&rest).is_some() {
+            // `- key: value` starts a mapping (or nested list) aligned with the text after the dash
+            let offset = line.text.len() - rest.len();
+            lines[*pos].indent = indent + offset;
+            lines[*pos].text = rest;
+            items.push(yaml_block(lines, pos, indent + offset)?);
+        } else {
+            items.push(yaml_scalar(&rest).context(format!("line {}", number))?);
+            *pos += 1;
+        }
+    }
+
+    Ok(Value::Array(items))
+}
+
+fn yaml_mapping(lines: &mut [YamlLine], pos: &mut usize, indent: usize) -> Result<Value> {
+    let mut map = Map::new();
+
+    while *pos < lines.len() && lines[*pos].indent == indent {
+        let line = &lines[*pos];
+        let number = line.number;
+        if is_sequence_item(&line.text) {
+            anyhow::bail!("line {}: Unexpected list item", number);
+        }
+        let Some((key, rest)) = split_key(&line.text) else {
+            anyhow::bail!("line {}: Expected 'key: value'", number);
+        };
+        let key = yaml_key(key).context(format!("line {}", number))?;
+        let rest = rest.to_string();
+        *pos += 1;
+
+        let value = if rest.is_empty() {
+            match lines.get(*pos) {
+                Some(next) if next.indent > indent => {
+                    let child = next.indent;
+                    yaml_block(lines, pos, child)?
+                }
+                // Lists may sit at the same indentation as their key
+                Some(next) if next.indent == indent && is_sequence_item(&next.text) => {
+                    yaml_sequence(lines, pos, indent)?
+                }
+                _ => Value::Null,
+            }
+        } else {
+            yaml_scalar(&rest).context(format!("line {}", number))?
+        };
+
+        if map.insert(key.clone(), value).is_some() {
+            anyhow::bail!("line {}: Duplicate key '{}'", number, key);
+        }
+    }
+
+    if let Some(line) = lines.get(*pos) {
+        if line.indent > indent {
+            anyhow::bail!("line {}: Unexpected indentation", line.number);
+        }
+    }
+
+    Ok(Value::Object(map))
+}
+
+/// Split `key: value` at the first colon followed by a space or the end (outside quotes)
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let bytes = text.as_bytes();
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if i == 0 && (c == '"' || c == '\'') => quote = Some(c),
+            None if c == '[' || c == '{' => return None,
+            None if c == ':' && (i + 1 == bytes.len() || bytes[i + 1] == b' ') => {
+                return Some((text[..i].trim_end(), text[i + 1..].trim()));
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+fn yaml_key(key: &str) -> Result<String> {
+    if key.is_empty() {
+        anyhow::bail!("Expected a key");
+    }
+    match yaml_scalar(key)? {
+        Value::String(s) => Ok(s),
+        _ => Ok(key.to_string()),
+    }
+}
+
+fn yaml_scalar(text: &str) -> Result<Value> {
+    let mut parser = FlowParser { chars: text.chars().collect(), pos: 0 };
+    let value = parser.value(false)?;
+    parser.skip_spaces();
+    if parser.pos < parser.chars.len() {
+        anyhow::bail!("Unexpected text after value: '{}'", text);
+    }
+    Ok(value)
+}
+
+/// Flow-style values on a single line
+struct FlowParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl FlowParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, in_flow: bool) -> Result<Value> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        Some(',') => anyhow::bail!("Expected a value before ','"),
+                        _ => {}
+                    }
+                    items.push(self.value(true)?);
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => anyhow::bail!("Expected ',' or ']' in list"),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut map = Map::new();
+                loop {
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some('}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(map));
+                        }
+                        Some(',') => anyhow::bail!("Expected a key before ','"),
+                        _ => {}
+                    }
+                    let key = match self.value(true)? {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    self.skip_spaces();
+                    if self.peek() != Some(':') {
+                        anyhow::bail!("Expected ':' after key '{}'", key);
+                    }
+                    self.pos += 1;
+                    let value = self.value(true)?;
+                    if map.insert(key.clone(), value).is_some() {
+                        anyhow::bail!("Duplicate key '{}'", key);
+                    }
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {}
+                        _ => anyhow::bail!("Expected ',' or '}}' in mapping"),
+                    }
+                }
+            }
+            Some('"') => {
+                self.pos += 1;
+                let mut out = String::new();
+                loop {
+                    let c = self.peek().context("Unterminated string")?;
+                    self.pos += 1;
+                    match c {
+                        '"' => return Ok(Value::String(out)),
+                        '\\' => {
+                            let c = self.peek().context("Unterminated string")?;
+                            self.pos += 1;
+                            let (c, used) = escape(c, &self.chars[self.pos..])?;
+                            out.push(c);
+                            self.pos += used;
+                        }
+                        c => out.push(c),
+                    }
+                }
+            }
+            Some('\'') => {
+                self.pos += 1;
+                let mut out = String::new();
+                loop {
+                    let c = self.peek().context("Unterminated string")?;
+                    self.pos += 1;
+                    if c == '\'' {
+                        if self.peek() == Some('\'') {
+                            self.pos += 1;
+                            out.push('\'');
+                        } else {
+                            return Ok(Value::String(out));
+                        }
+                    } else {
+                        out.push(c);
+                    }
+                }
+            }
+            Some('|') | Some('>') if !in_flow => anyhow::bail!("Block scalars are not supported"),
+            Some('&') | Some('*') | Some('!') => anyhow::bail!("Anchors, aliases and tags are not supported"),
+            _ => {
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    if in_flow && (c == ',' || c == ']' || c == '}' || (c == ':' && self.is_flow_colon())) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                plain_scalar(word.trim(), in_flow)
+            }
+        }
+    }
+
+    fn is_flow_colon(&self) -> bool {
+        matches!(self.chars.get(self.pos + 1), None | Some(' ') | Some(',') | Some('}') | Some(']'))
+    }
+}
+
+/// Read a plain scalar with the core schema, refusing those a YAML parser would read
+/// as something else
+fn plain_scalar(text: &str, in_flow: bool) -> Result<Value> {
+    let indicator = text.starts_with(['@', '`', '%', ',', ']', '}', '|', '>'])
+        || ["-", "?", ":"].iter().any(|i| text == *i || text.starts_with(&format!("{} ", i)));
+    if indicator {
+        anyhow::bail!("Unsupported value '{}'", text);
+    }
+    if text.contains(": ") || text.ends_with(':') {
+        anyhow::bail!("Unexpected ':' in '{}' (quote the value)", text);
+    }
+    if in_flow && text.contains(['[', '{']) {
+        anyhow::bail!("Unexpected bracket in '{}' (quote the value)", text);
+    }
+
+    Ok(match text {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => match yaml_number(text) {
+            Some(number) => number,
+            None => {
+                let lower = text.trim_start_matches(['+', '-']).to_ascii_lowercase();
+                if lower.starts_with("0x") || lower.starts_with("0o") || lower == ".inf" || lower == ".nan" {
+                    anyhow::bail!("Unsupported number '{}'", text);
+                }
+                Value::String(text.to_string())
+            }
+        },
+    })
+}
+
+/// Core schema decimal: `[+-]digits` or `[+-](.digits|digits[.digits])[e[+-]digits]`
+fn yaml_number(text: &str) -> Option<Value> {
+    let bytes = text.as_bytes();
+    let mut pos = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let int = digit_run(&bytes[pos..], false);
+    pos += int;
+    let mut float = false;
+    let mut fraction = 0;
+    if bytes.get(pos) == Some(&b'.') {
+        fraction = digit_run(&bytes[pos + 1..], false);
+        pos += 1 + fraction;
+        float = true;
+    }
+    if int == 0 && fraction == 0 {
+        return None;
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        pos += usize::from(matches!(bytes.get(pos), Some(b'+' | b'-')));
+        let exponent = digit_run(&bytes[pos..], false);
+        if exponent == 0 {
+            return None;
+        }
+        pos += exponent;
+        float = true;
+    }
+    if pos != bytes.len() {
+        return None;
+    }
+    decimal(text, float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn expected() -> Value {
+        json!({
+            "opc": { "host": "0.0.0.0", "port": 7890 },
+            "target_fps": 60.5,
+            "outputs": [
+                {
+                    "name": "desk",
+                    "port": "/dev/ttyUSB0",
+                    "baud_rate": 2000000,
+                    "led_count": 120,
+                    "color": { "gamma": [2.2, 2.2, 2.2] },
+                    "enabled": true
+                },
+                { "name": "shelf # 2", "port": "COM4", "mirrors": [], "white": null }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_toml_config() {
+        let text = r#"
+# Installer notes go here
+target_fps = 60.5
+
+[opc]
+host = "0.0.0.0"
+port = 7_890
+
+[[outputs]]
+name = "desk"            # above the door
+port = '/dev/ttyUSB0'
+baud_rate = 2000000
+led_count = 120
+color.gamma = [
+    2.2, 2.2, 2.2,       # per channel
+]
+enabled = true
+
+[[outputs]]
+name = "shelf # 2"
+port = "COM4"
+mirrors = []
+"#;
+        let mut value = Format::Toml.parse(text).unwrap();
+        value["outputs"][1]["white"] = Value::Null;
+        assert_eq!(value, expected());
+
+        let err = Format::Toml.parse("a = 1\na = 2\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("line 2"));
+    }
+
+    #[test]
+    fn test_yaml_config() {
+        let text = r#"
+---
+# Installer notes go here
+opc:
+  host: "0.0.0.0"
+  port: 7890
+target_fps: 60.5
+outputs:
+  - name: desk          # above the door
+    port: /dev/ttyUSB0
+    baud_rate: 2000000
+    led_count: 120
+    color:
+      gamma: [2.2, 2.2, 2.2]
+    enabled: true
+  - name: 'shelf # 2'
+    port: COM4
+    mirrors: []
+    white: ~
+"#;
+        assert_eq!(Format::Yaml.parse(text).unwrap(), expected());
+        assert_eq!(Format::from_path("setup.YML"), Format::Yaml);
+        assert_eq!(Format::from_path("config.json"), Format::Json);
+
+        let err = Format::Yaml.parse("opc:\n  port: 1\n    host: x\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("line 3"));
+    }
+
+    #[test]
+    fn test_rejects_outside_subset() {
+        for text in [
+            r#"a = "\0""#, r#"a = "\/""#, r#"a = "\u12""#, "a = 0x1F", "a = 01", "a = 1__0", "a = .5",
+            "a = inf", "a = 1979-05-27", "\"a\" = 1", "a = '''x'''", "a = { b.c = 1 }", "a = { b = 1, }",
+            "[a]\n[a]\n", "[a.b]\n[a]\n", "a = { x = 1 }\n[a]\n", "a = { x = 1 }\na.y = 2\n",
+            "a = [{ x = 1 }]\n[[a]]\n", "[a]\n[[a]]\n",
+        ] {
+            assert!(Format::Toml.parse(text).is_err(), "accepted TOML {:?}", text);
+        }
+        for text in [
+            r#"a: "\0""#, r#"a: "\/""#, "a: 0x1F", "a: .inf", "a: b: c", "a: - b", "a: &x 1", "a: *x",
+            "a: |\n  text\n", "a: [1,,2]", "a: {x: 1, x: 2}", "? a\n: 1\n", "a: 1\n---\nb: 2\n",
+            "a: 1\n...\n", "%YAML 1.2\n---\na: 1\n", "a:\n  \tb: 1\n",
+        ] {
+            assert!(Format::Yaml.parse(text).is_err(), "accepted YAML {:?}", text);
+        }
+
+        let toml = "[[o]]\n[o.c]\nx = 1\n[[o]]\n[o.c]\nx = -2.5e3\n[a]\n[a.b]\n";
+        assert_eq!(Format::Toml.parse(toml).unwrap(),
+                   json!({ "o": [{ "c": { "x": 1 } }, { "c": { "x": -2500.0 } }], "a": { "b": {} } }));
+        let yaml = "a: it's # note\nb: \"say \\\"hi\\\" # here\"\nc: 192.168.1.5\nd: 007\ne: [+1, .5]\n";
+        assert_eq!(Format::Yaml.parse(yaml).unwrap(),
+                   json!({ "a": "it's", "b": "say \"hi\" # here", "c": "192.168.1.5", "d": 7, "e": [1, 0.5] }));
+    }
+}
@@ -0,0 +1,675 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::config::{ControlConfig, OutputConfig};
+use crate::http::{self, Request};
+use crate::health::Health;
+use crate::hyperion_json;
+use crate::inspect;
+use crate::net;
+use crate::opc_server::SharedState;
+use crate::output::Output;
+use crate::preview;
+use crate::schedule::{self, ScheduleMode};
+use crate::summary::Summary;
+use crate::ui;
+use crate::websocket;
+
+/// Interval at which frame rates are sampled for the status endpoint
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frame rates measured over the last sample interval
+#[derive(Default)]
+struct Rates {
+    received_fps: f64,
+    /// Output fps keyed by port
+    output_fps: HashMap<String, f64>,
+}
+
+/// Runtime control server (HTTP REST API)
+///
+/// Endpoints:
+/// - `GET  /status`                  server, client and per-output status
+/// - `GET  /health`                 healthy, degraded or disconnected, overall and per output (503 if any is disconnected)
+/// - `GET  /summary`                 inputs, channels and outputs, as printed at startup
+/// - `GET  /outputs`                 list outputs and their state
+/// - `POST /outputs`                 body with one output's settings, as in the config file: open it
+/// - `DELETE /outputs/{id}`          close the output (until the next reload)
+/// - `POST /outputs/{id}/disable`    blank the output and stop writing to it
+/// - `POST /outputs/{id}/enable`     resume writing frames to the output
+/// - `POST /outputs/{id}/blank`      force the output to black (keeps streaming)
+/// - `POST /outputs/{id}/unblank`    stop forcing the output to black
+/// - `POST /outputs/{id}/brightness` body `{"brightness": 0.0-1.0}`
+/// - `POST /outputs/{id}`            body with any of `enabled`, `blanked`, `brightness`
+/// - `POST /outputs/{id}/test`       body `{"color": [r, g, b]}`: fill the strip with one color
+/// - `POST /outputs/{id}/inspect`    optional body `{"frames": n, "diff": bool}`: annotate the next frames in a file
+/// - `GET  /channels`                messages, bytes, last message time and sender of each OPC channel
+/// - `GET  /channels/{n}/frame`      the last pixel data received on channel `n`, as hex
+/// - `POST /blank`, `POST /unblank`  blank or unblank all outputs
+/// - `POST /brightness`              body `{"brightness": 0.0-1.0}` for all outputs
+/// - `POST /inspect`                 like `/outputs/{id}/inspect`, for all outputs
+/// - `POST /reload`                  re-read the config file and reopen all outputs
+/// - `GET  /schedule`                whether the `schedule` has the outputs on, and any override
+/// - `POST /schedule`                body `{"override": "on"|"off"|"auto"}`
+/// - `GET  /preview`                 live preview page (when `preview` is enabled)
+/// - `GET  /preview/ws`              WebSocket stream of the latest frame per output
+/// - `GET  /ui`                      configuration page (when `ui` is enabled)
+/// - `GET  /config`, `PUT /config`   read, or validate, save and reload, the config file (when `ui` is enabled)
+///
+/// `{id}` is the output's `name`, or its port (percent-encoded, e.g. `%2Fdev%2FttyACM0`)
+pub struct ControlServer {
+    config: ControlConfig,
+    state: SharedState,
+    rates: Mutex<Rates>,
+    debug: bool,
+    ddebug: bool,
+}
+
+impl ControlServer {
+    pub fn new(config: ControlConfig, state: SharedState, debug: bool, ddebug: bool) -> Self {
+        ControlServer {
+            config,
+            state,
+            rates: Mutex::new(Rates::default()),
+            debug,
+            ddebug,
+        }
+    }
+
+    /// Bind the listener and serve requests on a background thread
+    pub fn spawn(self) -> Result<thread::JoinHandle<()>> {
+        let addr = net::resolve(&self.config.host, self.config.port)?;
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to bind control server to {}", addr))?;
+
+        // Nonblocking so the accept loop can observe the running flag
+        listener.set_nonblocking(true)?;
+
+        if self.debug {
+            println!("✓ Control server listening on http://{}", addr);
+        }
+
+        let server = Arc::new(self);
+
+        let sampler = Arc::clone(&server);
+        thread::spawn(move || sampler.sample_rates());
+
+        Ok(thread::spawn(move || server.serve(listener)))
+    }
+
+    /// Periodically sample frame counters to compute frame rates
+    fn sample_rates(&self) {
+        let mut last_received = self.state.frames_received.load(Ordering::Relaxed);
+        let mut last_sent: HashMap<String, u64> = HashMap::new();
+        let mut last_time = Instant::now();
+
+        while self.state.running.load(Ordering::Relaxed) {
+            thread::sleep(RATE_SAMPLE_INTERVAL);
+
+            let elapsed = last_time.elapsed().as_secs_f64();
+            last_time = Instant::now();
+
+            let received = self.state.frames_received.load(Ordering::Relaxed);
+            let mut rates = Rates {
+                received_fps: received.saturating_sub(last_received) as f64 / elapsed,
+                output_fps: HashMap::new(),
+            };
+            last_received = received;
+
+            for output in self.state.outputs.read().unwrap().iter() {
+                let port = output.config().port.clone();
+                let sent = output.frames_sent();
+                let previous = last_sent.insert(port.clone(), sent).unwrap_or(sent);
+                rates.output_fps.insert(port, sent.saturating_sub(previous) as f64 / elapsed);
+            }
+
+            *self.rates.lock().unwrap() = rates;
+        }
+    }
+
+    fn serve(&self, listener: TcpListener) {
+        while self.state.running.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = self.handle_connection(stream) {
+                        eprintln!("Control request error: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    eprintln!("Error accepting control connection: {}", e);
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+        // Accepted sockets inherit nonblocking mode on some platforms
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let request = http::read_request(&mut stream)?;
+
+        if self.config.ui && request.method == "GET" && request.segments() == ["ui"] {
+            return http::write_response(&mut stream, 200, "text/html; charset=utf-8", ui::PAGE.as_bytes());
+        }
+
+        if self.config.preview && request.method == "GET" {
+            match request.segments().as_slice() {
+                [page] if page == "preview" => {
+                    return http::write_response(&mut stream, 200, "text/html; charset=utf-8", preview::PAGE.as_bytes());
+                }
+                [page, ws] if page == "preview" && ws == "ws" && websocket::is_upgrade_request(&request) => {
+                    // Preview clients stay connected, so stream on their own thread
+                    let state = self.state.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = preview::stream(stream, &request, &state) {
+                            eprintln!("Preview client error: {}", e);
+                        }
+                    });
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        let (status, body) = self.route(&request);
+        http::write_json(&mut stream, status, &body)
+    }
+
+    fn route(&self, request: &Request) -> (u16, serde_json::Value) {
+        let segments = request.segments();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["status"]) => (200, self.status()),
+            ("GET", ["health"]) => {
+                let report = self.state.health.report(&self.state);
+                let status = if report.status == Health::Disconnected { 503 } else { 200 };
+                (status, json!(report))
+            }
+            ("GET", ["summary"]) => (200, json!(Summary::collect(&self.state))),
+            ("GET", ["outputs"]) => (200, json!({ "outputs": self.output_list() })),
+            ("POST", ["outputs"]) => {
+                match parse_body(request) {
+                    Ok(body) => self.add_output(body),
+                    Err(e) => e,
+                }
+            }
+            ("DELETE", ["outputs", id]) => {
+                if self.state.remove_output(id) {
+                    (200, json!({"removed": id}))
+                } else {
+                    (404, json!({"error": format!("no output named {}", id)}))
+                }
+            }
+            ("POST", ["outputs", id, "enable"]) => self.update_outputs(Some(id), &json!({"enabled": true})),
+            ("POST", ["outputs", id, "disable"]) => self.update_outputs(Some(id), &json!({"enabled": false})),
+            ("POST", ["outputs", id, "blank"]) => self.update_outputs(Some(id), &json!({"blanked": true})),
+            ("POST", ["outputs", id, "unblank"]) => self.update_outputs(Some(id), &json!({"blanked": false})),
+            ("POST", ["outputs", id, "test"]) => {
+                match parse_body(request) {
+                    Ok(body) => self.test_color(id, &body),
+                    Err(e) => e,
+                }
+            }
+            ("POST", ["outputs", id, "inspect"]) => {
+                match parse_optional_body(request) {
+                    Ok(body) => self.inspect(Some(id), &body),
+                    Err(e) => e,
+                }
+            }
+            ("POST", ["outputs", id, "brightness"]) | ("POST", ["outputs", id]) => {
+                match parse_body(request) {
+                    Ok(body) => self.update_outputs(Some(id), &body),
+                    Err(e) => e,
+                }
+            }
+            ("GET", ["channels"]) => (200, json!({ "channels": self.state.channel_stats.counters() })),
+            ("GET", ["channels", channel, "frame"]) => self.last_frame(channel),
+            ("POST", ["blank"]) => self.update_outputs(None, &json!({"blanked": true})),
+            ("POST", ["unblank"]) => self.update_outputs(None, &json!({"blanked": false})),
+            ("POST", ["brightness"]) => {
+                match parse_body(request) {
+                    Ok(body) => self.update_outputs(None, &body),
+                    Err(e) => e,
+                }
+            }
+            ("POST", ["inspect"]) => {
+                match parse_optional_body(request) {
+                    Ok(body) => self.inspect(None, &body),
+                    Err(e) => e,
+                }
+            }
+            ("POST", ["reload"]) => self.reload(),
+            ("POST", ["json-rpc"]) => {
+                match parse_body(request) {
+                    Ok(body) => (200, hyperion_json::handle_request(&self.state, &body, self.debug)),
+                    Err(e) => e,
+                }
+            }
+            ("GET", ["config"]) if self.config.ui => match ui::read_config(&self.state.config_path) {
+                Ok(config) => (200, config),
+                Err(e) => (500, json!({"error": format!("{:#}", e)})),
+            },
+            ("PUT", ["config"]) if self.config.ui => {
+                match parse_body(request) {
+                    Ok(body) => self.save_config(&body),
+                    Err(e) => e,
+                }
+            }
+            (_, ["config"]) if self.config.ui => (405, json!({"error": "method not allowed"})),
+            ("GET", ["schedule"]) => match self.schedule_status() {
+                serde_json::Value::Null => (404, json!({"error": "no schedule configured"})),
+                status => (200, status),
+            },
+            ("POST", ["schedule"]) => {
+                match parse_body(request) {
+                    Ok(body) => self.override_schedule(&body),
+                    Err(e) => e,
+                }
+            }
+            (_, ["status"]) | (_, ["health"]) | (_, ["summary"]) | (_, ["outputs", ..]) | (_, ["channels", ..]) | (_, ["blank"]) | (_, ["unblank"])
+            | (_, ["brightness"]) | (_, ["inspect"]) | (_, ["reload"]) | (_, ["schedule"]) | (_, ["json-rpc"]) => {
+                (405, json!({"error": "method not allowed"}))
+            }
+            _ => (404, json!({"error": "not found"})),
+        }
+    }
+
+    /// The last pixel data of a channel, byte for byte as the client sent it
+    fn last_frame(&self, channel: &str) -> (u16, serde_json::Value) {
+        let Ok(channel) = channel.parse::<u8>() else {
+            return (400, json!({"error": format!("invalid channel {}", channel)}));
+        };
+        match self.state.channel_stats.last_frame(channel) {
+            Some((counters, frame)) => {
+                let data: String = frame.iter().map(|b| format!("{:02x}", b)).collect();
+                let mut body = json!(counters);
+                body["length"] = json!(frame.len());
+                body["data"] = json!(data);
+                (200, body)
+            }
+            None => (404, json!({"error": format!("nothing received on channel {}", channel)})),
+        }
+    }
+
+    fn status(&self) -> serde_json::Value {
+        let rates = self.rates.lock().unwrap();
+
+        let clients: Vec<_> = self.state.clients.lock().unwrap().iter().map(|c| {
+            json!({
+                "addr": c.addr.to_string(),
+                "connected_at": c.connected_at.duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs()).unwrap_or(0),
+            })
+        }).collect();
+
+        json!({
+            "uptime_secs": self.state.started.elapsed().as_secs(),
+            "frames_received": self.state.frames_received.load(Ordering::Relaxed),
+            "frames_throttled": self.state.frames_throttled.load(Ordering::Relaxed),
+            "messages_rejected": self.state.messages_rejected.load(Ordering::Relaxed),
+            "frames_preempted": self.state.frames_preempted.load(Ordering::Relaxed),
+            "received_fps": rates.received_fps,
+            "clients": clients,
+            "outputs": self.output_list_with_rates(&rates),
+            "pending_outputs": self.pending_list(),
+            "schedule": self.schedule_status(),
+            "frame_sync": self.state.frame_tick.as_ref().map(|tick| json!({
+                "role": tick.role(),
+                "ticks": tick.stats.ticks.load(Ordering::Relaxed),
+                "missed_ticks": tick.stats.missed.load(Ordering::Relaxed),
+                "timeouts": tick.stats.timeouts.load(Ordering::Relaxed),
+            })),
+            "timed_frames": self.state.timed.as_ref().map(|timed| json!({
+                "queued": timed.queued(),
+                "scheduled": timed.stats.scheduled.load(Ordering::Relaxed),
+                "late": timed.stats.late.load(Ordering::Relaxed),
+                "dropped": timed.stats.dropped.load(Ordering::Relaxed),
+            })),
+            "relays": self.state.relays.iter().map(|r| json!({
+                "target": r.target,
+                "connected": r.stats.connected.load(Ordering::Relaxed),
+                "messages_sent": r.stats.messages_sent.load(Ordering::Relaxed),
+                "messages_dropped": r.stats.messages_dropped.load(Ordering::Relaxed),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Schedule state, or null when no schedule is configured
+    fn schedule_status(&self) -> serde_json::Value {
+        match &self.state.schedule {
+            Some(schedule) => json!({
+                "active": schedule.is_active(),
+                "scheduled": schedule.scheduled_now(),
+                "override": schedule.mode().name(),
+            }),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    /// Switch the outputs on or off regardless of the schedule, or follow it again
+    fn override_schedule(&self, body: &serde_json::Value) -> (u16, serde_json::Value) {
+        let Some(schedule) = &self.state.schedule else {
+            return (404, json!({"error": "no schedule configured"}));
+        };
+        let Some(mode) = body.get("override").and_then(|v| v.as_str()).and_then(ScheduleMode::parse) else {
+            return (400, json!({"error": "expected \"override\": \"on\", \"off\" or \"auto\""}));
+        };
+
+        schedule.set_mode(mode);
+        schedule::update(&self.state, schedule, self.debug);
+        if self.debug {
+            println!("✓ Schedule override: {}", mode.name());
+        }
+        (200, self.schedule_status())
+    }
+
+    /// Outputs still waiting for their ports
+    fn pending_list(&self) -> Vec<serde_json::Value> {
+        self.state.pending.lock().unwrap().entries().iter().map(|p| {
+            json!({
+                "id": p.group[0].id(),
+                "port": p.port(),
+                "attempts": p.attempts,
+                "last_error": p.last_error,
+            })
+        }).collect()
+    }
+
+    fn output_list(&self) -> Vec<serde_json::Value> {
+        self.output_list_with_rates(&self.rates.lock().unwrap())
+    }
+
+    fn output_list_with_rates(&self, rates: &Rates) -> Vec<serde_json::Value> {
+        // Taken before the outputs are locked again below
+        let health: HashMap<String, Health> = self.state.health.report(&self.state).outputs.into_iter()
+            .map(|output| (output.id, output.health))
+            .collect();
+        self.state.outputs.read().unwrap().iter().map(|o| {
+            let config = o.config();
+            let state = o.state();
+            json!({
+                "id": config.id(),
+                "name": config.name,
+                "port": config.port,
+                "enabled": o.is_enabled(),
+                "blanked": o.is_blanked(),
+                "brightness": o.brightness(),
+                "connected": state.connected.load(Ordering::Relaxed),
+                "frames_sent": state.frames_sent.load(Ordering::Relaxed),
+                "bytes_sent": state.bytes_sent.load(Ordering::Relaxed),
+                "frames_dropped": state.frames_dropped.load(Ordering::Relaxed),
+                "frames_deduped": state.frames_deduped.load(Ordering::Relaxed),
+                "frames_late": state.frames_late.load(Ordering::Relaxed),
+                "pacing_ms": state.pacing_us.load(Ordering::Relaxed) / 1000,
+                "errors": state.errors.load(Ordering::Relaxed),
+                "health": health.get(config.id()).copied().unwrap_or(Health::Healthy),
+                "fps": rates.output_fps.get(&config.port).copied().unwrap_or(0.0),
+                "mirrors": o.mirrors().iter().map(|(port, state)| json!({
+                    "port": port,
+                    "connected": state.connected.load(Ordering::Relaxed),
+                    "frames_sent": state.frames_sent.load(Ordering::Relaxed),
+                    "errors": state.errors.load(Ordering::Relaxed),
+                })).collect::<Vec<_>>(),
+            })
+        }).collect()
+    }
+
+    /// Apply `enabled`, `blanked` and/or `brightness` from `changes` to one output (by id) or all
+    fn update_outputs(&self, id: Option<&str>, changes: &serde_json::Value) -> (u16, serde_json::Value) {
+        let enabled = changes.get("enabled").and_then(|v| v.as_bool());
+        let blanked = changes.get("blanked").and_then(|v| v.as_bool());
+        let brightness = changes.get("brightness").and_then(|v| v.as_f64());
+
+        if enabled.is_none() && blanked.is_none() && brightness.is_none() {
+            return (400, json!({"error": "expected any of \"enabled\", \"blanked\", \"brightness\""}));
+        }
+
+        let outputs = self.state.outputs.read().unwrap();
+        let matched: Vec<&Output> = outputs.iter()
+            .filter(|o| match id {
+                Some(id) => o.config().name.as_deref() == Some(id) || o.config().port == id,
+                None => true,
+            })
+            .collect();
+
+        if matched.is_empty() {
+            if let Some(id) = id {
+                return (404, json!({"error": format!("no output named {}", id)}));
+            }
+        }
+
+        for output in &matched {
+            if let Some(enabled) = enabled {
+                output.set_enabled(enabled);
+            }
+            if let Some(blanked) = blanked {
+                output.set_blanked(blanked);
+            }
+            if let Some(brightness) = brightness {
+                output.set_brightness(brightness as f32);
+            }
+            self.state.record_adjustment(output.config().id(), brightness.map(|b| b as f32), enabled);
+            if self.debug {
+                println!("✓ Output {} updated: {}", output.config().id(), changes);
+            }
+        }
+
+        let updated: Vec<_> = matched.iter().map(|o| o.config().id()).collect();
+        (200, json!({"updated": updated}))
+    }
+
+    fn add_output(&self, body: serde_json::Value) -> (u16, serde_json::Value) {
+        let config: OutputConfig = match serde_json::from_value(body) {
+            Ok(config) => config,
+            Err(e) => return (400, json!({"error": format!("invalid output: {}", e)})),
+        };
+        match self.state.add_output(config, self.debug, self.ddebug) {
+            Ok(id) => (200, json!({"added": id})),
+            Err(e) => {
+                eprintln!("✗ Could not add output: {:#}", e);
+                (400, json!({"error": format!("{:#}", e)}))
+            }
+        }
+    }
+
+    /// Fill every LED of an output with one color, until the next frame arrives
+    fn test_color(&self, id: &str, body: &serde_json::Value) -> (u16, serde_json::Value) {
+        let color: Option<Vec<u8>> = body.get("color")
+            .and_then(|c| serde_json::from_value(c.clone()).ok())
+            .filter(|c: &Vec<u8>| c.len() == 3);
+        let Some(color) = color else {
+            return (400, json!({"error": "expected \"color\": [r, g, b]"}));
+        };
+
+        let outputs = self.state.outputs.read().unwrap();
+        let Some(output) = outputs.iter()
+            .find(|o| o.config().name.as_deref() == Some(id) || o.config().port == id) else {
+            return (404, json!({"error": format!("no output named {}", id)}));
+        };
+        if let Err(e) = output.send_frame(color.repeat(output.config().led_count)) {
+            return (500, json!({"error": format!("{:#}", e)}));
+        }
+        if self.debug {
+            println!("✓ Output {} test color {:?}", output.config().id(), color);
+        }
+        (200, json!({"tested": output.config().id(), "color": color}))
+    }
+
+    /// Capture the next `frames` frames (default `inspect::DEFAULT_FRAMES`) of one output (by id) or all
+    fn inspect(&self, id: Option<&str>, body: &serde_json::Value) -> (u16, serde_json::Value) {
+        let frames = match body.get("frames") {
+            None => inspect::DEFAULT_FRAMES,
+            Some(frames) => match frames.as_u64() {
+                Some(frames) => frames as usize,
+                None => return (400, json!({"error": "expected \"frames\": a number of frames"})),
+            },
+        };
+        let diff = body.get("diff").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let outputs = self.state.outputs.read().unwrap();
+        let matched: Vec<&Output> = outputs.iter()
+            .filter(|o| match id {
+                Some(id) => o.config().name.as_deref() == Some(id) || o.config().port == id,
+                None => true,
+            })
+            .collect();
+        if matched.is_empty() {
+            if let Some(id) = id {
+                return (404, json!({"error": format!("no output named {}", id)}));
+            }
+        }
+
+        let mut captures = Vec::new();
+        for output in matched {
+            match output.inspect(frames, diff, self.state.quiet) {
+                Ok(path) => {
+                    if self.debug {
+                        println!("✓ Inspecting {} frames of {} to {}", frames, output.config().id(), path.display());
+                    }
+                    captures.push(json!({"output": output.config().id(), "path": path}));
+                }
+                Err(e) => return (400, json!({"error": format!("{:#}", e)})),
+            }
+        }
+        (200, json!({"inspecting": captures, "frames": frames, "diff": diff}))
+    }
+
+    /// Write a config from the UI back to disk, then reopen the outputs it lists
+    fn save_config(&self, config: &serde_json::Value) -> (u16, serde_json::Value) {
+        if let Err(e) = ui::save_config(&self.state.config_path, config, &self.state.load_options) {
+            eprintln!("✗ Config not saved: {:#}", e);
+            return (400, json!({"saved": false, "error": format!("{:#}", e)}));
+        }
+        if self.debug {
+            println!("✓ Config saved to {}", self.state.config_path);
+        }
+
+        match self.state.reload_outputs(self.debug, self.ddebug) {
+            Ok(count) => (200, json!({"saved": true, "outputs": count})),
+            Err(e) => {
+                eprintln!("✗ Config reload failed: {}", e);
+                (500, json!({"saved": true, "error": format!("{:#}", e)}))
+            }
+        }
+    }
+
+    fn reload(&self) -> (u16, serde_json::Value) {
+        if self.debug {
+            println!("Reloading outputs from {}...", self.state.config_path);
+        }
+
+        match self.state.reload_outputs(self.debug, self.ddebug) {
+            Ok(count) => (200, json!({"reloaded": true, "outputs": count})),
+            Err(e) => {
+                eprintln!("✗ Config reload failed: {}", e);
+                (500, json!({"reloaded": false, "error": format!("{:#}", e)}))
+            }
+        }
+    }
+}
+
+/// Parse a JSON request body, producing a ready-to-send 400 response on failure
+fn parse_body(request: &Request) -> std::result::Result<serde_json::Value, (u16, serde_json::Value)> {
+    serde_json::from_slice(&request.body)
+        .map_err(|e| (400, json!({"error": format!("invalid JSON: {}", e)})))
+}
+
+/// Like `parse_body`, with an empty body read as `{}`
+fn parse_optional_body(request: &Request) -> std::result::Result<serde_json::Value, (u16, serde_json::Value)> {
+    if request.body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(json!({}));
+    }
+    parse_body(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbiter::Source;
+    use crate::config::Config;
+    use crate::mock_serial;
+    use crate::opc_server::OpcServer;
+    use std::io::{Read, Write};
+
+    fn server(port: &str) -> OpcServer {
+        let config: Config = serde_json::from_value(json!({
+            "opc": {"host": "127.0.0.1", "port": 0, "enabled": false},
+            "persist_state": false,
+            "outputs": [OutputConfig {
+                name: Some("desk".to_string()),
+                hardware_type: Some("mock".to_string()),
+                ..OutputConfig::test_default(port)
+            }],
+        })).unwrap();
+        OpcServer::new(config, String::new(), false, false).unwrap()
+    }
+
+    fn control(state: &SharedState) -> ControlServer {
+        let config = ControlConfig { host: "127.0.0.1".to_string(), port: 0, preview: false, ui: false };
+        ControlServer::new(config, state.clone(), false, false)
+    }
+
+    fn post(path: &str) -> Request {
+        Request { method: "POST".to_string(), path: path.to_string(), query: String::new(), headers: Vec::new(), body: Vec::new() }
+    }
+
+    #[test]
+    fn test_disable_and_enable_routes() {
+        let server = server("mock-control-pause");
+        let state = server.state();
+        let control = control(state);
+        let enabled = || state.outputs.read().unwrap()[0].is_enabled();
+
+        assert_eq!(control.route(&post("/outputs/desk/disable")), (200, json!({"updated": ["desk"]})));
+        assert!(!enabled());
+        // The worker blanks the strip once, then takes no more frames
+        let frames = mock_serial::wait_for_frames("mock-control-pause", 1, Duration::from_secs(2));
+        assert_eq!(frames[0][6..36], [0; 30]);
+        state.handle_opc_message(Source::Opc, 0, 0, &[200; 30]);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(mock_serial::frames("mock-control-pause").len(), 1);
+        assert_eq!(control.output_list()[0]["enabled"], false);
+
+        // Outputs are found by port as well as by name
+        assert_eq!(control.route(&post("/outputs/mock-control-pause/enable")), (200, json!({"updated": ["desk"]})));
+        assert!(enabled());
+        state.handle_opc_message(Source::Opc, 0, 0, &[200; 30]);
+        let frames = mock_serial::wait_for_frames("mock-control-pause", 2, Duration::from_secs(2));
+        assert_eq!(frames[1][6..36], [200; 30]);
+
+        let (status, body) = control.route(&post("/outputs/shelf/disable"));
+        assert_eq!((status, body["error"].as_str()), (404, Some("no output named shelf")));
+        assert_eq!(control.route(&Request { method: "GET".to_string(), ..post("/outputs/desk/enable") }).0, 405);
+        assert!(enabled());
+    }
+
+    #[test]
+    fn test_requests_over_http() {
+        let server = server("mock-control-http");
+        let control = control(server.state());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"POST /outputs/desk/disable HTTP/1.1\r\nContent-Length: 0\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        control.handle_connection(stream).unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("{\"updated\":[\"desk\"]}"));
+        assert!(!server.state().outputs.read().unwrap()[0].is_enabled());
+    }
+}
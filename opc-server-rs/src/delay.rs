@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most frames held back at once; older frames are dropped beyond this
+const MAX_DELAYED_FRAMES: usize = 256;
+
+struct Inner {
+    /// Frames with the time they become due and the time they arrived, oldest first
+    frames: VecDeque<(Instant, Instant, Vec<u8>)>,
+    closed: bool,
+}
+
+/// Holds frames back for a fixed time before they reach an output's queue
+///
+/// Used to line up outputs whose controllers or baud rates add different
+/// latencies. Frames keep their spacing, so the frame rate is unchanged.
+pub struct DelayLine {
+    inner: Mutex<Inner>,
+    /// Signalled when a frame is pushed or the line is closed
+    changed: Condvar,
+    delay: Duration,
+}
+
+impl DelayLine {
+    pub fn new(delay: Duration) -> Self {
+        DelayLine {
+            inner: Mutex::new(Inner {
+                frames: VecDeque::new(),
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            delay,
+        }
+    }
+
+    /// Add a frame, due `delay` from now
+    ///
+    /// Returns a frame that was dropped (the oldest one if the line is full, or the
+    /// incoming one if it is closed), so its buffer can be reused.
+    pub fn push(&self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return Some(frame);
+        }
+
+        let dropped = if inner.frames.len() >= MAX_DELAYED_FRAMES {
+            inner.frames.pop_front().map(|(_, _, frame)| frame)
+        } else {
+            None
+        };
+        let now = Instant::now();
+        inner.frames.push_back((now + self.delay, now, frame));
+        self.changed.notify_one();
+        dropped
+    }
+
+    /// Wait for the oldest frame to become due and take it, with the time it arrived
+    ///
+    /// Returns `None` once the line is closed; frames still held are discarded.
+    pub fn pop_due(&self) -> Option<(Vec<u8>, Instant)> {
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if inner.closed {
+                return None;
+            }
+
+            let now = Instant::now();
+            match inner.frames.front() {
+                Some((due, _, _)) if *due <= now => {
+                    return inner.frames.pop_front().map(|(_, received, frame)| (frame, received));
+                }
+                Some((due, _, _)) => {
+                    let wait = *due - now;
+                    inner = self.changed.wait_timeout(inner, wait).unwrap().0;
+                }
+                None => {
+                    inner = self.changed.wait(inner).unwrap();
+                }
+            }
+        }
+    }
+
+    /// Close the line, waking the forwarding thread
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_released_in_order_after_delay() {
+        let line = DelayLine::new(Duration::from_millis(20));
+        let start = Instant::now();
+        assert_eq!(line.push(vec![1]), None);
+        assert_eq!(line.push(vec![2]), None);
+
+        let (frame, received) = line.pop_due().unwrap();
+        assert_eq!(frame, vec![1]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        // Frames keep the time they arrived, for latency measurements
+        assert!(received - start < Duration::from_millis(20));
+        assert_eq!(line.pop_due().map(|(frame, _)| frame), Some(vec![2]));
+
+        line.close();
+        assert_eq!(line.pop_due(), None);
+        assert_eq!(line.push(vec![3]), Some(vec![3]));
+    }
+}
@@ -0,0 +1,127 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::config::{StandbyConfig, StandbyEffect};
+
+/// Default standby color: a warm white
+const DEFAULT_COLOR: [u8; 3] = [255, 140, 40];
+
+/// Length of one breath, rainbow cycle or twinkle at speed 1
+const CYCLE: Duration = Duration::from_secs(4);
+
+/// Animation an output shows while no input sends to it (`standby`)
+#[derive(Debug, Clone)]
+pub struct Standby {
+    effect: StandbyEffect,
+    speed: f32,
+    palette: Vec<[u8; 3]>,
+    led_count: usize,
+}
+
+impl Standby {
+    pub fn new(config: &StandbyConfig, led_count: usize) -> Self {
+        let palette = config.palette.clone().filter(|p| !p.is_empty()).unwrap_or_else(|| vec![DEFAULT_COLOR]);
+        Standby {
+            effect: config.effect,
+            speed: config.speed.unwrap_or(1.0).max(0.0),
+            palette,
+            led_count,
+        }
+    }
+
+    /// RGB pixel data of the animation `elapsed` after it started
+    pub fn render(&self, elapsed: Duration) -> Vec<u8> {
+        let cycles = elapsed.as_secs_f32() / CYCLE.as_secs_f32() * self.speed;
+        let n = self.led_count;
+        let mut frame = Vec::with_capacity(n * 3);
+
+        match self.effect {
+            StandbyEffect::Solid => {
+                for i in 0..n {
+                    frame.extend(self.palette[i % self.palette.len()]);
+                }
+            }
+            StandbyEffect::Breathe => {
+                // Each breath fades in and out, moving on to the next palette color
+                let color = self.palette[cycles as usize % self.palette.len()];
+                let level = (1.0 - (2.0 * PI * cycles).cos()) / 2.0;
+                for _ in 0..n {
+                    frame.extend(scale(color, level));
+                }
+            }
+            StandbyEffect::Rainbow => {
+                for i in 0..n {
+                    frame.extend(hue(i as f32 / n as f32 + cycles));
+                }
+            }
+            StandbyEffect::Twinkle => {
+                // Every LED sparkles on its own period and phase, derived from its index
+                for i in 0..n {
+                    let seed = mix(i as u32);
+                    let rate = 0.5 + (seed & 0xff) as f32 / 255.0;
+                    let phase = (seed >> 8 & 0xff) as f32 / 255.0;
+                    let wave = (2.0 * PI * (cycles * rate + phase)).sin().max(0.0);
+                    let color = self.palette[(seed >> 16) as usize % self.palette.len()];
+                    frame.extend(scale(color, wave * wave * wave));
+                }
+            }
+        }
+        frame
+    }
+}
+
+fn scale(color: [u8; 3], level: f32) -> [u8; 3] {
+    color.map(|c| (c as f32 * level).round() as u8)
+}
+
+/// Scramble an index into well-spread bits (a small integer hash)
+fn mix(mut x: u32) -> u32 {
+    x = (x ^ 61) ^ (x >> 16);
+    x = x.wrapping_mul(9);
+    x ^= x >> 4;
+    x = x.wrapping_mul(0x27d4eb2d);
+    x ^ (x >> 15)
+}
+
+/// Fully saturated color at `position` around the color wheel (0.0 red, 1/3 green, 2/3 blue)
+pub fn hue(position: f32) -> [u8; 3] {
+    let h = position.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c: f32| (c * 255.0).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standby(effect: StandbyEffect, palette: Option<Vec<[u8; 3]>>) -> Standby {
+        Standby::new(&StandbyConfig { effect, speed: None, palette, after_ms: None }, 3)
+    }
+
+    #[test]
+    fn test_standby_effects() {
+        let solid = standby(StandbyEffect::Solid, Some(vec![[1, 2, 3], [4, 5, 6]]));
+        assert_eq!(solid.render(Duration::ZERO), vec![1, 2, 3, 4, 5, 6, 1, 2, 3]);
+
+        // A breath is dark at its start and brightest halfway through
+        let breathe = standby(StandbyEffect::Breathe, None);
+        assert_eq!(breathe.render(Duration::ZERO), vec![0; 9]);
+        assert_eq!(breathe.render(CYCLE / 2), DEFAULT_COLOR.repeat(3));
+
+        let rainbow = standby(StandbyEffect::Rainbow, None);
+        assert_eq!(&rainbow.render(Duration::ZERO)[..3], &[255, 0, 0]);
+        assert_eq!(rainbow.render(CYCLE), rainbow.render(Duration::ZERO));
+
+        let twinkle = standby(StandbyEffect::Twinkle, None).render(Duration::from_millis(1500));
+        assert_eq!(twinkle.len(), 9);
+        assert_eq!(hue(2.0 / 3.0), [0, 0, 255]);
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::arbiter::Source;
+use crate::color::ColorCorrection;
+use crate::config::FadecandyConfig;
+use crate::http;
+use crate::net;
+use crate::opc_server::SharedState;
+use crate::output::Output;
+use crate::websocket::{self, Message, WebSocket};
+
+/// OPC System Exclusive command
+pub const OPC_SYSEX: u8 = 0xFF;
+
+/// SysEx system ID assigned to Fadecandy
+const SYSTEM_ID_FADECANDY: u16 = 0x0001;
+
+/// Fadecandy SysEx: set global color correction (JSON payload)
+const SYSEX_SET_COLOR_CORRECTION: u16 = 0x0001;
+
+/// Fadecandy SysEx: set firmware configuration (flags byte)
+const SYSEX_SET_FIRMWARE_CONFIG: u16 = 0x0002;
+
+/// Firmware configuration flags as defined by the Fadecandy firmware
+pub const FIRMWARE_NO_DITHERING: u8 = 0x01;
+pub const FIRMWARE_NO_INTERPOLATION: u8 = 0x02;
+
+/// Runtime state of the Fadecandy emulation
+///
+/// Serial strips have no temporal dithering or keyframe interpolation engine, so the
+/// firmware configuration is recorded and reported back to clients but not applied.
+pub struct FadecandyState {
+    pub firmware_config: AtomicU8,
+}
+
+impl FadecandyState {
+    pub fn new(config: &FadecandyConfig) -> Self {
+        let mut flags = 0;
+        if !config.dithering {
+            flags |= FIRMWARE_NO_DITHERING;
+        }
+        if !config.interpolation {
+            flags |= FIRMWARE_NO_INTERPOLATION;
+        }
+        FadecandyState {
+            firmware_config: AtomicU8::new(flags),
+        }
+    }
+}
+
+/// Handle an OPC SysEx message (command 0xFF) addressed to Fadecandy
+///
+/// Color correction is accepted from every OPC client, as `opc.py` and other reference
+/// clients send it to any server; the firmware configuration only with emulation on.
+pub fn handle_sysex(state: &SharedState, fadecandy: Option<&FadecandyState>, data: &[u8]) {
+    if data.len() < 4 {
+        return;
+    }
+
+    let system_id = u16::from_be_bytes([data[0], data[1]]);
+    let command_id = u16::from_be_bytes([data[2], data[3]]);
+    let payload = &data[4..];
+
+    if system_id != SYSTEM_ID_FADECANDY {
+        return;
+    }
+
+    match command_id {
+        SYSEX_SET_COLOR_CORRECTION => {
+            match serde_json::from_slice::<ColorCorrection>(payload) {
+                Ok(correction) => {
+                    if state.ddebug {
+                        eprintln!("[DEBUG] SysEx color correction: {:?}", correction);
+                    }
+                    if let Some(store) = &state.persist {
+                        store.record_color_correction(&correction);
+                    }
+                    state.set_color_correction(Some(correction));
+                }
+                Err(e) => eprintln!("✗ Invalid SysEx color correction: {}", e),
+            }
+        }
+        SYSEX_SET_FIRMWARE_CONFIG => {
+            if let (Some(fadecandy), Some(&flags)) = (fadecandy, payload.first()) {
+                fadecandy.firmware_config.store(flags, Ordering::Relaxed);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// WebSocket server speaking the Fadecandy (fcserver) JSON protocol
+///
+/// Text messages are JSON requests (`list_connected_devices`, `server_info`,
+/// `device_options`, `device_pixels`, `device_color`); binary messages are OPC packets.
+/// Each output is presented to clients as a connected Fadecandy device whose serial
+/// number is the output's id.
+pub struct FadecandyServer {
+    config: FadecandyConfig,
+    host: String,
+    state: SharedState,
+    debug: bool,
+}
+
+impl FadecandyServer {
+    pub fn new(config: FadecandyConfig, default_host: &str, state: SharedState, debug: bool) -> Self {
+        let host = config.host.clone().unwrap_or_else(|| default_host.to_string());
+        FadecandyServer { config, host, state, debug }
+    }
+
+    /// Bind the WebSocket listener and serve clients on background threads
+    pub fn spawn(self) -> Result<()> {
+        let addr = net::resolve(&self.host, self.config.websocket_port)?;
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to bind Fadecandy WebSocket server to {}", addr))?;
+
+        // Nonblocking so the accept loop can observe the running flag
+        listener.set_nonblocking(true)?;
+
+        if self.debug {
+            println!("✓ Fadecandy WebSocket server listening on ws://{}", addr);
+        }
+
+        thread::spawn(move || {
+            while self.state.running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer_addr)) => {
+                        let state = self.state.clone();
+                        let debug = self.debug;
+                        thread::spawn(move || {
+                            if debug {
+                                println!("✓ Fadecandy client connected from {}", peer_addr);
+                            }
+                            if let Err(e) = handle_connection(stream, &state) {
+                                eprintln!("Fadecandy client error: {}", e);
+                            }
+                            if debug {
+                                println!("Fadecandy client {} disconnected", peer_addr);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        eprintln!("Error accepting Fadecandy connection: {}", e);
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, state: &SharedState) -> Result<()> {
+    // Accepted sockets inherit nonblocking mode on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let request = http::read_request(&mut stream)?;
+    if !websocket::is_upgrade_request(&request) {
+        return http::write_response(&mut stream, 400, "text/plain", b"Fadecandy WebSocket endpoint\n");
+    }
+
+    let mut ws = WebSocket::accept(stream.try_clone()?, &request)?;
+
+    // Short timeout so the loop can observe the running flag
+    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    while state.running.load(Ordering::Relaxed) {
+        match ws.read_message()? {
+            None => continue,
+            Some(Message::Close) => break,
+            Some(Message::Binary(packet)) => {
+                // Binary messages are whole OPC packets; the length field is ignored
+                if packet.len() >= 4 && state.admit_message(None) {
+                    state.handle_opc_message(Source::Fadecandy, packet[0], packet[1], &packet[4..]);
+                }
+            }
+            Some(Message::Text(text)) => {
+                let reply = match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(message) => handle_json(state, &message),
+                    Err(e) => json!({"error": format!("invalid JSON: {}", e)}),
+                };
+                ws.send_text(&reply.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle one fcserver JSON request, producing its reply
+fn handle_json(state: &SharedState, message: &serde_json::Value) -> serde_json::Value {
+    let message_type = message.get("type").and_then(|v| v.as_str()).unwrap_or("");
+
+    let mut reply = json!({ "type": message_type });
+    if let Some(sequence) = message.get("sequence") {
+        reply["sequence"] = sequence.clone();
+    }
+
+    match message_type {
+        "list_connected_devices" => {
+            reply["devices"] = json!(list_devices(state));
+        }
+        "server_info" => {
+            let firmware_config = state.fadecandy.as_ref()
+                .map(|f| f.firmware_config.load(Ordering::Relaxed))
+                .unwrap_or(0);
+            reply["version"] = json!(concat!("opc_server ", env!("CARGO_PKG_VERSION")));
+            reply["config"] = json!({
+                "color": state.color_correction.lock().unwrap().clone(),
+                "dither": firmware_config & FIRMWARE_NO_DITHERING == 0,
+                "interpolate": firmware_config & FIRMWARE_NO_INTERPOLATION == 0,
+            });
+        }
+        "device_options" => {
+            // Per-device LED/dither/interpolation options have no serial equivalent
+            if with_device(state, message, |_| {}).is_none() {
+                reply["error"] = json!("Device not found");
+            }
+        }
+        "device_pixels" => {
+            let pixels: Vec<u8> = message.get("pixels")
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().map(|p| p.as_f64().unwrap_or(0.0).clamp(0.0, 255.0) as u8).collect())
+                .unwrap_or_default();
+            if with_device(state, message, |o| { let _ = o.send_frame(pixels.clone()); }).is_none() {
+                reply["error"] = json!("Device not found");
+            }
+        }
+        "device_color" => {
+            let color: Vec<u8> = message.get("color")
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().take(3).map(|c| (c.as_f64().unwrap_or(0.0).clamp(0.0, 1.0) * 255.0).round() as u8).collect())
+                .unwrap_or_default();
+            if color.len() != 3 {
+                reply["error"] = json!("Expected color as [r, g, b]");
+            } else if with_device(state, message, |o| {
+                let _ = o.send_frame(color.repeat(o.config().led_count));
+            }).is_none() {
+                reply["error"] = json!("Device not found");
+            }
+        }
+        _ => {
+            reply["error"] = json!("Unknown message type");
+        }
+    }
+
+    reply
+}
+
+/// Describe every output as a connected Fadecandy device
+fn list_devices(state: &SharedState) -> Vec<serde_json::Value> {
+    let timestamp = state.started_at.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    state.outputs.read().unwrap().iter().map(|o| {
+        json!({
+            "type": "fadecandy",
+            "serial": o.config().id(),
+            "timestamp": timestamp,
+            "version": "emulated",
+        })
+    }).collect()
+}
+
+/// Run `f` on the output addressed by the message's `device.serial`
+fn with_device<F: FnOnce(&Output)>(state: &SharedState, message: &serde_json::Value, f: F) -> Option<()> {
+    let serial = message.get("device")?.get("serial")?.as_str()?;
+    let outputs = state.outputs.read().unwrap();
+    let output = outputs.iter().find(|o| o.config().id() == serial)?;
+    f(output);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firmware_flags_from_config() {
+        let config: FadecandyConfig = serde_json::from_str(r#"{"dithering": false}"#).unwrap();
+        let state = FadecandyState::new(&config);
+        assert_eq!(state.firmware_config.load(Ordering::Relaxed), FIRMWARE_NO_DITHERING);
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{FrameSyncConfig, FrameSyncRole};
+use crate::net;
+
+pub const DEFAULT_PORT: u16 = 7893;
+const DEFAULT_RATE_HZ: f64 = 60.0;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Tick packet: magic, then the tick's sequence number (u64 big-endian)
+const MAGIC: &[u8; 4] = b"OPCT";
+pub const PACKET_SIZE: usize = 12;
+
+pub fn encode(sequence: u64) -> [u8; PACKET_SIZE] {
+    let mut packet = [0u8; PACKET_SIZE];
+    packet[..4].copy_from_slice(MAGIC);
+    packet[4..].copy_from_slice(&sequence.to_be_bytes());
+    packet
+}
+
+/// Sequence number of a tick packet; None for anything else arriving on the port
+pub fn decode(packet: &[u8]) -> Option<u64> {
+    if packet.len() != PACKET_SIZE || &packet[..4] != MAGIC {
+        return None;
+    }
+    Some(u64::from_be_bytes(packet[4..].try_into().ok()?))
+}
+
+/// Counters shown in `/status`
+#[derive(Debug, Default)]
+pub struct FrameSyncStats {
+    pub ticks: AtomicU64,
+    /// Ticks the network lost, from gaps in the sequence numbers (followers)
+    pub missed: AtomicU64,
+    /// Frames sent without a tick because none came in time
+    pub timeouts: AtomicU64,
+}
+
+/// Frame release ticks shared by several hosts (`frame_sync`)
+///
+/// The master ticks at a fixed rate and sends each tick over UDP; followers tick when
+/// a packet arrives. Output workers wait in `wait_next` with a finished frame, so the
+/// outputs of every host start writing within the network's jitter of each other.
+/// When ticks stop (master down), outputs send after `timeout_ms`, then stop waiting
+/// until ticks come back.
+pub struct FrameTick {
+    role: FrameSyncRole,
+    sequence: Mutex<u64>,
+    ticked: Condvar,
+    timeout: Duration,
+    /// Set when a wait timed out; outputs don't wait again until the next tick
+    stalled: AtomicBool,
+    /// Leave out the notice that ticks resumed (`--quiet`)
+    quiet: AtomicBool,
+    pub stats: FrameSyncStats,
+}
+
+impl FrameTick {
+    pub fn new(config: &FrameSyncConfig) -> Self {
+        FrameTick {
+            role: config.role,
+            sequence: Mutex::new(0),
+            ticked: Condvar::new(),
+            timeout: config.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT),
+            stalled: AtomicBool::new(false),
+            quiet: AtomicBool::new(false),
+            stats: FrameSyncStats::default(),
+        }
+    }
+
+    pub fn role(&self) -> FrameSyncRole {
+        self.role
+    }
+
+    pub fn set_quiet(&self, quiet: bool) {
+        self.quiet.store(quiet, Ordering::Relaxed);
+    }
+
+    /// Record tick `sequence` and release the waiting outputs
+    pub fn tick(&self, sequence: u64) {
+        let mut current = self.sequence.lock().unwrap();
+        // A restarted master counts from 1 again, which is a tick like any other
+        if sequence > *current + 1 && self.stats.ticks.load(Ordering::Relaxed) > 0 {
+            self.stats.missed.fetch_add(sequence - *current - 1, Ordering::Relaxed);
+        }
+        *current = sequence;
+        self.stats.ticks.fetch_add(1, Ordering::Relaxed);
+        self.ticked.notify_all();
+        drop(current);
+
+        if self.stalled.swap(false, Ordering::Relaxed) && !self.quiet.load(Ordering::Relaxed) {
+            println!("✓ Frame sync ticks resumed");
+        }
+    }
+
+    /// Wait for the next tick; false if none came within the timeout
+    pub fn wait_next(&self) -> bool {
+        if self.stalled.load(Ordering::Relaxed) {
+            self.stats.timeouts.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let mut sequence = self.sequence.lock().unwrap();
+        let start = *sequence;
+        while *sequence == start {
+            let now = Instant::now();
+            if now >= deadline {
+                self.stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                if !self.stalled.swap(true, Ordering::Relaxed) {
+                    eprintln!("Warning: No frame sync tick for {}ms; sending frames unsynchronized",
+                              self.timeout.as_millis());
+                }
+                return false;
+            }
+            sequence = self.ticked.wait_timeout(sequence, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+/// Start sending (master) or receiving (follower) ticks on a background thread
+pub fn spawn(config: &FrameSyncConfig, tick: Arc<FrameTick>, running: Arc<AtomicBool>, debug: bool) -> Result<()> {
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    match config.role {
+        FrameSyncRole::Master => {
+            let rate = config.rate_hz.unwrap_or(DEFAULT_RATE_HZ);
+            if !(rate > 0.0 && rate.is_finite()) {
+                anyhow::bail!("frame_sync.rate_hz must be greater than 0");
+            }
+            let default_targets = vec!["255.255.255.255".to_string()];
+            let targets = config.targets.as_ref().unwrap_or(&default_targets).iter()
+                .map(|host| net::resolve(host, port))
+                .collect::<Result<Vec<_>>>()
+                .context("Invalid frame_sync.targets")?;
+            let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to open frame sync socket")?;
+            socket.set_broadcast(true)?;
+
+            if debug {
+                println!("✓ Frame sync master: {} ticks/s to {}", rate,
+                         targets.iter().map(SocketAddr::to_string).collect::<Vec<_>>().join(", "));
+            }
+            let interval = Duration::from_secs_f64(1.0 / rate);
+            thread::spawn(move || send_ticks(socket, &targets, interval, &tick, &running));
+        }
+        FrameSyncRole::Follower => {
+            let addr = net::resolve(config.host.as_deref().unwrap_or("0.0.0.0"), port)?;
+            let socket = UdpSocket::bind(addr)
+                .context(format!("Failed to bind frame sync listener to {}", addr))?;
+            // Short timeout so the loop can observe the running flag
+            socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+            if debug {
+                println!("✓ Frame sync follower listening on {}", addr);
+            }
+            thread::spawn(move || receive_ticks(socket, &tick, &running));
+        }
+    }
+    Ok(())
+}
+
+fn send_ticks(socket: UdpSocket, targets: &[SocketAddr], interval: Duration, tick: &FrameTick, running: &AtomicBool) {
+    let mut next = Instant::now() + interval;
+    let mut sequence = 0u64;
+    let mut failing = vec![false; targets.len()];
+
+    while running.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        }
+        // Keep the schedule, but don't burst to catch up after a long stall
+        next = (next + interval).max(Instant::now());
+
+        sequence += 1;
+        let packet = encode(sequence);
+        for (target, failing) in targets.iter().zip(failing.iter_mut()) {
+            match socket.send_to(&packet, target) {
+                Ok(_) => *failing = false,
+                Err(e) if !*failing => {
+                    eprintln!("✗ Failed to send frame sync tick to {}: {}", target, e);
+                    *failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+        tick.tick(sequence);
+    }
+}
+
+fn receive_ticks(socket: UdpSocket, tick: &FrameTick, running: &AtomicBool) {
+    let mut buf = [0u8; 64];
+    while running.load(Ordering::Relaxed) {
+        match socket.recv_from(&mut buf) {
+            Ok((len, _)) => {
+                if let Some(sequence) = decode(&buf[..len]) {
+                    tick.tick(sequence);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => {
+                eprintln!("Error receiving frame sync tick: {}", e);
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_release_waiting_outputs() {
+        assert_eq!(decode(&encode(0x0102_0304_0506_0708)), Some(0x0102_0304_0506_0708));
+        assert_eq!(decode(b"OPCX\0\0\0\0\0\0\0\x01"), None);
+        assert_eq!(decode(&encode(1)[..8]), None);
+
+        let config: FrameSyncConfig = serde_json::from_value(serde_json::json!({
+            "role": "follower", "timeout_ms": 50,
+        })).unwrap();
+        let tick = Arc::new(FrameTick::new(&config));
+
+        let waiter = {
+            let tick = Arc::clone(&tick);
+            thread::spawn(move || tick.wait_next())
+        };
+        let mut sequence = 0;
+        while !waiter.is_finished() {
+            sequence += 1;
+            tick.tick(sequence);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(waiter.join().unwrap());
+
+        // Two ticks lost on the way
+        tick.tick(sequence + 3);
+        assert_eq!(tick.stats.missed.load(Ordering::Relaxed), 2);
+
+        // Without ticks the wait gives up, and later frames don't wait at all until ticks resume
+        assert!(!tick.wait_next());
+        let started = Instant::now();
+        assert!(!tick.wait_next());
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert_eq!(tick.stats.timeouts.load(Ordering::Relaxed), 2);
+        tick.tick(sequence + 4);
+        assert!(!tick.stalled.load(Ordering::Relaxed));
+    }
+}
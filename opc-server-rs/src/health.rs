@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, HealthConfig};
+use crate::net;
+use crate::opc_server::SharedState;
+
+/// Share of frames that may fail before an output is degraded (`error_budget`)
+const DEFAULT_ERROR_BUDGET: f64 = 0.01;
+
+/// Window errors and reconnects are counted over (`window_ms`)
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Counters are sampled at most this often, however often health is asked for
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the `health` subcommand waits for the server
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check the `health` section
+pub fn check(config: &HealthConfig) -> Result<()> {
+    if let Some(budget) = config.error_budget {
+        if !(0.0..=1.0).contains(&budget) {
+            anyhow::bail!("error_budget must be between 0.0 and 1.0");
+        }
+    }
+    if config.window_ms == Some(0) {
+        anyhow::bail!("window_ms must be greater than 0");
+    }
+    Ok(())
+}
+
+/// State of one output, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Healthy,
+    /// Writing, but failing more frames or reconnecting more often than the budget allows
+    Degraded,
+    /// Not writing: the port failed or has not opened yet
+    Disconnected,
+}
+
+impl Health {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::Degraded => "degraded",
+            Health::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// Health of one output, with what it was judged on
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputHealth {
+    pub id: String,
+    pub health: Health,
+    /// Failed frames in the window
+    pub failures: u64,
+    /// Share of the window's frames that failed
+    pub error_rate: f64,
+    /// Times the output was reattached in the window
+    pub reconnects: usize,
+    /// Why the output is not healthy
+    pub reason: Option<String>,
+}
+
+/// Health of the server: its worst output, and every output
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: Health,
+    pub outputs: Vec<OutputHealth>,
+}
+
+/// Frames written and failed, as read at one moment
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    frames: u64,
+    failures: u64,
+}
+
+#[derive(Default)]
+struct Track {
+    samples: VecDeque<Sample>,
+    reconnects: VecDeque<Instant>,
+}
+
+/// Judges each output's health from its counters over a sliding window
+///
+/// Counters are sampled whenever health is asked for (stats, control API). An output
+/// first seen with counters already running has them counted as if they fell in the
+/// window, so a failing output is not reported healthy on the first check. Reopened
+/// outputs start from fresh counters, and their samples start over too.
+pub struct HealthTracker {
+    error_budget: f64,
+    max_reconnects: usize,
+    window: Duration,
+    tracks: Mutex<HashMap<String, Track>>,
+}
+
+impl HealthTracker {
+    pub fn new(config: Option<&HealthConfig>) -> Self {
+        let config = config.cloned().unwrap_or_default();
+        HealthTracker {
+            error_budget: config.error_budget.unwrap_or(DEFAULT_ERROR_BUDGET),
+            max_reconnects: config.max_reconnects.unwrap_or(0) as usize,
+            window: config.window_ms.map(Duration::from_millis).unwrap_or(DEFAULT_WINDOW),
+            tracks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Note that output `id` was reattached after failing to open
+    pub fn record_reconnect(&self, id: &str) {
+        self.tracks.lock().unwrap().entry(id.to_string()).or_default().reconnects.push_back(Instant::now());
+    }
+
+    /// Judge an open output from its running counters
+    pub fn evaluate(&self, id: &str, connected: bool, frames: u64, failures: u64, now: Instant) -> OutputHealth {
+        let mut tracks = self.tracks.lock().unwrap();
+        let track = tracks.entry(id.to_string()).or_default();
+
+        // Counters going backwards mean the output was reopened
+        if track.samples.back().is_some_and(|last| frames < last.frames || failures < last.failures) {
+            track.samples.clear();
+        }
+        if track.samples.is_empty() {
+            track.samples.push_back(Sample { at: now, frames: 0, failures: 0 });
+        }
+        let current = Sample { at: now, frames, failures };
+        if track.samples.back().is_some_and(|last| now.duration_since(last.at) >= SAMPLE_INTERVAL) {
+            track.samples.push_back(current);
+        }
+        // Keep the newest sample from before the window as the baseline
+        let window_start = now.checked_sub(self.window).unwrap_or(now);
+        while track.samples.len() > 1 && track.samples[1].at <= window_start {
+            track.samples.pop_front();
+        }
+        while track.reconnects.front().is_some_and(|&at| at <= window_start) {
+            track.reconnects.pop_front();
+        }
+
+        let baseline = track.samples[0];
+        let failed = failures.saturating_sub(baseline.failures);
+        let attempted = frames.saturating_sub(baseline.frames) + failed;
+        let error_rate = if attempted == 0 { 0.0 } else { failed as f64 / attempted as f64 };
+        let reconnects = track.reconnects.len();
+        let window_secs = self.window.as_secs_f64();
+
+        let (health, reason) = if !connected {
+            (Health::Disconnected, Some("port stopped writing".to_string()))
+        } else if failed > 0 && error_rate > self.error_budget {
+            (Health::Degraded, Some(format!("{} failed frames in {}s ({:.1}%)", failed, window_secs, error_rate * 100.0)))
+        } else if reconnects > self.max_reconnects {
+            (Health::Degraded, Some(format!("reconnected {} time(s) in {}s", reconnects, window_secs)))
+        } else {
+            (Health::Healthy, None)
+        };
+        OutputHealth { id: id.to_string(), health, failures: failed, error_rate, reconnects, reason }
+    }
+
+    /// Health of every open and pending output
+    pub fn report(&self, state: &SharedState) -> HealthReport {
+        let now = Instant::now();
+        let mut outputs: Vec<OutputHealth> = state.outputs.read().unwrap().iter().map(|output| {
+            let output_state = output.state();
+            let failures = output_state.errors.load(Ordering::Relaxed) + output_state.frames_late.load(Ordering::Relaxed);
+            self.evaluate(output.config().id(), output.is_connected(), output.frames_sent(), failures, now)
+        }).collect();
+
+        let tracks = self.tracks.lock().unwrap();
+        for pending in state.pending.lock().unwrap().entries() {
+            let id = pending.group[0].id();
+            outputs.push(OutputHealth {
+                id: id.to_string(),
+                health: Health::Disconnected,
+                failures: 0,
+                error_rate: 0.0,
+                reconnects: tracks.get(id).map_or(0, |track| track.reconnects.len()),
+                reason: Some(format!("{} failed attempt(s) to open: {}", pending.attempts, pending.last_error)),
+            });
+        }
+
+        let status = outputs.iter().map(|output| output.health).max().unwrap_or(Health::Healthy);
+        HealthReport { status, outputs }
+    }
+}
+
+/// Ask the running server's control API for its health (`opc_server health`)
+pub fn query(config: &Config) -> Result<HealthReport> {
+    let Some(control) = &config.control else {
+        anyhow::bail!("health needs the control API (a `control` section in the config)");
+    };
+    // A server listening on every address is asked on the loopback one
+    let host = match control.host.as_str() {
+        "0.0.0.0" => "127.0.0.1",
+        "::" => "::1",
+        host => host,
+    };
+    let addr = net::resolve(host, control.port)?;
+    let mut stream = TcpStream::connect_timeout(&addr, QUERY_TIMEOUT)
+        .context(format!("Could not reach the control API at {}", addr))?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    write!(stream, "GET /health HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", addr)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)
+        .context(format!("No answer from the control API at {}", addr))?;
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body).unwrap_or_default();
+    let value: serde_json::Value = serde_json::from_str(body)
+        .context(format!("Unexpected answer from the control API at {}", addr))?;
+
+    let status = |value: &serde_json::Value| match value.as_str() {
+        Some("healthy") => Health::Healthy,
+        Some("degraded") => Health::Degraded,
+        _ => Health::Disconnected,
+    };
+    let outputs = value["outputs"].as_array().into_iter().flatten().map(|output| OutputHealth {
+        id: output["id"].as_str().unwrap_or_default().to_string(),
+        health: status(&output["health"]),
+        failures: output["failures"].as_u64().unwrap_or(0),
+        error_rate: output["error_rate"].as_f64().unwrap_or(0.0),
+        reconnects: output["reconnects"].as_u64().unwrap_or(0) as usize,
+        reason: output["reason"].as_str().map(str::to_string),
+    }).collect();
+    Ok(HealthReport { status: status(&value["status"]), outputs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_from_errors_and_reconnects() {
+        let tracker = HealthTracker::new(Some(&HealthConfig {
+            error_budget: Some(0.1),
+            max_reconnects: Some(1),
+            window_ms: Some(10_000),
+        }));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // 5 failures in 100 frames is within a 10% budget; 20 more in the next 100 are not
+        assert_eq!(tracker.evaluate("desk", true, 95, 5, at(0)).health, Health::Healthy);
+        let health = tracker.evaluate("desk", true, 175, 25, at(5));
+        assert_eq!((health.health, health.failures), (Health::Degraded, 25));
+        // Once the failures leave the window, the output is healthy again
+        assert_eq!(tracker.evaluate("desk", true, 1175, 25, at(20)).health, Health::Healthy);
+
+        // A second reconnect in the window degrades; a port that stopped writing is disconnected
+        tracker.record_reconnect("shelf");
+        assert_eq!(tracker.evaluate("shelf", true, 0, 0, Instant::now()).health, Health::Healthy);
+        tracker.record_reconnect("shelf");
+        assert_eq!(tracker.evaluate("shelf", true, 0, 0, Instant::now()).reconnects, 2);
+        assert_eq!(tracker.evaluate("shelf", true, 0, 0, Instant::now()).health, Health::Degraded);
+        assert_eq!(tracker.evaluate("shelf", false, 0, 0, Instant::now()).health, Health::Disconnected);
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::opc_server::SharedState;
+use crate::output::Output;
+use crate::pending::PendingOutput;
+use crate::serial::{self, Device};
+
+/// Time between scans of the serial ports
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happened to a configured port's device between two scans
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Arrived,
+    Removed,
+    Unchanged,
+}
+
+fn change(port: &str, before: &[Device], after: &[Device]) -> Change {
+    let was_present = serial::is_present(port, before) == Some(true);
+    let is_present = serial::is_present(port, after) == Some(true);
+    match (was_present, is_present) {
+        (false, true) => Change::Arrived,
+        (true, false) => Change::Removed,
+        _ => Change::Unchanged,
+    }
+}
+
+/// Watch the serial ports and reconnect outputs as their devices come and go
+///
+/// The port list is scanned every second (udev on Linux, SetupAPI on Windows, IOKit on
+/// macOS). An output whose device disappears, or whose worker stopped on a write
+/// error, is closed and moved to the pending outputs; a pending output is retried as
+/// soon as its device shows up instead of waiting for the next retry.
+pub fn spawn(state: SharedState, debug: bool) {
+    thread::spawn(move || {
+        let mut before = serial::devices().unwrap_or_default();
+
+        while state.running.load(Ordering::Relaxed) {
+            thread::sleep(SCAN_INTERVAL);
+            let after = match serial::devices() {
+                Ok(devices) => devices,
+                Err(e) => {
+                    if state.ddebug {
+                        eprintln!("[DEBUG hotplug] Failed to list serial ports: {}", e);
+                    }
+                    continue;
+                }
+            };
+
+            let closed = scan(&state, &before, &after, debug);
+            // Stopping a worker can block on the port, so it happens outside the locks
+            drop(closed);
+            before = after;
+        }
+    });
+}
+
+/// Move unplugged or failed outputs to pending and hurry the ones whose device arrived
+fn scan(state: &SharedState, before: &[Device], after: &[Device], debug: bool) -> Vec<Output> {
+    let now = Instant::now();
+    let mut outputs = state.outputs.write().unwrap();
+    let mut pending = state.pending.lock().unwrap();
+    let mut closed = Vec::new();
+
+    let mut i = 0;
+    while i < outputs.len() {
+        let output = &outputs[i];
+        let label = output.config().label();
+        let reason = if change(&output.config().port, before, after) == Change::Removed {
+            eprintln!("✗ Output {} was unplugged", label);
+            "device removed"
+        } else if !output.is_connected() {
+            "write failed"
+        } else {
+            i += 1;
+            continue;
+        };
+
+        let output = outputs.remove(i);
+        output.state().connected.store(false, Ordering::Relaxed);
+        if debug {
+            println!("Waiting for {} to come back", label);
+        }
+        pending.push(PendingOutput::new(output.entries().to_vec(), reason.to_string(), now));
+        closed.push(output);
+    }
+
+    let arrived: Vec<String> = pending.entries().iter()
+        .map(|entry| entry.port().to_string())
+        .filter(|port| change(port, before, after) == Change::Arrived)
+        .collect();
+    for port in arrived {
+        if debug {
+            println!("✓ Device for {} plugged in", port);
+        }
+        pending.retry_now(&port, now);
+    }
+
+    closed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change() {
+        let acm = Device { name: "/dev/ttyACM0".to_string(), usb: Some((0x2e8a, 0x000a, None)) };
+        let none: &[Device] = &[];
+        let plugged = std::slice::from_ref(&acm);
+
+        assert_eq!(change("/dev/ttyACM0", none, plugged), Change::Arrived);
+        assert_eq!(change("usb:2e8a:000a", plugged, none), Change::Removed);
+        assert_eq!(change("/dev/ttyACM0", plugged, plugged), Change::Unchanged);
+        // Ports that are never enumerated are left to write errors
+        assert_eq!(change("/dev/pts/4", none, none), Change::Unchanged);
+    }
+}
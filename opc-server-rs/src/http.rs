@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// Largest request body accepted by the embedded HTTP server
+const MAX_BODY_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Minimal HTTP/1.1 request (enough for the control API)
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Query string, without the `?`
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Get a header value by case-insensitive name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Path split into percent-decoded segments, ignoring empty ones
+    pub fn segments(&self) -> Vec<String> {
+        self.path.split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect()
+    }
+
+    /// Percent-decoded value of a query string parameter
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.split('&')
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .find(|(key, _)| percent_decode(key) == name)
+            .map(|(_, value)| percent_decode(value))
+    }
+}
+
+/// Read a single HTTP request from the stream
+pub fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).context("Failed to read request line")?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().context("Missing method")?.to_string();
+    let target = parts.next().context("Missing request target")?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+
+    let content_length = headers.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    if content_length > MAX_BODY_SIZE {
+        anyhow::bail!("Request body too large ({} bytes)", content_length);
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).context("Failed to read request body")?;
+
+    Ok(Request { method, path, query, headers, body })
+}
+
+/// Write a complete HTTP response and close the exchange
+pub fn write_response(stream: &mut TcpStream, status: u16, content_type: &str, body: &[u8]) -> Result<()> {
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, reason_phrase(status), content_type, body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Write a JSON response
+pub fn write_json(stream: &mut TcpStream, status: u16, value: &serde_json::Value) -> Result<()> {
+    let body = serde_json::to_vec(value)?;
+    write_response(stream, status, "application/json", &body)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// Decode %XX escapes (and '+' as space) in a URL component
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(b) => {
+                        out.push(b);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(b'%');
+                        i += 1;
+                    }
+                }
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Send `raw` over a loopback connection and parse it on the other end
+    fn parse(raw: &[u8]) -> Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(raw).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        read_request(&mut stream)
+    }
+
+    #[test]
+    fn test_read_request() {
+        let request = parse(b"POST /outputs/desk%20lamp/inspect?frames=2&name=a+b HTTP/1.1\r\n\
+                              Host: localhost\r\nContent-Length: 11\r\nX-Token:  secret \r\n\r\n{\"diff\":1}\nextra").unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/outputs/desk%20lamp/inspect");
+        assert_eq!(request.segments(), ["outputs", "desk lamp", "inspect"]);
+        assert_eq!(request.query_param("frames").as_deref(), Some("2"));
+        assert_eq!(request.query_param("name").as_deref(), Some("a b"));
+        assert_eq!(request.query_param("missing"), None);
+        assert_eq!(request.header("x-token"), Some("secret"));
+        assert_eq!(request.header("content-type"), None);
+        // Only Content-Length bytes of the body are read
+        assert_eq!(request.body, b"{\"diff\":1}\n");
+
+        let request = parse(b"GET //status/ HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!((request.segments(), request.query.as_str(), request.body.len()), (vec!["status".to_string()], "", 0));
+    }
+
+    #[test]
+    fn test_read_request_rejects_bad_requests() {
+        assert!(parse(b"\r\n\r\n").is_err());
+        assert!(parse(b"GET\r\n\r\n").is_err());
+        let too_large = format!("PUT /config HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_SIZE + 1);
+        let Err(e) = parse(too_large.as_bytes()) else { panic!("accepted an oversized body") };
+        assert!(format!("{:#}", e).contains("too large"));
+        // The connection closes before the promised body arrives
+        assert!(parse(b"PUT /config HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb+c"), "a/b c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%C3%A9"), "é");
+    }
+}
@@ -0,0 +1,529 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::arbiter;
+use crate::config::HyperionConfig;
+use crate::net;
+use crate::opc_server::SharedState;
+
+/// Largest request accepted; a full HD RGB image is about 6MB
+const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+/// Priority used by clients that send data without registering first
+const DEFAULT_PRIORITY: i32 = 100;
+
+/// Hyperion.ng flatbuffers server (`hyperionnet` schema, as used by Hyperion.ng
+/// forwarders, hyperion-remote and screen grabbers)
+///
+/// Requests and replies are flatbuffers prefixed with a 4-byte big-endian length.
+/// Image pixels are taken in row-major order as pixel data for `opc_channel` (send
+/// an image `led_count` wide and 1 high to address LEDs directly); a color command
+/// fills every LED on the channel.
+pub struct HyperionServer {
+    config: HyperionConfig,
+    host: String,
+    state: SharedState,
+    priorities: Arc<Mutex<Priorities>>,
+    debug: bool,
+}
+
+impl HyperionServer {
+    pub fn new(config: HyperionConfig, default_host: &str, state: SharedState, debug: bool) -> Self {
+        let host = config.host.clone().unwrap_or_else(|| default_host.to_string());
+        HyperionServer {
+            config,
+            host,
+            state,
+            priorities: Arc::new(Mutex::new(Priorities::default())),
+            debug,
+        }
+    }
+
+    /// Bind the listener and serve clients on background threads
+    pub fn spawn(self) -> Result<()> {
+        let addr = net::resolve(&self.host, self.config.port)?;
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to bind Hyperion server to {}", addr))?;
+
+        // Nonblocking so the accept loop can observe the running flag
+        listener.set_nonblocking(true)?;
+
+        if self.debug {
+            println!("✓ Hyperion flatbuffers server listening on {}", addr);
+        }
+
+        let server = Arc::new(self);
+        thread::spawn(move || {
+            while server.state.running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer_addr)) => {
+                        let server = Arc::clone(&server);
+                        thread::spawn(move || {
+                            if server.debug {
+                                println!("✓ Hyperion client connected from {}", peer_addr);
+                            }
+                            if let Err(e) = server.handle_connection(stream) {
+                                eprintln!("Hyperion client error: {}", e);
+                            }
+                            if server.debug {
+                                println!("Hyperion client {} disconnected", peer_addr);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        eprintln!("Error accepting Hyperion connection: {}", e);
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+        // Accepted sockets inherit nonblocking mode on some platforms
+        stream.set_nonblocking(false)?;
+        // Short timeout so the loop can observe the running flag and expire sources
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+        let mut priority = None;
+        let result = self.serve_requests(&mut stream, &mut priority);
+
+        // Like Hyperion, a client's source goes away with its connection
+        if let Some(priority) = priority {
+            self.clear(Some(priority));
+        }
+        result
+    }
+
+    fn serve_requests(&self, stream: &mut TcpStream, priority: &mut Option<i32>) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut read_buf = [0u8; 65536];
+
+        while self.state.running.load(Ordering::Relaxed) {
+            // Handle every complete request in the buffer
+            while buffer.len() >= 4 {
+                let size = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+                if size > MAX_REQUEST_SIZE {
+                    anyhow::bail!("Request of {} bytes exceeds the {} byte limit", size, MAX_REQUEST_SIZE);
+                }
+                if buffer.len() < 4 + size {
+                    break;
+                }
+
+                let reply = match parse_request(&buffer[4..4 + size]) {
+                    Ok(command) => self.handle_command(command, priority),
+                    Err(e) => build_reply(Some(&e.to_string()), None),
+                };
+                buffer.drain(..4 + size);
+
+                stream.write_all(&(reply.len() as u32).to_be_bytes())?;
+                stream.write_all(&reply)?;
+            }
+
+            match stream.read(&mut read_buf) {
+                Ok(0) => break,
+                Ok(n) => buffer.extend_from_slice(&read_buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    self.expire();
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply one request, producing its reply
+    fn handle_command(&self, command: Command, priority: &mut Option<i32>) -> Vec<u8> {
+        match command {
+            Command::Register { priority: registered, origin } => {
+                if self.debug {
+                    println!("✓ Hyperion source '{}' registered at priority {}", origin, registered);
+                }
+                // Re-registering moves the client's source to the new priority
+                if let Some(previous) = priority.replace(registered).filter(|&p| p != registered) {
+                    self.clear(Some(previous));
+                }
+                build_reply(None, Some(registered))
+            }
+            Command::Color { rgb, duration } => {
+                let frame = rgb.repeat(self.state.channel_led_count(self.config.opc_channel));
+                self.show(priority.unwrap_or(DEFAULT_PRIORITY), frame, duration);
+                build_reply(None, None)
+            }
+            Command::Image { mut data, width, height, duration } => {
+                data.truncate(width.max(0) as usize * height.max(0) as usize * 3);
+                self.show(priority.unwrap_or(DEFAULT_PRIORITY), data, duration);
+                build_reply(None, None)
+            }
+            Command::Clear { priority: cleared } => {
+                // Hyperion uses -1 to clear every priority
+                self.clear((cleared >= 0).then_some(cleared));
+                build_reply(None, None)
+            }
+        }
+    }
+
+    fn show(&self, priority: i32, frame: Vec<u8>, duration_ms: i32) {
+        let expires = (duration_ms > 0).then(|| Instant::now() + Duration::from_millis(duration_ms as u64));
+        let update = self.priorities.lock().unwrap().set(priority, frame, expires);
+        self.forward(update);
+    }
+
+    fn clear(&self, priority: Option<i32>) {
+        let update = self.priorities.lock().unwrap().clear(priority);
+        self.forward(update);
+    }
+
+    fn expire(&self) {
+        let update = self.priorities.lock().unwrap().expire(Instant::now());
+        self.forward(update);
+    }
+
+    fn forward(&self, frame: Option<Vec<u8>>) {
+        if let Some(frame) = frame {
+            if self.state.admit_message(None) {
+                self.state.handle_opc_message(arbiter::Source::Hyperion, self.config.opc_channel, 0, &frame);
+            }
+        }
+    }
+}
+
+/// A source's latest frame and when it stops being shown
+struct Source {
+    frame: Vec<u8>,
+    expires: Option<Instant>,
+}
+
+/// Competing Hyperion sources; the lowest priority number is shown
+#[derive(Default)]
+struct Priorities {
+    sources: HashMap<i32, Source>,
+    /// Length of the last frame shown, for blanking once every source is gone
+    last_len: usize,
+}
+
+impl Priorities {
+    fn winner(&self) -> Option<i32> {
+        self.sources.keys().min().copied()
+    }
+
+    /// Store a source's frame; returns the frame to show if that source is visible
+    fn set(&mut self, priority: i32, frame: Vec<u8>, expires: Option<Instant>) -> Option<Vec<u8>> {
+        self.sources.insert(priority, Source { frame, expires });
+        if self.winner() != Some(priority) {
+            return None;
+        }
+        let frame = self.sources[&priority].frame.clone();
+        self.last_len = frame.len();
+        Some(frame)
+    }
+
+    /// Remove one source (or all); returns the frame to show if the visible source changed
+    fn clear(&mut self, priority: Option<i32>) -> Option<Vec<u8>> {
+        let previous = self.winner();
+        match priority {
+            Some(priority) => {
+                self.sources.remove(&priority);
+            }
+            None => self.sources.clear(),
+        }
+        self.after_removal(previous)
+    }
+
+    /// Drop sources whose duration has passed
+    fn expire(&mut self, now: Instant) -> Option<Vec<u8>> {
+        let previous = self.winner();
+        self.sources.retain(|_, s| s.expires.is_none_or(|t| t > now));
+        self.after_removal(previous)
+    }
+
+    fn after_removal(&mut self, previous: Option<i32>) -> Option<Vec<u8>> {
+        let winner = self.winner();
+        if winner == previous {
+            return None;
+        }
+        match winner {
+            // Fall back to the next source's last frame
+            Some(priority) => {
+                let frame = self.sources[&priority].frame.clone();
+                self.last_len = frame.len();
+                Some(frame)
+            }
+            // Nothing left to show: go dark
+            None => Some(vec![0u8; self.last_len]),
+        }
+    }
+}
+
+/// One Hyperion flatbuffers request
+#[derive(Debug, PartialEq)]
+enum Command {
+    Color { rgb: [u8; 3], duration: i32 },
+    Image { data: Vec<u8>, width: i32, height: i32, duration: i32 },
+    Clear { priority: i32 },
+    Register { priority: i32, origin: String },
+}
+
+/// `Command` union type ids in `hyperion_request.fbs`
+const COMMAND_COLOR: u8 = 1;
+const COMMAND_IMAGE: u8 = 2;
+const COMMAND_CLEAR: u8 = 3;
+const COMMAND_REGISTER: u8 = 4;
+
+/// `ImageType` union type id of `RawImage`
+const IMAGE_RAW: u8 = 1;
+
+/// Decode a `hyperionnet.Request`
+fn parse_request(buf: &[u8]) -> Result<Command> {
+    let request = Table::root(buf).context("Malformed request")?;
+
+    // Union fields take two slots: the type id, then the table
+    let command_type = request.u8(0).unwrap_or(0);
+    let command = request.table(1).context("Request has no command")?;
+
+    match command_type {
+        COMMAND_COLOR => {
+            let rgb = command.i32(0).unwrap_or(-1);
+            Ok(Command::Color {
+                rgb: [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+                duration: command.i32(1).unwrap_or(-1),
+            })
+        }
+        COMMAND_IMAGE => {
+            if command.u8(0) != Some(IMAGE_RAW) {
+                anyhow::bail!("Only raw RGB images are supported");
+            }
+            let image = command.table(1).context("Image has no data")?;
+            Ok(Command::Image {
+                data: image.bytes(0).unwrap_or_default().to_vec(),
+                width: image.i32(1).unwrap_or(-1),
+                height: image.i32(2).unwrap_or(-1),
+                duration: command.i32(2).unwrap_or(-1),
+            })
+        }
+        COMMAND_CLEAR => Ok(Command::Clear { priority: command.i32(0).unwrap_or(-1) }),
+        COMMAND_REGISTER => Ok(Command::Register {
+            priority: command.i32(0).unwrap_or(DEFAULT_PRIORITY),
+            origin: String::from_utf8_lossy(command.bytes(1).unwrap_or_default()).into_owned(),
+        }),
+        other => anyhow::bail!("Unsupported command type {}", other),
+    }
+}
+
+/// Build a `hyperionnet.Reply` (`error: string`, `video: int`, `registered: int`)
+fn build_reply(error: Option<&str>, registered: Option<i32>) -> Vec<u8> {
+    let mut fields = Vec::new();
+    if let Some(error) = error {
+        fields.push((0, Field::Bytes(error.as_bytes())));
+    }
+    if let Some(registered) = registered {
+        fields.push((2, Field::I32(registered)));
+    }
+    let mut buf = vec![0u8; 4];
+    let root = encode_table(&mut buf, &fields);
+    buf[..4].copy_from_slice(&(root as u32).to_le_bytes());
+    buf
+}
+
+/// Read-only view of a flatbuffers table
+#[derive(Clone, Copy)]
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+impl<'a> Table<'a> {
+    fn root(buf: &'a [u8]) -> Option<Self> {
+        let pos = read_u32(buf, 0)? as usize;
+        read_u32(buf, pos)?;
+        Some(Table { buf, pos })
+    }
+
+    /// Absolute position of field `index`, if present
+    fn field(&self, index: usize) -> Option<usize> {
+        let soffset = read_u32(self.buf, self.pos)? as i32 as i64;
+        let vtable = usize::try_from(self.pos as i64 - soffset).ok()?;
+        let vtable_size = read_u16(self.buf, vtable)? as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_size {
+            return None;
+        }
+        match read_u16(self.buf, vtable + entry)? {
+            0 => None,
+            offset => Some(self.pos + offset as usize),
+        }
+    }
+
+    fn u8(&self, index: usize) -> Option<u8> {
+        self.buf.get(self.field(index)?).copied()
+    }
+
+    fn i32(&self, index: usize) -> Option<i32> {
+        Some(read_u32(self.buf, self.field(index)?)? as i32)
+    }
+
+    /// Follow an offset field to the object it references
+    fn target(&self, index: usize) -> Option<usize> {
+        let field = self.field(index)?;
+        field.checked_add(read_u32(self.buf, field)? as usize)
+    }
+
+    fn table(&self, index: usize) -> Option<Table<'a>> {
+        let pos = self.target(index)?;
+        read_u32(self.buf, pos)?;
+        Some(Table { buf: self.buf, pos })
+    }
+
+    /// A `[ubyte]` vector or a string
+    fn bytes(&self, index: usize) -> Option<&'a [u8]> {
+        let pos = self.target(index)?;
+        let len = read_u32(self.buf, pos)? as usize;
+        self.buf.get(pos + 4..pos + 4 + len)
+    }
+}
+
+/// Field value for `encode_table`
+enum Field<'a> {
+    // Replies only need strings and ints; these encode requests in tests
+    #[allow(dead_code)]
+    U8(u8),
+    I32(i32),
+    /// Written as a `[ubyte]` vector / string
+    Bytes(&'a [u8]),
+    #[allow(dead_code)]
+    Table(Vec<(usize, Field<'a>)>),
+}
+
+fn pad_to_4(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+/// Append a table (preceded by its vtable) and the objects it references, returning its position
+///
+/// Children are written after their parent, so every offset points forward as
+/// flatbuffers requires.
+fn encode_table(buf: &mut Vec<u8>, fields: &[(usize, Field)]) -> usize {
+    let slots = fields.iter().map(|(index, _)| index + 1).max().unwrap_or(0);
+
+    // Every field gets a 4-byte slot after the vtable offset
+    let mut entries = vec![0u16; slots];
+    for (n, (index, _)) in fields.iter().enumerate() {
+        entries[*index] = (4 + 4 * n) as u16;
+    }
+
+    pad_to_4(buf);
+    let vtable = buf.len();
+    buf.extend_from_slice(&((4 + 2 * slots) as u16).to_le_bytes());
+    buf.extend_from_slice(&((4 + 4 * fields.len()) as u16).to_le_bytes());
+    for entry in entries {
+        buf.extend_from_slice(&entry.to_le_bytes());
+    }
+
+    pad_to_4(buf);
+    let table = buf.len();
+    buf.extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+    for (_, value) in fields {
+        let slot = match value {
+            Field::U8(v) => *v as u32,
+            Field::I32(v) => *v as u32,
+            Field::Bytes(_) | Field::Table(_) => 0, // patched below
+        };
+        buf.extend_from_slice(&slot.to_le_bytes());
+    }
+
+    for (n, (_, value)) in fields.iter().enumerate() {
+        let slot = table + 4 + 4 * n;
+        let target = match value {
+            Field::Bytes(bytes) => {
+                pad_to_4(buf);
+                let pos = buf.len();
+                buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+                buf.push(0); // strings are NUL-terminated
+                pos
+            }
+            Field::Table(children) => encode_table(buf, children),
+            _ => continue,
+        };
+        buf[slot..slot + 4].copy_from_slice(&((target - slot) as u32).to_le_bytes());
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(command_type: u8, command: Vec<(usize, Field)>) -> Vec<u8> {
+        let mut buf = vec![0u8; 4];
+        let root = encode_table(&mut buf, &[(0, Field::U8(command_type)), (1, Field::Table(command))]);
+        buf[..4].copy_from_slice(&(root as u32).to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let register = request(COMMAND_REGISTER, vec![(0, Field::I32(50)), (1, Field::Bytes(b"grabber"))]);
+        assert_eq!(parse_request(&register).unwrap(),
+                   Command::Register { priority: 50, origin: "grabber".into() });
+
+        let color = request(COMMAND_COLOR, vec![(0, Field::I32(0x102030)), (1, Field::I32(500))]);
+        assert_eq!(parse_request(&color).unwrap(), Command::Color { rgb: [0x10, 0x20, 0x30], duration: 500 });
+
+        let raw = vec![(0, Field::Bytes(&[1, 2, 3, 4, 5, 6])), (1, Field::I32(2)), (2, Field::I32(1))];
+        let image = request(COMMAND_IMAGE, vec![(0, Field::U8(IMAGE_RAW)), (1, Field::Table(raw))]);
+        assert_eq!(parse_request(&image).unwrap(),
+                   Command::Image { data: vec![1, 2, 3, 4, 5, 6], width: 2, height: 1, duration: -1 });
+
+        assert!(parse_request(&[0xff; 3]).is_err());
+    }
+
+    #[test]
+    fn test_reply_round_trip() {
+        let reply = build_reply(Some("oops"), Some(7));
+        let table = Table::root(&reply).unwrap();
+        assert_eq!(table.bytes(0), Some(&b"oops"[..]));
+        assert_eq!(table.i32(1), None);
+        assert_eq!(table.i32(2), Some(7));
+    }
+
+    #[test]
+    fn test_priorities_lowest_wins() {
+        let mut priorities = Priorities::default();
+        assert_eq!(priorities.set(100, vec![1], None), Some(vec![1]));
+        assert_eq!(priorities.set(50, vec![2], None), Some(vec![2]));
+        // Hidden behind priority 50
+        assert_eq!(priorities.set(100, vec![3], None), None);
+        // Clearing the winner falls back to the next source
+        assert_eq!(priorities.clear(Some(50)), Some(vec![3]));
+
+        let now = Instant::now();
+        assert_eq!(priorities.set(10, vec![4], Some(now)), Some(vec![4]));
+        assert_eq!(priorities.expire(now), Some(vec![3]));
+        assert_eq!(priorities.clear(None), Some(vec![0]));
+    }
+}
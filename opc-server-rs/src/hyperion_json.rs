@@ -0,0 +1,336 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::net;
+use crate::opc_server::SharedState;
+use crate::schedule::{self, ScheduleMode};
+
+/// Longest request line accepted; status and adjustment requests are tiny
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Minimal Hyperion JSON-RPC server (`hyperion.json_port`), for dashboards and remote apps
+///
+/// Requests and replies are JSON objects, one per line, like Hyperion's JSON server on
+/// port 19444; the same commands are served over HTTP at the control server's
+/// `/json-rpc`. Only status, brightness and on/off are supported: `serverinfo`,
+/// `sysinfo`, `adjustment` (brightness), `componentstate` (`ALL` or `LEDDEVICE`
+/// blanks the outputs) and `clear`/`clearall`. Other commands get an error reply.
+pub struct HyperionJsonServer {
+    host: String,
+    port: u16,
+    state: SharedState,
+    debug: bool,
+}
+
+impl HyperionJsonServer {
+    pub fn new(host: &str, port: u16, state: SharedState, debug: bool) -> Self {
+        HyperionJsonServer { host: host.to_string(), port, state, debug }
+    }
+
+    /// Bind the listener and serve clients on background threads
+    pub fn spawn(self) -> Result<()> {
+        let addr = net::resolve(&self.host, self.port)?;
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to bind Hyperion JSON server to {}", addr))?;
+
+        // Nonblocking so the accept loop can observe the running flag
+        listener.set_nonblocking(true)?;
+
+        if self.debug {
+            println!("✓ Hyperion JSON server listening on {}", addr);
+        }
+
+        thread::spawn(move || {
+            while self.state.running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer_addr)) => {
+                        let state = self.state.clone();
+                        let debug = self.debug;
+                        thread::spawn(move || {
+                            if debug {
+                                println!("✓ Hyperion JSON client connected from {}", peer_addr);
+                            }
+                            if let Err(e) = handle_connection(stream, &state, debug) {
+                                eprintln!("Hyperion JSON client error: {}", e);
+                            }
+                            if debug {
+                                println!("Hyperion JSON client {} disconnected", peer_addr);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        eprintln!("Error accepting Hyperion JSON connection: {}", e);
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &SharedState, debug: bool) -> Result<()> {
+    // Accepted sockets inherit nonblocking mode on some platforms
+    stream.set_nonblocking(false)?;
+    // Short timeout so the loop can observe the running flag
+    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+
+    while state.running.load(Ordering::Relaxed) {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) if line.ends_with(b"\n") => {}
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                if line.len() > MAX_REQUEST_SIZE {
+                    anyhow::bail!("Request exceeds the {} byte limit", MAX_REQUEST_SIZE);
+                }
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+
+        let request = std::mem::take(&mut line);
+        if request.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let reply = match serde_json::from_slice::<Value>(&request) {
+            Ok(request) => handle_request(state, &request, debug),
+            Err(e) => json!({"success": false, "error": format!("Invalid JSON: {}", e)}),
+        };
+        writer.write_all(format!("{}\n", reply).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Answer one Hyperion JSON-RPC request
+pub fn handle_request(state: &SharedState, request: &Value, debug: bool) -> Value {
+    let command = request.get("command").and_then(Value::as_str).unwrap_or_default();
+    let result = match command {
+        "serverinfo" => Ok(Some(server_info(state))),
+        "sysinfo" => Ok(Some(sys_info())),
+        "adjustment" => set_brightness(state, request, debug).map(|_| None),
+        "componentstate" => set_component(state, request, debug).map(|_| None),
+        // Nothing to clear: this bridge only shows what its inputs send
+        "clear" | "clearall" => Ok(None),
+        "" => Err("Missing command".to_string()),
+        _ => Err(format!("Command '{}' is not supported by this bridge", command)),
+    };
+
+    let mut reply = json!({"command": command});
+    if let Some(tan) = request.get("tan") {
+        reply["tan"] = tan.clone();
+    }
+    match result {
+        Ok(info) => {
+            reply["success"] = json!(true);
+            if let Some(info) = info {
+                reply["info"] = info;
+            }
+        }
+        Err(error) => {
+            reply["success"] = json!(false);
+            reply["error"] = json!(error);
+        }
+    }
+    reply
+}
+
+/// Brightness in percent, as Hyperion reports it: the brightest output
+fn brightness_percent(state: &SharedState) -> u32 {
+    let outputs = state.outputs.read().unwrap();
+    let brightness = outputs.iter().map(|o| o.brightness()).fold(0.0, f32::max);
+    (brightness * 100.0).round() as u32
+}
+
+/// On unless every output is blanked
+fn leds_on(state: &SharedState) -> bool {
+    let outputs = state.outputs.read().unwrap();
+    outputs.is_empty() || outputs.iter().any(|o| !o.is_blanked())
+}
+
+fn server_info(state: &SharedState) -> Value {
+    let on = leds_on(state);
+    json!({
+        "components": [
+            {"name": "ALL", "enabled": on},
+            {"name": "LEDDEVICE", "enabled": on},
+        ],
+        "adjustment": [{"id": "default", "brightness": brightness_percent(state)}],
+        "priorities": [],
+        "priorities_autoselect": true,
+        "instance": [{"instance": 0, "friendly_name": "OPC serial bridge", "running": true}],
+        "ledDevices": {"active": "opc_server"},
+    })
+}
+
+fn sys_info() -> Value {
+    json!({
+        "hyperion": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "build": concat!("opc_server ", env!("CARGO_PKG_VERSION")),
+        },
+        "system": {
+            "productType": std::env::consts::OS,
+            "architecture": std::env::consts::ARCH,
+        },
+    })
+}
+
+/// `{"adjustment": {"brightness": 0-100}}` sets every output
+fn set_brightness(state: &SharedState, request: &Value, debug: bool) -> Result<(), String> {
+    let brightness = request.pointer("/adjustment/brightness")
+        .and_then(Value::as_f64)
+        .ok_or("Expected adjustment.brightness")?;
+    let brightness = (brightness / 100.0).clamp(0.0, 1.0) as f32;
+    for output in state.outputs.read().unwrap().iter() {
+        output.set_brightness(brightness);
+        state.record_adjustment(output.config().id(), Some(brightness), None);
+    }
+    if debug {
+        println!("✓ Hyperion JSON: brightness {:.2}", brightness);
+    }
+    Ok(())
+}
+
+/// `{"componentstate": {"component": "LEDDEVICE", "state": false}}` blanks every output
+///
+/// With a `schedule`, this is the control API's schedule override (`on` or `off`), so
+/// the scheduler doesn't undo it at the next window.
+fn set_component(state: &SharedState, request: &Value, debug: bool) -> Result<(), String> {
+    let component = request.pointer("/componentstate/component").and_then(Value::as_str);
+    let on = request.pointer("/componentstate/state")
+        .and_then(Value::as_bool)
+        .ok_or("Expected componentstate.state")?;
+    match component {
+        Some("ALL") | Some("LEDDEVICE") => {}
+        // Grabbers, smoothing and the like don't exist here; accept them so apps don't complain
+        Some(_) => return Ok(()),
+        None => return Err("Expected componentstate.component".to_string()),
+    }
+    match &state.schedule {
+        Some(schedule_state) => {
+            schedule_state.set_mode(if on { ScheduleMode::On } else { ScheduleMode::Off });
+            schedule::update(state, schedule_state, debug);
+        }
+        None => {
+            for output in state.outputs.read().unwrap().iter() {
+                output.set_blanked(!on);
+            }
+        }
+    }
+    if debug {
+        println!("✓ Hyperion JSON: outputs {}", if on { "on" } else { "off" });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, OutputConfig};
+    use crate::opc_server::OpcServer;
+
+    fn server(port: &str, schedule: Option<Value>) -> OpcServer {
+        let mut config = json!({
+            "opc": {"host": "127.0.0.1", "port": 0, "enabled": false},
+            "persist_state": false,
+            "outputs": [OutputConfig { hardware_type: Some("mock".to_string()), ..OutputConfig::test_default(port) }],
+        });
+        if let Some(schedule) = schedule {
+            config["schedule"] = schedule;
+        }
+        let config: Config = serde_json::from_value(config).unwrap();
+        OpcServer::new(config, String::new(), false, false).unwrap()
+    }
+
+    fn request(state: &SharedState, request: Value) -> Value {
+        handle_request(state, &request, false)
+    }
+
+    #[test]
+    fn test_handle_request_replies() {
+        let server = server("mock-hyperion-json-requests", None);
+        let state = server.state();
+
+        let reply = request(state, json!({"command": "serverinfo", "tan": 7}));
+        assert_eq!(reply["success"], true);
+        assert_eq!(reply["tan"], 7);
+        assert_eq!(reply["info"]["components"][1], json!({"name": "LEDDEVICE", "enabled": true}));
+        assert_eq!(reply["info"]["adjustment"][0]["brightness"], 100);
+
+        assert_eq!(request(state, json!({"command": "clear"}))["success"], true);
+        let reply = request(state, json!({"command": "effect"}));
+        assert_eq!(reply["success"], false);
+        assert_eq!(reply["error"], "Command 'effect' is not supported by this bridge");
+        assert_eq!(request(state, json!({"tan": 1}))["error"], "Missing command");
+    }
+
+    #[test]
+    fn test_set_brightness() {
+        let server = server("mock-hyperion-json-brightness", None);
+        let state = server.state();
+
+        assert_eq!(request(state, json!({"command": "adjustment", "adjustment": {"brightness": 50}}))["success"], true);
+        assert_eq!(state.outputs.read().unwrap()[0].brightness(), 128.0 / 255.0);
+        assert_eq!(request(state, json!({"command": "serverinfo"}))["info"]["adjustment"][0]["brightness"], 50);
+
+        let reply = request(state, json!({"command": "adjustment", "adjustment": {}}));
+        assert_eq!(reply["error"], "Expected adjustment.brightness");
+    }
+
+    #[test]
+    fn test_set_component_blanks_outputs() {
+        let server = server("mock-hyperion-json-component", None);
+        let state = server.state();
+        let blanked = || state.outputs.read().unwrap()[0].is_blanked();
+        let component = |name: &str, on: bool| {
+            request(state, json!({"command": "componentstate", "componentstate": {"component": name, "state": on}}))
+        };
+
+        assert_eq!(component("LEDDEVICE", false)["success"], true);
+        assert!(blanked());
+        assert_eq!(request(state, json!({"command": "serverinfo"}))["info"]["components"][0]["enabled"], false);
+        // Components this bridge doesn't have are accepted and change nothing
+        assert_eq!(component("SMOOTHING", true)["success"], true);
+        assert!(blanked());
+        component("ALL", true);
+        assert!(!blanked());
+
+        let reply = request(state, json!({"command": "componentstate", "componentstate": {"state": true}}));
+        assert_eq!(reply["error"], "Expected componentstate.component");
+    }
+
+    #[test]
+    fn test_set_component_overrides_schedule() {
+        let server = server("mock-hyperion-json-schedule", Some(json!([{"on": "08:00", "off": "09:00"}])));
+        let state = server.state();
+        let schedule = state.schedule.as_ref().unwrap();
+        let component = |on: bool| {
+            request(state, json!({"command": "componentstate", "componentstate": {"component": "LEDDEVICE", "state": on}}))
+        };
+
+        component(true);
+        assert_eq!(schedule.mode(), ScheduleMode::On);
+        assert!(schedule.is_active());
+        component(false);
+        assert_eq!(schedule.mode(), ScheduleMode::Off);
+        assert!(!schedule.is_active());
+        assert!(state.outputs.read().unwrap()[0].is_blanked());
+        component(true);
+        assert!(!state.outputs.read().unwrap()[0].is_blanked());
+    }
+}
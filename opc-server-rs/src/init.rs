@@ -0,0 +1,304 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::protocol::{wait_for_ack, AckMode, AckResult};
+use crate::serial;
+use crate::wled::{self, WledInfo, WLED_BAUD_RATES};
+
+/// Baud rate Adalight sketches use
+const ADALIGHT_BAUD: u32 = 115200;
+
+/// Default baud rate for HyperSerial AWA firmwares
+const AWA_BAUD: u32 = 2000000;
+
+/// How long to listen for the `Ada` greeting after opening a port
+const ADALIGHT_GREETING_TIMEOUT: Duration = Duration::from_millis(2500);
+
+const PIXEL_FORMATS: &[&str] = &["RGB", "GRB", "BGR", "RGBW", "GRBW"];
+
+/// What was found on a serial port
+enum Detected {
+    Wled { baud: u32, info: WledInfo },
+    Adalight,
+    Unknown,
+}
+
+/// Answers for one output entry
+#[derive(Debug, Clone, PartialEq)]
+struct OutputAnswers {
+    name: String,
+    port: String,
+    protocol: String,
+    baud_rate: u32,
+    /// Set for WLED devices, which are detected at this rate and switched to `baud_rate`
+    handshake_baud_rate: Option<u32>,
+    led_count: usize,
+    pixel_format: String,
+    opc_channel: u8,
+}
+
+/// Scan serial ports, ask about each device and write a starter config to `path`
+///
+/// Reads answers from stdin; at end of input the remaining defaults are taken.
+pub fn run(path: &str, force: bool, ddebug: bool) -> Result<()> {
+    if Path::new(path).exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite)", path);
+    }
+
+    let stdin = io::stdin();
+    let mut prompt = Prompt { input: stdin.lock(), output: io::stdout() };
+
+    let ports = serialport::available_ports().context("Failed to list serial ports")?;
+    if ports.is_empty() {
+        anyhow::bail!("No serial ports found; connect a controller and run init again");
+    }
+
+    println!("Found {} serial port(s):", ports.len());
+    for port in &ports {
+        println!("  {} ({})", port.port_name, describe(&port.port_type));
+    }
+
+    let mut outputs: Vec<OutputAnswers> = Vec::new();
+    for port in &ports {
+        println!();
+        if !prompt.confirm(&format!("Configure {} ({})?", port.port_name, describe(&port.port_type)), true)? {
+            continue;
+        }
+
+        println!("Probing {}...", port.port_name);
+        let detected = probe(&port.port_name, ddebug);
+        let default_name = format!("output{}", outputs.len() + 1);
+
+        let (protocol, baud_rate, handshake_baud_rate, led_count, name) = match &detected {
+            Detected::Wled { baud, info } => {
+                println!("✓ WLED {} (version {}, {} LEDs) at {} baud",
+                         info.name.as_deref().unwrap_or("device"),
+                         info.version.as_deref().unwrap_or("unknown"),
+                         info.led_count.map(|c| c.to_string()).unwrap_or_else(|| "?".to_string()),
+                         baud);
+                let name = info.name.clone().unwrap_or(default_name);
+                ("awa".to_string(), *baud, Some(*baud), info.led_count, name)
+            }
+            Detected::Adalight => {
+                println!("✓ Adalight device (sent 'Ada') at {} baud", ADALIGHT_BAUD);
+                ("adalight".to_string(), ADALIGHT_BAUD, None, None, default_name)
+            }
+            Detected::Unknown => {
+                println!("✗ No WLED or Adalight reply (HyperSerial AWA firmwares stay silent)");
+                let protocol = prompt.choose("Protocol", &["awa", "adalight"], "awa")?;
+                let baud = if protocol == "awa" { AWA_BAUD } else { ADALIGHT_BAUD };
+                let baud = prompt.ask_parsed("Baud rate", baud)?;
+                (protocol, baud, None, None, default_name)
+            }
+        };
+
+        let name = prompt.ask("Name", &name)?;
+        let led_count = prompt.ask_parsed("Number of LEDs", led_count.unwrap_or(60))?;
+        let pixel_format = prompt.choose("Color order", PIXEL_FORMATS, "RGB")?;
+        let opc_channel = prompt.ask_parsed("OPC channel (0 = broadcast)", 0u8)?;
+
+        outputs.push(OutputAnswers {
+            name,
+            port: port.port_name.clone(),
+            protocol,
+            baud_rate,
+            handshake_baud_rate,
+            led_count,
+            pixel_format,
+            opc_channel,
+        });
+    }
+
+    if outputs.is_empty() {
+        anyhow::bail!("No outputs configured, nothing written");
+    }
+
+    println!();
+    let host = prompt.ask("OPC listen address", "0.0.0.0")?;
+    let opc_port = prompt.ask_parsed("OPC port", 7890u16)?;
+
+    let config = build_config(&host, opc_port, &outputs);
+    // Make sure the server will accept what we write
+    serde_json::from_value::<Config>(config.clone()).context("Generated config is invalid")?;
+
+    let text = serde_json::to_string_pretty(&config)?;
+    fs::write(path, text + "\n").context(format!("Failed to write {}", path))?;
+    println!("✓ Wrote {} with {} output(s); start with: opc_server {}", path, outputs.len(), path);
+    Ok(())
+}
+
+fn describe(port_type: &serialport::SerialPortType) -> String {
+    match port_type {
+        serialport::SerialPortType::UsbPort(usb) => {
+            let product = usb.product.as_deref().or(usb.manufacturer.as_deref()).unwrap_or("USB serial");
+            format!("{}, USB {:04x}:{:04x}", product, usb.vid, usb.pid)
+        }
+        serialport::SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+        serialport::SerialPortType::PciPort => "PCI".to_string(),
+        serialport::SerialPortType::Unknown => "unknown type".to_string(),
+    }
+}
+
+/// Look for a WLED JSON API, then for an Adalight sketch's `Ada` greeting
+fn probe(port_name: &str, ddebug: bool) -> Detected {
+    for &baud in WLED_BAUD_RATES {
+        if let Ok(info) = wled::handshake(port_name, baud, ddebug) {
+            return Detected::Wled { baud, info };
+        }
+    }
+
+    let greeting = serialport::new(serial::device_path(port_name), ADALIGHT_BAUD)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut port| {
+            // Opening resets most Arduinos; the sketch greets once it has booted
+            serial::raise_control_lines(port.as_mut()).ok();
+            thread::sleep(Duration::from_millis(50));
+            Ok(wait_for_ack(port.as_mut(), AckMode::Ada, ADALIGHT_GREETING_TIMEOUT)?)
+        });
+
+    match greeting {
+        Ok(AckResult::Ack) => Detected::Adalight,
+        Ok(_) => Detected::Unknown,
+        Err(e) => {
+            if ddebug {
+                eprintln!("[DEBUG {}] Adalight probe failed: {:#}", port_name, e);
+            }
+            Detected::Unknown
+        }
+    }
+}
+
+/// Config for the answers; outputs on the same channel follow each other
+fn build_config(host: &str, port: u16, outputs: &[OutputAnswers]) -> serde_json::Value {
+    let mut next_offset = [0usize; 256];
+    let entries: Vec<_> = outputs.iter().map(|output| {
+        let offset = &mut next_offset[output.opc_channel as usize];
+        let mut entry = json!({
+            "name": output.name,
+            "port": output.port,
+            "protocol": output.protocol,
+            "baud_rate": output.baud_rate,
+            "opc_channel": output.opc_channel,
+            "opc_offset": *offset,
+            "led_count": output.led_count,
+            "pixel_format": output.pixel_format,
+        });
+        if let Some(handshake) = output.handshake_baud_rate {
+            entry["hardware_type"] = json!("WLED");
+            entry["handshake_baud_rate"] = json!(handshake);
+        }
+        *offset += output.led_count;
+        entry
+    }).collect();
+
+    json!({
+        "opc": { "host": host, "port": port },
+        "outputs": entries,
+    })
+}
+
+/// Line-based questions with defaults
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Print the question and read a line; None for an empty answer or end of input
+    fn read_answer(&mut self, question: &str, hint: &str) -> Result<Option<String>> {
+        write!(self.output, "{} [{}]: ", question, hint)?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            writeln!(self.output)?;
+            return Ok(None);
+        }
+        let answer = line.trim();
+        Ok((!answer.is_empty()).then(|| answer.to_string()))
+    }
+
+    /// Ask a question; an empty answer (or end of input) takes the default
+    fn ask(&mut self, question: &str, default: &str) -> Result<String> {
+        Ok(self.read_answer(question, default)?.unwrap_or_else(|| default.to_string()))
+    }
+
+    fn ask_parsed<T: FromStr + ToString>(&mut self, question: &str, default: T) -> Result<T> {
+        let default = default.to_string();
+        loop {
+            match self.ask(question, &default)?.parse() {
+                Ok(value) => return Ok(value),
+                Err(_) => writeln!(self.output, "  Please enter a number")?,
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool> {
+        loop {
+            let Some(answer) = self.read_answer(question, if default { "Y/n" } else { "y/N" })? else {
+                return Ok(default);
+            };
+            match answer.to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(self.output, "  Please answer y or n")?,
+            }
+        }
+    }
+
+    /// Pick one of `options` (case-insensitive)
+    fn choose(&mut self, question: &str, options: &[&str], default: &str) -> Result<String> {
+        let question = format!("{} ({})", question, options.join("/"));
+        loop {
+            let answer = self.ask(&question, default)?;
+            if let Some(option) = options.iter().find(|o| o.eq_ignore_ascii_case(&answer)) {
+                return Ok(option.to_string());
+            }
+            writeln!(self.output, "  Please choose one of: {}", options.join(", "))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answers_build_valid_config() {
+        let input = "\nyes\n300\ngrb\nx\n2\n";
+        let mut prompt = Prompt { input: input.as_bytes(), output: Vec::new() };
+        assert_eq!(prompt.ask("Name", "desk").unwrap(), "desk");
+        assert!(prompt.confirm("Configure?", false).unwrap());
+        assert_eq!(prompt.ask_parsed("LEDs", 60usize).unwrap(), 300);
+        assert_eq!(prompt.choose("Color order", PIXEL_FORMATS, "RGB").unwrap(), "GRB");
+        // Invalid answers are asked again; end of input takes the default
+        assert_eq!(prompt.ask_parsed("Channel", 0u8).unwrap(), 2);
+        assert_eq!(prompt.ask_parsed("Port", 7890u16).unwrap(), 7890);
+
+        let output = |name: &str, led_count, handshake| OutputAnswers {
+            name: name.to_string(),
+            port: format!("/dev/{}", name),
+            protocol: "awa".to_string(),
+            baud_rate: 2000000,
+            handshake_baud_rate: handshake,
+            led_count,
+            pixel_format: "GRB".to_string(),
+            opc_channel: 1,
+        };
+        let value = build_config("0.0.0.0", 7890, &[output("left", 100, None), output("right", 50, Some(115200))]);
+        let config: Config = serde_json::from_value(value.clone()).unwrap();
+
+        assert!(config.unknown_keys(&value).is_empty());
+        assert_eq!(config.outputs[1].opc_offset, 100);
+        assert!(config.outputs[1].is_wled());
+        assert!(!config.outputs[0].is_wled());
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+
+use crate::config::{FadecandyConfig, HyperionConfig, OpcConfig};
+use crate::fadecandy::FadecandyServer;
+use crate::hyperion::HyperionServer;
+use crate::hyperion_json::HyperionJsonServer;
+use crate::net::Cidr;
+use crate::opc_server::SharedState;
+use crate::opc_websocket::OpcWebSocketServer;
+
+/// A source of frames: a listener for some network protocol, a capture, or a generator
+///
+/// Inputs deliver what they receive to the server's shared state (`handle_opc_message`
+/// and `show_pixels`, tagged with their `arbiter::Source`), which routes it to the
+/// outputs. They are started and stopped by the `InputManager`.
+pub trait InputSource: Send {
+    /// Short name for logs, e.g. "OPC WebSocket"
+    fn name(&self) -> &'static str;
+
+    /// Bind or open the input and start delivering frames from background threads
+    ///
+    /// `state.running` is the input's own flag: its threads run until it is cleared,
+    /// when the input or the whole server stops.
+    fn start(&mut self, state: SharedState) -> Result<()>;
+
+    /// Release anything the input holds beyond its threads (default: nothing)
+    fn stop(&mut self) {}
+}
+
+/// OPC messages over WebSocket (`opc.websocket_port`)
+pub struct OpcWebSocketInput {
+    pub config: OpcConfig,
+    pub port: u16,
+    pub allowed_clients: Option<Vec<Cidr>>,
+    pub debug: bool,
+}
+
+impl InputSource for OpcWebSocketInput {
+    fn name(&self) -> &'static str {
+        "OPC WebSocket"
+    }
+
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        OpcWebSocketServer::new(self.config.clone(), self.port, self.allowed_clients.clone(), state, self.debug).spawn()
+    }
+}
+
+/// Fadecandy emulation (`fadecandy`)
+pub struct FadecandyInput {
+    pub config: FadecandyConfig,
+    pub default_host: String,
+    pub debug: bool,
+}
+
+impl InputSource for FadecandyInput {
+    fn name(&self) -> &'static str {
+        "Fadecandy"
+    }
+
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        FadecandyServer::new(self.config.clone(), &self.default_host, state, self.debug).spawn()
+    }
+}
+
+/// Hyperion flatbuffers clients, plus the JSON-RPC server when `json_port` is set (`hyperion`)
+pub struct HyperionInput {
+    pub config: HyperionConfig,
+    pub default_host: String,
+    pub debug: bool,
+}
+
+impl InputSource for HyperionInput {
+    fn name(&self) -> &'static str {
+        "Hyperion"
+    }
+
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        HyperionServer::new(self.config.clone(), &self.default_host, state.clone(), self.debug).spawn()?;
+        // Status and brightness for Hyperion dashboards and remote apps
+        if let Some(port) = self.config.json_port {
+            let host = self.config.host.as_deref().unwrap_or(&self.default_host);
+            HyperionJsonServer::new(host, port, state, self.debug).spawn()?;
+        }
+        Ok(())
+    }
+}
+
+/// Screen capture (`ambilight`)
+#[cfg(feature = "ambilight")]
+pub struct AmbilightInput {
+    pub config: crate::config::AmbilightConfig,
+    pub debug: bool,
+}
+
+#[cfg(feature = "ambilight")]
+impl InputSource for AmbilightInput {
+    fn name(&self) -> &'static str {
+        "Ambilight"
+    }
+
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        crate::ambilight::spawn(self.config.clone(), state, self.debug)
+    }
+}
+
+/// Audio-reactive effects (`audio`)
+#[cfg(feature = "audio")]
+pub struct AudioInput {
+    pub config: crate::config::AudioConfig,
+    pub debug: bool,
+}
+
+#[cfg(feature = "audio")]
+impl InputSource for AudioInput {
+    fn name(&self) -> &'static str {
+        "Audio"
+    }
+
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        crate::audio::spawn(self.config.clone(), state, self.debug)
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::protocol::{FrameField, PIXELS_FIELD};
+use crate::stats;
+
+/// Frames captured when a request doesn't say how many
+pub const DEFAULT_FRAMES: usize = 10;
+
+/// Most frames one capture may ask for; each is a few lines per LED in the file
+pub const MAX_FRAMES: usize = 1000;
+
+/// Header bytes shown per field before the hex is cut short
+const FIELD_HEX_BYTES: usize = 16;
+
+/// Where a capture of output `id` goes: the temp directory, named after the output and the time
+pub fn default_path(id: &str) -> PathBuf {
+    let name: String = id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    std::env::temp_dir().join(format!("opc-inspect-{}-{}.txt", name.trim_matches('_'), stats::now_ms()))
+}
+
+/// A frame inspection in progress: the next frames an output writes, annotated in a text file
+///
+/// Each frame's header and checksum fields are listed with the checks the device
+/// would make, followed by its pixels in wire order. With `diff`, frames after the
+/// first list only the LEDs that changed.
+pub struct Capture {
+    path: PathBuf,
+    file: BufWriter<File>,
+    label: String,
+    /// Letter of each byte in a pixel, e.g. `GRBW`
+    channels: Vec<char>,
+    total: usize,
+    written: usize,
+    diff: bool,
+    /// Pixel bytes of the previous frame, for `diff`
+    previous: Option<Vec<u8>>,
+}
+
+impl Capture {
+    /// Start capturing `frames` frames of the output `label`, whose pixels are in `pixel_format` order
+    pub fn create(path: &Path, label: &str, pixel_format: Option<&str>, stride: usize, frames: usize, diff: bool) -> Result<Self> {
+        if frames == 0 || frames > MAX_FRAMES {
+            anyhow::bail!("frames must be between 1 and {}", MAX_FRAMES);
+        }
+        let file = File::create(path)
+            .context(format!("Failed to create {}", path.display()))?;
+        let mut channels: Vec<char> = pixel_format.unwrap_or("RGB").chars().collect();
+        channels.resize(stride, '?');
+        Ok(Capture {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            label: label.to_string(),
+            channels,
+            total: frames,
+            written: 0,
+            diff,
+            previous: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Frames written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Annotate one frame; false once the capture is complete and the file is flushed
+    pub fn record(&mut self, frame: &[u8], fields: &[FrameField]) -> io::Result<bool> {
+        self.written += 1;
+        writeln!(self.file, "# Frame {} of {}, {}, {} bytes, at {} ms",
+                 self.written, self.total, self.label, frame.len(), stats::now_ms())?;
+
+        let pixels = match fields.iter().find(|f| f.name == PIXELS_FIELD) {
+            Some(field) => field.bytes.clone(),
+            None => {
+                // Unknown layout: the raw bytes
+                writeln!(self.file, "(this protocol does not describe its frames)")?;
+                for (row, chunk) in frame.chunks(16).enumerate() {
+                    writeln!(self.file, "{:06x}  {}", row * 16, hex(chunk))?;
+                }
+                writeln!(self.file)?;
+                return self.finish_frame();
+            }
+        };
+
+        for field in fields {
+            let hex = if field.name == PIXELS_FIELD {
+                format!("[{}..{}]", field.bytes.start, field.bytes.end)
+            } else if field.bytes.len() > FIELD_HEX_BYTES {
+                format!("{} ...", hex(&frame[field.bytes.start..field.bytes.start + FIELD_HEX_BYTES]))
+            } else {
+                hex(&frame[field.bytes.clone()])
+            };
+            writeln!(self.file, "{:<10} {:<24} {}", field.name, hex, field.note)?;
+        }
+        self.write_pixels(&frame[pixels])?;
+        writeln!(self.file)?;
+        self.finish_frame()
+    }
+
+    fn write_pixels(&mut self, pixels: &[u8]) -> io::Result<()> {
+        let stride = self.channels.len();
+        let header: String = self.channels.iter().map(|c| format!(" {:>3}", c)).collect();
+        let previous = self.previous.take().filter(|p| self.diff && p.len() == pixels.len());
+
+        match &previous {
+            Some(previous) => {
+                let mut changed = 0;
+                for (led, (old, new)) in previous.chunks(stride).zip(pixels.chunks(stride)).enumerate() {
+                    if old != new {
+                        if changed == 0 {
+                            writeln!(self.file, "changed LEDs ({}):", self.channels.iter().collect::<String>())?;
+                        }
+                        writeln!(self.file, "LED {:>5}:{} ->{}", led, values(old), values(new))?;
+                        changed += 1;
+                    }
+                }
+                if changed == 0 {
+                    writeln!(self.file, "no LEDs changed")?;
+                }
+            }
+            None => {
+                writeln!(self.file, "LED       {}", header)?;
+                for (led, pixel) in pixels.chunks(stride).enumerate() {
+                    writeln!(self.file, "LED {:>5}:{}", led, values(pixel))?;
+                }
+            }
+        }
+
+        self.previous = Some(pixels.to_vec());
+        Ok(())
+    }
+
+    fn finish_frame(&mut self) -> io::Result<bool> {
+        if self.written < self.total {
+            return Ok(true);
+        }
+        self.file.flush()?;
+        Ok(false)
+    }
+}
+
+/// A capture written on its own thread, so annotating frames never holds up the output
+///
+/// The worker hands over copies of the frames it writes; once the capture has all of
+/// them the thread flushes the file and, unless `quiet`, reports where it is.
+pub struct Inspection {
+    frames: mpsc::Sender<(Vec<u8>, Vec<FrameField>)>,
+    remaining: usize,
+}
+
+impl Inspection {
+    pub fn start(mut capture: Capture, quiet: bool) -> Self {
+        let (frames, received) = mpsc::channel::<(Vec<u8>, Vec<FrameField>)>();
+        let remaining = capture.total;
+        thread::spawn(move || {
+            for (frame, fields) in received {
+                match capture.record(&frame, &fields) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        if !quiet {
+                            println!("✓ Inspected {} frames of {}: {}", capture.written(), capture.label, capture.path().display());
+                        }
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("✗ Frame inspection of {} stopped: {}", capture.label, e);
+                        return;
+                    }
+                }
+            }
+        });
+        Inspection { frames, remaining }
+    }
+
+    /// Hand over a frame the output wrote; false once the capture needs no more
+    pub fn record(&mut self, frame: &[u8], fields: Vec<FrameField>) -> bool {
+        self.remaining -= 1;
+        self.frames.send((frame.to_vec(), fields)).is_ok() && self.remaining > 0
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn values(pixel: &[u8]) -> String {
+    pixel.iter().map(|v| format!(" {:>3}", v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{build_awa_frame, describe_awa_frame};
+
+    #[test]
+    fn test_capture_annotates_and_diffs() {
+        let path = std::env::temp_dir().join(format!("opc_inspect_test_{}.txt", std::process::id()));
+        let mut capture = Capture::create(&path, "desk", Some("GRB"), 3, 2, true).unwrap();
+
+        let first = build_awa_frame(&[1, 2, 3, 4, 5, 6], 3);
+        let second = build_awa_frame(&[1, 2, 3, 9, 5, 6], 3);
+        assert!(capture.record(&first, &describe_awa_frame(&first, 3)).unwrap());
+        assert!(!capture.record(&second, &describe_awa_frame(&second, 3)).unwrap());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.contains("# Frame 1 of 2, desk, 15 bytes"), "{}", text);
+        assert!(text.contains("magic      41 77 61                 ok"), "{}", text);
+        assert!(text.contains("count      00 01                    2 LEDs"), "{}", text);
+        assert!(text.contains("LED          G   R   B\nLED     0:   1   2   3\nLED     1:   4   5   6"), "{}", text);
+        // The second frame lists only the LED that changed
+        assert!(text.contains("changed LEDs (GRB):\nLED     1:   4   5   6 ->   9   5   6\n"), "{}", text);
+        assert!(!text.contains("LED     0:   1   2   3 ->"), "{}", text);
+
+        assert!(Capture::create(&path, "desk", None, 3, 0, false).is_err());
+        assert!(default_path("/dev/ttyUSB0").to_str().unwrap().contains("opc-inspect-dev_ttyUSB0-"));
+    }
+    #[test]
+    fn test_inspection_writes_on_its_own_thread() {
+        let path = std::env::temp_dir().join(format!("opc_inspection_test_{}.txt", std::process::id()));
+        let mut inspection = Inspection::start(Capture::create(&path, "desk", None, 3, 2, false).unwrap(), true);
+        let frame = build_awa_frame(&[1, 2, 3], 3);
+        assert!(inspection.record(&frame, describe_awa_frame(&frame, 3)));
+        assert!(!inspection.record(&frame, describe_awa_frame(&frame, 3)));
+
+        // The file is complete once the thread has flushed it
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
+        let mut text = String::new();
+        while !text.contains("# Frame 2 of 2") && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            text = std::fs::read_to_string(&path).unwrap_or_default();
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.contains("# Frame 2 of 2, desk, 12 bytes"), "{}", text);
+    }
+}
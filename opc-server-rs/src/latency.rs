@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+/// Samples kept between stats reports; older ones are overwritten
+const MAX_SAMPLES: usize = 4096;
+
+/// Durations recorded by a worker since the last report (serial write+flush times,
+/// or the time frames took from reaching the output to leaving it)
+#[derive(Debug, Default)]
+pub struct LatencySamples {
+    /// Microseconds per sample
+    samples: Vec<u32>,
+    /// Samples recorded since the last report (may exceed the samples kept)
+    count: usize,
+}
+
+/// Average, percentiles and maximum of the samples in one report interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub average: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySamples {
+    pub fn record(&mut self, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u32::MAX as u128) as u32;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(micros);
+        } else {
+            self.samples[self.count % MAX_SAMPLES] = micros;
+        }
+        self.count += 1;
+    }
+
+    /// Summarize the samples since the last call and start a new interval
+    pub fn take_summary(&mut self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let total: u64 = self.samples.iter().map(|&s| s as u64).sum();
+        let average = total / self.samples.len() as u64;
+        self.samples.sort_unstable();
+        // Nearest-rank percentile of the sorted samples
+        let percentile = |p: usize| {
+            let rank = (self.samples.len() * p).div_ceil(100).max(1) - 1;
+            Duration::from_micros(self.samples[rank] as u64)
+        };
+        let summary = LatencySummary {
+            count: self.count,
+            average: Duration::from_micros(average),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: percentile(100),
+        };
+
+        self.samples.clear();
+        self.count = 0;
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_average_and_percentiles() {
+        let mut samples = LatencySamples::default();
+        assert_eq!(samples.take_summary(), None);
+
+        for ms in 1..=100 {
+            samples.record(Duration::from_millis(ms));
+        }
+        let summary = samples.take_summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.average, Duration::from_micros(50_500));
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+
+        // Each report covers only its own interval
+        samples.record(Duration::from_millis(7));
+        assert_eq!(samples.take_summary().unwrap().p99, Duration::from_millis(7));
+    }
+}
@@ -0,0 +1,72 @@
+//! OpenPixelControlSerial - OPC server for serial LED strips
+
+#[cfg(feature = "ambilight")]
+pub mod ambilight;
+pub mod arbiter;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod autobaud;
+pub mod channel_stats;
+pub mod child_input;
+pub mod color;
+pub mod config;
+pub mod config_format;
+pub mod control;
+pub mod delay;
+pub mod effects;
+pub mod fadecandy;
+pub mod frame_sync;
+pub mod health;
+pub mod hotplug;
+pub mod http;
+pub mod hyperion;
+pub mod hyperion_json;
+pub mod init;
+pub mod input;
+pub mod inspect;
+pub mod latency;
+pub mod limiter;
+pub mod matrix;
+pub mod mirror;
+pub mod mock_serial;
+pub mod net;
+pub mod null_serial;
+pub mod opc_protocol;
+pub mod opc_server;
+pub mod opc_websocket;
+pub mod output;
+pub mod pacing;
+pub mod pending;
+pub mod persist;
+pub mod pixel_format;
+pub mod pool;
+pub mod power;
+pub mod preview;
+pub mod protocol;
+pub mod queue;
+pub mod record;
+pub mod relay;
+pub mod resample;
+pub mod sched;
+pub mod schedule;
+pub mod script;
+pub mod segment;
+pub mod serial;
+#[cfg(windows)]
+pub mod service;
+pub mod signals;
+pub mod simulator;
+pub mod sink;
+pub mod smoothing;
+pub mod stats;
+pub mod strip_type;
+pub mod summary;
+pub mod sync;
+pub mod systemd;
+pub mod timed;
+pub mod tls;
+pub mod transform;
+pub mod ui;
+pub mod websocket;
+pub mod wled;
+pub mod writer;
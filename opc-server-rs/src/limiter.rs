@@ -0,0 +1,73 @@
+use std::time::Instant;
+
+/// Token-bucket rate limiter for incoming OPC messages
+///
+/// Allows `rate` messages per second on average, with bursts of up to 100ms worth
+/// of messages (at least two) so normal frame timing jitter is not throttled.
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> Self {
+        let capacity = (rate / 10.0).max(2.0);
+        RateLimiter {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token for one message, returning false if the message should be dropped
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Send `messages` messages `step` apart, counting how many are allowed
+    fn count_allowed(limiter: &mut RateLimiter, messages: usize, step: Duration) -> usize {
+        let mut now = limiter.last;
+        (0..messages).filter(|_| {
+            now += step;
+            limiter.allow_at(now)
+        }).count()
+    }
+
+    #[test]
+    fn test_flood_is_limited_to_rate() {
+        // One second of a 1000 fps client against a 60 fps limit
+        let mut limiter = RateLimiter::new(60.0);
+        let allowed = count_allowed(&mut limiter, 1000, Duration::from_millis(1));
+        // 60 for the second plus the initial 100ms burst allowance
+        assert!((65..=66).contains(&allowed), "allowed {}", allowed);
+    }
+
+    #[test]
+    fn test_steady_rate_within_limit_is_not_throttled() {
+        let mut limiter = RateLimiter::new(60.0);
+        let allowed = count_allowed(&mut limiter, 600, Duration::from_micros(16_700));
+        assert_eq!(allowed, 600);
+    }
+}
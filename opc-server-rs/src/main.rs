@@ -1,46 +1,194 @@
 use anyhow::Result;
-use clap::Parser;
-use std::fs;
+use clap::{Parser, Subcommand};
+use std::time::Duration;
 
-mod config;
-mod opc_server;
-mod output;
-mod pixel_format;
-mod protocol;
-
-use config::Config;
-use opc_server::OpcServer;
+use opc_server::config::{self, Config, LoadOptions, Override};
+use opc_server::health::Health;
+use opc_server::opc_server::OpcServer;
+use opc_server::protocol;
+use opc_server::serial;
+use opc_server::sink;
+use opc_server::stats::StatsFormat;
 
 #[derive(Parser)]
 #[command(name = "opc_server")]
 #[command(about = "OpenPixelControlSerial - OPC Server\n\nReceives OPC data over TCP and outputs to serial LED strips.", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Path to configuration file (JSON)
-    config: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Enable debug output (statistics)
+    /// Path to configuration file (JSON, TOML or YAML)
+    #[arg(required = true)]
+    config: Option<String>,
+
+    /// Record the incoming OPC stream with timestamps to this file
+    #[arg(long, value_name = "FILE")]
+    record: Option<String>,
+
+    /// Override a config value, e.g. --set opc.port=7891 or --set outputs.desk.brightness=0.5 (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    overrides: Vec<String>,
+
+    /// Accept config files with unknown keys (warn instead of refusing to start)
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Hostname matched against outputs' `host` field (default: this machine's)
+    #[arg(long, value_name = "NAME", global = true)]
+    hostname: Option<String>,
+
+    /// Periodic statistics format: text, or json (one object per line, printed even without --debug)
+    #[arg(long, value_name = "FORMAT", default_value = "text", global = true)]
+    stats_format: StatsFormat,
+
+    /// Report each output's latency percentiles, from frames reaching the output to the serial flush
+    #[arg(long, global = true)]
+    latency: bool,
+
+    /// Wait up to this long for the configured serial ports to appear before opening the outputs
+    #[arg(long, value_name = "SECONDS", global = true, conflicts_with = "dry_run")]
+    wait_for_ports: Option<f64>,
+
+    /// Open no devices: outputs accept frames and drop them, or append them to DIR/<output>.bin
+    #[arg(long, value_name = "DIR", num_args = 0..=1, require_equals = true, global = true)]
+    dry_run: Option<Option<std::path::PathBuf>>,
+
+    /// Print periodic statistics without the rest of --debug
+    #[arg(long, global = true)]
+    stats: bool,
+
+    /// Seconds between periodic statistics
+    #[arg(long, value_name = "SECONDS", default_value_t = 5.0, global = true)]
+    stats_interval: f64,
+
+    /// Print only errors, warnings and requested reports (no startup summary)
+    #[arg(long, global = true, conflicts_with_all = ["debug", "ddebug"])]
+    quiet: bool,
+
+    /// Run as a Windows service, for services created with `sc create` (stopped by the Service Control Manager)
+    #[cfg(windows)]
     #[arg(long)]
+    service: bool,
+
+    /// Enable debug output (statistics)
+    #[arg(long, global = true)]
     debug: bool,
 
-    /// Enable detailed debug (hex dumps every frame)
-    #[arg(long)]
+    /// Enable detailed debug (per-frame timing and transforms)
+    #[arg(long, global = true)]
     ddebug: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Scan serial ports, ask about each device and write a starter config
+    Init {
+        /// Config file to write
+        #[arg(default_value = "config.json")]
+        output: String,
+
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Play a recording (made with --record) into the configured outputs
+    Replay {
+        /// Path to configuration file (JSON, TOML or YAML)
+        config: String,
+
+        /// Recording to play back
+        recording: String,
+
+        /// Start over at the end of the recording
+        #[arg(long = "loop")]
+        looping: bool,
+
+        /// Playback speed multiplier (2.0 = twice as fast)
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Ask the running server for its outputs' health; exit 0 when healthy (for container healthchecks)
+    Health {
+        /// Path to configuration file (JSON, TOML or YAML)
+        config: String,
+
+        /// Also exit 0 when some outputs are degraded
+        #[arg(long)]
+        allow_degraded: bool,
+    },
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let config_path = match &cli.command {
+        Some(Command::Init { output, force }) => {
+            return opc_server::init::run(output, *force, cli.ddebug);
+        }
+        Some(Command::Replay { speed, .. }) if *speed <= 0.0 => {
+            anyhow::bail!("--speed must be greater than 0");
+        }
+        Some(Command::Replay { config, .. }) | Some(Command::Health { config, .. }) => config.clone(),
+        None => cli.config.clone().expect("config is required without a subcommand"),
+    };
+    if cli.stats_interval <= 0.0 || !cli.stats_interval.is_finite() {
+        anyhow::bail!("--stats-interval must be greater than 0");
+    }
+
+    // Environment overrides first, so --set wins
+    let mut overrides = Override::from_env()?;
+    for spec in &cli.overrides {
+        overrides.push(Override::parse(spec)?);
+    }
+
     // Load configuration
-    let config_data = fs::read_to_string(&cli.config)?;
-    let config: Config = serde_json::from_str(&config_data)?;
+    let options = LoadOptions { overrides, lenient: cli.lenient, hostname: cli.hostname.clone() };
+    let config = Config::load_with(&config_path, &options)?;
+
+    if let Some(Command::Health { allow_degraded, .. }) = &cli.command {
+        return health(&config, *allow_degraded, cli.quiet);
+    }
 
     // ddebug implies debug
-    let debug = cli.debug || cli.ddebug;
+    let ddebug = cli.ddebug || env_flag("DDEBUG");
+    let debug = cli.debug || ddebug || env_flag("DEBUG");
+    
+    if let Some(dir) = cli.dry_run.clone() {
+        if !cli.quiet {
+            match &dir {
+                Some(dir) => println!("Dry run: writing frames to {} instead of the devices", dir.display()),
+                None => println!("Dry run: no devices are opened"),
+            }
+        }
+        sink::enable(dir)?;
+    }
+    
+    if let Some(timeout) = cli.wait_for_ports {
+        if timeout < 0.0 || !timeout.is_finite() {
+            anyhow::bail!("--wait-for-ports must not be negative");
+        }
+        // Mock and network outputs have no device to wait for
+        let ports: Vec<&str> = config.outputs.iter()
+            .filter(|o| o.hardware_type.as_deref() != Some("mock") && !protocol::is_network(&o.protocol))
+            .map(|o| o.port.as_str())
+            .collect();
+        let missing = serial::wait_for_ports(&ports, Duration::from_secs_f64(timeout), debug);
+        if !missing.is_empty() {
+            eprintln!("Warning: {} still missing after {}s", missing.join(", "), timeout);
+        }
+    }
     
     // Create server
-    let mut server = OpcServer::new(config, debug, cli.ddebug)?;
+    let mut server = OpcServer::new(config, config_path, debug, ddebug)?;
+    server.set_load_options(options);
+    server.set_stats_format(cli.stats_format);
+    server.set_latency_report(cli.latency);
+    server.set_stats_report(cli.stats);
+    server.set_stats_interval(Duration::from_secs_f64(cli.stats_interval));
+    server.set_quiet(cli.quiet);
     
-    // Set up Ctrl-C handler with graceful shutdown
+    // Graceful shutdown on Ctrl-C, SIGTERM/SIGHUP, and Ctrl-Break or console close on Windows
     let running = server.get_running_flag();
     let debug_for_handler = debug;
     let result = ctrlc::set_handler(move || {
@@ -54,11 +202,53 @@ fn main() -> Result<()> {
         eprintln!("Warning: Could not set Ctrl-C handler: {}", e);
     }
     
-    // Run server (blocks until shutdown)
-    server.run()?;
+    // Stop and shutdown requests of the Service Control Manager do the same; dropping the
+    // service after the shutdown below reports it stopped
+    #[cfg(windows)]
+    let _service = if cli.service { Some(opc_server::service::start(server.get_running_flag())?) } else { None };
+    
+    match &cli.command {
+        Some(Command::Replay { recording, looping, speed, .. }) => {
+            // Blocks until the recording ends (or Ctrl-C when looping)
+            server.replay(recording, *speed, *looping)?;
+        }
+        Some(Command::Init { .. }) | Some(Command::Health { .. }) => {
+            unreachable!("init and health return before the server starts")
+        }
+        None => {
+            if let Some(path) = &cli.record {
+                server.record_to(path)?;
+            }
+            // Run server (blocks until shutdown)
+            server.run()?;
+        }
+    }
     
     // Graceful shutdown - send black frames to turn off LEDs
     server.shutdown();
 
     Ok(())
 }
+
+/// Whether `OPC_SERVER_<name>` is set to a true value (1, true, yes, on)
+fn env_flag(name: &str) -> bool {
+    std::env::var(format!("{}{}", config::ENV_PREFIX, name))
+        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+/// `opc_server health`: print the server's health and exit non-zero unless it is healthy
+fn health(config: &Config, allow_degraded: bool, quiet: bool) -> Result<()> {
+    let report = opc_server::health::query(config)?;
+    if !quiet {
+        println!("{}", report.status.name());
+        for output in report.outputs.iter().filter(|o| o.health != Health::Healthy) {
+            println!("  {}: {} ({})", output.id, output.health.name(), output.reason.as_deref().unwrap_or("no reason given"));
+        }
+    }
+    match report.status {
+        Health::Healthy => Ok(()),
+        Health::Degraded if allow_degraded => Ok(()),
+        _ => std::process::exit(1),
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+
+use crate::config::{Corner, MatrixConfig};
+
+/// Check that a matrix layout fits the output it is configured on
+pub fn check(matrix: &MatrixConfig, led_count: usize) -> Result<()> {
+    if !matches!(matrix.rotation, 0 | 90 | 180 | 270) {
+        anyhow::bail!("matrix rotation must be 0, 90, 180 or 270, not {}", matrix.rotation);
+    }
+    if matrix.width * matrix.height != led_count {
+        anyhow::bail!("matrix is {}x{} = {} pixels, but led_count is {}",
+                      matrix.width, matrix.height, matrix.width * matrix.height, led_count);
+    }
+    Ok(())
+}
+
+/// Reorders a row-major image into the wiring order of a matrix panel
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    /// Image pixel shown by each LED, in wiring order
+    sources: Vec<usize>,
+}
+
+impl Matrix {
+    pub fn new(config: &MatrixConfig) -> Self {
+        let (width, height) = (config.width, config.height);
+        // The panel's own rows run across the image's columns when it is turned sideways
+        let (panel_width, panel_height) = match config.rotation {
+            90 | 270 => (height, width),
+            _ => (width, height),
+        };
+
+        let sources = (0..width * height).map(|led| {
+            let row = led / panel_width;
+            let mut col = led % panel_width;
+            if config.serpentine && row % 2 == 1 {
+                col = panel_width - 1 - col;
+            }
+
+            // Panel position, counted from its top-left corner
+            let x = match config.origin {
+                Corner::TopLeft | Corner::BottomLeft => col,
+                Corner::TopRight | Corner::BottomRight => panel_width - 1 - col,
+            };
+            let y = match config.origin {
+                Corner::TopLeft | Corner::TopRight => row,
+                Corner::BottomLeft | Corner::BottomRight => panel_height - 1 - row,
+            };
+
+            // Image position shown there
+            let (ix, iy) = match config.rotation {
+                90 => (y, height - 1 - x),
+                180 => (width - 1 - x, height - 1 - y),
+                270 => (width - 1 - y, x),
+                _ => (x, y),
+            };
+            iy * width + ix
+        }).collect();
+
+        Matrix { sources }
+    }
+
+    /// Copy the RGB image `src` into `dst` in wiring order
+    pub fn apply(&self, src: &[u8], dst: &mut [u8]) {
+        for (pixel, &source) in dst.chunks_exact_mut(3).zip(&self.sources) {
+            pixel.copy_from_slice(&src[source * 3..source * 3 + 3]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(width: usize, height: usize, serpentine: bool, rotation: u16, origin: Corner) -> Vec<usize> {
+        Matrix::new(&MatrixConfig { width, height, serpentine, rotation, origin }).sources
+    }
+
+    #[test]
+    fn test_matrix_layouts() {
+        // 3x2 image:  0 1 2
+        //             3 4 5
+        assert_eq!(layout(3, 2, false, 0, Corner::TopLeft), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(layout(3, 2, true, 0, Corner::TopLeft), vec![0, 1, 2, 5, 4, 3]);
+        assert_eq!(layout(3, 2, true, 0, Corner::BottomRight), vec![5, 4, 3, 0, 1, 2]);
+        assert_eq!(layout(3, 2, false, 180, Corner::TopLeft), vec![5, 4, 3, 2, 1, 0]);
+
+        // Turned clockwise the panel is 2 wide and 3 high:  3 0
+        //                                                   4 1
+        //                                                   5 2
+        assert_eq!(layout(3, 2, false, 90, Corner::TopLeft), vec![3, 0, 4, 1, 5, 2]);
+        assert_eq!(layout(3, 2, false, 270, Corner::TopLeft), vec![2, 5, 1, 4, 0, 3]);
+
+        let mut wired = vec![0u8; 6];
+        Matrix::new(&MatrixConfig { width: 2, height: 1, serpentine: false, rotation: 180, origin: Corner::TopLeft })
+            .apply(&[1, 1, 1, 2, 2, 2], &mut wired);
+        assert_eq!(wired, vec![2, 2, 2, 1, 1, 1]);
+
+        let config = MatrixConfig { width: 3, height: 2, serpentine: false, rotation: 45, origin: Corner::TopLeft };
+        assert!(check(&config, 6).is_err());
+        assert!(check(&MatrixConfig { rotation: 0, ..config }, 8).is_err());
+    }
+}
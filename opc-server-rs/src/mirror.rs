@@ -0,0 +1,167 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::OutputConfig;
+use crate::mock_serial::MockPort;
+use crate::null_serial::delegate_serial_port;
+use crate::output::{Output, OutputState};
+use crate::pacing::PacedPort;
+use crate::pool::BufferPool;
+use crate::queue::{FrameQueue, QueuePolicy};
+use crate::simulator::SimulatorPort;
+use crate::sink::{self, SinkPort};
+
+/// Extra serial port that receives the exact bytes written to its source output
+pub struct Mirror {
+    port_name: String,
+    queue: Arc<FrameQueue>,
+    pool: Arc<BufferPool>,
+    state: Arc<OutputState>,
+    worker_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Mirror {
+    /// Open `port_name` with the source output's serial settings and start its writer thread
+    pub fn open(source: &OutputConfig, port_name: &str, debug: bool) -> Result<Self> {
+        let mut config = source.clone();
+        config.port = port_name.to_string();
+        config.name = None;
+
+        let port = if config.hardware_type.as_deref() == Some("simulator") {
+            Box::new(SimulatorPort::new(&config)) as Box<dyn SerialPort>
+        } else if config.hardware_type.as_deref() == Some("mock") {
+            Box::new(MockPort::new(&config)) as Box<dyn SerialPort>
+        } else if sink::enabled() {
+            Box::new(SinkPort::open(&config)?) as Box<dyn SerialPort>
+        } else {
+            Output::open_standard_port(&config)?
+        };
+
+        // The freshest frame wins if the mirror falls behind its source
+        let queue = Arc::new(FrameQueue::new(1, QueuePolicy::DropOldest));
+        let pool = Arc::new(BufferPool::new());
+        let state = Arc::new(OutputState::new(255));
+        let mut port = PacedPort::wrap(port, &config, &state);
+
+        let worker_queue = Arc::clone(&queue);
+        let worker_pool = Arc::clone(&pool);
+        let worker_state = Arc::clone(&state);
+        let worker_port = config.port.clone();
+
+        let worker_handle = thread::spawn(move || {
+            loop {
+                let frame = match worker_queue.pop_timeout(Duration::from_millis(100)) {
+                    Ok(frame) => frame,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+
+                let result = port.write_all(&frame).and_then(|_| port.flush());
+                let len = frame.len() as u64;
+                worker_pool.put(frame);
+
+                if let Err(e) = result {
+                    eprintln!("✗ Serial error on mirror {}: {}", worker_port, e);
+                    eprintln!("✗ Output {} is now disconnected", worker_port);
+                    worker_state.errors.fetch_add(1, Ordering::Relaxed);
+                    worker_state.connected.store(false, Ordering::Relaxed);
+                    break;
+                }
+                worker_state.frames_sent.fetch_add(1, Ordering::Relaxed);
+                worker_state.bytes_sent.fetch_add(len, Ordering::Relaxed);
+            }
+            worker_queue.close();
+        });
+
+        if debug {
+            println!("✓ Opened {} (mirror of {})", config.port, source.id());
+        }
+
+        Ok(Mirror {
+            port_name: config.port,
+            queue,
+            pool,
+            state,
+            worker_handle: Some(worker_handle),
+        })
+    }
+
+    /// Port this mirror writes to
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Get a clone of the mirror's runtime state (frames sent, errors, connected)
+    pub fn state(&self) -> Arc<OutputState> {
+        Arc::clone(&self.state)
+    }
+
+    fn send(&self, data: &[u8]) {
+        if let Some(dropped) = self.queue.push(self.pool.copy_from(data)) {
+            self.state.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            self.pool.put(dropped);
+        }
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        // Closing lets the writer finish the queued frame (e.g. the final blank) first
+        self.queue.close();
+        if let Some(handle) = self.worker_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Serial port wrapper that fans every flushed write out to mirror ports
+///
+/// The source worker transforms and frames each frame once; the finished bytes are
+/// written to the primary port and copied to each mirror's writer thread on flush,
+/// so all ports transmit in parallel. Reads and settings use the primary port only.
+pub struct MirroredPort {
+    inner: Box<dyn SerialPort>,
+    /// Bytes written since the last flush
+    pending: Vec<u8>,
+    mirrors: Vec<Mirror>,
+}
+
+impl MirroredPort {
+    pub fn new(inner: Box<dyn SerialPort>, mirrors: Vec<Mirror>) -> Self {
+        MirroredPort {
+            inner,
+            pending: Vec::new(),
+            mirrors,
+        }
+    }
+}
+
+impl Write for MirroredPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.pending.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for mirror in &self.mirrors {
+            mirror.send(&self.pending);
+        }
+        self.pending.clear();
+        self.inner.flush()
+    }
+}
+
+impl io::Read for MirroredPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+delegate_serial_port!(MirroredPort, inner);
@@ -0,0 +1,98 @@
+use serialport::SerialPort;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+use crate::null_serial::{delegate_serial_port, NullSerial};
+
+/// Frames written by each port, shared between the port and `frames`
+type FrameLog = Arc<Mutex<Vec<Vec<u8>>>>;
+
+fn registry() -> &'static Mutex<HashMap<String, FrameLog>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, FrameLog>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Stand-in serial port that records every frame in memory (`hardware_type: "mock"`)
+///
+/// Meant for tests: the bytes written between flushes are one frame, kept under the
+/// output's `port` name until read with `frames` or `take_frames`. Reopening a port
+/// (reload, replug) keeps appending to the same log. A flush takes as long as sending
+/// the frame at the output's `baud_rate` would, so low rates make a slow output.
+pub struct MockPort {
+    /// Bytes written since the last flush (one protocol frame)
+    pending: Vec<u8>,
+    frames: FrameLog,
+    serial: NullSerial,
+}
+
+impl MockPort {
+    pub fn new(config: &OutputConfig) -> Self {
+        let frames = registry().lock().unwrap()
+            .entry(config.port.clone())
+            .or_default()
+            .clone();
+        MockPort {
+            pending: Vec::new(),
+            frames,
+            serial: NullSerial::new(&config.port, config.baud_rate),
+        }
+    }
+}
+
+/// Frames written to the mock port `port` so far
+pub fn frames(port: &str) -> Vec<Vec<u8>> {
+    match registry().lock().unwrap().get(port) {
+        Some(frames) => frames.lock().unwrap().clone(),
+        None => Vec::new(),
+    }
+}
+
+/// Frames written to the mock port `port` so far, clearing its log
+pub fn take_frames(port: &str) -> Vec<Vec<u8>> {
+    match registry().lock().unwrap().get(port) {
+        Some(frames) => std::mem::take(&mut *frames.lock().unwrap()),
+        None => Vec::new(),
+    }
+}
+
+/// Wait up to `timeout` until `port` has written at least `count` frames, and return them all
+pub fn wait_for_frames(port: &str, count: usize, timeout: Duration) -> Vec<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let written = frames(port);
+        if written.len() >= count || Instant::now() >= deadline {
+            return written;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+impl io::Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            // 10 bits per byte on the wire (8N1)
+            let baud_rate = self.serial.baud_rate().unwrap_or(0);
+            if baud_rate > 0 {
+                std::thread::sleep(Duration::from_secs_f64(self.pending.len() as f64 * 10.0 / baud_rate as f64));
+            }
+            self.frames.lock().unwrap().push(std::mem::take(&mut self.pending));
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for MockPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "mock port has no input"))
+    }
+}
+
+delegate_serial_port!(MockPort, serial);
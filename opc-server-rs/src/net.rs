@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use std::net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs};
+
+use crate::config::OpcConfig;
+
+/// Resolve a listen address; `host` may be a hostname, an IPv4 address or a bare
+/// IPv6 literal such as `::`
+pub fn resolve(host: &str, port: u16) -> Result<SocketAddr> {
+    (host, port).to_socket_addrs()
+        .context(format!("Invalid listen address {}:{}", host, port))?
+        .next()
+        .context(format!("No address found for {}", host))
+}
+
+/// This machine's hostname, which picks the outputs with a `host` field
+pub fn hostname() -> Option<String> {
+    #[cfg(unix)]
+    let name = {
+        let mut buf = [0u8; 256];
+        let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if result != 0 {
+            return None;
+        }
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    };
+
+    #[cfg(not(unix))]
+    let name = std::env::var("COMPUTERNAME").ok()?;
+
+    Some(name).filter(|n| !n.is_empty())
+}
+
+/// Bind the OPC listener, applying the socket options from the config
+///
+/// Binding `::` listens on IPv6 and, unless `ipv6_only` is set, IPv4 as well.
+pub fn bind_listener(config: &OpcConfig) -> Result<TcpListener> {
+    let addr = resolve(&config.host, config.port)?;
+
+    #[cfg(unix)]
+    let listener = sys::bind(addr, config);
+
+    #[cfg(not(unix))]
+    let listener = {
+        if config.recv_buffer_size.is_some() || config.interface.is_some() || config.listen_backlog.is_some() {
+            eprintln!("Warning: recv_buffer_size, interface and listen_backlog are not supported on this platform, ignoring");
+        }
+        TcpListener::bind(addr)
+    };
+
+    listener.context(format!("Failed to bind to {}", addr))
+}
+
+/// An address range in CIDR notation (`192.168.1.0/24`, `fd00::/8`, or a single address)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = addr.trim().parse()
+            .context(format!("Invalid address in {:?}", s))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>()
+                .ok()
+                .filter(|&len| len <= max_len)
+                .context(format!("Invalid prefix length in {:?}", s))?,
+            None => max_len,
+        };
+
+        Ok(Cidr { network, prefix_len })
+    }
+
+    /// Check whether an address falls inside this range
+    /// (IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses)
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+            v4 => v4,
+        };
+
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                prefix_matches(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                prefix_matches(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], addr: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = prefix_len as usize / 8;
+    let remaining_bits = prefix_len % 8;
+
+    if network[..full_bytes] != addr[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = 0xFFu8 << (8 - remaining_bits);
+    network[full_bytes] & mask == addr[full_bytes] & mask
+}
+
+/// Compare a received secret without leaking how many leading bytes matched
+pub fn secrets_match(expected: &[u8], received: &[u8]) -> bool {
+    expected.len() == received.len()
+        && expected.iter().zip(received).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddr, TcpListener};
+    use std::os::unix::io::{FromRawFd, RawFd};
+
+    use crate::config::OpcConfig;
+
+    /// Pending connections queued by the kernel (default of `opc.listen_backlog`)
+    const LISTEN_BACKLOG: u32 = 128;
+
+    /// Create the listening socket by hand so options that must be set before
+    /// `bind`/`listen` (receive buffer, dual-stack, device binding) can be applied
+    pub fn bind(addr: SocketAddr, config: &OpcConfig) -> io::Result<TcpListener> {
+        let domain = if addr.is_ipv6() { libc::AF_INET6 } else { libc::AF_INET };
+
+        let fd = unsafe { libc::socket(domain, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Owns the descriptor from here on, so it is closed on any error below
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+        // Match std's sockets, which are never inherited by child processes
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+        set_option(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+        if addr.is_ipv6() {
+            set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, config.ipv6_only as libc::c_int)?;
+        }
+        if let Some(size) = config.recv_buffer_size {
+            // Set on the listener so accepted sockets inherit it with a matching TCP window
+            set_option(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, size as libc::c_int)?;
+        }
+        if let Some(interface) = &config.interface {
+            bind_to_device(fd, interface)?;
+        }
+
+        let result = match addr {
+            SocketAddr::V4(v4) => {
+                let mut sin: libc::sockaddr_in = unsafe { mem::zeroed() };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = v4.port().to_be();
+                sin.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(v4.ip().octets()) };
+                unsafe {
+                    libc::bind(fd, &sin as *const _ as *const libc::sockaddr,
+                               mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+                }
+            }
+            SocketAddr::V6(v6) => {
+                let mut sin6: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = v6.port().to_be();
+                sin6.sin6_addr = libc::in6_addr { s6_addr: v6.ip().octets() };
+                sin6.sin6_flowinfo = v6.flowinfo();
+                sin6.sin6_scope_id = v6.scope_id();
+                unsafe {
+                    libc::bind(fd, &sin6 as *const _ as *const libc::sockaddr,
+                               mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+                }
+            }
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let backlog = config.listen_backlog.unwrap_or(LISTEN_BACKLOG).min(libc::c_int::MAX as u32);
+        if unsafe { libc::listen(fd, backlog as libc::c_int) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(listener)
+    }
+
+    fn set_option(fd: RawFd, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+        let result = unsafe {
+            libc::setsockopt(fd, level, name, &value as *const _ as *const libc::c_void,
+                             mem::size_of::<libc::c_int>() as libc::socklen_t)
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Only accept connections arriving on the named network interface (e.g. `eth0`)
+    #[cfg(target_os = "linux")]
+    fn bind_to_device(fd: RawFd, interface: &str) -> io::Result<()> {
+        let result = unsafe {
+            libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE,
+                             interface.as_ptr() as *const libc::c_void,
+                             interface.len() as libc::socklen_t)
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn bind_to_device(_fd: RawFd, interface: &str) -> io::Result<()> {
+        eprintln!("Warning: binding to interface {} is only supported on Linux, ignoring", interface);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_bare_ipv6() {
+        assert_eq!(resolve("::", 7890).unwrap(), "[::]:7890".parse().unwrap());
+        assert_eq!(resolve("127.0.0.1", 7890).unwrap(), "127.0.0.1:7890".parse().unwrap());
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let lan = Cidr::parse("192.168.1.0/24").unwrap();
+        assert!(lan.contains("192.168.1.42".parse().unwrap()));
+        assert!(!lan.contains("192.168.2.1".parse().unwrap()));
+        assert!(lan.contains("::ffff:192.168.1.7".parse().unwrap()));
+
+        let odd = Cidr::parse("10.0.0.0/12").unwrap();
+        assert!(odd.contains("10.15.255.255".parse().unwrap()));
+        assert!(!odd.contains("10.16.0.0".parse().unwrap()));
+
+        let host = Cidr::parse("fd00::1").unwrap();
+        assert!(host.contains("fd00::1".parse().unwrap()));
+        assert!(!host.contains("fd00::2".parse().unwrap()));
+
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("laptop").is_err());
+    }
+}
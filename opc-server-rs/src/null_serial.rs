@@ -0,0 +1,306 @@
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::io;
+use std::time::Duration;
+
+/// Serial settings of a port with no serial line behind it (a network socket, a pipe to
+/// another program, a file or memory)
+///
+/// Stand-in ports keep one of these for the serial side of `SerialPort` and implement
+/// only reading and writing themselves, handing the rest to it with
+/// `delegate_serial_port!`. Baud rate and timeout are remembered, the other settings are
+/// accepted and ignored, the modem lines always read as ready and nothing is ever
+/// waiting to be read or written. On its own it discards everything written to it.
+#[derive(Debug, Clone)]
+pub struct NullSerial {
+    name: String,
+    baud_rate: u32,
+    timeout: Duration,
+}
+
+impl NullSerial {
+    pub fn new(name: &str, baud_rate: u32) -> Self {
+        NullSerial {
+            name: name.to_string(),
+            baud_rate,
+            timeout: Duration::from_millis(1000),
+        }
+    }
+}
+
+impl io::Write for NullSerial {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for NullSerial {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("{} has no input", self.name)))
+    }
+}
+
+impl SerialPort for NullSerial {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(serialport::Error::new(serialport::ErrorKind::Unknown, format!("{} cannot be cloned", self.name)))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Implement `SerialPort` for a port type by handing every call to one of its fields
+/// (`delegate_serial_port!(PacedPort, inner)`) or to what a method returns
+/// (`delegate_serial_port!(ThreadedPort, port())`)
+///
+/// For wrappers around another port, and stand-in ports keeping a `NullSerial`; the
+/// type implements `Read` and `Write` itself.
+macro_rules! delegate_serial_port {
+    ($port:ty, $($target:tt)+) => {
+        impl ::serialport::SerialPort for $port {
+            fn name(&self) -> Option<String> {
+                self.$($target)+.name()
+            }
+
+            fn baud_rate(&self) -> ::serialport::Result<u32> {
+                self.$($target)+.baud_rate()
+            }
+
+            fn data_bits(&self) -> ::serialport::Result<::serialport::DataBits> {
+                self.$($target)+.data_bits()
+            }
+
+            fn flow_control(&self) -> ::serialport::Result<::serialport::FlowControl> {
+                self.$($target)+.flow_control()
+            }
+
+            fn parity(&self) -> ::serialport::Result<::serialport::Parity> {
+                self.$($target)+.parity()
+            }
+
+            fn stop_bits(&self) -> ::serialport::Result<::serialport::StopBits> {
+                self.$($target)+.stop_bits()
+            }
+
+            fn timeout(&self) -> ::std::time::Duration {
+                self.$($target)+.timeout()
+            }
+
+            fn set_baud_rate(&mut self, baud_rate: u32) -> ::serialport::Result<()> {
+                self.$($target)+.set_baud_rate(baud_rate)
+            }
+
+            fn set_data_bits(&mut self, data_bits: ::serialport::DataBits) -> ::serialport::Result<()> {
+                self.$($target)+.set_data_bits(data_bits)
+            }
+
+            fn set_flow_control(&mut self, flow_control: ::serialport::FlowControl) -> ::serialport::Result<()> {
+                self.$($target)+.set_flow_control(flow_control)
+            }
+
+            fn set_parity(&mut self, parity: ::serialport::Parity) -> ::serialport::Result<()> {
+                self.$($target)+.set_parity(parity)
+            }
+
+            fn set_stop_bits(&mut self, stop_bits: ::serialport::StopBits) -> ::serialport::Result<()> {
+                self.$($target)+.set_stop_bits(stop_bits)
+            }
+
+            fn set_timeout(&mut self, timeout: ::std::time::Duration) -> ::serialport::Result<()> {
+                self.$($target)+.set_timeout(timeout)
+            }
+
+            fn write_request_to_send(&mut self, level: bool) -> ::serialport::Result<()> {
+                self.$($target)+.write_request_to_send(level)
+            }
+
+            fn write_data_terminal_ready(&mut self, level: bool) -> ::serialport::Result<()> {
+                self.$($target)+.write_data_terminal_ready(level)
+            }
+
+            fn read_clear_to_send(&mut self) -> ::serialport::Result<bool> {
+                self.$($target)+.read_clear_to_send()
+            }
+
+            fn read_data_set_ready(&mut self) -> ::serialport::Result<bool> {
+                self.$($target)+.read_data_set_ready()
+            }
+
+            fn read_ring_indicator(&mut self) -> ::serialport::Result<bool> {
+                self.$($target)+.read_ring_indicator()
+            }
+
+            fn read_carrier_detect(&mut self) -> ::serialport::Result<bool> {
+                self.$($target)+.read_carrier_detect()
+            }
+
+            fn bytes_to_read(&self) -> ::serialport::Result<u32> {
+                self.$($target)+.bytes_to_read()
+            }
+
+            fn bytes_to_write(&self) -> ::serialport::Result<u32> {
+                self.$($target)+.bytes_to_write()
+            }
+
+            fn clear(&self, buffer_to_clear: ::serialport::ClearBuffer) -> ::serialport::Result<()> {
+                self.$($target)+.clear(buffer_to_clear)
+            }
+
+            fn try_clone(&self) -> ::serialport::Result<Box<dyn ::serialport::SerialPort>> {
+                self.$($target)+.try_clone()
+            }
+
+            fn set_break(&self) -> ::serialport::Result<()> {
+                self.$($target)+.set_break()
+            }
+
+            fn clear_break(&self) -> ::serialport::Result<()> {
+                self.$($target)+.clear_break()
+            }
+        }
+    };
+}
+
+pub(crate) use delegate_serial_port;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    /// Wrapper passing everything on, as pacing or mirroring ports do
+    struct Wrapper {
+        inner: NullSerial,
+    }
+
+    impl Write for Wrapper {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl io::Read for Wrapper {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    delegate_serial_port!(Wrapper, inner);
+
+    #[test]
+    fn test_settings_reach_the_delegate() {
+        let mut port: Box<dyn SerialPort> = Box::new(Wrapper { inner: NullSerial::new("stub", 115200) });
+        port.set_baud_rate(2000000).unwrap();
+        port.set_timeout(Duration::from_millis(50)).unwrap();
+        assert_eq!(port.baud_rate().unwrap(), 2000000);
+        assert_eq!(port.timeout(), Duration::from_millis(50));
+        assert_eq!(port.name().as_deref(), Some("stub"));
+        assert!(port.read_clear_to_send().unwrap());
+        assert!(port.try_clone().is_err());
+        assert_eq!(port.write(&[1, 2, 3]).unwrap(), 3);
+        assert_eq!(port.read(&mut [0; 4]).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
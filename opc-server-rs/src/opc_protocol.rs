@@ -0,0 +1,159 @@
+use anyhow::Result;
+
+/// Size of the OPC header: channel, command and big-endian data length
+pub const HEADER_SIZE: usize = 4;
+
+/// One complete OPC message, borrowed from the decoder's buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcMessage<'a> {
+    pub channel: u8,
+    pub command: u8,
+    pub data: &'a [u8],
+}
+
+/// Streaming OPC decoder: push received bytes in, take complete messages out
+///
+/// Bytes may arrive split anywhere, including inside a header, or with many messages
+/// coalesced into one read. Messages are returned in place, without copying; the
+/// buffer is compacted on the next `push`.
+#[derive(Debug)]
+pub struct OpcDecoder {
+    buffer: Vec<u8>,
+    /// Bytes at the front of `buffer` already returned as messages
+    consumed: usize,
+    max_message_size: usize,
+}
+
+impl OpcDecoder {
+    /// A decoder refusing messages whose data is longer than `max_message_size`
+    pub fn new(max_message_size: usize) -> Self {
+        OpcDecoder {
+            buffer: Vec::new(),
+            consumed: 0,
+            max_message_size,
+        }
+    }
+
+    /// Add received bytes
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.consumed > 0 {
+            self.buffer.drain(..self.consumed);
+            self.consumed = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Bytes received but not yet returned as messages
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.consumed
+    }
+
+    /// Channel of the next message, as soon as its header has arrived
+    pub fn next_channel(&self) -> Option<u8> {
+        let pending = &self.buffer[self.consumed..];
+        (pending.len() >= HEADER_SIZE).then(|| pending[0])
+    }
+
+    /// The next complete message, or None until more bytes arrive
+    ///
+    /// A header announcing more than `max_message_size` bytes is an error as soon as
+    /// it is complete, before the data is buffered; the stream can't be resynchronized
+    /// after that, so the connection should be dropped.
+    pub fn next_message(&mut self) -> Result<Option<OpcMessage<'_>>> {
+        let pending = &self.buffer[self.consumed..];
+        if pending.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let length = u16::from_be_bytes([pending[2], pending[3]]) as usize;
+        if length > self.max_message_size {
+            anyhow::bail!("OPC message of {} bytes exceeds max_message_size ({})", length, self.max_message_size);
+        }
+        if pending.len() < HEADER_SIZE + length {
+            return Ok(None);
+        }
+
+        let start = self.consumed;
+        self.consumed += HEADER_SIZE + length;
+        let message = &self.buffer[start..self.consumed];
+        Ok(Some(OpcMessage {
+            channel: message[0],
+            command: message[1],
+            data: &message[HEADER_SIZE..],
+        }))
+    }
+}
+
+/// Encode one OPC message (data longer than 65535 bytes doesn't fit the length field)
+pub fn encode(channel: u8, command: u8, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let length = u16::try_from(data.len())
+        .map_err(|_| anyhow::anyhow!("OPC message of {} bytes is too long to encode", data.len()))?;
+    out.extend_from_slice(&[channel, command]);
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(channel: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode(channel, 0, data, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Decode everything currently buffered into (channel, data) pairs
+    fn drain(decoder: &mut OpcDecoder) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        while let Some(m) = decoder.next_message().unwrap() {
+            messages.push((m.channel, m.data.to_vec()));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_fragmented_message() {
+        let bytes = message(3, &[1, 2, 3, 4, 5, 6]);
+        let mut decoder = OpcDecoder::new(u16::MAX as usize);
+
+        // One byte at a time, including through the header
+        for (i, byte) in bytes.iter().enumerate() {
+            assert!(drain(&mut decoder).is_empty(), "message complete after {} bytes", i);
+            decoder.push(&[*byte]);
+        }
+        assert_eq!(drain(&mut decoder), vec![(3, vec![1, 2, 3, 4, 5, 6])]);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_coalesced_messages() {
+        let mut bytes = message(0, &[255, 0, 0]);
+        bytes.extend(message(1, &[]));
+        bytes.extend(message(2, &[9, 9, 9]));
+        // Start of a fourth message
+        bytes.extend(&message(4, &[7, 7, 7])[..5]);
+
+        let mut decoder = OpcDecoder::new(u16::MAX as usize);
+        decoder.push(&bytes);
+        assert_eq!(drain(&mut decoder), vec![(0, vec![255, 0, 0]), (1, vec![]), (2, vec![9, 9, 9])]);
+        assert_eq!(decoder.buffered(), 5);
+
+        decoder.push(&[7, 7]);
+        assert_eq!(drain(&mut decoder), vec![(4, vec![7, 7, 7])]);
+    }
+
+    #[test]
+    fn test_oversized_length() {
+        let mut decoder = OpcDecoder::new(6);
+        decoder.push(&message(0, &[0; 6]));
+        assert_eq!(drain(&mut decoder).len(), 1);
+
+        // Refused as soon as the header is in, without waiting for the data
+        decoder.push(&[0, 0, 0, 7]);
+        assert!(decoder.next_message().is_err());
+
+        assert!(encode(0, 0, &vec![0; 70000], &mut Vec::new()).is_err());
+    }
+}
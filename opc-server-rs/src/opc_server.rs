@@ -1,48 +1,618 @@
 use anyhow::{Context, Result};
 use std::io::{Read, ErrorKind};
-use std::net::{TcpListener, TcpStream};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::config::Config;
+use crate::arbiter::{Arbiter, Source};
+use crate::channel_stats::ChannelStats;
+use crate::color::ColorCorrection;
+use crate::config::{Config, LoadOptions, OpcConfig, OutputConfig, OverflowPolicy, SourcesConfig};
+use crate::control::ControlServer;
+use crate::fadecandy::{self, FadecandyState, OPC_SYSEX};
+use crate::net::{self, Cidr};
+use crate::opc_protocol::OpcDecoder;
+use crate::health::HealthTracker;
+use crate::hotplug;
+use crate::input::{FadecandyInput, HyperionInput, InputSource, OpcWebSocketInput};
+use crate::limiter::RateLimiter;
 use crate::output::Output;
+use crate::pending::{PendingOutput, PendingOutputs};
+use crate::persist::{self, StateStore};
+use crate::record::{self, Recorder};
+use crate::relay::Relay;
+use crate::sched;
+use crate::schedule::{self, Schedule, ScheduleState};
+use crate::signals;
+use crate::stats::{self, StatsCollector, StatsFormat};
+use crate::strip_type;
+use crate::summary::{self, Summary};
+use crate::frame_sync::{self, FrameTick};
+use crate::sync::SyncGate;
+use crate::timed::{self, TimedFrames};
+use crate::tls::{self, TlsSession};
+use crate::systemd;
 
 const RECV_BUFFER_SIZE: usize = 16384; // 16KB
 
+/// Stop reading from a client once this much unparsed data is buffered
+const MAX_BUFFERED: usize = 256 * 1024; // 256KB
+
+/// Default time a client may leave a message incomplete before it is disconnected
+const DEFAULT_MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client has to send the auth token after connecting
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Information about a connected OPC client
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,
+    pub connected_at: SystemTime,
+}
+
+/// State shared between the OPC listener and auxiliary threads (stats, control API)
+#[derive(Clone)]
+pub struct SharedState {
+    pub config_path: String,
+    /// Overrides and strictness the config was loaded with, re-applied on reload
+    pub load_options: Arc<LoadOptions>,
+    pub outputs: Arc<RwLock<Vec<Output>>>,
+    /// Outputs whose ports could not be opened yet
+    pub pending: Arc<Mutex<PendingOutputs>>,
+    /// Time between attempts to open pending outputs; None disables retries
+    pub output_retry: Option<Duration>,
+    pub frames_received: Arc<AtomicU64>,
+    /// Messages dropped by input rate limiting
+    pub frames_throttled: Arc<AtomicU64>,
+    /// Messages rejected by validation (partial pixels, oversized, never completed)
+    pub messages_rejected: Arc<AtomicU64>,
+    /// Unix time in milliseconds of the last complete OPC message (0 = never)
+    pub last_message_ms: Arc<AtomicU64>,
+    /// Unix time in milliseconds the OPC accept/read loop last went round (systemd watchdog)
+    pub heartbeat_ms: Arc<AtomicU64>,
+    /// Pixel messages dropped because a higher-priority source holds the channel
+    pub frames_preempted: Arc<AtomicU64>,
+    /// Messages, bytes and the last frame of each OPC channel, as received
+    pub channel_stats: Arc<ChannelStats>,
+    /// Present when `sources` priorities are configured
+    pub arbiter: Option<Arc<Arbiter>>,
+    /// Limits messages from all sources together (`opc.max_total_messages_per_sec`)
+    pub input_limiter: Option<Arc<Mutex<RateLimiter>>>,
+    pub clients: Arc<Mutex<Vec<ClientInfo>>>,
+    pub running: Arc<AtomicBool>,
+    pub started: Instant,
+    pub started_at: SystemTime,
+    /// Global color correction applied to every output (also reapplied after reload)
+    pub color_correction: Arc<Mutex<Option<ColorCorrection>>>,
+    /// Present when Fadecandy emulation is enabled
+    pub fadecandy: Option<Arc<FadecandyState>>,
+    /// Present when the incoming stream is being recorded (`--record`)
+    pub recorder: Option<Arc<Mutex<Recorder>>>,
+    /// Present when `opc.sync_outputs` is enabled
+    pub sync: Option<Arc<SyncGate>>,
+    /// Present when a `schedule` is configured
+    pub schedule: Option<Arc<ScheduleState>>,
+    /// Downstream OPC servers (`relay`)
+    pub relays: Arc<Vec<Relay>>,
+    /// Present when runtime adjustments are kept across restarts (`persist_state`)
+    pub persist: Option<Arc<StateStore>>,
+    /// Present when `frame_sync` is configured
+    pub frame_tick: Option<Arc<FrameTick>>,
+    /// Present when `opc.timed_frames` is enabled
+    pub timed: Option<Arc<TimedFrames>>,
+    /// Error budget and reconnect history of each output (`health`)
+    pub health: Arc<HealthTracker>,
+    /// Listeners and captures the config enables, for the startup summary
+    pub inputs: Arc<Vec<summary::Input>>,
+    /// Leave out informational lines such as outputs being added (`--quiet`)
+    pub quiet: bool,
+    pub ddebug: bool,
+}
+
+impl SharedState {
+    /// Whether every output a message on `channel` would go to is too far behind to take it
+    ///
+    /// False when no running output listens to the channel, so such messages never hold
+    /// up a client.
+    pub fn channel_backed_up(&self, channel: u8) -> bool {
+        let outputs = self.outputs.read().unwrap();
+        let mut targets = outputs.iter()
+            .filter(|o| o.listens_to(channel) && o.is_enabled() && o.is_connected())
+            .peekable();
+        targets.peek().is_some() && targets.all(|o| o.is_backed_up())
+    }
+    
+    /// Apply input rate limits to one message, counting it if throttled
+    ///
+    /// The per-connection limiter, if any, is checked first so one flooding client
+    /// does not use up the global budget.
+    pub fn admit_message(&self, connection_limiter: Option<&mut RateLimiter>) -> bool {
+        let allowed = connection_limiter.map(|l| l.allow()).unwrap_or(true)
+            && self.input_limiter.as_ref().map(|l| l.lock().unwrap().allow()).unwrap_or(true);
+        
+        if !allowed {
+            self.frames_throttled.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+    
+    /// Whether the schedule (if any) currently lets the outputs light up
+    pub fn schedule_active(&self) -> bool {
+        self.schedule.as_ref().map(|s| s.is_active()).unwrap_or(true)
+    }
+    
+    /// Re-read the config file and rebuild all outputs
+    ///
+    /// The OPC listener and control server settings are not changed; those need a restart.
+    /// The new outputs are opened without holding the outputs lock, so frames keep flowing
+    /// to the old ones meanwhile, and swapped in at the end. Only old outputs on ports the
+    /// new config also uses are closed first, since a port can't be opened twice; if no
+    /// output opens, those are reopened and the rest of the old set is kept.
+    pub fn reload_outputs(&self, debug: bool, ddebug: bool) -> Result<usize> {
+        let config = Config::load_with(&self.config_path, &self.load_options)?;
+        
+        let reused: Vec<Output> = {
+            let mut outputs = self.outputs.write().unwrap();
+            let (reused, kept) = std::mem::take(&mut *outputs).into_iter()
+                .partition(|o| o.entries().iter().any(|e| config.outputs.iter().any(|c| c.port == e.port)));
+            *outputs = kept;
+            reused
+        };
+        let previous: Vec<Vec<OutputConfig>> = reused.iter().map(|o| o.entries().to_vec()).collect();
+        // Closed (applying their shutdown policy) outside the lock
+        drop(reused);
+        
+        let (opened, pending) = open_outputs(&config, self.sync.as_ref(), self.output_retry, debug, ddebug);
+        
+        if opened.is_empty() && self.output_retry.is_none() {
+            let mut restored = Vec::new();
+            for group in &previous {
+                match open_group(group, self.sync.as_ref(), debug, ddebug) {
+                    Ok(output) => restored.push(output),
+                    Err(e) => eprintln!("✗ Failed to reopen {}: {}", group[0].label(), e),
+                }
+            }
+            self.attach(&restored);
+            self.outputs.write().unwrap().extend(restored);
+            anyhow::bail!("No outputs could be opened; keeping the previous outputs");
+        }
+        
+        self.attach(&opened);
+        let count = opened.len();
+        let replaced = {
+            let mut outputs = self.outputs.write().unwrap();
+            self.pending.lock().unwrap().replace(pending);
+            std::mem::replace(&mut *outputs, opened)
+        };
+        // Outputs the new config no longer lists are closed outside the lock too
+        drop(replaced);
+        
+        Ok(count)
+    }
+    
+    /// Apply the server-wide settings to outputs about to join the pipeline
+    fn attach(&self, outputs: &[Output]) {
+        let lut = self.color_correction.lock().unwrap().as_ref().map(|c| Arc::new(c.build_lut()));
+        for output in outputs {
+            output.set_color_lut(lut.clone());
+            output.set_frame_tick(self.frame_tick.clone());
+            output.set_blanked(!self.schedule_active());
+            self.restore_adjustments(output);
+        }
+    }
+    
+    /// Try to open the pending outputs that are due, attaching the ones that come up
+    pub fn retry_pending(&self, debug: bool, ddebug: bool) {
+        let Some(interval) = self.output_retry else {
+            return;
+        };
+        
+        loop {
+            // Open without holding any lock: a WLED handshake can take seconds
+            let Some((generation, group)) = self.pending.lock().unwrap().next_due(Instant::now()) else {
+                return;
+            };
+            let result = open_group(&group, self.sync.as_ref(), debug, ddebug);
+            
+            let mut outputs = self.outputs.write().unwrap();
+            let mut pending = self.pending.lock().unwrap();
+            if pending.generation != generation {
+                // A reload replaced the pending list while the port was being opened;
+                // a stale output is dropped (and closed) outside the locks
+                drop(pending);
+                drop(outputs);
+                continue;
+            }
+            
+            match result {
+                Ok(output) => {
+                    let attempts = pending.remove(&group[0].port).map(|entry| entry.attempts).unwrap_or(0);
+                    let lut = self.color_correction.lock().unwrap().as_ref().map(|c| Arc::new(c.build_lut()));
+                    output.set_color_lut(lut);
+                    output.set_frame_tick(self.frame_tick.clone());
+                    output.set_blanked(!self.schedule_active());
+                    self.restore_adjustments(&output);
+                    if !self.quiet {
+                        println!("✓ Output {} attached after {} failed attempt(s)", group[0].label(), attempts);
+                    }
+                    self.health.record_reconnect(group[0].id());
+                    outputs.push(output);
+                }
+                Err(e) => {
+                    let attempts = pending.record_failure(&group[0].port, format!("{:#}", e), Instant::now() + interval);
+                    if let (true, Some(attempts)) = (debug, attempts) {
+                        println!("✗ {} still unavailable (attempt {}): {:#}", group[0].label(), attempts, e);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Open a new output at runtime, without touching the others or the OPC listener
+    ///
+    /// The output lasts until it is removed or the config is reloaded. Its port must not
+    /// be in use by another output, and its name must be unique.
+    pub fn add_output(&self, mut config: OutputConfig, debug: bool, ddebug: bool) -> Result<String> {
+        strip_type::apply(&mut config)?;
+        config.check()?;
+        let in_use = |outputs: &[Output], pending: &PendingOutputs| {
+            let entries = outputs.iter().flat_map(|o| o.entries())
+                .chain(pending.entries().iter().flat_map(|p| &p.group));
+            for entry in entries {
+                if entry.port == config.port {
+                    anyhow::bail!("Port {} is already used by {}", config.port, entry.label());
+                }
+                if config.name.is_some() && entry.name == config.name {
+                    anyhow::bail!("Output name '{}' is already used", entry.id());
+                }
+            }
+            Ok(())
+        };
+        in_use(&self.outputs.read().unwrap(), &self.pending.lock().unwrap())?;
+        
+        // Open without holding any lock: a WLED handshake can take seconds
+        let output = open_group(std::slice::from_ref(&config), self.sync.as_ref(), debug, ddebug)?;
+        
+        let mut outputs = self.outputs.write().unwrap();
+        in_use(&outputs, &self.pending.lock().unwrap())?;
+        let lut = self.color_correction.lock().unwrap().as_ref().map(|c| Arc::new(c.build_lut()));
+        output.set_color_lut(lut);
+        output.set_frame_tick(self.frame_tick.clone());
+        output.set_blanked(!self.schedule_active());
+        self.restore_adjustments(&output);
+        if !self.quiet {
+            println!("✓ Output {} added", config.label());
+        }
+        outputs.push(output);
+        Ok(config.id().to_string())
+    }
+    
+    /// Close an output (by name or port), applying its shutdown policy; pending outputs
+    /// are no longer retried. Returns false if no output matches.
+    pub fn remove_output(&self, id: &str) -> bool {
+        let matches = |entry: &OutputConfig| entry.name.as_deref() == Some(id) || entry.port == id;
+        
+        let mut outputs = self.outputs.write().unwrap();
+        if let Some(index) = outputs.iter().position(|o| o.entries().iter().any(matches)) {
+            let output = outputs.remove(index);
+            drop(outputs);
+            if !self.quiet {
+                println!("✓ Output {} removed", output.config().label());
+            }
+            // Dropping the output stops its worker, which closes the port
+            drop(output);
+            return true;
+        }
+        drop(outputs);
+        
+        let mut pending = self.pending.lock().unwrap();
+        let port = pending.entries().iter()
+            .find(|p| p.group.iter().any(matches))
+            .map(|p| p.port().to_string());
+        match port.and_then(|port| pending.discard(&port)) {
+            Some(entry) => {
+                if !self.quiet {
+                    println!("✓ Pending output {} removed", entry.label());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Number of pixels the outputs on `channel` take from it (0 when none listens)
+    pub fn channel_led_count(&self, channel: u8) -> usize {
+        self.outputs.read().unwrap().iter()
+            .flat_map(|o| match o.segments() {
+                [] => vec![(o.config().opc_channel, o.config().opc_offset + o.config().led_count)],
+                segments => segments.iter().map(|s| (s.opc_channel, s.opc_offset + s.led_count)).collect(),
+            })
+            .filter(|&(c, _)| c == channel)
+            .map(|(_, end)| end)
+            .max()
+            .unwrap_or(0)
+    }
+    
+    /// Give an output that just opened the brightness and enabled state it had been set to at runtime
+    fn restore_adjustments(&self, output: &Output) {
+        if let Some(store) = &self.persist {
+            store.restore(output);
+        }
+    }
+    
+    /// Remember a runtime change to an output (by id), if state is persisted
+    pub fn record_adjustment(&self, id: &str, brightness: Option<f32>, enabled: Option<bool>) {
+        if let Some(store) = &self.persist {
+            store.record_output(id, brightness, enabled);
+        }
+    }
+    
+    /// Set (or clear) the global color correction on all outputs
+    pub fn set_color_correction(&self, correction: Option<ColorCorrection>) {
+        let lut = correction.as_ref().map(|c| Arc::new(c.build_lut()));
+        for output in self.outputs.read().unwrap().iter() {
+            output.set_color_lut(lut.clone());
+        }
+        *self.color_correction.lock().unwrap() = correction;
+    }
+    
+    /// Handle one complete OPC message from any input
+    pub fn handle_opc_message(&self, source: Source, channel: u8, command: u8, data: &[u8]) {
+        self.handle_message_from(source, None, channel, command, data);
+    }
+    
+    /// Handle one complete OPC message from a connected client, which the channel
+    /// statistics name as the channel's last sender
+    pub fn handle_client_message(&self, source: Source, client: SocketAddr, channel: u8, command: u8, data: &[u8]) {
+        self.handle_message_from(source, Some(client), channel, command, data);
+    }
+    
+    fn handle_message_from(&self, source: Source, client: Option<SocketAddr>, channel: u8, command: u8, data: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.lock().unwrap().record(channel, command, data) {
+                eprintln!("✗ Recording failed: {}", e);
+            }
+        }
+        
+        match command {
+            0 => {
+                self.channel_stats.record(source, client, channel, data);
+                
+                // A higher-priority input is driving this channel
+                if !self.admit(source, channel) {
+                    return;
+                }
+                
+                // Downstream bridges get what this one shows, and keep their own schedules
+                for relay in self.relays.iter() {
+                    relay.forward(channel, command, data);
+                }
+                
+                self.show_admitted_pixels(channel, data);
+            }
+            OPC_SYSEX => {
+                // Timed frames are forwarded as they are, so downstream bridges show them at the same time
+                for relay in self.relays.iter() {
+                    relay.forward(channel, command, data);
+                }
+                match (&self.timed, timed::decode(data)) {
+                    (Some(timed), Some((time_us, pixels))) => {
+                        timed.schedule(source, channel, time_us, pixels);
+                    }
+                    _ => fadecandy::handle_sysex(self, self.fadecandy.as_deref(), data),
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Show pixels on a channel that were received earlier (timed frames), unless
+    /// another input has taken the channel since
+    pub fn show_pixels(&self, source: Source, channel: u8, data: &[u8]) {
+        if self.admit(source, channel) {
+            self.show_admitted_pixels(channel, data);
+        }
+    }
+    
+    /// Whether `source` may drive `channel`, counting the frame as preempted if not
+    fn admit(&self, source: Source, channel: u8) -> bool {
+        if let Some(arbiter) = &self.arbiter {
+            if !arbiter.admit(source, channel) {
+                self.frames_preempted.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+        true
+    }
+    
+    fn show_admitted_pixels(&self, channel: u8, data: &[u8]) {
+        // Outside the scheduled windows the outputs stay dark
+        if !self.schedule_active() {
+            return;
+        }
+        
+        // Set pixel colors
+        self.process_pixel_data(channel, data);
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.last_message_ms.store(stats::now_ms(), Ordering::Relaxed);
+    }
+    
+    /// Process OPC pixel data and distribute to outputs
+    fn process_pixel_data(&self, channel: u8, pixel_data: &[u8]) {
+        if self.ddebug {
+            eprintln!("[DEBUG] Received: channel={}, byte_count={}, pixel_count={}",
+                     channel, pixel_data.len(), pixel_data.len() / 3);
+            let hex: String = pixel_data.iter().take(30)
+                .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+            eprintln!("[DEBUG] First 30 bytes received: {}", hex);
+        }
+        
+        let outputs = self.outputs.read().unwrap();
+        
+        // In sync mode the message goes to all of its outputs or, while the previous one is
+        // still being sent, to none of them
+        if let Some(sync) = &self.sync {
+            let expected = outputs.iter()
+                .filter(|o| o.syncs_with(channel) && o.is_enabled() && o.is_connected())
+                .count();
+            if !sync.begin(channel, expected) {
+                return;
+            }
+        }
+        
+        // Distribute to each output listening to this channel
+        for output in outputs.iter() {
+            let output_config = output.config();
+            
+            // Shared ports place each message into their combined frame
+            if !output.segments().is_empty() {
+                let _ = output.send_segments(channel, pixel_data);
+                continue;
+            }
+            
+            // Check if this output listens to this channel
+            if output_config.opc_channel != channel {
+                continue;
+            }
+            
+            let needed_bytes = output_config.led_count * 3;
+            
+            // Cut this output's pixels from the message (wrapping if configured); short
+            // slices follow the output's `short_frames` policy
+            let Some(sliced_data) = output.frame_buffer(pixel_data) else {
+                if self.ddebug {
+                    eprintln!("[DEBUG] Output {}: dropped short frame ({} bytes from offset {})",
+                             output_config.label(), pixel_data.len(), output_config.opc_offset * 3);
+                }
+                // Don't hold up the other outputs of the sync round
+                let counted = output.syncs_with(channel) && output.is_enabled() && output.is_connected();
+                if let Some(sync) = self.sync.as_ref().filter(|_| counted) {
+                    sync.arrive(channel, sync.generation(channel));
+                }
+                continue;
+            };
+            
+            if self.ddebug {
+                eprintln!("[DEBUG] Output {}: sliced={} bytes ({} pixels), needed={} bytes",
+                         output_config.label(), sliced_data.len(), sliced_data.len() / 3, needed_bytes);
+                let hex: String = sliced_data.iter().take(30)
+                    .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+                eprintln!("[DEBUG] First 30 bytes to output: {}", hex);
+            }
+            
+            // Send to output (non-blocking, skip-ahead)
+            let _ = output.send_frame(sliced_data);
+        }
+    }
+}
+
 /// OPC Server that receives OPC data and distributes to serial outputs
 pub struct OpcServer {
     config: Config,
-    outputs: Vec<Output>,
-    frames_received: Arc<AtomicU64>,
-    running: Arc<AtomicBool>,
+    state: SharedState,
+    /// Parsed `opc.allowed_clients`; None admits every client
+    allowed_clients: Option<Vec<Cidr>>,
     debug: bool,
     ddebug: bool,
+    stats_format: StatsFormat,
+    /// Print `[Latency]` lines with the statistics (`--latency`)
+    latency_report: bool,
+    /// Print periodic statistics without `--debug` (`--stats`)
+    stats_report: bool,
+    stats_interval: Duration,
+}
+
+/// Time between statistics reports unless `--stats-interval` says otherwise
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the retry thread checks for pending outputs that are due
+const RETRY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Open every output in the config, logging the ones that fail
+///
+/// Failed outputs are returned as pending when retries are enabled, and skipped otherwise.
+fn open_outputs(
+    config: &Config,
+    sync: Option<&Arc<SyncGate>>,
+    retry: Option<Duration>,
+    debug: bool,
+    ddebug: bool,
+) -> (Vec<Output>, Vec<PendingOutput>) {
+    let mut outputs = Vec::new();
+    let mut pending = Vec::new();
+    
+    // Entries naming the same port share one output, in first-appearance order
+    let mut groups: Vec<Vec<OutputConfig>> = Vec::new();
+    for output_config in &config.outputs {
+        match groups.iter_mut().find(|g| g[0].port == output_config.port) {
+            Some(group) => group.push(output_config.clone()),
+            None => groups.push(vec![output_config.clone()]),
+        }
+    }
+    
+    for group in groups {
+        match open_group(&group, sync, debug, ddebug) {
+            Ok(output) => outputs.push(output),
+            Err(e) => match retry {
+                Some(interval) => {
+                    eprintln!("✗ Failed to open {}: {} (retrying every {:.1}s)", group[0].label(), e, interval.as_secs_f64());
+                    pending.push(PendingOutput::new(group, format!("{:#}", e), Instant::now() + interval));
+                }
+                None => eprintln!("✗ Failed to open {}: {}", group[0].label(), e),
+            },
+        }
+    }
+    
+    (outputs, pending)
+}
+
+/// Open one output; entries naming the same port share it
+fn open_group(group: &[OutputConfig], sync: Option<&Arc<SyncGate>>, debug: bool, ddebug: bool) -> Result<Output> {
+    if group.len() == 1 {
+        Output::new(group[0].clone(), sync.cloned(), debug, ddebug)
+    } else {
+        Output::new_shared(group, sync.cloned(), debug, ddebug)
+    }
 }
 
 impl OpcServer {
     /// Get a clone of the running flag for signal handlers
     pub fn get_running_flag(&self) -> Arc<AtomicBool> {
-        Arc::clone(&self.running)
+        Arc::clone(&self.state.running)
     }
     
-    /// Gracefully shutdown - send black frames to all outputs
+    /// State shared with the inputs, outputs and control servers
+    pub fn state(&self) -> &SharedState {
+        &self.state
+    }
+    
+    /// Gracefully shutdown - stop all outputs, applying each output's shutdown policy
     pub fn shutdown(&mut self) {
+        systemd::notify("STOPPING=1");
+        
         if self.debug {
             println!("Turning off LEDs...");
         }
         
-        for output in &self.outputs {
-            let config = output.config();
-            let black_data = vec![0u8; config.led_count * 3];
-            
-            // Send black frame
-            let _ = output.send_frame(black_data);
+        // Dropping an output stops its worker, which blanks/holds/fades/releases the LEDs
+        // according to its on_shutdown policy before the port is closed
+        self.state.outputs.write().unwrap().clear();
+        
+        if let Some(recorder) = &self.state.recorder {
+            if let Err(e) = recorder.lock().unwrap().flush() {
+                eprintln!("✗ Failed to finish recording: {}", e);
+            }
         }
         
-        // Give worker threads time to process the black frames
-        thread::sleep(Duration::from_millis(100));
+        // Changes from the last second before stopping
+        if let Some(store) = &self.state.persist {
+            if let Err(e) = store.save_if_changed() {
+                eprintln!("✗ {:#}", e);
+            }
+        }
         
         if self.debug {
             println!("✓ Server stopped");
@@ -50,71 +620,556 @@ impl OpcServer {
     }
     
     /// Create a new OPC server
-    pub fn new(config: Config, debug: bool, ddebug: bool) -> Result<Self> {
-        let mut outputs = Vec::new();
+    pub fn new(config: Config, config_path: String, debug: bool, ddebug: bool) -> Result<Self> {
+        let allowed_clients = match &config.opc.allowed_clients {
+            Some(list) => Some(list.iter()
+                .map(|entry| Cidr::parse(entry))
+                .collect::<Result<Vec<_>>>()
+                .context("Invalid opc.allowed_clients")?),
+            None => None,
+        };
+        
+        // Audio effects always need arbitration, so live inputs take over their channels
+        let arbiter = match (&config.sources, &config.audio) {
+            (Some(sources), _) => Some(Arc::new(Arbiter::from_config(sources)?)),
+            (None, Some(_)) => Some(Arc::new(Arbiter::from_config(&SourcesConfig::default())?)),
+            (None, None) => None,
+        };
+        
+        let sync = config.opc.sync_outputs.then(|| Arc::new(SyncGate::new()));
         
         // Initialize all outputs
-        for output_config in &config.outputs {
-            match Output::new(output_config.clone(), debug, ddebug) {
-                Ok(output) => outputs.push(output),
-                Err(e) => eprintln!("✗ Failed to open {}: {}", output_config.port, e),
+        let output_retry = config.output_retry();
+        let (outputs, pending) = open_outputs(&config, sync.as_ref(), output_retry, debug, ddebug);
+        
+        if outputs.is_empty() {
+            if output_retry.is_none() {
+                anyhow::bail!("No outputs could be opened");
             }
+            eprintln!("Warning: No outputs could be opened yet; waiting for them to appear");
         }
         
-        if outputs.is_empty() {
-            anyhow::bail!("No outputs could be opened");
+        let fadecandy = config.fadecandy.as_ref().map(|fc| Arc::new(FadecandyState::new(fc)));
+        let relays = config.relay.iter().flatten()
+            .map(|relay| Relay::spawn(relay, debug))
+            .collect();
+        let schedule = match &config.schedule {
+            Some(windows) => Some(Arc::new(ScheduleState::new(Schedule::from_config(windows)?))),
+            None => None,
+        };
+        // A state file that can't be read is left alone rather than overwritten
+        let persist = match config.state_file(&config_path).map(|path| StateStore::open(&path)) {
+            Some(Ok(store)) => Some(Arc::new(store)),
+            Some(Err(e)) => {
+                eprintln!("Warning: {:#}; runtime adjustments will not be kept", e);
+                None
+            }
+            None => None,
+        };
+        let frame_tick = config.frame_sync.as_ref().map(|fs| Arc::new(FrameTick::new(fs)));
+        for output in &outputs {
+            output.set_frame_tick(frame_tick.clone());
         }
         
-        Ok(OpcServer {
-            config,
-            outputs,
+        let state = SharedState {
+            config_path,
+            load_options: Arc::new(LoadOptions::default()),
+            outputs: Arc::new(RwLock::new(outputs)),
+            pending: Arc::new(Mutex::new(PendingOutputs::new(pending))),
+            output_retry,
             frames_received: Arc::new(AtomicU64::new(0)),
+            frames_throttled: Arc::new(AtomicU64::new(0)),
+            messages_rejected: Arc::new(AtomicU64::new(0)),
+            frames_preempted: Arc::new(AtomicU64::new(0)),
+            channel_stats: Arc::new(ChannelStats::new()),
+            last_message_ms: Arc::new(AtomicU64::new(0)),
+            heartbeat_ms: Arc::new(AtomicU64::new(stats::now_ms())),
+            arbiter,
+            input_limiter: config.opc.max_total_messages_per_sec
+                .map(|rate| Arc::new(Mutex::new(RateLimiter::new(rate)))),
+            clients: Arc::new(Mutex::new(Vec::new())),
             running: Arc::new(AtomicBool::new(true)),
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            color_correction: Arc::new(Mutex::new(None)),
+            fadecandy,
+            recorder: None,
+            sync,
+            schedule,
+            relays: Arc::new(relays),
+            persist,
+            frame_tick,
+            timed: config.opc.timed_frames.then(|| Arc::new(TimedFrames::new())),
+            health: Arc::new(HealthTracker::new(config.health.as_ref())),
+            inputs: Arc::new(summary::inputs(&config)),
+            quiet: false,
+            ddebug,
+        };
+        
+        // Fadecandy emulation starts with Fadecandy's default color curve
+        if let Some(fc) = &config.fadecandy {
+            let correction = fc.color.clone().unwrap_or_else(ColorCorrection::fadecandy_default);
+            state.set_color_correction(Some(correction));
+        }
+        
+        // Adjustments made at runtime before the last restart win over the config file
+        if let Some(store) = &state.persist {
+            for output in state.outputs.read().unwrap().iter() {
+                store.restore(output);
+            }
+            if let Some(correction) = store.color_correction() {
+                state.set_color_correction(Some(correction));
+            }
+            if debug {
+                println!("✓ Runtime adjustments are kept in {}", store.path().display());
+            }
+        }
+        
+        Ok(OpcServer {
+            config,
+            state,
+            allowed_clients,
             debug,
             ddebug,
+            stats_format: StatsFormat::default(),
+            latency_report: false,
+            stats_report: false,
+            stats_interval: DEFAULT_STATS_INTERVAL,
         })
     }
     
+    /// Print periodic statistics in this format; JSON stats are printed even without --debug
+    pub fn set_stats_format(&mut self, format: StatsFormat) {
+        self.stats_format = format;
+    }
+    
+    /// Also print each output's frame latency percentiles with the periodic statistics
+    /// (printed even without --debug)
+    pub fn set_latency_report(&mut self, enabled: bool) {
+        self.latency_report = enabled;
+    }
+    
+    /// Print periodic statistics even without --debug
+    pub fn set_stats_report(&mut self, enabled: bool) {
+        self.stats_report = enabled;
+    }
+    
+    /// Time between periodic statistics (default: `DEFAULT_STATS_INTERVAL`)
+    pub fn set_stats_interval(&mut self, interval: Duration) {
+        self.stats_interval = interval;
+    }
+    
+    /// Print nothing but errors, warnings and explicitly requested reports (call before `run`)
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.state.quiet = quiet;
+        if let Some(tick) = &self.state.frame_tick {
+            tick.set_quiet(quiet);
+        }
+    }
+    
+    /// Print the inputs, channels and outputs, marking the outputs that failed to open
+    /// (one JSON line with `--stats-format json`)
+    fn print_summary(&self) {
+        if self.state.quiet {
+            return;
+        }
+        let summary = Summary::collect(&self.state);
+        match self.stats_format {
+            StatsFormat::Text => print!("{}", summary.render(summary::use_color())),
+            StatsFormat::Json => println!("{}", serde_json::json!({ "summary": summary })),
+        }
+    }
+    
+    fn stats_enabled(&self) -> bool {
+        self.debug || self.stats_report || self.latency_report || self.stats_format == StatsFormat::Json
+    }
+    
+    /// Keep the options the config was loaded with, so reloads apply them again
+    pub fn set_load_options(&mut self, options: LoadOptions) {
+        self.state.load_options = Arc::new(options);
+    }
+    
+    /// Record every incoming OPC message to a file (call before `run`)
+    pub fn record_to(&mut self, path: &str) -> Result<()> {
+        self.state.recorder = Some(Arc::new(Mutex::new(Recorder::create(path)?)));
+        if self.debug {
+            println!("✓ Recording OPC stream to {}", path);
+        }
+        Ok(())
+    }
+    
+    /// Play a recording into the outputs instead of listening for clients
+    pub fn replay(&self, path: &str, speed: f64, looping: bool) -> Result<()> {
+        let messages = record::load(path)?;
+        
+        self.print_summary();
+        if self.debug {
+            let length = messages.last().map(|m| m.offset).unwrap_or_default();
+            println!("✓ Replaying {} messages ({:.1}s) from {} at {}x{}",
+                     messages.len(), length.as_secs_f64(), path, speed,
+                     if looping { ", looping" } else { "" });
+        }
+        if self.stats_enabled() {
+            self.spawn_stats_thread();
+        }
+        self.spawn_retry_thread();
+        self.spawn_schedule_thread();
+        self.spawn_persist_thread();
+        self.spawn_frame_sync()?;
+        if let Some(timed) = &self.state.timed {
+            timed::spawn(self.state.clone(), timed.clone());
+        }
+        
+        record::replay(&messages, speed, looping, &self.state.running, |message| {
+            self.state.handle_opc_message(Source::Replay, message.channel, message.command, &message.data);
+        });
+        
+        Ok(())
+    }
+    
     /// Run the OPC server
     pub fn run(&self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.opc.host, self.config.opc.port);
-        let listener = TcpListener::bind(&addr)
-            .context(format!("Failed to bind to {}", addr))?;
+        self.print_summary();
+        
+        // Spawn statistics thread if debug or JSON stats are enabled
+        if self.stats_enabled() {
+            self.spawn_stats_thread();
+        }
+        
+        self.spawn_retry_thread();
+        self.spawn_schedule_thread();
+        self.spawn_persist_thread();
+        self.spawn_frame_sync()?;
+        if let Some(timed) = &self.state.timed {
+            timed::spawn(self.state.clone(), timed.clone());
+        }
+        
+        if let Err(e) = signals::dump_on_sigusr1(self.state.clone()) {
+            eprintln!("Warning: Could not install SIGUSR1 handler: {}", e);
+        }
+        if let Err(e) = signals::inspect_on_sigusr2(self.state.clone()) {
+            eprintln!("Warning: Could not install SIGUSR2 handler: {}", e);
+        }
+        
+        // Spawn runtime control server if configured
+        if let Some(control_config) = &self.config.control {
+            ControlServer::new(
+                control_config.clone(),
+                self.state.clone(),
+                self.debug,
+                self.ddebug,
+            ).spawn()?;
+        }
+        
+        // Every listener and capture the config enables
+        let mut inputs = InputManager::from_config(&self.config, self.allowed_clients.clone(), self.debug, self.ddebug);
+        inputs.start_all(&self.state)?;
+        if self.debug {
+            println!("(Press Ctrl-C to stop)");
+        }
+        
+        // Outputs are open and every listener is bound
+        systemd::notify("READY=1");
+        systemd::spawn_watchdog(self.state.heartbeat_ms.clone(), self.state.running.clone(), self.debug);
+        
+        // The OPC accept loop keeps the heartbeat; without it, this loop does
+        while self.state.running.load(Ordering::Relaxed) {
+            if !self.config.opc.enabled {
+                self.state.heartbeat_ms.store(stats::now_ms(), Ordering::Relaxed);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        
+        // Waits for the OPC clients to finish their current message
+        inputs.stop_all();
+        Ok(())
+    }
+    
+    /// Keep trying outputs that failed to open, if retries are enabled, and watch for
+    /// devices being plugged in and removed
+    fn spawn_retry_thread(&self) {
+        if self.state.output_retry.is_none() {
+            return;
+        }
+        if self.config.hotplug() {
+            hotplug::spawn(self.state.clone(), self.debug);
+        }
+        let state = self.state.clone();
+        let (debug, ddebug) = (self.debug, self.ddebug);
+        
+        thread::spawn(move || {
+            while state.running.load(Ordering::Relaxed) {
+                thread::sleep(RETRY_POLL_INTERVAL);
+                state.retry_pending(debug, ddebug);
+            }
+        });
+    }
+    
+    fn spawn_frame_sync(&self) -> Result<()> {
+        match (&self.config.frame_sync, &self.state.frame_tick) {
+            (Some(config), Some(tick)) => {
+                frame_sync::spawn(config, tick.clone(), self.state.running.clone(), self.debug)
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    fn spawn_persist_thread(&self) {
+        if let Some(store) = &self.state.persist {
+            persist::spawn(self.state.clone(), store.clone());
+        }
+    }
+    
+    fn spawn_schedule_thread(&self) {
+        if let Some(schedule) = &self.state.schedule {
+            schedule::spawn(self.state.clone(), schedule.clone(), self.debug);
+        }
+    }
+    
+    /// Spawn statistics thread
+    fn spawn_stats_thread(&self) {
+        let state = self.state.clone();
+        let format = self.stats_format;
+        // Without --debug or --stats, --latency prints only the latency lines
+        let stats_lines = self.debug || self.stats_report || format == StatsFormat::Json;
+        let interval = self.stats_interval;
+        let latency_lines = self.latency_report && format == StatsFormat::Text;
+        
+        thread::spawn(move || {
+            let mut collector = StatsCollector::default();
+            
+            while state.running.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                let report = collector.collect(&state, interval);
+                if stats_lines {
+                    println!("{}", report.render(format));
+                }
+                if latency_lines {
+                    for line in report.latency_lines() {
+                        println!("{}", line);
+                    }
+                }
+            }
+        });
+    }
+    
+}
+
+/// The enabled inputs, each started with its own running flag so it can be stopped on
+/// its own; frames from all of them meet in the shared state
+pub struct InputManager {
+    inputs: Vec<(Box<dyn InputSource>, Arc<AtomicBool>)>,
+}
+
+impl InputManager {
+    pub fn new() -> Self {
+        InputManager { inputs: Vec::new() }
+    }
+    
+    /// Every input `config` enables, OPC over TCP first
+    pub fn from_config(config: &Config, allowed_clients: Option<Vec<Cidr>>, debug: bool, ddebug: bool) -> Self {
+        let mut manager = InputManager::new();
+        if config.opc.enabled {
+            manager.add(Box::new(OpcTcpInput::new(config.opc.clone(), allowed_clients.clone(), debug, ddebug)));
+        }
+        if let Some(port) = config.opc.websocket_port {
+            manager.add(Box::new(OpcWebSocketInput { config: config.opc.clone(), port, allowed_clients, debug }));
+        }
+        if let Some(fadecandy_config) = &config.fadecandy {
+            manager.add(Box::new(FadecandyInput {
+                config: fadecandy_config.clone(),
+                default_host: config.opc.host.clone(),
+                debug,
+            }));
+        }
+        if let Some(hyperion_config) = &config.hyperion {
+            manager.add(Box::new(HyperionInput {
+                config: hyperion_config.clone(),
+                default_host: config.opc.host.clone(),
+                debug,
+            }));
+        }
+        // The config loader rejects these without their features
+        #[cfg(feature = "ambilight")]
+        if let Some(ambilight_config) = &config.ambilight {
+            manager.add(Box::new(crate::input::AmbilightInput { config: ambilight_config.clone(), debug }));
+        }
+        #[cfg(feature = "audio")]
+        if let Some(audio_config) = &config.audio {
+            manager.add(Box::new(crate::input::AudioInput { config: audio_config.clone(), debug }));
+        }
+        manager
+    }
+    
+    pub fn add(&mut self, input: Box<dyn InputSource>) {
+        self.inputs.push((input, Arc::new(AtomicBool::new(false))));
+    }
+    
+    /// Start every input; if one fails, the ones already started are stopped again
+    pub fn start_all(&mut self, state: &SharedState) -> Result<()> {
+        for i in 0..self.inputs.len() {
+            let (input, running) = &mut self.inputs[i];
+            running.store(true, Ordering::Relaxed);
+            let mut input_state = state.clone();
+            input_state.running = Arc::clone(running);
+            if let Err(e) = input.start(input_state) {
+                running.store(false, Ordering::Relaxed);
+                self.stop_all();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Stop every input: all running flags are cleared first, so they wind down together
+    pub fn stop_all(&mut self) {
+        let mut started = Vec::new();
+        for (input, running) in &mut self.inputs {
+            if running.swap(false, Ordering::Relaxed) {
+                started.push(input);
+            }
+        }
+        for input in started {
+            input.stop();
+        }
+    }
+}
+
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// OPC clients over TCP on `opc.host`:`opc.port`
+pub struct OpcTcpInput {
+    config: OpcConfig,
+    allowed_clients: Option<Vec<Cidr>>,
+    debug: bool,
+    ddebug: bool,
+    accept_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl OpcTcpInput {
+    pub fn new(config: OpcConfig, allowed_clients: Option<Vec<Cidr>>, debug: bool, ddebug: bool) -> Self {
+        OpcTcpInput { config, allowed_clients, debug, ddebug, accept_handle: None }
+    }
+}
+
+impl InputSource for OpcTcpInput {
+    fn name(&self) -> &'static str {
+        "OPC"
+    }
+    
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        if self.config.tls.is_some() {
+            tls::check_openssl()?;
+        }
+        let listener = net::bind_listener(&self.config)?;
+        let addr = listener.local_addr()?;
         
         // Set nonblocking so accept() can check running flag periodically
         listener.set_nonblocking(true)?;
         
         if self.debug {
-            println!("✓ OPC Server listening on {}", addr);
+            println!("✓ OPC Server listening on {}{}", addr, if self.config.tls.is_some() { " (TLS)" } else { "" });
             println!("Waiting for OPC client connection...");
-            println!("(Press Ctrl-C to stop)");
         }
         
-        // Spawn statistics thread if debug enabled
-        if self.debug {
-            self.spawn_stats_thread();
+        let opc = OpcListener {
+            config: self.config.clone(),
+            allowed_clients: self.allowed_clients.clone(),
+            state,
+            debug: self.debug,
+            ddebug: self.ddebug,
+        };
+        self.accept_handle = Some(thread::spawn(move || opc.accept_loop(listener)));
+        Ok(())
+    }
+    
+    fn stop(&mut self) {
+        // The accept loop returns once every client thread has ended
+        if let Some(handle) = self.accept_handle.take() {
+            let _ = handle.join();
         }
+    }
+}
+
+/// Accept loop and client handling of the OPC TCP input
+struct OpcListener {
+    config: OpcConfig,
+    allowed_clients: Option<Vec<Cidr>>,
+    state: SharedState,
+    debug: bool,
+    ddebug: bool,
+}
+
+impl OpcListener {
+    fn accept_loop(&self, listener: TcpListener) {
+        // Client threads are started from this one, so they share its priority and CPUs
+        sched::apply("OPC listener", self.config.priority, self.config.cpu_affinity.as_deref(), self.debug);
         
-        loop {
+        // Clients are served on their own threads, which borrow the listener until they end
+        let active_clients = AtomicUsize::new(0);
+        thread::scope(|scope| loop {
             // Check if we should stop
-            if !self.running.load(Ordering::Relaxed) {
+            if !self.state.running.load(Ordering::Relaxed) {
                 break;
             }
+            self.state.heartbeat_ms.store(stats::now_ms(), Ordering::Relaxed);
+            
+            // Without max_clients one client is served at a time, so two clients never
+            // interleave their frames; the next connection waits in the backlog
+            if self.config.max_clients.is_none() && active_clients.load(Ordering::Relaxed) > 0 {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
             
             // Try to accept a connection
             match listener.accept() {
-                Ok((stream, peer_addr)) => {
-                    if self.debug {
-                        println!("✓ Client connected from {}", peer_addr);
+                Ok((mut stream, peer_addr)) => {
+                    if !self.is_allowed(peer_addr) {
+                        eprintln!("✗ Rejected connection from {} (not in allowed_clients)", peer_addr);
+                        continue;
                     }
-                    
-                    if let Err(e) = self.handle_client(stream) {
-                        eprintln!("Error handling client: {}", e);
+                    if let Some(max_clients) = self.config.max_clients {
+                        if active_clients.load(Ordering::Relaxed) >= max_clients {
+                            eprintln!("✗ Rejected connection from {} (max_clients {} reached)", peer_addr, max_clients);
+                            continue;
+                        }
                     }
                     
                     if self.debug {
-                        println!("Client disconnected");
+                        println!("✓ Client connected from {}", peer_addr);
                     }
+                    
+                    active_clients.fetch_add(1, Ordering::Relaxed);
+                    self.state.clients.lock().unwrap().push(ClientInfo {
+                        addr: peer_addr,
+                        connected_at: SystemTime::now(),
+                    });
+                    
+                    let active_clients = &active_clients;
+                    scope.spawn(move || {
+                        // With TLS the client is served from the decrypted stream
+                        let served = match &self.config.tls {
+                            Some(tls) => TlsSession::accept(&stream, tls)
+                                .and_then(|mut session| self.handle_client(&mut session.stream, peer_addr)),
+                            None => self.handle_client(&mut stream, peer_addr),
+                        };
+                        if let Err(e) = served {
+                            eprintln!("Error handling client {}: {}", peer_addr, e);
+                        }
+                        
+                        self.state.clients.lock().unwrap().retain(|c| c.addr != peer_addr);
+                        // Make room before closing, so a client reconnecting right away is let in
+                        active_clients.fetch_sub(1, Ordering::Relaxed);
+                        drop(stream);
+                        
+                        if self.debug {
+                            println!("Client {} disconnected", peer_addr);
+                        }
+                    });
                 }
                 Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
                     // No connection ready, sleep briefly to avoid busy-waiting
@@ -125,24 +1180,76 @@ impl OpcServer {
                     thread::sleep(Duration::from_millis(100));
                 }
             }
+        });
+    }
+    
+    /// Check a client address against `opc.allowed_clients`
+    fn is_allowed(&self, addr: SocketAddr) -> bool {
+        match &self.allowed_clients {
+            Some(allowed) => allowed.iter().any(|cidr| cidr.contains(addr.ip())),
+            None => true,
         }
+    }
+    
+    /// Read and check the shared-secret preamble (`auth_token` followed by a newline)
+    fn authenticate(&self, stream: &mut TcpStream, token: &str) -> Result<()> {
+        stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+        
+        let expected = format!("{}\n", token);
+        let mut received = vec![0u8; expected.len()];
+        stream.read_exact(&mut received)
+            .context("Client did not send the auth token")?;
         
+        if !net::secrets_match(expected.as_bytes(), &received) {
+            anyhow::bail!("Client sent an invalid auth token");
+        }
+        
+        stream.set_read_timeout(None)?;
         Ok(())
     }
     
     /// Handle a single client connection with NON-BLOCKING TCP reads
-    fn handle_client(&self, mut stream: TcpStream) -> Result<()> {
+    fn handle_client(&self, stream: &mut TcpStream, peer_addr: SocketAddr) -> Result<()> {
+        // Accepted sockets inherit nonblocking mode on some platforms
+        stream.set_nonblocking(false)?;
+        if let Some(token) = &self.config.auth_token {
+            self.authenticate(stream, token)?;
+        }
+        
         // CRITICAL: Set socket to non-blocking mode (like Python's setblocking(False))
         stream.set_nonblocking(true)
             .context("Failed to set socket to non-blocking mode")?;
         
-        let mut buffer = Vec::new();
+        if self.config.tcp_nodelay {
+            stream.set_nodelay(true)
+                .context("Failed to set TCP_NODELAY")?;
+        }
+        
+        let max_message_size = self.config.max_message_size.unwrap_or(u16::MAX as usize);
+        let mut decoder = OpcDecoder::new(max_message_size);
         let mut read_buf = vec![0u8; RECV_BUFFER_SIZE];
+        let mut limiter = self.config.max_messages_per_sec.map(RateLimiter::new);
         
-        while self.running.load(Ordering::Relaxed) {
+        let message_timeout = self.config.message_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_MESSAGE_TIMEOUT);
+        // Last time a message completed (or the client connected)
+        let mut last_progress = Instant::now();
+        let idle_timeout = self.config.idle_timeout_ms.map(Duration::from_millis);
+        // Last time the client sent anything
+        let mut last_data = Instant::now();
+        let backpressure = self.config.overflow == OverflowPolicy::Backpressure;
+        // Whether the next message is waiting for its outputs to make room
+        let mut held = false;
+        
+        while self.state.running.load(Ordering::Relaxed) {
+            self.state.heartbeat_ms.store(stats::now_ms(), Ordering::Relaxed);
+            
             // NON-BLOCKING TCP DRAIN: Read all available data (like Python)
             // This loop continues until we get WouldBlock (no more data available)
-            loop {
+            // Stop early if the client outpaces parsing, so the buffer stays bounded,
+            // or while a message is held for backpressure, so TCP slows the client down
+            while !held && decoder.buffered() < MAX_BUFFERED {
                 match stream.read(&mut read_buf) {
                     Ok(0) => {
                         // Connection closed by client
@@ -150,7 +1257,8 @@ impl OpcServer {
                     }
                     Ok(n) => {
                         // Got data, append to buffer and continue draining
-                        buffer.extend_from_slice(&read_buf[..n]);
+                        decoder.push(&read_buf[..n]);
+                        last_data = Instant::now();
                     }
                     Err(e) if e.kind() == ErrorKind::WouldBlock => {
                         // No more data available right now - this is expected in non-blocking mode
@@ -167,27 +1275,61 @@ impl OpcServer {
                 }
             }
             
-            // Process complete OPC messages from buffer
-            while buffer.len() >= 4 {
-                // OPC header: channel (1 byte), command (1 byte), length (2 bytes, big-endian)
-                let channel = buffer[0];
-                let command = buffer[1];
-                let length = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
-                
-                // Check if we have the complete message
-                let message_size = 4 + length;
-                if buffer.len() < message_size {
-                    break; // Wait for more data
+            // Process complete OPC messages in place
+            let mut completed = 0;
+            held = false;
+            loop {
+                // Leave the message in the buffer until one of its outputs has room
+                if backpressure {
+                    if let Some(channel) = decoder.next_channel() {
+                        if self.state.channel_backed_up(channel) {
+                            held = true;
+                            break;
+                        }
+                    }
                 }
                 
-                // Extract and process message
-                let message_data: Vec<u8> = buffer.drain(..message_size).skip(4).collect();
+                // Refuse to buffer oversized messages at all
+                let message = match decoder.next_message() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break, // Wait for more data
+                    Err(e) => {
+                        self.state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+                        anyhow::bail!("{}, disconnecting", e);
+                    }
+                };
+                completed += 1;
+                
+                // Pixel data must hold whole RGB triples when configured
+                if message.command == 0 && self.config.reject_partial_pixels && !message.data.len().is_multiple_of(3) {
+                    self.state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+                    if self.ddebug {
+                        eprintln!("[DEBUG] Rejected pixel data of {} bytes (not a multiple of 3)", message.data.len());
+                    }
+                    continue;
+                }
                 
-                // Process OPC message
-                if command == 0 {
-                    // Set pixel colors
-                    self.process_pixel_data(channel, &message_data);
-                    self.frames_received.fetch_add(1, Ordering::Relaxed);
+                // Process OPC message, unless it exceeds the input rate limits
+                if self.state.admit_message(limiter.as_mut()) {
+                    self.state.handle_client_message(Source::Opc, peer_addr, message.channel, message.command, message.data);
+                }
+            }
+            
+            // Drop clients that start a message and never finish it
+            if completed > 0 || decoder.buffered() == 0 || held {
+                last_progress = Instant::now();
+            } else if last_progress.elapsed() > message_timeout {
+                self.state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+                anyhow::bail!("Incomplete OPC message for over {:?}, disconnecting", message_timeout);
+            }
+            
+            // Drop clients that went quiet, which includes ones that vanished without closing
+            // the connection; a held client is quiet because it isn't being read
+            if held {
+                last_data = Instant::now();
+            } else if let Some(idle_timeout) = idle_timeout {
+                if last_data.elapsed() > idle_timeout {
+                    anyhow::bail!("No data for over {:?}, disconnecting", idle_timeout);
                 }
             }
             
@@ -198,85 +1340,90 @@ impl OpcServer {
         Ok(())
     }
     
-    /// Process OPC pixel data and distribute to outputs
-    fn process_pixel_data(&self, channel: u8, pixel_data: &[u8]) {
-        if self.ddebug {
-            eprintln!("[DEBUG] Received: channel={}, byte_count={}, pixel_count={}",
-                     channel, pixel_data.len(), pixel_data.len() / 3);
-            let hex: String = pixel_data.iter().take(30)
-                .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
-            eprintln!("[DEBUG] First 30 bytes received: {}", hex);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Input that records the running flag it was started with
+    struct FlagInput {
+        running: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+        fail: bool,
+    }
+    
+    impl InputSource for FlagInput {
+        fn name(&self) -> &'static str {
+            "test"
         }
         
-        // Distribute to each output listening to this channel
-        for output in &self.outputs {
-            let output_config = output.config();
-            
-            // Check if this output listens to this channel
-            if output_config.opc_channel != channel {
-                continue;
-            }
-            
-            // Calculate byte offset and length for this output
-            let offset_bytes = output_config.opc_offset * 3; // RGB stride
-            let needed_bytes = output_config.led_count * 3;
-            
-            // Slice data for this output - send exactly what we get, AWA header will match
-            let end_byte = (offset_bytes + needed_bytes).min(pixel_data.len());
-            let sliced_data = if offset_bytes < pixel_data.len() {
-                pixel_data[offset_bytes..end_byte].to_vec()
-            } else {
-                // No data for this output
-                Vec::new()
-            };
-            
-            if self.ddebug {
-                eprintln!("[DEBUG] Output {}: sliced={} bytes ({} pixels), needed={} bytes",
-                         output_config.port, sliced_data.len(), sliced_data.len() / 3, needed_bytes);
-                let hex: String = sliced_data.iter().take(30)
-                    .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
-                eprintln!("[DEBUG] First 30 bytes to output: {}", hex);
+        fn start(&mut self, state: SharedState) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("could not start");
             }
-            
-            // Send to output (non-blocking, skip-ahead)
-            let _ = output.send_frame(sliced_data);
+            *self.running.lock().unwrap() = Some(state.running);
+            Ok(())
         }
     }
     
-    /// Spawn statistics thread
-    fn spawn_stats_thread(&self) {
-        let frames_received = Arc::clone(&self.frames_received);
-        let running = Arc::clone(&self.running);
-        let output_counters: Vec<_> = self.outputs.iter().map(|o| {
-            (o.config().port.clone(), o.frames_sent_counter())
-        }).collect();
+    #[test]
+    fn test_inputs_stop_without_stopping_the_server() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "opc": {"host": "127.0.0.1", "port": 0, "enabled": false},
+            "outputs": [{"port": "mock-inputs", "hardware_type": "mock", "protocol": "awa",
+                         "baud_rate": 2000000, "opc_channel": 0, "led_count": 1}],
+        })).unwrap();
+        let server = OpcServer::new(config, String::new(), false, false).unwrap();
+        let started = Arc::new(Mutex::new(None));
         
-        thread::spawn(move || {
-            let mut last_received = 0u64;
-            let mut last_sent: Vec<u64> = vec![0; output_counters.len()];
-            
-            while running.load(Ordering::Relaxed) {
-                thread::sleep(Duration::from_secs(5));
-                
-                let current_received = frames_received.load(Ordering::Relaxed);
-                let received_delta = current_received - last_received;
-                let received_fps = received_delta as f64 / 5.0;
-                
-                print!("[Stats] Received: {:.1} fps", received_fps);
-                
-                for (i, (port, counter)) in output_counters.iter().enumerate() {
-                    let current = counter.load(Ordering::Relaxed);
-                    let delta = current - last_sent[i];
-                    let fps = delta as f64 / 5.0;
-                    print!(", {}: {:.1} fps", port, fps);
-                    last_sent[i] = current;
-                }
-                
-                println!();
-                
-                last_received = current_received;
-            }
-        });
+        let mut inputs = InputManager::new();
+        inputs.add(Box::new(FlagInput { running: started.clone(), fail: false }));
+        inputs.start_all(&server.state).unwrap();
+        let running = started.lock().unwrap().clone().unwrap();
+        assert!(running.load(Ordering::Relaxed));
+        inputs.stop_all();
+        assert!(!running.load(Ordering::Relaxed));
+        assert!(server.state.running.load(Ordering::Relaxed));
+        
+        // An input that fails to start stops the ones started before it
+        let mut inputs = InputManager::new();
+        inputs.add(Box::new(FlagInput { running: started.clone(), fail: false }));
+        inputs.add(Box::new(FlagInput { running: started.clone(), fail: true }));
+        assert!(inputs.start_all(&server.state).is_err());
+        assert!(!started.lock().unwrap().as_ref().unwrap().load(Ordering::Relaxed));
     }
     
+    #[test]
+    fn test_failed_reload_keeps_previous_outputs() {
+        let path = std::env::temp_dir().join(format!("opc-reload-test-{}.json", std::process::id()));
+        let write_config = |outputs: serde_json::Value| {
+            let config = serde_json::json!({"opc": {"host": "127.0.0.1", "port": 0}, "output_retry_ms": 0,
+                                            "persist_state": false, "outputs": outputs});
+            std::fs::write(&path, config.to_string()).unwrap();
+        };
+        let mock = |port: &str| serde_json::json!({"port": port, "hardware_type": "mock", "protocol": "awa",
+                                                   "baud_rate": 2000000, "opc_channel": 0, "led_count": 1});
+        write_config(serde_json::json!([mock("mock-reload-a"), mock("mock-reload-b")]));
+        let config = Config::load_with(path.to_str().unwrap(), &LoadOptions::default()).unwrap();
+        let server = OpcServer::new(config, path.to_string_lossy().into_owned(), false, false).unwrap();
+        let ids = || {
+            let mut ids: Vec<String> = server.state.outputs.read().unwrap().iter()
+                .map(|o| o.config().id().to_string()).collect();
+            ids.sort();
+            ids
+        };
+        
+        // The reused port is closed for the reopen; when that fails it is reopened as before,
+        // and the output the new config dropped was never closed
+        let mut serial = mock("mock-reload-a");
+        serial.as_object_mut().unwrap().remove("hardware_type");
+        write_config(serde_json::json!([serial]));
+        assert!(server.state.reload_outputs(false, false).is_err());
+        assert_eq!(ids(), vec!["mock-reload-a", "mock-reload-b"]);
+        
+        write_config(serde_json::json!([mock("mock-reload-b"), mock("mock-reload-c")]));
+        assert_eq!(server.state.reload_outputs(false, false).unwrap(), 2);
+        assert_eq!(ids(), vec!["mock-reload-b", "mock-reload-c"]);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::arbiter::Source;
+use crate::config::OpcConfig;
+use crate::http;
+use crate::limiter::RateLimiter;
+use crate::net::{self, Cidr};
+use crate::opc_server::{ClientInfo, SharedState};
+use crate::websocket::{self, Message, WebSocket};
+
+/// OPC over WebSocket (`opc.websocket_port`), for browser-based editors and web tools
+///
+/// Every binary message is one OPC message: the 4-byte header followed by its data
+/// (the length field is not needed and ignored). Messages go through the same checks
+/// and limits as TCP clients; the `auth_token`, if set, is passed as `?token=`.
+pub struct OpcWebSocketServer {
+    config: OpcConfig,
+    port: u16,
+    allowed_clients: Option<Vec<Cidr>>,
+    state: SharedState,
+    debug: bool,
+}
+
+impl OpcWebSocketServer {
+    pub fn new(config: OpcConfig, port: u16, allowed_clients: Option<Vec<Cidr>>, state: SharedState, debug: bool) -> Self {
+        OpcWebSocketServer { config, port, allowed_clients, state, debug }
+    }
+
+    /// Bind the WebSocket listener and serve clients on background threads
+    pub fn spawn(self) -> Result<()> {
+        let addr = net::resolve(&self.config.host, self.port)?;
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to bind OPC WebSocket server to {}", addr))?;
+
+        // Nonblocking so the accept loop can observe the running flag
+        listener.set_nonblocking(true)?;
+
+        if self.debug {
+            println!("✓ OPC WebSocket server listening on ws://{}", addr);
+        }
+
+        thread::spawn(move || {
+            while self.state.running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer_addr)) => self.accept(stream, peer_addr),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        eprintln!("Error accepting OPC WebSocket connection: {}", e);
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn accept(&self, stream: TcpStream, peer_addr: SocketAddr) {
+        let allowed = match &self.allowed_clients {
+            Some(allowed) => allowed.iter().any(|cidr| cidr.contains(peer_addr.ip())),
+            None => true,
+        };
+        if !allowed {
+            eprintln!("✗ Rejected WebSocket connection from {} (not in allowed_clients)", peer_addr);
+            return;
+        }
+
+        let config = self.config.clone();
+        let state = self.state.clone();
+        let debug = self.debug;
+        thread::spawn(move || {
+            if debug {
+                println!("✓ WebSocket client connected from {}", peer_addr);
+            }
+            state.clients.lock().unwrap().push(ClientInfo {
+                addr: peer_addr,
+                connected_at: SystemTime::now(),
+            });
+            if let Err(e) = handle_connection(stream, peer_addr, &config, &state) {
+                eprintln!("WebSocket client error: {}", e);
+            }
+            state.clients.lock().unwrap().retain(|c| c.addr != peer_addr);
+            if debug {
+                println!("WebSocket client {} disconnected", peer_addr);
+            }
+        });
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, peer_addr: SocketAddr, config: &OpcConfig, state: &SharedState) -> Result<()> {
+    // Accepted sockets inherit nonblocking mode on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    if config.tcp_nodelay {
+        stream.set_nodelay(true).context("Failed to set TCP_NODELAY")?;
+    }
+
+    let request = http::read_request(&mut stream)?;
+    if !websocket::is_upgrade_request(&request) {
+        return http::write_response(&mut stream, 400, "text/plain", b"OPC WebSocket endpoint\n");
+    }
+    if let Some(token) = &config.auth_token {
+        let received = request.query_param("token").unwrap_or_default();
+        if !net::secrets_match(token.as_bytes(), received.as_bytes()) {
+            http::write_response(&mut stream, 401, "text/plain", b"Invalid auth token\n")?;
+            anyhow::bail!("Client sent an invalid auth token");
+        }
+    }
+
+    let mut ws = WebSocket::accept(stream.try_clone()?, &request)?;
+
+    // Short timeout so the loop can observe the running flag
+    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    let mut limiter = config.max_messages_per_sec.map(RateLimiter::new);
+    let max_message_size = config.max_message_size.unwrap_or(u16::MAX as usize);
+
+    while state.running.load(Ordering::Relaxed) {
+        let packet = match ws.read_message()? {
+            None => continue,
+            Some(Message::Close) => break,
+            Some(Message::Text(_)) => continue,
+            Some(Message::Binary(packet)) => packet,
+        };
+        if packet.len() < 4 {
+            state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+
+        let (channel, command, data) = (packet[0], packet[1], &packet[4..]);
+        if data.len() > max_message_size {
+            state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+            anyhow::bail!("OPC message of {} bytes exceeds max_message_size ({}), disconnecting",
+                          data.len(), max_message_size);
+        }
+        if command == 0 && config.reject_partial_pixels && !data.len().is_multiple_of(3) {
+            state.messages_rejected.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+
+        if state.admit_message(limiter.as_mut()) {
+            state.handle_client_message(Source::Opc, peer_addr, channel, command, data);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    use crate::config::{Config, OutputConfig};
+    use crate::mock_serial;
+    use crate::opc_server::OpcServer;
+
+    /// Connect a client to `handle_connection` running on its own thread
+    fn connect(config: &OpcConfig, state: &SharedState) -> (TcpStream, thread::JoinHandle<Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, peer_addr) = listener.accept().unwrap();
+        let (config, state) = (config.clone(), state.clone());
+        let handle = thread::spawn(move || handle_connection(stream, peer_addr, &config, &state));
+        (client, handle)
+    }
+
+    fn upgrade(client: &mut TcpStream, token: &str) -> String {
+        write!(client, "GET /?token={} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n", token).unwrap();
+        let mut response = Vec::new();
+        let mut byte = [0u8];
+        while !response.ends_with(b"\r\n\r\n") && client.read(&mut byte).unwrap() == 1 {
+            response.push(byte[0]);
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    #[test]
+    fn test_binary_messages_reach_outputs() {
+        let port = "mock-opc-websocket";
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "opc": {"host": "127.0.0.1", "port": 0, "enabled": false, "auth_token": "secret"},
+            "persist_state": false,
+            "outputs": [OutputConfig { hardware_type: Some("mock".to_string()), led_count: 1, ..OutputConfig::test_default(port) }],
+        })).unwrap();
+        let opc = config.opc.clone();
+        let server = OpcServer::new(config, String::new(), false, false).unwrap();
+        let state = server.state();
+
+        // A wrong token is turned away before the upgrade
+        let (mut client, handle) = connect(&opc, state);
+        assert!(upgrade(&mut client, "wrong").starts_with("HTTP/1.1 401"));
+        assert!(handle.join().unwrap().is_err());
+
+        let (mut client, handle) = connect(&opc, state);
+        assert!(upgrade(&mut client, "secret").starts_with("HTTP/1.1 101"));
+        // Unmasked binary frames: one OPC message, then one too short to have a header
+        client.write_all(&[0x82, 7, 0, 0, 0, 3, 10, 20, 30]).unwrap();
+        client.write_all(&[0x82, 2, 0, 0]).unwrap();
+        let frames = mock_serial::wait_for_frames(port, 1, Duration::from_secs(2));
+        assert_eq!(&frames[0][6..9], &[10, 20, 30]);
+
+        client.write_all(&[0x88, 0]).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(state.messages_rejected.load(Ordering::Relaxed), 1);
+    }
+}
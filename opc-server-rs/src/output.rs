@@ -1,134 +1,526 @@
 use anyhow::{Context, Result};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::mpsc::{self, SyncSender, Receiver, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
-use std::io::{Read, Write};
-use serialport::SerialPort;
+use std::time::{Duration, Instant};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use serialport::{ClearBuffer, SerialPort};
 
-use crate::config::OutputConfig;
-use crate::pixel_format::transform_pixels;
-use crate::protocol::{build_awa_frame, build_adalight_frame};
+use crate::autobaud;
+use crate::color::{ColorLut, ColorTemperature, LinearLight};
+use crate::config::{OutputConfig, ShortFramePolicy, ShutdownPolicy, StartPattern};
+use crate::pacing::PacedPort;
+use crate::pixel_format;
+use crate::power::{PowerLimit, SoftStart};
+use crate::script::FrameScript;
+use crate::pool::BufferPool;
+use crate::protocol::{self, wait_for_ack, AckMode, AckResult, Protocol};
+use crate::queue::FrameQueue;
+use crate::simulator::SimulatorPort;
+use crate::sink::{self, SinkPort};
+use crate::smoothing::Smoothing;
+use crate::delay::DelayLine;
+use crate::effects::Standby;
+use crate::inspect::{self, Capture, Inspection};
+use crate::latency::LatencySamples;
+use crate::matrix::Matrix;
+use crate::transform::TransformChain;
+use crate::mirror::{Mirror, MirroredPort};
+use crate::mock_serial::MockPort;
+use crate::segment::{self, Segment};
+use crate::sched;
+use crate::serial;
+use crate::stats;
+use crate::frame_sync::FrameTick;
+use crate::sync::{SyncGate, SYNC_TIMEOUT};
+use crate::writer::{FrameStamp, ThreadedPort};
 
-/// All supported WLED baud rates in priority order
-const WLED_BAUD_RATES: &[u32] = &[
-    115200,   // Default WLED speed
-    230400,
-    460800,
-    500000,
-    576000,
-    921600,
-    1000000,
-    1500000,
-    2000000,
-];
+/// Runtime state shared between an output handle and its worker thread
+pub struct OutputState {
+    pub frames_sent: AtomicU64,
+    /// Bytes written to the port, including protocol headers
+    pub bytes_sent: AtomicU64,
+    /// Frames discarded by the queue policy (or delay line) before reaching the port
+    pub frames_dropped: AtomicU64,
+    /// Frames not written because they were identical to the last one (`dedupe`)
+    pub frames_deduped: AtomicU64,
+    /// Frames abandoned because they missed the write deadline (`write_deadline_ms`)
+    pub frames_late: AtomicU64,
+    /// Time spent pausing between the chunks of frames (`inter_chunk_delay_us`)
+    pub pacing_us: AtomicU64,
+    /// Unix time in milliseconds of the last frame written (0 = never)
+    pub last_write_ms: AtomicU64,
+    pub errors: AtomicU64,
+    pub running: AtomicBool,
+    pub enabled: AtomicBool,
+    pub blanked: AtomicBool,
+    pub connected: AtomicBool,
+    /// Brightness scale applied before transforms (255 = full)
+    pub brightness: AtomicU8,
+    /// Color correction table applied before brightness, if any
+    pub color_lut: RwLock<Option<Arc<ColorLut>>>,
+    /// Network tick each frame waits for before it is written (`frame_sync`)
+    pub frame_tick: RwLock<Option<Arc<FrameTick>>>,
+    /// Last RGB frame after runtime adjustments (what the strip shows, before color-order
+    /// transforms), used for fade-outs and the live preview
+    pub last_frame: Mutex<Vec<u8>>,
+    /// Write+flush times since the stats thread last read them
+    pub write_latency: Mutex<LatencySamples>,
+    /// Time from each frame reaching the output (`send_frame`) to its flush completing,
+    /// since the stats thread last read them
+    pub frame_latency: Mutex<LatencySamples>,
+    /// Frame inspection in progress, fed the next frames the worker builds
+    pub capture: Mutex<Option<Inspection>>,
+}
+
+impl OutputState {
+    pub(crate) fn new(brightness: u8) -> Self {
+        OutputState {
+            frames_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            frames_dropped: AtomicU64::new(0),
+            frames_deduped: AtomicU64::new(0),
+            frames_late: AtomicU64::new(0),
+            pacing_us: AtomicU64::new(0),
+            last_write_ms: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            running: AtomicBool::new(true),
+            enabled: AtomicBool::new(true),
+            blanked: AtomicBool::new(false),
+            connected: AtomicBool::new(true),
+            brightness: AtomicU8::new(brightness),
+            color_lut: RwLock::new(None),
+            frame_tick: RwLock::new(None),
+            last_frame: Mutex::new(Vec::new()),
+            write_latency: Mutex::new(LatencySamples::default()),
+            frame_latency: Mutex::new(LatencySamples::default()),
+            capture: Mutex::new(None),
+        }
+    }
+}
 
 /// LED output handler with dedicated worker thread
 pub struct Output {
     config: OutputConfig,
-    sender: SyncSender<Vec<u8>>,
-    frames_sent: Arc<AtomicU64>,
-    running: Arc<AtomicBool>,
+    /// Config entries this output was opened from (several for a shared port)
+    entries: Vec<OutputConfig>,
+    queue: Arc<FrameQueue>,
+    /// Frame buffers recycled between the OPC reader and the worker
+    pool: Arc<BufferPool>,
+    state: Arc<OutputState>,
+    /// Port and runtime state of each mirror fed by this output's worker
+    mirrors: Vec<(String, Arc<OutputState>)>,
+    /// Frames wait here for `delay_ms` before reaching the queue, if configured
+    delay: Option<Arc<DelayLine>>,
+    delay_handle: Option<thread::JoinHandle<()>>,
+    /// Entries combined on a shared serial port; empty for a regular output
+    segments: Vec<Segment>,
+    /// Combined RGB frame of a shared port, updated one segment at a time; for a
+    /// regular output, the last full frame kept for `short_frames: "pad_last"`
+    composite: Mutex<Vec<u8>>,
+    /// Whether the worker takes part in sync rounds (`opc.sync_outputs`)
+    synced: bool,
+    /// Set once a message has covered this output's pixels, or a warning said it did not
+    range_checked: AtomicBool,
+    /// Panel wiring that frames are reordered into, if `matrix` is configured
+    matrix: Option<Matrix>,
+    /// `transforms`, then `opc_offset` and `wrap` or `resample`, run on each message's channel data
+    routing: TransformChain,
+    /// Table of the output's own `color`, used while no global correction is set
+    own_lut: Option<Arc<ColorLut>>,
     worker_handle: Option<thread::JoinHandle<()>>,
 }
 
 impl Output {
     /// Create a new output handler
-    pub fn new(config: OutputConfig, debug: bool, ddebug: bool) -> Result<Self> {
-        // Handle WLED devices with baud rate detection
-        let port = if config.hardware_type.as_deref() == Some("WLED") {
-            Self::open_wled_port(&config, debug, ddebug)?
+    ///
+    /// With a sync gate, the worker waits for the other outputs of each OPC message
+    /// before transmitting (`opc.sync_outputs`).
+    pub fn new(mut config: OutputConfig, sync: Option<Arc<SyncGate>>, debug: bool, ddebug: bool) -> Result<Self> {
+        // Reopening (after a replug) detects the rate again
+        let entry = config.clone();
+        
+        // The protocol opens the port, so devices needing a handshake (WLED) can do it first
+        let mut protocol = protocol::for_output(&config)?;
+        let port = if config.hardware_type.as_deref() == Some("simulator") {
+            // Terminal preview instead of a serial port
+            Box::new(SimulatorPort::new(&config)) as Box<dyn SerialPort>
+        } else if config.hardware_type.as_deref() == Some("mock") {
+            // In-memory frame log for tests
+            Box::new(MockPort::new(&config)) as Box<dyn SerialPort>
+        } else if sink::enabled() {
+            // --dry-run: no device, no handshake
+            Box::new(SinkPort::open(&config)?) as Box<dyn SerialPort>
+        } else if config.is_autobaud() {
+            // Mirrors and the logs below use the rate that was found
+            let (baud, port) = autobaud::detect(&config, protocol.as_ref(), debug, ddebug)?;
+            config.baud_rate = baud;
+            port
         } else {
-            // Standard port opening for non-WLED devices
-            Self::open_standard_port(&config)?
+            protocol.connect(&config, debug, ddebug)?
         };
         
-        // Create BOUNDED channel with capacity 1 for skip-ahead behavior (like Python Queue(maxsize=1))
-        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(1);
-        
         // Shared state
-        let frames_sent = Arc::new(AtomicU64::new(0));
-        let running = Arc::new(AtomicBool::new(true));
+        let state = Arc::new(OutputState::new(brightness_to_u8(config.brightness.unwrap_or(1.0))));
+        let own_lut = config.color.as_ref().map(|c| Arc::new(c.build_lut()));
+        *state.color_lut.write().unwrap() = own_lut.clone();
+        
+        // Bridges that can't take a frame in one burst get it in paced chunks
+        let port = PacedPort::wrap(port, &config, &state);
+        
+        // Mirrors get a copy of every finished protocol frame; a mirror that fails to open is skipped
+        let mut mirrors = Vec::new();
+        for mirror_port in config.mirrors.iter().flatten() {
+            match Mirror::open(&config, mirror_port, debug) {
+                Ok(mirror) => mirrors.push(mirror),
+                Err(e) => eprintln!("✗ Failed to open mirror {}: {}", mirror_port, e),
+            }
+        }
+        let mirror_states = mirrors.iter()
+            .map(|m| (m.port_name().to_string(), m.state()))
+            .collect();
+        let port = if mirrors.is_empty() {
+            port
+        } else {
+            Box::new(MirroredPort::new(port, mirrors)) as Box<dyn SerialPort>
+        };
+        
+        // Bounded queue; the default depth of 1 with drop_newest is skip-ahead (like Python Queue(maxsize=1))
+        let queue = Arc::new(FrameQueue::new(config.queue_depth.unwrap_or(1), config.queue_policy));
+        let pool = Arc::new(BufferPool::new());
         
         // Spawn worker thread
         let worker_config = config.clone();
-        let worker_frames_sent = Arc::clone(&frames_sent);
-        let worker_running = Arc::clone(&running);
+        let worker_state = Arc::clone(&state);
+        let worker_queue = Arc::clone(&queue);
+        let worker_pool = Arc::clone(&pool);
+        let worker_sync = sync.clone();
         
         let worker_handle = thread::spawn(move || {
-            worker_thread(port, receiver, worker_config, worker_frames_sent, worker_running, ddebug);
+            sched::apply(&worker_config.label(), worker_config.priority, worker_config.cpu_affinity.as_deref(), debug);
+            // The worker keeps the pixel work; a thread of its own does the writing, started
+            // from here so it gets the worker's priority and CPUs
+            let (port, stamp) = if worker_config.writer_thread {
+                let threaded = ThreadedPort::new(port, Arc::clone(&worker_state));
+                let stamp = threaded.stamp();
+                (Box::new(threaded) as Box<dyn SerialPort>, Some(stamp))
+            } else {
+                (port, None)
+            };
+            worker_thread(port, stamp, &worker_queue, &worker_pool, worker_config, worker_state, protocol, worker_sync, ddebug);
+            // Wake any source blocked on a full queue once the worker is gone
+            worker_queue.close();
         });
         
-        if debug {
-            println!("✓ Opened {} (channel {}, offset {}, {} @ {} baud, {} LEDs)",
-                     config.port, config.opc_channel, config.opc_offset,
-                     config.protocol, config.baud_rate, config.led_count);
+        // Delayed outputs get a forwarding thread that releases frames into the queue when due
+        let delay_ms = config.delay_ms.filter(|&ms| ms > 0);
+        if delay_ms.is_some() && sync.is_some() {
+            eprintln!("Warning: delay_ms on {} is ignored with opc.sync_outputs", config.label());
         }
+        let (delay, delay_handle) = match delay_ms.filter(|_| sync.is_none()) {
+            Some(ms) => {
+                let delay = Arc::new(DelayLine::new(Duration::from_millis(ms)));
+                let forward_delay = Arc::clone(&delay);
+                let forward_queue = Arc::clone(&queue);
+                let forward_pool = Arc::clone(&pool);
+                let forward_state = Arc::clone(&state);
+                let handle = thread::spawn(move || {
+                    while let Some((frame, received)) = forward_delay.pop_due() {
+                        if let Some(dropped) = forward_queue.push_received(frame, received) {
+                            forward_state.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                            forward_pool.put(dropped);
+                        }
+                    }
+                });
+                (Some(delay), Some(handle))
+            }
+            None => (None, None),
+        };
         
+        let matrix = config.matrix.as_ref().map(Matrix::new);
+        let routing = TransformChain::for_output(&config);
         Ok(Output {
+            entries: vec![entry],
             config,
-            sender,
-            frames_sent,
-            running,
+            queue,
+            pool,
+            state,
+            mirrors: mirror_states,
+            delay,
+            delay_handle,
+            segments: Vec::new(),
+            composite: Mutex::new(Vec::new()),
+            synced: sync.is_some(),
+            range_checked: AtomicBool::new(false),
+            matrix,
+            routing,
+            own_lut,
             worker_handle: Some(worker_handle),
         })
     }
     
+    /// Create one output for several entries that share a serial port
+    ///
+    /// The entries' pixels are concatenated into a single protocol frame, written by
+    /// one worker thread.
+    pub fn new_shared(configs: &[OutputConfig], sync: Option<Arc<SyncGate>>, debug: bool, ddebug: bool) -> Result<Self> {
+        let (config, segments) = segment::combine(configs)?;
+        let led_count = config.led_count;
+        
+        // Sync rounds are per channel, so a port fed from several channels is not synchronized
+        let single_channel = segments.iter().all(|s| s.opc_channel == config.opc_channel);
+        let sync = sync.filter(|_| single_channel);
+        let synced = sync.is_some();
+        
+        let mut output = Self::new(config, sync, debug, ddebug)?;
+        output.synced = synced;
+        output.segments = segments;
+        output.entries = configs.to_vec();
+        output.composite = Mutex::new(vec![0u8; led_count * 3]);
+        Ok(output)
+    }
+    
+    /// Check whether this output takes pixel data from `channel`
+    pub fn listens_to(&self, channel: u8) -> bool {
+        if self.segments.is_empty() {
+            self.config.opc_channel == channel
+        } else {
+            self.segments.iter().any(|s| s.opc_channel == channel)
+        }
+    }
+    
+    /// Whether the worker is behind: its queue is full, so the next frame would be dropped
+    /// (delayed outputs never are; their delay line drops the oldest frame instead)
+    pub fn is_backed_up(&self) -> bool {
+        self.delay.is_none() && self.queue.is_full()
+    }
+    
+    /// Check whether this output takes part in the sync round for `channel`
+    pub fn syncs_with(&self, channel: u8) -> bool {
+        self.synced && self.listens_to(channel)
+    }
+    
+    /// Entries combined on this output's shared port (empty for a regular output)
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+    
+    /// Update the segments fed by `channel` and send the combined frame (shared ports only)
+    pub fn send_segments(&self, channel: u8, pixel_data: &[u8]) -> Result<()> {
+        if !self.range_checked.load(Ordering::Relaxed) {
+            let mut listening = self.segments.iter().filter(|s| s.opc_channel == channel).peekable();
+            if listening.peek().is_some() {
+                match listening.find(|s| !s.is_covered_by(pixel_data.len())) {
+                    Some(s) => self.warn_uncovered(channel, pixel_data.len(), s.opc_offset, s.led_count),
+                    None => self.range_checked.store(true, Ordering::Relaxed),
+                }
+            }
+        }
+        
+        let mut composite = self.composite.lock().unwrap();
+        if !segment::place(&mut composite, &self.segments, channel, pixel_data) {
+            return Ok(());
+        }
+        let frame = self.pool.copy_from(&composite);
+        drop(composite);
+        self.send_frame(frame)
+    }
+    
+    /// Config entries to open this output again, e.g. after its device was replugged
+    pub fn entries(&self) -> &[OutputConfig] {
+        &self.entries
+    }
+    
     /// Get the configuration for this output
     pub fn config(&self) -> &OutputConfig {
         &self.config
     }
     
-    /// Send a frame to this output (non-blocking, skip-ahead)
+    /// Check whether this output is currently enabled
+    pub fn is_enabled(&self) -> bool {
+        self.state.enabled.load(Ordering::Relaxed)
+    }
+    
+    /// Pause or resume this output at runtime
+    /// A paused output is blanked once by its worker and then stops writing to the port
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state.enabled.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Check whether the worker is still writing to the port (false after a serial error)
+    pub fn is_connected(&self) -> bool {
+        self.state.connected.load(Ordering::Relaxed)
+    }
+    
+    /// Check whether this output is currently forced to black
+    pub fn is_blanked(&self) -> bool {
+        self.state.blanked.load(Ordering::Relaxed)
+    }
+    
+    /// Force this output to black right away (frames keep flowing, but all pixels are zeroed)
+    pub fn set_blanked(&self, blanked: bool) {
+        self.state.blanked.store(blanked, Ordering::Relaxed);
+    }
+    
+    /// Get the runtime brightness (0.0 - 1.0)
+    pub fn brightness(&self) -> f32 {
+        self.state.brightness.load(Ordering::Relaxed) as f32 / 255.0
+    }
+    
+    /// Set the runtime brightness (0.0 - 1.0, clamped)
+    pub fn set_brightness(&self, brightness: f32) {
+        self.state.brightness.store(brightness_to_u8(brightness), Ordering::Relaxed);
+    }
+    
+    /// Replace the global color correction table (None returns to the output's own `color`)
+    pub fn set_color_lut(&self, lut: Option<Arc<ColorLut>>) {
+        *self.state.color_lut.write().unwrap() = lut.or_else(|| self.own_lut.clone());
+    }
+    
+    /// Hold each frame for the next tick of a multi-host frame sync (None sends right away)
+    pub fn set_frame_tick(&self, tick: Option<Arc<FrameTick>>) {
+        *self.state.frame_tick.write().unwrap() = tick;
+    }
+    
+    /// Annotate the next `frames` frames this output writes into a file, listing only
+    /// changed LEDs after the first frame with `diff`; returns the file's path
+    ///
+    /// Replaces an inspection still running on this output. `quiet` leaves out the
+    /// notice when the file is complete.
+    pub fn inspect(&self, frames: usize, diff: bool, quiet: bool) -> Result<PathBuf> {
+        let path = inspect::default_path(self.config.id());
+        let stride = pixel_format::stride(self.config.pixel_format.as_deref());
+        let capture = Capture::create(&path, &self.config.label(), self.config.pixel_format.as_deref(), stride, frames, diff)?;
+        *self.state.capture.lock().unwrap() = Some(Inspection::start(capture, quiet));
+        Ok(path)
+    }
+    
+    /// Send a frame to this output, following its queue policy when the queue is full
+    /// (drop_newest and drop_oldest never block; block waits for the worker)
     pub fn send_frame(&self, pixel_data: Vec<u8>) -> Result<()> {
-        // try_send implements skip-ahead: if channel is full, frame is discarded
-        match self.sender.try_send(pixel_data) {
-            Ok(_) => Ok(()),
-            Err(TrySendError::Full(_)) => {
-                // Channel full, frame dropped (skip-ahead behavior)
-                Ok(())
-            }
-            Err(TrySendError::Disconnected(_)) => {
-                // Channel disconnected
-                Ok(())
-            }
+        // Paused outputs discard incoming frames
+        if !self.is_enabled() {
+            return Ok(());
         }
+        
+        // Dropped frames and a stopped worker are not errors for the caller
+        let dropped = match &self.delay {
+            Some(delay) => delay.push(pixel_data),
+            None => self.queue.push(pixel_data),
+        };
+        if let Some(dropped) = dropped {
+            self.state.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            self.pool.put(dropped);
+        }
+        Ok(())
+    }
+    
+    /// Cut this output's pixels out of a message into a recycled buffer, ready for `send_frame`
+    ///
+    /// Data shorter than the strip is handled by the output's `short_frames` policy;
+    /// None means the frame is dropped. Matrix outputs get the frame in wiring order.
+    pub fn frame_buffer(&self, channel_data: &[u8]) -> Option<Vec<u8>> {
+        let mut frame = self.fit_frame(channel_data)?;
+        let Some(matrix) = &self.matrix else {
+            return Some(frame);
+        };
+        
+        // Pixels a short frame leaves out are black, so the image stays in place
+        let needed = self.config.led_count * 3;
+        frame.resize(needed, 0);
+        let mut wired = self.pool.get(needed);
+        wired.resize(needed, 0);
+        matrix.apply(&frame, &mut wired);
+        self.pool.put(frame);
+        Some(wired)
+    }
+    
+    /// This output's pixels of a message, after its routing chain and the `short_frames` policy
+    fn fit_frame(&self, channel_data: &[u8]) -> Option<Vec<u8>> {
+        let config = &self.config;
+        let needed = config.led_count * 3;
+        let mut buffer = self.pool.get(channel_data.len().max(needed));
+        let coverage = self.routing.apply(channel_data, &mut buffer);
+        let copied = buffer.len() / 3;
+        if coverage.supplied >= coverage.span {
+            self.range_checked.store(true, Ordering::Relaxed);
+        } else if !self.range_checked.load(Ordering::Relaxed) {
+            self.warn_uncovered(config.opc_channel, channel_data.len(), config.opc_offset, coverage.span);
+        }
+        
+        let policy = config.short_frames.unwrap_or_default();
+        let mut held = self.composite.lock().unwrap();
+        if copied < config.led_count {
+            let frame = fit_short_frame(policy, &buffer, &mut held, needed).map(|frame| self.pool.copy_from(frame));
+            drop(held);
+            self.pool.put(buffer);
+            return frame;
+        }
+        if policy == ShortFramePolicy::PadLast {
+            held.clone_from(&buffer);
+        }
+        Some(buffer)
+    }
+    
+    /// Warn, once per output, that a client's messages don't reach all of its pixels
+    fn warn_uncovered(&self, channel: u8, len: usize, offset: usize, led_count: usize) {
+        if self.range_checked.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        eprintln!("Warning: {} shows pixels {}-{} of channel {}, which the client's messages don't reach ({} pixels sent)",
+                  self.config.label(), offset, offset + led_count.max(1) - 1, channel, len / 3);
     }
     
     /// Get number of frames sent
-    #[allow(dead_code)]
     pub fn frames_sent(&self) -> u64 {
-        self.frames_sent.load(Ordering::Relaxed)
+        self.state.frames_sent.load(Ordering::Relaxed)
     }
     
     /// Reset frame counter
     #[allow(dead_code)]
     pub fn reset_counter(&self) {
-        self.frames_sent.store(0, Ordering::Relaxed)
+        self.state.frames_sent.store(0, Ordering::Relaxed)
+    }
+    
+    /// Get a clone of the shared runtime state (for statistics and the control API)
+    pub fn state(&self) -> Arc<OutputState> {
+        Arc::clone(&self.state)
     }
     
-    /// Get a clone of the frames sent counter (for statistics)
-    pub fn frames_sent_counter(&self) -> Arc<AtomicU64> {
-        Arc::clone(&self.frames_sent)
+    /// Port and runtime state of each mirror of this output
+    pub fn mirrors(&self) -> &[(String, Arc<OutputState>)] {
+        &self.mirrors
     }
     
     /// Stop the output and wait for worker thread
     pub fn stop(&mut self) {
-        self.running.store(false, Ordering::Relaxed);
+        self.state.running.store(false, Ordering::Relaxed);
+        
+        // Frames still being delayed are discarded
+        if let Some(delay) = &self.delay {
+            delay.close();
+        }
+        if let Some(handle) = self.delay_handle.take() {
+            let _ = handle.join();
+        }
+        
+        self.queue.close();
         
         if let Some(handle) = self.worker_handle.take() {
             let _ = handle.join();
         }
     }
     
-    /// Open a standard serial port (non-WLED)
-    fn open_standard_port(config: &OutputConfig) -> Result<Box<dyn SerialPort>> {
-        let mut port = serialport::new(&config.port, config.baud_rate)
-            .data_bits(serialport::DataBits::Eight)
-            .parity(serialport::Parity::None)
-            .stop_bits(serialport::StopBits::One)
-            .flow_control(serialport::FlowControl::None)
+    /// Open a standard serial port (non-WLED) with the output's line settings
+    pub fn open_standard_port(config: &OutputConfig) -> Result<Box<dyn SerialPort>> {
+        let path = serial::resolve(&config.port)
+            .context(format!("Failed to open serial port {}", config.port))?;
+        let settings = serial::line_settings(config)?;
+        let mut port = settings.apply(serialport::new(path, config.baud_rate))
+            .dtr_on_open(config.dtr.unwrap_or(true))
             .open()
             .context(format!("Failed to open serial port {}", config.port))?;
         
@@ -136,346 +528,868 @@ impl Output {
         port.set_timeout(Duration::from_millis(1000))
             .context("Failed to set serial port timeout")?;
         
-        // Set DTR to match Python's pyserial defaults
-        if let Err(e) = port.write_data_terminal_ready(true) {
-            eprintln!("Warning: Failed to set DTR on {}: {}", config.port, e);
+        // DTR/RTS default to raised, matching Python's pyserial
+        if let Err(e) = serial::set_control_lines(port.as_mut(), config) {
+            eprintln!("Warning: Failed to set DTR/RTS on {}: {}", config.label(), e);
         }
         
-        // Allow device to initialize
-        thread::sleep(Duration::from_millis(100));
+        // Allow device to initialize (AVR Arduinos that reset on DTR need about 2 seconds)
+        thread::sleep(Duration::from_millis(config.init_delay_ms.unwrap_or(100)));
+        
+        // Adalight sketches announce themselves once booted; frames sent earlier are lost
+        if let Some(ms) = config.wait_for_ada_ms {
+            match wait_for_ack(port.as_mut(), AckMode::Ada, Duration::from_millis(ms)) {
+                Ok(AckResult::Ack) => {}
+                Ok(_) => eprintln!("Warning: No 'Ada' greeting from {} within {}ms, sending anyway", config.label(), ms),
+                Err(e) => eprintln!("Warning: Failed to read greeting from {}: {}", config.label(), e),
+            }
+            // The sketch repeats its greeting until data arrives; don't mistake it for acknowledgements
+            let _ = port.clear(ClearBuffer::Input);
+        }
         
         Ok(port)
     }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Worker thread function - blocks on queue waiting for frames, sends to serial port
+#[allow(clippy::too_many_arguments)]
+fn worker_thread(
+    port: Box<dyn SerialPort>,
+    // Set with `writer_thread`, whose thread records each frame's write once it is on the wire
+    stamp: Option<FrameStamp>,
+    queue: &FrameQueue,
+    pool: &BufferPool,
+    config: OutputConfig,
+    state: Arc<OutputState>,
+    protocol: Box<dyn Protocol>,
+    sync: Option<Arc<SyncGate>>,
+    ddebug: bool,
+) {
+    let label = config.label();
+    let shutdown_policy = config.shutdown_policy();
+    let mut sender = FrameSender::new(port, protocol, stamp, &config, &state, pool, ddebug);
+    
+    // A port that fails during the start pattern is disconnected like one failing later
+    let started = match config.on_start {
+        Some(pattern) => run_start_pattern(&mut sender, pattern).is_ok(),
+        None => true,
+    };
+    
+    let mut soft_start = SoftStart::from_config(&config);
+    let mut smoothing = Smoothing::from_config(&config);
+    let color_temperature = config.color_temperature_k.map(ColorTemperature::new);
+    let mut script = FrameScript::from_config(&config);
+    
+    let transition = Duration::from_millis(config.transition_ms.unwrap_or(0));
+    let idle_timeout = config.idle_timeout_ms.map(Duration::from_millis);
+    let layout = Layout::new(&config);
+    let standby = config.standby.as_ref().map(|standby| Standby::new(standby, layout.span));
+    let standby_after = Duration::from_millis(config.standby.as_ref().and_then(|s| s.after_ms).unwrap_or(5000));
+    // When the standby animation started, while it is running
+    let mut standby_since: Option<Instant> = None;
     
-    /// Open and initialize a WLED device with baud rate detection
-    fn open_wled_port(config: &OutputConfig, debug: bool, ddebug: bool) -> Result<Box<dyn SerialPort>> {
-        if debug {
-            println!("Detecting WLED device on {}...", config.port);
+    let mut was_enabled = true;
+    let mut was_blanked = false;
+    // When live data started after the strip was blank, for the fade-in
+    let mut live_since: Option<Instant> = None;
+    let mut last_data = Instant::now();
+    
+    // Devices that only keep up with so many frames a second (LIFX, Hue) get no more
+    let min_interval = config.max_fps.or(sender.protocol.max_fps()).map(|fps| Duration::from_secs_f64(1.0 / fps));
+    
+    while started && state.running.load(Ordering::Relaxed) {
+        // Handle pause/resume transitions requested through the control API
+        let is_enabled = state.enabled.load(Ordering::Relaxed);
+        if is_enabled != was_enabled {
+            if !is_enabled {
+                // Blank the strip once, then stop writing until resumed
+                if sender.blank().is_err() {
+                    break;
+                }
+                state.last_frame.lock().unwrap().fill(0);
+            }
+            was_enabled = is_enabled;
         }
         
-        // Build list of baud rates to try in priority order:
-        // 1. Configured baud_rate (data rate)
-        // 2. Configured handshake_baud_rate (control baud)
-        // 3. All WLED standard rates
-        let mut baud_rates_to_try = Vec::new();
-        
-        // Add configured data rate first
-        baud_rates_to_try.push(config.baud_rate);
-        
-        // Add handshake baud if different and specified
-        if let Some(handshake_baud) = config.handshake_baud_rate {
-            if handshake_baud != config.baud_rate {
-                baud_rates_to_try.push(handshake_baud);
+        // Go dark as soon as the output is blanked, even if no frames are arriving
+        let is_blanked = state.blanked.load(Ordering::Relaxed);
+        if is_blanked && !was_blanked && was_enabled {
+            if sender.blank().is_err() {
+                break;
             }
+            state.last_frame.lock().unwrap().fill(0);
         }
+        was_blanked = is_blanked;
         
-        // Add all standard WLED rates (skip duplicates)
-        for &rate in WLED_BAUD_RATES {
-            if !baud_rates_to_try.contains(&rate) {
-                baud_rates_to_try.push(rate);
+        // Devices that only keep up with so many frames a second wait before taking a frame,
+        // then send the newest one rather than one that aged during the wait
+        if let (Some(interval), Some(last)) = (min_interval, sender.last_write) {
+            let next = last + interval;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+                for stale in queue.skip_to_newest() {
+                    state.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                    pool.put(stale);
+                }
             }
         }
         
-        // Try each baud rate until we get a response
-        let mut detected_baud = None;
-        let mut wled_response = String::new();
+        // Block waiting for frame (like Python's queue.get()), waking up for standby animation
+        // frames and smoothing steps
+        let settling = was_enabled && live_since.is_some() && smoothing.as_ref().is_some_and(|s| s.unsettled().is_some());
+        let wait = if standby_since.is_some() || settling { PATTERN_FRAME_INTERVAL } else { Duration::from_millis(100) };
+        let popped = queue.pop_received_timeout(wait);
+        // Sync round the frame belongs to; read right away, before the round can move on
+        let round = match (&popped, &sync) {
+            (Ok(_), Some(sync)) => Some(sync.generation(config.opc_channel)),
+            _ => None,
+        };
+        
+        // Smoothing keeps stepping toward the last frame when no new one comes, which
+        // sources that only send changes rely on; the step goes through the pipeline as a frame
+        let stepping = settling && matches!(popped, Err(mpsc::RecvTimeoutError::Timeout));
+        let popped = match (stepping, &smoothing) {
+            (true, Some(smoothing)) => Ok((pool.copy_from(smoothing.unsettled().unwrap_or_default()), Instant::now())),
+            _ => popped,
+        };
         
-        for &baud in &baud_rates_to_try {
-            if ddebug {
-                eprintln!("[DEBUG {}] Trying baud rate {}...", config.port, baud);
+        match popped {
+            Ok(_) if !state.enabled.load(Ordering::Relaxed) => {
+                // Frame queued before the pause took effect, discard it (one arriving right
+                // after a resume is kept, even before the loop has seen the resume)
+                if let (Some(sync), Some(round)) = (&sync, round) {
+                    sync.arrive(config.opc_channel, round);
+                }
+                continue;
             }
-            
-            match Self::try_wled_handshake(&config.port, baud, ddebug) {
-                Ok(response) => {
-                    detected_baud = Some(baud);
-                    wled_response = response;
-                    if debug {
-                        println!("✓ WLED device detected at {} baud on {}", baud, config.port);
+            Ok((mut pixel_data, received)) => {
+                // Steady noisy input; after a pause the average starts over from this frame
+                if let Some(smoothing) = &mut smoothing {
+                    if live_since.is_none() {
+                        smoothing.reset();
                     }
-                    break;
+                    smoothing.apply(&mut pixel_data);
                 }
-                Err(e) => {
-                    if ddebug {
-                        eprintln!("[DEBUG {}] No response at {} baud: {}", config.port, baud, e);
+                
+                // Apply runtime blanking, color correction and brightness before color-order transforms
+                if state.blanked.load(Ordering::Relaxed) {
+                    pixel_data.fill(0);
+                } else {
+                    let color_lut = state.color_lut.read().unwrap().clone();
+                    if let Some(lut) = color_lut {
+                        lut.apply(&mut pixel_data);
                     }
+                    if let Some(color_temperature) = &color_temperature {
+                        color_temperature.apply(&mut pixel_data);
+                    }
+                    scale_brightness(&mut pixel_data, state.brightness.load(Ordering::Relaxed), config.linear_light);
+                }
+                
+                // Fade in from black when live data starts
+                if !stepping {
+                    last_data = Instant::now();
+                }
+                standby_since = None;
+                let since = *live_since.get_or_insert(last_data);
+                let elapsed = since.elapsed();
+                if elapsed < transition {
+                    let level = (255 * elapsed.as_millis() / transition.as_millis()) as u8;
+                    scale_brightness(&mut pixel_data, level, config.linear_light);
+                }
+                
+                // Let the user's script rework the frame; limits below still apply to its result
+                if let Some(script) = &mut script {
+                    script.process(&mut pixel_data);
+                }
+                
+                // Keep the estimated current draw within the output's power budget
+                if let Some(power_limit) = &sender.power_limit {
+                    if power_limit.apply(&mut pixel_data) && ddebug {
+                        eprintln!("[DEBUG {}] Frame scaled down by power limit", label);
+                    }
+                }
+                
+                // Spread sudden brightness jumps over several frames
+                let mut last_frame = state.last_frame.lock().unwrap();
+                if let Some(soft_start) = &mut soft_start {
+                    if soft_start.apply(&mut pixel_data, &last_frame) && ddebug {
+                        eprintln!("[DEBUG {}] Frame scaled down by soft start", label);
+                    }
+                }
+                last_frame.clone_from(&pixel_data);
+                drop(last_frame);
+                
+                // Static content is skipped, but resent now and then in case the strip missed it
+                if !sender.build(pixel_data) {
+                    if let (Some(sync), Some(round)) = (&sync, round) {
+                        sync.arrive(config.opc_channel, round);
+                    }
+                    continue;
+                }
+                
+                // Wait until every output of this OPC message has its frame ready
+                if let (Some(sync), Some(round)) = (&sync, round) {
+                    sync.arrive_and_wait(config.opc_channel, round, SYNC_TIMEOUT);
+                }
+                
+                // Then for the tick that releases this frame on every host
+                let frame_tick = state.frame_tick.read().unwrap().clone();
+                if let Some(tick) = frame_tick {
+                    tick.wait_next();
+                }
+                
+                match sender.write(received) {
+                    Ok(Written::Sent) => {}
+                    Ok(Written::Late) => {
+                        // Go on with the freshest frame
+                        for stale in queue.skip_to_newest() {
+                            state.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                            pool.put(stale);
+                        }
+                    }
+                    Err(_) => break, // Exit worker thread on error
                 }
             }
-        }
-        
-        let detected_baud = detected_baud.context(format!(
-            "Failed to detect WLED device on {} (tried {} baud rates)",
-            config.port,
-            baud_rates_to_try.len()
-        ))?;
-        
-        if ddebug {
-            eprintln!("[DEBUG {}] WLED response: {}", config.port, wled_response);
-        }
-        
-        // Now switch to the configured baud rate if different
-        if detected_baud != config.baud_rate {
-            if debug {
-                println!("Switching {} from {} to {} baud...", config.port, detected_baud, config.baud_rate);
-            }
-            
-            let mut port = serialport::new(&config.port, detected_baud)
-                .data_bits(serialport::DataBits::Eight)
-                .parity(serialport::Parity::None)
-                .stop_bits(serialport::StopBits::One)
-                .flow_control(serialport::FlowControl::None)
-                .timeout(Duration::from_millis(500))
-                .open()
-                .context(format!("Failed to reopen {} at detected baud", config.port))?;
-            
-            // Set DTR
-            if let Err(e) = port.write_data_terminal_ready(true) {
-                eprintln!("Warning: Failed to set DTR on {}: {}", config.port, e);
-            }
-            thread::sleep(Duration::from_millis(100));
-            
-            // Send baud change command based on target rate
-            let baud_byte = Self::get_wled_baud_byte(config.baud_rate)
-                .context(format!("Unsupported WLED baud rate: {}", config.baud_rate))?;
-            
-            port.write_all(&[baud_byte])
-                .context("Failed to send baud change command")?;
-            port.flush().context("Failed to flush baud change command")?;
-            
-            // Wait for confirmation
-            thread::sleep(Duration::from_millis(200));
-            
-            // Try to read confirmation (optional, may not always work)
-            let mut buf = vec![0u8; 100];
-            if let Ok(n) = port.read(&mut buf) {
-                if ddebug {
-                    let response = String::from_utf8_lossy(&buf[..n]);
-                    eprintln!("[DEBUG {}] Baud change response: {}", config.port, response);
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // No data for a while: fade the last frame out and wait for the next fade-in
+                if let Some(idle_timeout) = idle_timeout {
+                    if live_since.is_some() && was_enabled && last_data.elapsed() >= idle_timeout {
+                        let last_frame = state.last_frame.lock().unwrap().clone();
+                        if fade_out(&mut sender, &last_frame, transition).and_then(|_| sender.blank()).is_err() {
+                            break;
+                        }
+                        state.last_frame.lock().unwrap().fill(0);
+                        live_since = None;
+                    }
+                }
+                
+                // Nothing to show: run the standby animation instead of leaving the strip dark
+                if let Some(standby) = &standby {
+                    if was_enabled && last_data.elapsed() >= standby_after {
+                        let since = *standby_since.get_or_insert_with(Instant::now);
+                        let mut pixels = layout.apply(&standby.render(since.elapsed()), pool);
+                        if state.blanked.load(Ordering::Relaxed) {
+                            pixels.fill(0);
+                        } else {
+                            if let Some(lut) = state.color_lut.read().unwrap().as_ref() {
+                                lut.apply(&mut pixels);
+                            }
+                            if let Some(color_temperature) = &color_temperature {
+                                color_temperature.apply(&mut pixels);
+                            }
+                        }
+                        scale_brightness(&mut pixels, state.brightness.load(Ordering::Relaxed), config.linear_light);
+                        let mut last_frame = state.last_frame.lock().unwrap();
+                        if let Some(soft_start) = &mut soft_start {
+                            soft_start.apply(&mut pixels, &last_frame);
+                        }
+                        last_frame.clone_from(&pixels);
+                        drop(last_frame);
+                        if sender.show(pixels).is_err() {
+                            break;
+                        }
+                        // Live data fades in again when it returns
+                        live_since = None;
+                    }
                 }
+                continue;
             }
-            
-            // Close and reopen at new baud rate
-            drop(port);
-            thread::sleep(Duration::from_millis(100));
-            
-            let mut port = serialport::new(&config.port, config.baud_rate)
-                .data_bits(serialport::DataBits::Eight)
-                .parity(serialport::Parity::None)
-                .stop_bits(serialport::StopBits::One)
-                .flow_control(serialport::FlowControl::None)
-                .timeout(Duration::from_millis(1000))
-                .open()
-                .context(format!("Failed to reopen {} at new baud", config.port))?;
-            
-            if let Err(e) = port.write_data_terminal_ready(true) {
-                eprintln!("Warning: Failed to set DTR on {}: {}", config.port, e);
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                // Channel closed, exit worker
+                break;
             }
-            thread::sleep(Duration::from_millis(100));
-            
-            if debug {
-                println!("✓ WLED device on {} now running at {} baud", config.port, config.baud_rate);
+        }
+    }
+    
+    // Apply the shutdown policy on exit (best effort)
+    let restore_device = match shutdown_policy {
+        ShutdownPolicy::Hold => return, // WLED returns to its own effect after its realtime timeout
+        ShutdownPolicy::Blank => {
+            let _ = sender.blank();
+            false
+        }
+        ShutdownPolicy::Fade => {
+            let duration = Duration::from_millis(config.shutdown_fade_ms.unwrap_or(1000));
+            let last_frame = state.last_frame.lock().unwrap().clone();
+            let _ = fade_out(&mut sender, &last_frame, duration).and_then(|_| sender.blank());
+            false
+        }
+        ShutdownPolicy::Preset => {
+            if !sender.protocol.restores_device_state() {
+                let _ = sender.blank();
             }
-            
-            Ok(port)
-        } else {
-            // Already at correct baud, just open normally
-            Self::open_standard_port(config)
+            true
+        }
+    };
+    
+    // e.g. hand WLED devices back to their own effects/presets, or switch them off
+    let FrameSender { mut port, mut protocol, .. } = sender;
+    if let Err(e) = protocol.teardown(port.as_mut(), restore_device) {
+        eprintln!("Warning: Failed to release {}: {}", label, e);
+    }
+}
+
+/// The last stretch of every frame a worker sends, live or made up by the worker
+/// itself (start pattern, standby animation, fades and blank frames): black level, wire
+/// format, dedupe, framing, inspection, the write and its deadline, stats and the
+/// device's acknowledgement
+struct FrameSender<'a> {
+    port: Box<dyn SerialPort>,
+    protocol: Box<dyn Protocol>,
+    // Set with `writer_thread`, whose thread records each frame's write once it is on the wire
+    stamp: Option<FrameStamp>,
+    config: &'a OutputConfig,
+    state: &'a OutputState,
+    pool: &'a BufferPool,
+    label: String,
+    /// Bytes per pixel on the wire
+    stride: usize,
+    /// Compiled `pixel_format`, run after the runtime corrections
+    wire_format: TransformChain,
+    power_limit: Option<PowerLimit>,
+    /// Protocol frame buffer, reused for every frame
+    frame: Vec<u8>,
+    ack_timeout: Duration,
+    /// Longest a frame may take to write before it is abandoned (default: the port's timeout)
+    write_deadline: Option<Duration>,
+    dedupe_refresh: Duration,
+    /// Hash of the last pixels written, while nothing else has been written since
+    last_sent: Option<u64>,
+    last_write: Option<Instant>,
+    ddebug: bool,
+}
+
+/// What became of a frame handed to `FrameSender::write`
+enum Written {
+    Sent,
+    /// Abandoned when the write deadline passed
+    Late,
+}
+
+impl<'a> FrameSender<'a> {
+    fn new(port: Box<dyn SerialPort>, protocol: Box<dyn Protocol>, stamp: Option<FrameStamp>, config: &'a OutputConfig,
+           state: &'a OutputState, pool: &'a BufferPool, ddebug: bool) -> Self {
+        FrameSender {
+            port,
+            protocol,
+            stamp,
+            config,
+            state,
+            pool,
+            label: config.label(),
+            stride: pixel_format::stride(config.pixel_format.as_deref()),
+            wire_format: TransformChain::pixel_format(config.pixel_format.as_deref()),
+            power_limit: PowerLimit::from_config(config),
+            frame: Vec::new(),
+            ack_timeout: Duration::from_millis(config.ack_timeout_ms.unwrap_or(100)),
+            write_deadline: config.write_deadline_ms.map(Duration::from_millis),
+            dedupe_refresh: Duration::from_millis(config.dedupe_refresh_ms.unwrap_or(1000)),
+            last_sent: None,
+            last_write: None,
+            ddebug,
         }
     }
     
-    /// Try WLED handshake at a specific baud rate
-    fn try_wled_handshake(port_name: &str, baud: u32, ddebug: bool) -> Result<String> {
-        let mut port = serialport::new(port_name, baud)
-            .data_bits(serialport::DataBits::Eight)
-            .parity(serialport::Parity::None)
-            .stop_bits(serialport::StopBits::One)
-            .flow_control(serialport::FlowControl::None)
-            .timeout(Duration::from_millis(500))
-            .open()
-            .context("Failed to open port")?;
+    /// Turn RGB pixels into the next protocol frame; false when dedupe skips them
+    fn build(&mut self, mut pixels: Vec<u8>) -> bool {
+        let config = self.config;
+        if config.black_threshold.is_some() || config.min_brightness.is_some() {
+            pixel_format::apply_black_level(&mut pixels, config.black_threshold.unwrap_or(0), config.min_brightness.unwrap_or(0));
+        }
         
-        // Set DTR
-        if let Err(e) = port.write_data_terminal_ready(true) {
-            if ddebug {
-                eprintln!("Warning: Failed to set DTR: {}", e);
+        // Color order and white channel of the wire format
+        self.wire_format.run(&mut pixels);
+        
+        // Static content: skip the write, but resend now and then in case the strip missed it
+        if config.dedupe {
+            let hash = frame_hash(&pixels);
+            let recent = self.last_write.is_some_and(|last| last.elapsed() < self.dedupe_refresh);
+            if recent && self.last_sent == Some(hash) {
+                self.pool.put(pixels);
+                self.state.frames_deduped.fetch_add(1, Ordering::Relaxed);
+                return false;
             }
+            self.last_sent = Some(hash);
         }
         
-        // Give device time to initialize
-        thread::sleep(Duration::from_millis(150));
+        // Build protocol frame
+        self.protocol.build_frame_into(&pixels, self.stride, &mut self.frame);
         
-        // Clear any pending data aggressively
-        port.clear(serialport::ClearBuffer::All).ok();
-        thread::sleep(Duration::from_millis(50));
+        // Pixel buffer goes back to the pool for the next incoming frame
+        let pixel_count = pixels.len() / self.stride;
+        self.pool.put(pixels);
         
-        // Send WLED version query
-        let query = b"{\"v\":true}\n";
-        port.write_all(query).context("Failed to write query")?;
-        port.flush().context("Failed to flush")?;
+        if self.ddebug {
+            eprintln!("[DEBUG {}] Sending frame: {} bytes ({} pixels, {} stride)", 
+                     self.label, self.frame.len(), pixel_count, self.stride);
+        }
         
-        // Wait for response (increased timeout)
-        thread::sleep(Duration::from_millis(300));
+        // Frame inspection requested through the control API or SIGUSR2
+        let mut capture = self.state.capture.lock().unwrap();
+        if let Some(active) = capture.as_mut() {
+            if !active.record(&self.frame, self.protocol.describe_frame(&self.frame, self.stride)) {
+                *capture = None;
+            }
+        }
+        drop(capture);
         
-        // Read response
-        let mut buffer = vec![0u8; 1024];
-        let n = match port.read(&mut buffer) {
-            Ok(n) => n,
+        // e.g. WLED devices are switched into live mode before the first streamed frame
+        if let Err(e) = self.protocol.prepare(self.port.as_mut()) {
+            eprintln!("Warning: Failed to prepare {} for streaming: {}", self.label, e);
+        }
+        true
+    }
+    
+    /// Write the frame `build` made, whose pixels arrived at `received`, and wait for its
+    /// acknowledgement
+    ///
+    /// An error means the port is gone; it has been logged and the output marked disconnected.
+    fn write(&mut self, received: Instant) -> io::Result<Written> {
+        // Stale bytes would be mistaken for this frame's acknowledgement
+        if self.config.ack.is_some() {
+            let _ = self.port.clear(ClearBuffer::Input);
+        }
+        
+        // Send to serial port - use write_all to ensure all bytes sent
+        let write_started = Instant::now();
+        self.last_write = Some(write_started);
+        let written = match self.write_deadline {
+            Some(deadline) => write_within(self.port.as_mut(), &self.frame, write_started + deadline),
+            None => self.port.write_all(&self.frame),
+        };
+        match written {
+            Ok(_) => {}
+            Err(e) if self.write_deadline.is_some() && e.kind() == io::ErrorKind::TimedOut => {
+                // Abandon the frame rather than stall
+                let _ = self.port.clear(ClearBuffer::Output);
+                self.state.frames_late.fetch_add(1, Ordering::Relaxed);
+                // The device saw at most part of it: no dedupe against it, no delta on top of it
+                self.last_sent = None;
+                self.protocol.frame_abandoned();
+                if self.ddebug {
+                    eprintln!("[DEBUG {}] Frame aborted after {:?}: write deadline missed", self.label, write_started.elapsed());
+                }
+                return Ok(Written::Late);
+            }
             Err(e) => {
-                // Close port and wait before returning error
-                drop(port);
-                thread::sleep(Duration::from_millis(200));
-                return Err(e).context("No response received");
+                if self.ddebug {
+                    eprintln!("[DEBUG {}] write_all failed", self.label);
+                }
+                eprintln!("✗ Serial error on {}: {}", self.label, e);
+                return Err(self.disconnected(e));
             }
-        };
+        }
         
-        if n == 0 {
-            // Close port and wait before returning error
-            drop(port);
-            thread::sleep(Duration::from_millis(200));
-            anyhow::bail!("Empty response");
+        let flush_started = Instant::now();
+        if let Some(stamp) = &self.stamp {
+            stamp.received(received);
         }
         
-        let response = String::from_utf8_lossy(&buffer[..n]).to_string();
+        // Flush to ensure data goes out immediately
+        if let Err(e) = self.port.flush() {
+            if self.ddebug {
+                eprintln!("[DEBUG {}] flush failed", self.label);
+            }
+            eprintln!("✗ Failed to flush {}: {}", self.label, e);
+            return Err(self.disconnected(e));
+        }
         
-        // Validate it looks like a JSON response
-        if response.contains("{") || response.contains("ver") {
-            // Success - close cleanly and wait before returning
-            drop(port);
-            thread::sleep(Duration::from_millis(100));
-            Ok(response)
-        } else {
-            // Invalid response - close and wait before returning error
-            drop(port);
-            thread::sleep(Duration::from_millis(200));
-            anyhow::bail!("Invalid response: {}", response)
-        }
-    }
-    
-    /// Get the baud change byte for a given baud rate
-    fn get_wled_baud_byte(baud: u32) -> Option<u8> {
-        match baud {
-            115200 => Some(0xB0),
-            230400 => Some(0xB1),
-            460800 => Some(0xB2),
-            500000 => Some(0xB3),
-            576000 => Some(0xB4),
-            921600 => Some(0xB5),
-            1000000 => Some(0xB6),
-            1500000 => Some(0xB7),
-            2000000 => Some(0xB8),
-            _ => None,
+        // A writer thread has only been handed the frame, and records it once written
+        if self.stamp.is_none() {
+            let flushed = Instant::now();
+            let latency = flushed - received;
+            if self.ddebug {
+                eprintln!("[DEBUG {}] write_all {:?} + flush {:?} for {} bytes, {:?} after the frame arrived",
+                         self.label, flush_started - write_started, flushed - flush_started, self.frame.len(), latency);
+            }
+            record_write(self.state, self.frame.len(), flushed - write_started, latency);
         }
+        
+        // Devices that acknowledge frames: a NAK or no answer counts as an error
+        if let Some(mode) = self.config.ack {
+            match wait_for_ack(self.port.as_mut(), mode, self.ack_timeout) {
+                Ok(AckResult::Ack) => {}
+                Ok(result) => {
+                    if self.ddebug {
+                        eprintln!("[DEBUG {}] Frame not acknowledged: {:?}", self.label, result);
+                    }
+                    self.last_sent = None;
+                    self.protocol.frame_abandoned();
+                    self.state.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    eprintln!("✗ Failed to read acknowledgement from {}: {}", self.label, e);
+                    self.state.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(Written::Sent)
+    }
+    
+    /// Count a failed write and mark the output disconnected
+    fn disconnected(&self, e: io::Error) -> io::Error {
+        eprintln!("✗ Output {} is now disconnected", self.label);
+        self.state.errors.fetch_add(1, Ordering::Relaxed);
+        self.state.connected.store(false, Ordering::Relaxed);
+        e
+    }
+    
+    /// Send RGB pixels the worker made up itself, after the power limit, right away
+    fn show(&mut self, mut pixels: Vec<u8>) -> io::Result<()> {
+        if let Some(power_limit) = &self.power_limit {
+            power_limit.apply(&mut pixels);
+        }
+        if self.build(pixels) {
+            self.write(Instant::now())?;
+        }
+        Ok(())
+    }
+    
+    /// Send a full-length black frame
+    fn blank(&mut self) -> io::Result<()> {
+        let len = self.config.led_count * 3;
+        let mut pixels = self.pool.get(len);
+        pixels.resize(len, 0);
+        self.show(pixels)
     }
 }
 
-impl Drop for Output {
-    fn drop(&mut self) {
-        self.stop();
+/// How an output's live frames are laid out on the strip, for the standby animation: it
+/// is drawn as channel data and goes through the output's `transforms`, `opc_offset`,
+/// `wrap`, `resample` and `matrix` like a message from a client
+struct Layout {
+    routing: TransformChain,
+    matrix: Option<Matrix>,
+    /// Channel pixels to draw
+    span: usize,
+    led_count: usize,
+}
+
+impl Layout {
+    fn new(config: &OutputConfig) -> Self {
+        let source = match config.resample {
+            Some(_) => config.source_pixels.unwrap_or(config.led_count),
+            None => config.led_count,
+        };
+        Layout {
+            routing: TransformChain::for_output(config),
+            matrix: config.matrix.as_ref().map(Matrix::new),
+            span: config.opc_offset + source,
+            led_count: config.led_count,
+        }
+    }
+    
+    /// This output's pixels of the drawn channel data, in wiring order
+    fn apply(&self, drawn: &[u8], pool: &BufferPool) -> Vec<u8> {
+        let needed = self.led_count * 3;
+        let mut routed = pool.get(needed);
+        self.routing.apply(drawn, &mut routed);
+        // Transforms may leave fewer pixels than the strip has; the rest stays dark
+        routed.resize(needed, 0);
+        let Some(matrix) = &self.matrix else {
+            return routed;
+        };
+        let mut wired = pool.get(needed);
+        wired.resize(needed, 0);
+        matrix.apply(&routed, &mut wired);
+        pool.put(routed);
+        wired
     }
 }
 
-/// Worker thread function - blocks on queue waiting for frames, sends to serial port
-fn worker_thread(
-    mut port: Box<dyn SerialPort>,
-    receiver: Receiver<Vec<u8>>,
-    config: OutputConfig,
-    frames_sent: Arc<AtomicU64>,
-    running: Arc<AtomicBool>,
-    ddebug: bool,
-) {
-    // Determine stride based on pixel format
-    let stride = match config.pixel_format.as_deref() {
-        Some("RGBW") | Some("GRBW") => 4,
-        _ => 3,
-    };
+/// Count a frame written to the port in the output's stats
+pub(crate) fn record_write(state: &OutputState, bytes: usize, send_time: Duration, latency: Duration) {
+    state.write_latency.lock().unwrap().record(send_time);
+    state.frame_latency.lock().unwrap().record(latency);
+    state.frames_sent.fetch_add(1, Ordering::Relaxed);
+    state.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    state.last_write_ms.store(stats::now_ms(), Ordering::Relaxed);
+}
+
+/// Length of the lit segment in the startup chase pattern
+const CHASE_LENGTH: usize = 5;
+
+/// Show the configured startup pattern, then leave the strip blank
+fn run_start_pattern(sender: &mut FrameSender, pattern: StartPattern) -> io::Result<()> {
+    let config = sender.config;
+    let color = config.start_color.unwrap_or([255, 255, 255]);
+    let duration = Duration::from_millis(config.start_duration_ms.unwrap_or(2000));
+    let started = Instant::now();
     
-    while running.load(Ordering::Relaxed) {
-        // Block waiting for frame (like Python's queue.get())
-        match receiver.recv_timeout(Duration::from_millis(100)) {
-            Ok(pixel_data) => {
-                // Transform pixels if needed
-                let transformed = transform_pixels(
-                    pixel_data,
-                    config.pixel_format.as_deref()
-                );
-                
-                // Build protocol frame
-                let frame = match config.protocol.as_str() {
-                    "awa" => build_awa_frame(&transformed, stride),
-                    "adalight" => build_adalight_frame(&transformed, stride),
-                    _ => {
-                        eprintln!("Unknown protocol: {}", config.protocol);
-                        continue;
-                    }
-                };
-                
-                if ddebug {
-                    eprintln!("[DEBUG {}] Sending frame: {} bytes ({} pixels, {} stride)", 
-                             config.port, frame.len(), transformed.len() / stride, stride);
-                    
-                    // Show hex dump of complete frame being sent to serial
-                    let hex: String = frame.iter()
-                        .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
-                    eprintln!("[DEBUG {}] Complete serial frame: {}", config.port, hex);
-                }
-                
-                // Send to serial port - use write_all to ensure all bytes sent
-                match port.write_all(&frame) {
-                    Ok(_) => {
-                        if ddebug {
-                            let write_time = std::time::Instant::now().elapsed();
-                            eprintln!("[DEBUG {}] write_all took {:?} for {} bytes", 
-                                     config.port, write_time, frame.len());
-                        }
-                        
-                        // Flush to ensure data goes out immediately
-                        match port.flush() {
-                            Ok(_) => {
-                                if ddebug {
-                                    eprintln!("[DEBUG {}] flush took {:?}", config.port, std::time::Instant::now().elapsed());
-                                    eprintln!("[DEBUG {}] Total send time: {:?}", config.port, std::time::Instant::now().elapsed());
-                                }
-                                
-                                frames_sent.fetch_add(1, Ordering::Relaxed);
-                            }
-                            Err(e) => {
-                                if ddebug {
-                                    eprintln!("[DEBUG {}] flush failed", config.port);
-                                }
-                                eprintln!("✗ Failed to flush {}: {}", config.port, e);
-                                eprintln!("✗ Output {} is now disconnected", config.port);
-                                break; // Exit worker thread on error
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        if ddebug {
-                            eprintln!("[DEBUG {}] write_all failed", config.port);
-                        }
-                        eprintln!("✗ Serial error on {}: {}", config.port, e);
-                        eprintln!("✗ Output {} is now disconnected", config.port);
-                        break; // Exit worker thread on error
-                    }
-                }
+    match pattern {
+        StartPattern::Blank => {}
+        StartPattern::Color => {
+            sender.show(color.repeat(config.led_count))?;
+            while started.elapsed() < duration && sender.state.running.load(Ordering::Relaxed) {
+                thread::sleep(PATTERN_FRAME_INTERVAL);
             }
-            Err(mpsc::RecvTimeoutError::Timeout) => {
-                // No data, check if still running
-                continue;
+        }
+        StartPattern::Chase => {
+            // One pass along the strip, spread over the configured duration
+            let interval = (duration / config.led_count.max(1) as u32).max(PATTERN_FRAME_INTERVAL);
+            let mut position = 0;
+            while started.elapsed() < duration && sender.state.running.load(Ordering::Relaxed) {
+                sender.show(chase_frame(config.led_count, position, color))?;
+                position = (position + 1) % config.led_count.max(1);
+                thread::sleep(interval);
             }
-            Err(mpsc::RecvTimeoutError::Disconnected) => {
-                // Channel closed, exit worker
+        }
+    }
+    
+    sender.blank()
+}
+
+/// RGB frame with a short segment of `color` starting at `position`
+fn chase_frame(led_count: usize, position: usize, color: [u8; 3]) -> Vec<u8> {
+    let mut frame = vec![0u8; led_count * 3];
+    for i in position..(position + CHASE_LENGTH).min(led_count) {
+        frame[i * 3..i * 3 + 3].copy_from_slice(&color);
+    }
+    frame
+}
+
+/// Interval between frames of generated startup/shutdown patterns
+const PATTERN_FRAME_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Fade RGB pixel data to black over the given duration
+fn fade_out(sender: &mut FrameSender, frame: &[u8], duration: Duration) -> io::Result<()> {
+    if frame.is_empty() {
+        return Ok(());
+    }
+    
+    let steps = (duration.as_millis() / PATTERN_FRAME_INTERVAL.as_millis()) as u32;
+    
+    for step in 1..=steps {
+        let level = 255 - (255 * step / steps) as u8;
+        let mut faded = sender.pool.copy_from(frame);
+        scale_brightness(&mut faded, level, sender.config.linear_light);
+        sender.show(faded)?;
+        thread::sleep(PATTERN_FRAME_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Convert a 0.0 - 1.0 brightness to the 0 - 255 scale used by the worker
+fn brightness_to_u8(brightness: f32) -> u8 {
+    (brightness.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Scale pixel data in-place by brightness (255 = unchanged), in linear light if `linear`
+fn scale_brightness(data: &mut [u8], brightness: u8, linear: bool) {
+    if brightness == 255 {
+        return;
+    }
+    if linear {
+        LinearLight::get().scale(data, brightness);
+        return;
+    }
+    
+    for byte in data.iter_mut() {
+        *byte = ((*byte as u16 * brightness as u16 + 127) / 255) as u8;
+    }
+}
+
+/// Write all of `frame`, giving up with `TimedOut` once `deadline` has passed
+///
+/// The port's own timeout is restored afterwards, for blank frames and fades.
+fn write_within(port: &mut dyn SerialPort, frame: &[u8], deadline: Instant) -> io::Result<()> {
+    let timeout = port.timeout();
+    let result = write_before(port, frame, deadline);
+    let _ = port.set_timeout(timeout);
+    result
+}
+
+fn write_before(port: &mut dyn SerialPort, frame: &[u8], deadline: Instant) -> io::Result<()> {
+    let mut written = 0;
+    while written < frame.len() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "write deadline passed"));
+        }
+        port.set_timeout(left)?;
+        match port.write(&frame[written..]) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Hash of a frame's pixels, to recognize repeats
+fn frame_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Apply a `short_frames` policy to the pixel data of one message
+///
+/// `held` keeps the last full frame for `pad_last` and is the scratch buffer for
+/// padding. Data at least `needed` bytes long passes through unchanged.
+fn fit_short_frame<'a>(policy: ShortFramePolicy, data: &'a [u8], held: &'a mut Vec<u8>, needed: usize) -> Option<&'a [u8]> {
+    if data.len() >= needed {
+        if policy == ShortFramePolicy::PadLast {
+            held.clear();
+            held.extend_from_slice(data);
+        }
+        return Some(data);
+    }
+    
+    match policy {
+        ShortFramePolicy::Send => Some(data),
+        ShortFramePolicy::Drop => None,
+        ShortFramePolicy::PadBlack => {
+            held.clear();
+            held.extend_from_slice(data);
+            held.resize(needed, 0);
+            Some(held)
+        }
+        ShortFramePolicy::PadLast => {
+            held.resize(needed, 0);
+            held[..data.len()].copy_from_slice(data);
+            Some(held)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_fit_short_frame() {
+        let mut held = Vec::new();
+        let full = [1, 1, 1, 2, 2, 2];
+        let short = [9, 9, 9];
+        
+        assert_eq!(fit_short_frame(ShortFramePolicy::Send, &short, &mut held, 6), Some(&short[..]));
+        assert_eq!(fit_short_frame(ShortFramePolicy::Drop, &short, &mut held, 6), None);
+        assert_eq!(fit_short_frame(ShortFramePolicy::Drop, &full, &mut held, 6), Some(&full[..]));
+        assert_eq!(fit_short_frame(ShortFramePolicy::PadBlack, &short, &mut held, 6), Some(&[9, 9, 9, 0, 0, 0][..]));
+        
+        // pad_last starts from black, then keeps the tail of the last full frame
+        let mut held = Vec::new();
+        assert_eq!(fit_short_frame(ShortFramePolicy::PadLast, &short, &mut held, 6), Some(&[9, 9, 9, 0, 0, 0][..]));
+        fit_short_frame(ShortFramePolicy::PadLast, &full, &mut held, 6);
+        assert_eq!(fit_short_frame(ShortFramePolicy::PadLast, &short, &mut held, 6), Some(&[9, 9, 9, 2, 2, 2][..]));
+        assert_eq!(fit_short_frame(ShortFramePolicy::PadLast, &[], &mut held, 6), Some(&[9, 9, 9, 2, 2, 2][..]));
+    }
+    
+    #[test]
+    fn test_smoothing_settles_after_one_shot_change() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "mock-smoothing", "hardware_type": "mock", "protocol": "adalight",
+            "baud_rate": 2000000, "opc_channel": 0, "led_count": 1, "smoothing": 0.8,
+        })).unwrap();
+        let output = Output::new(config, None, false, false).unwrap();
+        output.send_frame(vec![0, 0, 0]).unwrap();
+        crate::mock_serial::wait_for_frames("mock-smoothing", 1, Duration::from_secs(2));
+        
+        // A source that sends one changed frame and then goes quiet still gets all the way there
+        output.send_frame(vec![200, 100, 50]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut last = Vec::new();
+        while Instant::now() < deadline {
+            last = crate::mock_serial::frames("mock-smoothing").last().cloned().unwrap_or_default();
+            if last.ends_with(&[200, 100, 50]) {
                 break;
             }
+            thread::sleep(Duration::from_millis(10));
         }
+        assert_eq!(&last[6..], &[200, 100, 50]);
     }
     
-    // Try to turn off LEDs on exit (best effort)
-    let blank_data = vec![0u8; config.led_count * 3];
-    let transformed = transform_pixels(blank_data, config.pixel_format.as_deref());
-    let frame = match config.protocol.as_str() {
-        "awa" => build_awa_frame(&transformed, stride),
-        "adalight" => build_adalight_frame(&transformed, stride),
-        _ => return,
-    };
-    let _ = port.write_all(&frame);
-    let _ = port.flush();
+    #[test]
+    fn test_max_fps_sends_newest_frame() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "mock-max-fps", "hardware_type": "mock", "protocol": "adalight",
+            "baud_rate": 2000000, "opc_channel": 0, "led_count": 1, "max_fps": 5, "queue_depth": 4,
+        })).unwrap();
+        let output = Output::new(config, None, false, false).unwrap();
+        output.send_frame(vec![1, 1, 1]).unwrap();
+        crate::mock_serial::wait_for_frames("mock-max-fps", 1, Duration::from_secs(2));
+        
+        // Frames queued while the output waits out its interval are skipped for the newest
+        for value in 2..=4 {
+            output.send_frame(vec![value; 3]).unwrap();
+        }
+        crate::mock_serial::wait_for_frames("mock-max-fps", 2, Duration::from_secs(2));
+        thread::sleep(Duration::from_millis(300));
+        let frames: Vec<u8> = crate::mock_serial::frames("mock-max-fps").iter().map(|frame| frame[6]).collect();
+        assert_eq!(frames, vec![1, 4]);
+        assert_eq!(output.state().frames_dropped.load(Ordering::Relaxed), 2);
+    }
+    
+    #[test]
+    fn test_standby_frames_take_the_live_path() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "mock-standby", "hardware_type": "mock", "protocol": "adalight",
+            "baud_rate": 2000000, "opc_channel": 0, "led_count": 2, "pixel_format": "GRB",
+            "transforms": [{"type": "reverse"}], "dedupe": true,
+            "standby": {"effect": "solid", "palette": [[10, 20, 30], [40, 50, 60]], "after_ms": 0},
+        })).unwrap();
+        let output = Output::new(config, None, false, false).unwrap();
+        crate::mock_serial::wait_for_frames("mock-standby", 1, Duration::from_secs(2));
+        thread::sleep(Duration::from_millis(200));
+    
+        // Laid out and wire-formatted like a live message, then written once and deduped
+        let frames = crate::mock_serial::frames("mock-standby");
+        assert_eq!(&frames[0][6..], &[50, 40, 60, 20, 10, 30]);
+        assert_eq!(frames.len(), 1);
+        let state = output.state();
+        assert_eq!(state.frames_sent.load(Ordering::Relaxed), 1);
+        assert!(state.frames_deduped.load(Ordering::Relaxed) > 0);
+    }
+    
+    /// Port that takes 4 bytes per write and then stalls for its whole timeout
+    struct StallingPort {
+        written: usize,
+        serial: crate::null_serial::NullSerial,
+    }
+    
+    impl Write for StallingPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written < 4 {
+                let n = buf.len().min(4 - self.written);
+                self.written += n;
+                return Ok(n);
+            }
+            thread::sleep(self.serial.timeout());
+            Err(io::Error::new(io::ErrorKind::TimedOut, "stalled"))
+        }
+        
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    impl io::Read for StallingPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.serial.read(buf)
+        }
+    }
+    
+    crate::null_serial::delegate_serial_port!(StallingPort, serial);
+    
+    #[test]
+    fn test_write_within_gives_up_on_stalled_port() {
+        let mut port = StallingPort { written: 0, serial: crate::null_serial::NullSerial::new("stall", 115200) };
+        let started = Instant::now();
+        let result = write_within(&mut port, &[0; 16], started + Duration::from_millis(50));
+        
+        // The stall is cut short at the deadline and the port's own timeout comes back
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(port.written, 4);
+        assert_eq!(port.timeout(), Duration::from_millis(1000));
+    }
 }
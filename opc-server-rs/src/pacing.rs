@@ -0,0 +1,103 @@
+use serialport::SerialPort;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+use crate::null_serial::delegate_serial_port;
+use crate::output::OutputState;
+
+/// Serial port wrapper that writes frames in chunks of `chunk_size` bytes, draining each
+/// chunk and pausing `inter_chunk_delay_us` before the next
+///
+/// For USB-serial bridges that drop bytes when a whole frame arrives in one burst. The
+/// pauses are added to the output's `pacing_us`, so the stats show what pacing costs.
+pub struct PacedPort {
+    inner: Box<dyn SerialPort>,
+    chunk_size: usize,
+    delay: Duration,
+    /// Bytes of the current chunk written so far
+    filled: usize,
+    state: Arc<OutputState>,
+}
+
+impl PacedPort {
+    /// Wrap `port` if the output sets `chunk_size`
+    pub fn wrap(port: Box<dyn SerialPort>, config: &OutputConfig, state: &Arc<OutputState>) -> Box<dyn SerialPort> {
+        match config.chunk_size {
+            Some(chunk_size) => Box::new(PacedPort {
+                inner: port,
+                chunk_size,
+                delay: Duration::from_micros(config.inter_chunk_delay_us.unwrap_or(0)),
+                filled: 0,
+                state: Arc::clone(state),
+            }),
+            None => port,
+        }
+    }
+}
+
+impl Write for PacedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.filled == self.chunk_size {
+                self.inner.flush()?;
+                let paused = Instant::now();
+                thread::sleep(self.delay);
+                self.state.pacing_us.fetch_add(paused.elapsed().as_micros() as u64, Ordering::Relaxed);
+                self.filled = 0;
+            }
+            let len = rest.len().min(self.chunk_size - self.filled);
+            self.inner.write_all(&rest[..len])?;
+            self.filled += len;
+            rest = &rest[len..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // The next frame starts a fresh chunk
+        self.filled = 0;
+        self.inner.flush()
+    }
+}
+
+impl io::Read for PacedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+delegate_serial_port!(PacedPort, inner);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock_serial::{self, MockPort};
+
+    #[test]
+    fn test_frames_written_in_paced_chunks() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "mock-paced", "hardware_type": "mock", "protocol": "awa",
+            "baud_rate": 2000000, "opc_channel": 0, "led_count": 3,
+            "chunk_size": 4, "inter_chunk_delay_us": 2000,
+        })).unwrap();
+        let state = Arc::new(OutputState::new(255));
+        let mut port = PacedPort::wrap(Box::new(MockPort::new(&config)), &config, &state);
+
+        port.write_all(&[1; 6]).unwrap();
+        port.write_all(&[2; 4]).unwrap();
+        port.flush().unwrap();
+        port.write_all(&[3; 2]).unwrap();
+        port.flush().unwrap();
+
+        // Each chunk reaches the port on its own; a flush ends the frame's last chunk
+        assert_eq!(mock_serial::frames("mock-paced"),
+                   vec![vec![1; 4], vec![1, 1, 2, 2], vec![2, 2], vec![3, 3]]);
+        assert!(state.pacing_us.load(Ordering::Relaxed) >= 4000);
+    }
+}
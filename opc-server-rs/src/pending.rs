@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+
+/// Default time between attempts to open an output that failed (`output_retry_ms`)
+pub const DEFAULT_OUTPUT_RETRY: Duration = Duration::from_secs(5);
+
+/// An output whose port could not be opened yet
+#[derive(Debug, Clone)]
+pub struct PendingOutput {
+    /// Entries sharing the port, opened together once it comes up
+    pub group: Vec<OutputConfig>,
+    pub attempts: u32,
+    pub last_error: String,
+    next_attempt: Instant,
+}
+
+impl PendingOutput {
+    /// An output that just failed its first attempt
+    pub fn new(group: Vec<OutputConfig>, error: String, next_attempt: Instant) -> Self {
+        PendingOutput { group, attempts: 1, last_error: error, next_attempt }
+    }
+
+    pub fn port(&self) -> &str {
+        &self.group[0].port
+    }
+
+    pub fn label(&self) -> String {
+        self.group[0].label()
+    }
+}
+
+/// Outputs waiting for their ports, e.g. USB devices that enumerate after boot
+#[derive(Debug, Default)]
+pub struct PendingOutputs {
+    entries: Vec<PendingOutput>,
+    /// Bumped whenever a reload replaces the list, so attempts started before it are discarded
+    pub generation: u64,
+}
+
+impl PendingOutputs {
+    pub fn new(entries: Vec<PendingOutput>) -> Self {
+        PendingOutputs { entries, generation: 0 }
+    }
+
+    pub fn entries(&self) -> &[PendingOutput] {
+        &self.entries
+    }
+
+    pub fn replace(&mut self, entries: Vec<PendingOutput>) {
+        self.entries = entries;
+        self.generation += 1;
+    }
+
+    pub fn push(&mut self, entry: PendingOutput) {
+        self.entries.push(entry);
+    }
+
+    /// Make an output due right away, e.g. because its device was just plugged in
+    pub fn retry_now(&mut self, port: &str, now: Instant) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.port() == port) {
+            entry.next_attempt = now;
+        }
+    }
+
+    /// The output whose attempt is most overdue, with the current generation
+    pub fn next_due(&self, now: Instant) -> Option<(u64, Vec<OutputConfig>)> {
+        self.entries.iter()
+            .filter(|entry| entry.next_attempt <= now)
+            .min_by_key(|entry| entry.next_attempt)
+            .map(|entry| (self.generation, entry.group.clone()))
+    }
+
+    /// Remove an output that has opened, returning its failed attempts
+    pub fn remove(&mut self, port: &str) -> Option<PendingOutput> {
+        let index = self.entries.iter().position(|entry| entry.port() == port)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Stop retrying one output for good, even if an attempt for it is under way
+    pub fn discard(&mut self, port: &str) -> Option<PendingOutput> {
+        let entry = self.remove(port)?;
+        self.generation += 1;
+        Some(entry)
+    }
+
+    /// Note another failed attempt and schedule the next one, returning the attempt count
+    pub fn record_failure(&mut self, port: &str, error: String, next_attempt: Instant) -> Option<u32> {
+        let entry = self.entries.iter_mut().find(|entry| entry.port() == port)?;
+        entry.attempts += 1;
+        entry.last_error = error;
+        entry.next_attempt = next_attempt;
+        Some(entry.attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(port: &str) -> OutputConfig {
+        serde_json::from_value(serde_json::json!({
+            "port": port, "protocol": "awa", "baud_rate": 2000000, "opc_channel": 0, "led_count": 10,
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_pending_schedule() {
+        let start = Instant::now();
+        let mut pending = PendingOutputs::new(vec![
+            PendingOutput::new(vec![output("/dev/ttyUSB0")], "busy".to_string(), start + Duration::from_secs(2)),
+            PendingOutput::new(vec![output("/dev/ttyUSB1")], "missing".to_string(), start + Duration::from_secs(1)),
+        ]);
+
+        assert!(pending.next_due(start).is_none());
+        let (generation, group) = pending.next_due(start + Duration::from_secs(3)).unwrap();
+        assert_eq!((generation, group[0].port.as_str()), (0, "/dev/ttyUSB1"));
+
+        // A failure pushes the output back behind the other one
+        let attempts = pending.record_failure("/dev/ttyUSB1", "missing".to_string(), start + Duration::from_secs(6));
+        assert_eq!(attempts, Some(2));
+        let (_, group) = pending.next_due(start + Duration::from_secs(3)).unwrap();
+        assert_eq!(group[0].port, "/dev/ttyUSB0");
+
+        assert_eq!(pending.remove("/dev/ttyUSB0").map(|entry| entry.attempts), Some(1));
+        assert!(pending.next_due(start + Duration::from_secs(3)).is_none());
+
+        pending.replace(Vec::new());
+        assert_eq!(pending.generation, 1);
+        assert!(pending.entries().is_empty());
+    }
+}
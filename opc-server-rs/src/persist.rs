@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::color::ColorCorrection;
+use crate::opc_server::SharedState;
+use crate::output::Output;
+
+/// How often changed state is written out; bursts of changes become one write
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runtime adjustments of one output, by output id
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct OutputAdjustments {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+/// Everything changed at runtime that outlives a restart
+///
+/// Only values actually changed are kept, so settings nobody touched keep following
+/// the config file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RuntimeState {
+    #[serde(default)]
+    pub outputs: BTreeMap<String, OutputAdjustments>,
+    /// Last color correction sent by a client (Fadecandy SysEx)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_correction: Option<ColorCorrection>,
+}
+
+/// The state file (`state_file`) and the adjustments recorded since startup
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<RuntimeState>,
+    /// Changes not yet written to the file
+    dirty: AtomicBool,
+}
+
+impl StateStore {
+    /// Read the state file if there is one; a missing file is an empty state
+    pub fn open(path: &Path) -> Result<Self> {
+        let state = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .context(format!("Failed to parse state file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RuntimeState::default(),
+            Err(e) => return Err(e).context(format!("Failed to read state file {}", path.display())),
+        };
+        Ok(StateStore {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The recorded color correction, if a client set one
+    pub fn color_correction(&self) -> Option<ColorCorrection> {
+        self.state.lock().unwrap().color_correction.clone()
+    }
+
+    /// Apply the recorded adjustments of an output that just opened
+    pub fn restore(&self, output: &Output) {
+        let state = self.state.lock().unwrap();
+        let Some(adjustments) = state.outputs.get(output.config().id()) else {
+            return;
+        };
+        if let Some(brightness) = adjustments.brightness {
+            output.set_brightness(brightness);
+        }
+        if let Some(enabled) = adjustments.enabled {
+            output.set_enabled(enabled);
+        }
+    }
+
+    /// Record a runtime change to an output
+    pub fn record_output(&self, id: &str, brightness: Option<f32>, enabled: Option<bool>) {
+        let mut state = self.state.lock().unwrap();
+        let adjustments = state.outputs.entry(id.to_string()).or_default();
+        if brightness.is_some() {
+            adjustments.brightness = brightness.map(|b| b.clamp(0.0, 1.0));
+        }
+        if enabled.is_some() {
+            adjustments.enabled = enabled;
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn record_color_correction(&self, correction: &ColorCorrection) {
+        let mut state = self.state.lock().unwrap();
+        if state.color_correction.as_ref() != Some(correction) {
+            state.color_correction = Some(correction.clone());
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Write the state file if anything changed since the last save
+    ///
+    /// The file is replaced in one step, so a crash mid-write leaves the old one.
+    pub fn save_if_changed(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(&*self.state.lock().unwrap())?;
+        let temp = self.path.with_extension("tmp");
+        let result = fs::write(&temp, json + "\n")
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .context(format!("Failed to write state file {}", self.path.display()));
+        if result.is_err() {
+            // Try again with the next change
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Default state file next to the config: `config.json` keeps its state in `config.state.json`
+pub fn default_path(config_path: &str) -> Option<PathBuf> {
+    let path = Path::new(config_path);
+    let stem = path.file_stem()?.to_str()?;
+    Some(path.with_file_name(format!("{}.state.json", stem)))
+}
+
+/// Save changes in the background until the server stops
+pub fn spawn(state: SharedState, store: std::sync::Arc<StateStore>) {
+    thread::spawn(move || {
+        while state.running.load(Ordering::Relaxed) {
+            thread::sleep(SAVE_INTERVAL);
+            if let Err(e) = store.save_if_changed() {
+                eprintln!("✗ {:#}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("opc_server_state_test_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = StateStore::open(&path).unwrap();
+        store.record_output("desk", Some(0.25), None);
+        store.record_output("desk", None, Some(false));
+        store.record_output("shelf", Some(2.0), None);
+        store.save_if_changed().unwrap();
+
+        let state = StateStore::open(&path).unwrap().state.into_inner().unwrap();
+        assert_eq!(state.outputs["desk"], OutputAdjustments { brightness: Some(0.25), enabled: Some(false) });
+        assert_eq!(state.outputs["shelf"], OutputAdjustments { brightness: Some(1.0), enabled: None });
+        assert_eq!(state.color_correction, None);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(default_path("/etc/opc/lights.yaml"), Some(PathBuf::from("/etc/opc/lights.state.json")));
+    }
+}
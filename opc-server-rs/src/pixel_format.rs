@@ -1,91 +1,98 @@
-/// Pixel format transformation
-pub fn transform_pixels(data: Vec<u8>, format: Option<&str>) -> Vec<u8> {
-    match format {
-        None | Some("RGB") => data, // No transformation needed
-        Some("GRB") => transform_grb(data),
-        Some("BGR") => transform_bgr(data),
-        Some("RGBW") => transform_rgbw(data),
-        Some("GRBW") => transform_grbw(data),
-        _ => data, // Unknown format, passthrough
-    }
-}
+use crate::transform::TransformChain;
 
-/// Transform RGB to GRB (swap R and G channels in-place)
-fn transform_grb(mut data: Vec<u8>) -> Vec<u8> {
-    let pixel_count = data.len() / 3;
-    
-    for i in 0..pixel_count {
-        let idx = i * 3;
-        data.swap(idx, idx + 1); // Swap R and G
-    }
-    
+/// Pixel format transformation
+///
+/// Compiles the format's chain on every call; outputs keep theirs from
+/// `TransformChain::pixel_format`.
+pub fn transform_pixels(mut data: Vec<u8>, format: Option<&str>) -> Vec<u8> {
+    TransformChain::pixel_format(format).run(&mut data);
     data
 }
 
-/// Transform RGB to BGR (swap R and B channels in-place)
-fn transform_bgr(mut data: Vec<u8>) -> Vec<u8> {
-    let pixel_count = data.len() / 3;
-    
-    for i in 0..pixel_count {
-        let idx = i * 3;
-        data.swap(idx, idx + 2); // Swap R and B
+/// Clamp the dim end of RGB pixel data in-place: channel values below `threshold`
+/// become 0, and the remaining lit ones are raised to at least `floor`
+///
+/// Black stays black, so blank frames are unaffected by the floor.
+pub fn apply_black_level(data: &mut [u8], threshold: u8, floor: u8) {
+    for value in data.iter_mut() {
+        if *value < threshold {
+            *value = 0;
+        } else if *value > 0 && *value < floor {
+            *value = floor;
+        }
     }
-    
-    data
 }
 
-/// Transform RGB to RGBW (extract white channel)
-fn transform_rgbw(data: Vec<u8>) -> Vec<u8> {
-    let pixel_count = data.len() / 3;
-    let mut result = Vec::with_capacity(pixel_count * 4);
-    
-    for i in 0..pixel_count {
-        let idx = i * 3;
-        let r = data[idx];
-        let g = data[idx + 1];
-        let b = data[idx + 2];
-        
-        // Extract white channel as minimum of RGB
-        let w = r.min(g).min(b);
-        
-        // Subtract white from RGB channels
-        result.push(r - w);
-        result.push(g - w);
-        result.push(b - w);
-        result.push(w);
+/// Bytes per pixel on the wire after `transform_pixels`
+pub fn stride(format: Option<&str>) -> usize {
+    match format {
+        Some("RGBW") | Some("GRBW") => 4,
+        _ => 3,
     }
-    
-    result
 }
 
-/// Transform RGB to GRBW (extract white channel, swap R and G)
-fn transform_grbw(data: Vec<u8>) -> Vec<u8> {
+/// Expand RGB to 4-byte pixels: the white channel is min(R, G, B), subtracted from
+/// each color, and the colors are written in `order` (indices into R, G, B)
+///
+/// Writes into `result`, sized up front, with fixed-size chunks so the loop has no
+/// bounds checks or reallocation and can be autovectorized.
+pub(crate) fn extract_white(data: &[u8], order: [usize; 3], result: &mut Vec<u8>) {
     let pixel_count = data.len() / 3;
-    let mut result = Vec::with_capacity(pixel_count * 4);
+    result.clear();
+    result.resize(pixel_count * 4, 0);
     
-    for i in 0..pixel_count {
-        let idx = i * 3;
-        let r = data[idx];
-        let g = data[idx + 1];
-        let b = data[idx + 2];
-        
-        // Extract white channel as minimum of RGB
-        let w = r.min(g).min(b);
-        
-        // Subtract white from RGB channels, then arrange as GRBW
-        result.push(g - w);
-        result.push(r - w);
-        result.push(b - w);
-        result.push(w);
+    for (out, pixel) in result.chunks_exact_mut(4).zip(data.chunks_exact(3)) {
+        let w = pixel[0].min(pixel[1]).min(pixel[2]);
+        out[0] = pixel[order[0]] - w;
+        out[1] = pixel[order[1]] - w;
+        out[2] = pixel[order[2]] - w;
+        out[3] = w;
     }
-    
-    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Pixel-at-a-time reference transform
+    fn transform_scalar(data: &[u8], format: &str) -> Vec<u8> {
+        let mut result = Vec::new();
+        for i in 0..data.len() / 3 {
+            let (r, g, b) = (data[i * 3], data[i * 3 + 1], data[i * 3 + 2]);
+            let w = r.min(g).min(b);
+            match format {
+                "GRB" => result.extend_from_slice(&[g, r, b]),
+                "BGR" => result.extend_from_slice(&[b, g, r]),
+                "RGBW" => result.extend_from_slice(&[r - w, g - w, b - w, w]),
+                "GRBW" => result.extend_from_slice(&[g - w, r - w, b - w, w]),
+                _ => unreachable!(),
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_transforms_match_scalar() {
+        // 1000 LEDs plus a trailing partial pixel, which is dropped
+        let data: Vec<u8> = (0..3001).map(|i| (i * 37 % 251) as u8).collect();
+        for format in ["GRB", "BGR", "RGBW", "GRBW"] {
+            let mut expected = transform_scalar(&data, format);
+            let result = transform_pixels(data.clone(), Some(format));
+            if format.len() == 3 {
+                // In-place swaps leave the partial pixel untouched
+                expected.push(data[3000]);
+            }
+            assert_eq!(result, expected, "format {}", format);
+        }
+    }
+
+    #[test]
+    fn test_black_level() {
+        let mut data = vec![0, 2, 3, 4, 10, 255];
+        apply_black_level(&mut data, 3, 8);
+        assert_eq!(data, vec![0, 0, 8, 8, 10, 255]);
+    }
+
     #[test]
     fn test_rgb_passthrough() {
         let data = vec![255, 0, 0, 0, 255, 0, 0, 0, 255];
@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+
+/// Most buffers kept for reuse by one pool
+const MAX_POOLED_BUFFERS: usize = 8;
+
+/// Pool of reusable frame buffers
+///
+/// Frames flow from the OPC reader to an output worker and back, so steady-state
+/// streaming reuses the same few allocations instead of allocating per frame.
+#[derive(Default)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take an empty buffer, reusing a pooled allocation when one is available
+    pub fn get(&self, capacity: usize) -> Vec<u8> {
+        match self.buffers.lock().unwrap().pop() {
+            Some(mut buffer) => {
+                buffer.clear();
+                buffer.reserve(capacity);
+                buffer
+            }
+            None => Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Take a buffer holding a copy of `data`
+    pub fn copy_from(&self, data: &[u8]) -> Vec<u8> {
+        let mut buffer = self.get(data.len());
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    /// Return a buffer to the pool (dropped if the pool is full)
+    pub fn put(&self, buffer: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < MAX_POOLED_BUFFERS && buffer.capacity() > 0 {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::new();
+        let buffer = pool.copy_from(&[1, 2, 3]);
+        let ptr = buffer.as_ptr();
+        pool.put(buffer);
+
+        let reused = pool.get(3);
+        assert!(reused.is_empty());
+        assert_eq!(reused.as_ptr(), ptr);
+    }
+}
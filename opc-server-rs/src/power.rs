@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+
+/// Default current drawn by one channel of one LED at full brightness (WS2812-class LEDs)
+const DEFAULT_MILLIAMPS_PER_CHANNEL: f32 = 20.0;
+
+/// Longest gap between frames that counts towards soft start headroom, so a pause
+/// in the stream doesn't let the next frame jump straight to full brightness
+const MAX_SOFT_START_STEP: Duration = Duration::from_millis(100);
+
+/// Current budget for one output
+///
+/// Frames whose estimated draw would exceed the budget are scaled down uniformly,
+/// similar to FastLED's power management.
+#[derive(Debug, Clone)]
+pub struct PowerLimit {
+    max_milliamps: f32,
+    /// Current per R, G, B channel at full brightness
+    milliamps_per_channel: [f32; 3],
+}
+
+impl PowerLimit {
+    /// Build the limit for an output, if it sets `max_milliamps`
+    pub fn from_config(config: &OutputConfig) -> Option<Self> {
+        config.max_milliamps.map(|max| PowerLimit {
+            max_milliamps: max as f32,
+            milliamps_per_channel: config.milliamps_per_channel
+                .unwrap_or([DEFAULT_MILLIAMPS_PER_CHANNEL; 3]),
+        })
+    }
+
+    /// Estimated current for RGB pixel data, in milliamps
+    pub fn estimate(&self, data: &[u8]) -> f32 {
+        let mut totals = [0u32; 3];
+        for pixel in data.chunks_exact(3) {
+            for (total, &value) in totals.iter_mut().zip(pixel) {
+                *total += value as u32;
+            }
+        }
+        totals.iter()
+            .zip(self.milliamps_per_channel.iter())
+            .map(|(&total, &ma)| total as f32 / 255.0 * ma)
+            .sum()
+    }
+
+    /// Scale RGB pixel data in-place so it stays within the budget
+    ///
+    /// Returns true if the frame was scaled down.
+    pub fn apply(&self, data: &mut [u8]) -> bool {
+        let estimate = self.estimate(data);
+        if estimate <= self.max_milliamps {
+            return false;
+        }
+
+        let scale = self.max_milliamps / estimate;
+        for value in data.iter_mut() {
+            *value = (*value as f32 * scale) as u8;
+        }
+        true
+    }
+}
+
+/// Inrush limit for one output (`soft_start_ms`)
+///
+/// The average brightness of a frame may only rise above the previous frame's at
+/// the configured rate, so a jump from black to white is spread over several frames.
+/// Brightness may always fall at once.
+#[derive(Debug, Clone)]
+pub struct SoftStart {
+    ramp: Duration,
+    last_frame_at: Instant,
+}
+
+impl SoftStart {
+    /// Build the limit for an output, if it sets `soft_start_ms`
+    pub fn from_config(config: &OutputConfig) -> Option<Self> {
+        config.soft_start_ms.filter(|&ms| ms > 0).map(|ms| SoftStart {
+            ramp: Duration::from_millis(ms),
+            last_frame_at: Instant::now(),
+        })
+    }
+
+    /// Scale RGB pixel data in-place so it is at most one step brighter than `previous`
+    ///
+    /// Returns true if the frame was scaled down.
+    pub fn apply(&mut self, data: &mut [u8], previous: &[u8]) -> bool {
+        let step = self.last_frame_at.elapsed().min(MAX_SOFT_START_STEP);
+        self.last_frame_at = Instant::now();
+        self.limit(data, previous, step)
+    }
+
+    fn limit(&self, data: &mut [u8], previous: &[u8], step: Duration) -> bool {
+        let allowed = average_level(previous) + step.as_secs_f32() / self.ramp.as_secs_f32();
+        let level = average_level(data);
+        if level <= allowed {
+            return false;
+        }
+
+        let scale = allowed / level;
+        for value in data.iter_mut() {
+            *value = (*value as f32 * scale) as u8;
+        }
+        true
+    }
+}
+
+/// Mean of all channel values, from 0.0 (black) to 1.0 (full white)
+fn average_level(data: &[u8]) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    data.iter().map(|&v| v as u64).sum::<u64>() as f32 / (data.len() as f32 * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(max_milliamps: f32) -> PowerLimit {
+        PowerLimit {
+            max_milliamps,
+            milliamps_per_channel: [20.0; 3],
+        }
+    }
+
+    #[test]
+    fn test_frame_within_budget_is_untouched() {
+        let mut data = vec![255, 255, 255, 0, 0, 0];
+        assert!(!limit(60.0).apply(&mut data));
+        assert_eq!(data, vec![255, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_frame_over_budget_is_scaled() {
+        // Two full-white pixels draw 120mA
+        let mut data = vec![255; 6];
+        let power = limit(60.0);
+        assert!(power.apply(&mut data));
+        assert!(power.estimate(&data) <= 60.0);
+        assert_eq!(data, vec![127; 6]);
+    }
+
+    #[test]
+    fn test_soft_start_limits_rise() {
+        let soft_start = SoftStart { ramp: Duration::from_millis(1000), last_frame_at: Instant::now() };
+
+        // Black to white within 100ms of a 1s ramp reaches a tenth of full brightness
+        let mut data = vec![255; 6];
+        assert!(soft_start.limit(&mut data, &[0; 6], Duration::from_millis(100)));
+        assert_eq!(data, vec![25; 6]);
+
+        // Falling is never limited
+        let mut data = vec![10; 6];
+        assert!(!soft_start.limit(&mut data, &[255; 6], Duration::from_millis(100)));
+        assert_eq!(data, vec![10; 6]);
+    }
+}
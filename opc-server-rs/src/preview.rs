@@ -0,0 +1,68 @@
+use anyhow::Result;
+use serde_json::json;
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::http::Request;
+use crate::opc_server::SharedState;
+use crate::websocket::{Message, WebSocket};
+
+/// Browser page rendering the preview stream
+pub const PAGE: &str = include_str!("preview.html");
+
+/// Interval between frames sent to a preview client
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stream the latest frame of every output to a WebSocket client until it disconnects
+///
+/// A text message describing the outputs is sent first and again whenever the output
+/// list changes (e.g. after a reload); each binary message then holds one frame per
+/// output in that order: a u16 big-endian byte length followed by RGB bytes.
+pub fn stream(stream: TcpStream, request: &Request, state: &SharedState) -> Result<()> {
+    let mut ws = WebSocket::accept(stream.try_clone()?, request)?;
+
+    // Short timeout so reading for a close message paces the send loop
+    stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+
+    let mut layout = serde_json::Value::Null;
+    let mut last_sent = Instant::now() - FRAME_INTERVAL;
+    let mut frame = Vec::new();
+
+    while state.running.load(Ordering::Relaxed) {
+        if let Some(Message::Close) = ws.read_message()? {
+            break;
+        }
+
+        if last_sent.elapsed() < FRAME_INTERVAL {
+            continue;
+        }
+        last_sent = Instant::now();
+
+        frame.clear();
+        let outputs = state.outputs.read().unwrap();
+
+        let current_layout = json!({
+            "outputs": outputs.iter().map(|o| json!({
+                "id": o.config().id(),
+                "led_count": o.config().led_count,
+            })).collect::<Vec<_>>(),
+        });
+
+        for output in outputs.iter() {
+            let pixels = output.state().last_frame.lock().unwrap().clone();
+            let length = pixels.len().min(u16::MAX as usize);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+            frame.extend_from_slice(&pixels[..length]);
+        }
+        drop(outputs);
+
+        if current_layout != layout {
+            ws.send_text(&current_layout.to_string())?;
+            layout = current_layout;
+        }
+        ws.send_binary(&frame)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Acknowledgement a device sends after each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AckMode {
+    /// `Ada\n` ready line, as sent by classic Adalight firmware
+    Ada,
+    /// Single ACK (0x06) or NAK (0x15) byte
+    Byte,
+}
+
+/// Outcome of waiting for a frame acknowledgement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckResult {
+    Ack,
+    Nak,
+    Timeout,
+}
+
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+
+/// Interval between checks for response bytes
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Look for a complete response in the bytes received so far
+fn parse_response(mode: AckMode, received: &[u8]) -> Option<AckResult> {
+    match mode {
+        AckMode::Ada => received.windows(4).any(|w| w == b"Ada\n").then_some(AckResult::Ack),
+        // Other bytes (e.g. debug output from the firmware) are skipped
+        AckMode::Byte => received.iter().find_map(|&b| match b {
+            ACK => Some(AckResult::Ack),
+            NAK => Some(AckResult::Nak),
+            _ => None,
+        }),
+    }
+}
+
+/// Wait up to `timeout` for the device to acknowledge the frame just written
+///
+/// The deadline also holds while the device keeps sending other bytes (boot logs,
+/// debug output), and only the tail a split response could start in is kept.
+pub fn wait_for_ack(port: &mut dyn SerialPort, mode: AckMode, timeout: Duration) -> std::io::Result<AckResult> {
+    let deadline = Instant::now() + timeout;
+    // `Ada\n` may arrive split over reads; single bytes are scanned as they come
+    let tail = match mode {
+        AckMode::Ada => 3,
+        AckMode::Byte => 0,
+    };
+    let mut buf = [0u8; 64];
+    let mut received = Vec::with_capacity(tail + buf.len());
+
+    loop {
+        let available = port.bytes_to_read().unwrap_or(0) as usize;
+        if available > 0 {
+            let want = available.min(buf.len());
+            let n = port.read(&mut buf[..want])?;
+            received.extend_from_slice(&buf[..n]);
+            if let Some(result) = parse_response(mode, &received) {
+                return Ok(result);
+            }
+            received.drain(..received.len().saturating_sub(tail));
+        }
+        if Instant::now() >= deadline {
+            return Ok(AckResult::Timeout);
+        }
+        if available == 0 {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(parse_response(AckMode::Ada, b"Ad"), None);
+        assert_eq!(parse_response(AckMode::Ada, b"noise Ada\n"), Some(AckResult::Ack));
+        assert_eq!(parse_response(AckMode::Byte, b"dbg"), None);
+        assert_eq!(parse_response(AckMode::Byte, &[b'x', NAK]), Some(AckResult::Nak));
+        assert_eq!(parse_response(AckMode::Byte, &[ACK]), Some(AckResult::Ack));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_times_out_on_a_stream_of_noise() {
+        use serialport::TTYPort;
+        use std::io::Write;
+
+        let (mut device, mut port) = TTYPort::pair().unwrap();
+        let noise = thread::spawn(move || {
+            let started = Instant::now();
+            while started.elapsed() < Duration::from_secs(2) {
+                if device.write_all(b"boot log line without a response\r\n").is_err() {
+                    break;
+                }
+            }
+        });
+
+        for mode in [AckMode::Ada, AckMode::Byte] {
+            let started = Instant::now();
+            assert_eq!(wait_for_ack(&mut port, mode, Duration::from_millis(100)).unwrap(), AckResult::Timeout);
+            assert!(started.elapsed() < Duration::from_millis(500));
+        }
+        // Closing the port ends the writer
+        drop(port);
+        noise.join().unwrap();
+
+        // The tail of a split response still counts
+        let (mut device, mut port) = TTYPort::pair().unwrap();
+        device.write_all(b"noise Ad").unwrap();
+        let finish = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            device.write_all(b"a\n").unwrap();
+            device
+        });
+        assert_eq!(wait_for_ack(&mut port, AckMode::Ada, Duration::from_secs(1)).unwrap(), AckResult::Ack);
+        finish.join().unwrap();
+    }
+}
@@ -1,15 +1,47 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::time::Duration;
+
+use super::{check, count_note, FrameField, PIXELS_FIELD};
+use crate::autobaud;
+use crate::config::{AdalightCount, OutputConfig};
+
+/// Rates Adalight sketches commonly use, tried after the configured ones
+pub const ADALIGHT_BAUD_RATES: &[u32] = &[115200, 230400, 460800, 500000, 921600, 1000000, 57600];
+
 /// Build Adalight protocol frame
+///
+/// The header carries the LED count minus one, as Adafruit's original LEDstream sketch
+/// and the Adalight senders (Hyperion, Prismatik's Adalight device) expect: a sketch
+/// reading the field as `hi * 256 + lo + 1` gets exactly the LEDs sent. Firmware that
+/// reads it as the raw count is off by one LED; those outputs set `adalight_count`.
 pub fn build_adalight_frame(pixel_data: &[u8], stride: usize) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(6 + pixel_data.len());
+    build_adalight_frame_into(pixel_data, stride, &mut frame);
+    frame
+}
+
+/// Build Adalight protocol frame into `frame`, reusing its allocation
+pub fn build_adalight_frame_into(pixel_data: &[u8], stride: usize, frame: &mut Vec<u8>) {
+    build_adalight_frame_counted(pixel_data, stride, AdalightCount::MinusOne, frame);
+}
+
+/// Build Adalight protocol frame into `frame` with the header's LED count in `count` convention
+pub fn build_adalight_frame_counted(pixel_data: &[u8], stride: usize, count: AdalightCount, frame: &mut Vec<u8>) {
     let led_count = pixel_data.len() / stride;
     
     // Adalight header: 'Ada' + LED count high + LED count low + checksum
-    // CRITICAL: LED count field is (actual_count - 1), matching AWA protocol convention
-    let count_minus_one = led_count.saturating_sub(1);
-    let count_hi = (count_minus_one >> 8) as u8 & 0xFF;
-    let count_lo = count_minus_one as u8 & 0xFF;
+    // CRITICAL: LED count field is (actual_count - 1) unless the firmware wants the raw count
+    let count_field = match count {
+        AdalightCount::MinusOne => led_count.saturating_sub(1),
+        AdalightCount::Actual => led_count,
+    };
+    let count_hi = (count_field >> 8) as u8;
+    let count_lo = count_field as u8;
     let checksum = count_hi ^ count_lo ^ 0x55;
     
-    let mut frame = Vec::with_capacity(6 + pixel_data.len());
+    frame.clear();
+    frame.reserve(6 + pixel_data.len());
     
     // Header
     frame.extend_from_slice(&[0x41, 0x64, 0x61]); // 'Ada'
@@ -19,6 +51,78 @@ pub fn build_adalight_frame(pixel_data: &[u8], stride: usize) -> Vec<u8> {
     
     // Pixel data
     frame.extend_from_slice(pixel_data);
+}
+
+/// Fields of an Adalight frame, checking its header checksum and LED count
+pub fn describe_adalight_frame(frame: &[u8], stride: usize, count: AdalightCount) -> Vec<FrameField> {
+    if frame.len() < 6 {
+        return Vec::new();
+    }
+    let field = (frame[3] as usize) << 8 | frame[4] as usize;
+    let announced = match count {
+        AdalightCount::MinusOne => field + 1,
+        AdalightCount::Actual => field,
+    };
+    let carried = (frame.len() - 6) / stride;
+    let checksum = frame[3] ^ frame[4] ^ 0x55;
     
-    frame
+    vec![
+        FrameField { name: "magic", bytes: 0..3, note: check(&frame[..3] == b"Ada", "Ada") },
+        FrameField { name: "count", bytes: 3..5, note: count_note(announced, carried) },
+        FrameField { name: "checksum", bytes: 5..6, note: check(frame[5] == checksum, &format!("{:02x}", checksum)) },
+        FrameField { name: PIXELS_FIELD, bytes: 6..frame.len(), note: format!("{} LEDs", carried) },
+    ]
+}
+
+/// Open an Adalight port once the sketch has greeted with `Ada`, probing baud rates
+///
+/// Each attempt reopens the port, which resets most Arduinos, so every rate gets the
+/// full greeting timeout.
+pub fn handshake(config: &OutputConfig, debug: bool, ddebug: bool) -> Result<Box<dyn SerialPort>> {
+    let timeout = config.wait_for_ada_ms.map(Duration::from_millis).unwrap_or(autobaud::GREETING_TIMEOUT);
+    let rates = config.baud_candidates(ADALIGHT_BAUD_RATES);
+    
+    let (baud, port) = autobaud::scan(&config.label(), &rates, ddebug, |baud| autobaud::open_greeted(config, baud, timeout))
+        .map_err(|e| anyhow::anyhow!("No 'Ada' greeting from {} ({}, {}ms each)", config.port, e, timeout.as_millis()))?;
+    
+    if baud != config.baud_rate {
+        eprintln!("Warning: Adalight device on {} answered at {} baud, not the configured {}; using {}",
+                  config.label(), baud, config.baud_rate, baud);
+    }
+    if debug {
+        println!("✓ Adalight device greeted on {} at {} baud", config.label(), baud);
+    }
+    Ok(port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_minus_one_header() {
+        // (LED count, header bytes after 'Ada'): count - 1 and hi ^ lo ^ 0x55
+        for (leds, header) in [(1, [0x00, 0x00, 0x55]), (2, [0x00, 0x01, 0x54]), (256, [0x00, 0xff, 0xaa]),
+                               (257, [0x01, 0x00, 0x54]), (300, [0x01, 0x2b, 0x7f])] {
+            let pixels = vec![7u8; leds * 3];
+            let frame = build_adalight_frame(&pixels, 3);
+            assert_eq!(&frame[..6], &[b'A', b'd', b'a', header[0], header[1], header[2]], "{} LEDs", leds);
+            assert_eq!(&frame[6..], &pixels[..]);
+
+            // The sketch's reading of the field is the number of LEDs that follow
+            let announced = (frame[3] as usize) << 8 | frame[4] as usize;
+            assert_eq!(announced + 1, leds);
+        }
+
+        // RGBW pixels count four bytes per LED
+        assert_eq!(&build_adalight_frame(&[0; 8], 4)[3..6], &[0x00, 0x01, 0x54]);
+
+        // Firmware reading the raw count
+        let mut frame = Vec::new();
+        build_adalight_frame_counted(&[7; 900], 3, AdalightCount::Actual, &mut frame);
+        assert_eq!(&frame[..6], &[b'A', b'd', b'a', 0x01, 0x2c, 0x78]);
+        assert_eq!(describe_adalight_frame(&frame, 3, AdalightCount::Actual)[1].note, "300 LEDs");
+        // Read with the other convention, the header is one LED off
+        assert_eq!(describe_adalight_frame(&frame, 3, AdalightCount::MinusOne)[1].note, "301 LEDs, BAD: frame carries 300");
+    }
 }
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::udp::{self, UdpPort};
+use super::{check, FrameField, Protocol, PIXELS_FIELD};
+use crate::config::OutputConfig;
+
+/// UDP port Art-Net nodes listen on
+pub const ARTNET_PORT: u16 = 6454;
+
+/// 15-bit port addresses: net, sub-net and universe
+pub const UNIVERSES: RangeInclusive<u16> = 0..=0x7fff;
+
+const ID: &[u8; 8] = b"Art-Net\0";
+
+/// OpCode of ArtDmx, the packet carrying one universe's slots
+const OP_DMX: u16 = 0x5000;
+
+const PROTOCOL_VERSION: u16 = 14;
+
+/// ID, OpCode, version, sequence, physical, universe and length
+const HEADER_LEN: usize = 18;
+
+/// Art-Net output: the output's pixels packed into consecutive universes, one ArtDmx
+/// packet each, sent to the node at `port`
+///
+/// Universes hold `universe_size` bytes; without it pixels are never split across
+/// universes, so an RGB universe carries 170 LEDs.
+#[derive(Default)]
+pub struct ArtNet {
+    universe: u16,
+    /// Pixel bytes per universe
+    slots: usize,
+    /// Sequence number of the last frame; receivers use it to drop reordered packets
+    sequence: AtomicU8,
+}
+
+/// First universe of an Art-Net output (default: 0)
+pub fn first_universe(config: &OutputConfig) -> u16 {
+    config.universe.unwrap_or(0)
+}
+
+/// Length of the ArtDmx packet starting `frame`
+pub fn artnet_packet_len(frame: &[u8]) -> Option<usize> {
+    let length = frame.get(16..18)?;
+    Some(HEADER_LEN + u16::from_be_bytes([length[0], length[1]]) as usize)
+}
+
+impl Protocol for ArtNet {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.universe = first_universe(config);
+        self.slots = udp::universe_slots(config);
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], _stride: usize, frame: &mut Vec<u8>) {
+        // 0 would tell receivers not to check the order, so it is skipped when wrapping
+        let sequence = match self.sequence.fetch_add(1, Ordering::Relaxed).wrapping_add(1) {
+            0 => {
+                self.sequence.store(1, Ordering::Relaxed);
+                1
+            }
+            sequence => sequence,
+        };
+
+        frame.clear();
+        for (i, slots) in pixels.chunks(self.slots).enumerate() {
+            let universe = self.universe.wrapping_add(i as u16);
+            // The length must be even
+            let length = slots.len() + slots.len() % 2;
+            frame.extend_from_slice(ID);
+            frame.extend_from_slice(&OP_DMX.to_le_bytes());
+            frame.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+            frame.push(sequence);
+            frame.push(0);
+            frame.push(universe as u8);
+            frame.push((universe >> 8) as u8 & 0x7f);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+            frame.extend_from_slice(slots);
+            frame.resize(frame.len() + length - slots.len(), 0);
+        }
+    }
+
+    /// `port` is the node's address, `host` or `host:port`; a broadcast address reaches every node
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let target = udp::resolve(&config.port, ARTNET_PORT)?;
+        Ok(Box::new(UdpPort::open(&config.port, vec![target], artnet_packet_len)?))
+    }
+
+    /// Fields of a frame that fits one universe; longer frames are shown as raw bytes
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        if frame.len() < HEADER_LEN || artnet_packet_len(frame) != Some(frame.len()) {
+            return Vec::new();
+        }
+        let length = frame.len() - HEADER_LEN;
+        let universe = u16::from_le_bytes([frame[14], frame[15]]);
+        vec![
+            FrameField { name: "id", bytes: 0..8, note: check(&frame[..8] == ID, "Art-Net") },
+            FrameField { name: "opcode", bytes: 8..10, note: check(frame[8..10] == OP_DMX.to_le_bytes(), "ArtDmx") },
+            FrameField { name: "version", bytes: 10..12, note: check(frame[10..12] == PROTOCOL_VERSION.to_be_bytes(), "14") },
+            FrameField { name: "sequence", bytes: 12..13, note: frame[12].to_string() },
+            FrameField { name: "universe", bytes: 14..16, note: format!("universe {}", universe) },
+            FrameField { name: "length", bytes: 16..18, note: check(length.is_multiple_of(2), &format!("{} slots", length)) },
+            FrameField { name: PIXELS_FIELD, bytes: HEADER_LEN..HEADER_LEN + length / stride * stride,
+                         note: format!("{} LEDs", length / stride) },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artdmx_packets() {
+        let artnet = ArtNet { universe: 0x1ff, slots: 6, ..Default::default() };
+        let frame = artnet.build_frame(&[1, 2, 3, 4, 5, 6, 7, 8, 9], 3);
+
+        // One packet per universe; the universe is little-endian, the length big-endian
+        assert_eq!(&frame[..HEADER_LEN], b"Art-Net\0\x00\x50\x00\x0e\x01\x00\xff\x01\x00\x06");
+        assert_eq!(&frame[HEADER_LEN..24], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(artnet_packet_len(&frame), Some(24));
+        // The last universe's 3 slots are padded to an even length
+        assert_eq!(&frame[24..24 + HEADER_LEN], b"Art-Net\0\x00\x50\x00\x0e\x01\x00\x00\x02\x00\x04");
+        assert_eq!(&frame[24 + HEADER_LEN..], &[7, 8, 9, 0]);
+        assert_eq!(artnet_packet_len(&frame[24..]), Some(frame.len() - 24));
+    }
+
+    #[test]
+    fn test_sequence_skips_zero() {
+        let artnet = ArtNet { slots: 510, ..Default::default() };
+        artnet.sequence.store(254, Ordering::Relaxed);
+        assert_eq!(artnet.build_frame(&[0; 3], 3)[12], 255);
+        assert_eq!(artnet.build_frame(&[0; 3], 3)[12], 1);
+        assert_eq!(artnet.build_frame(&[0; 3], 3)[12], 2);
+    }
+
+    #[test]
+    fn test_describe_frame() {
+        let artnet = ArtNet { universe: 3, slots: 510, ..Default::default() };
+        let frame = artnet.build_frame(&[1, 2, 3], 3);
+        let fields = artnet.describe_frame(&frame, 3);
+        let note = |name: &str| fields.iter().find(|f| f.name == name).unwrap().note.clone();
+        assert_eq!(note("id"), "ok");
+        assert_eq!(note("opcode"), "ok");
+        assert_eq!(note("version"), "ok");
+        assert_eq!(note("sequence"), "1");
+        assert_eq!(note("universe"), "universe 3");
+        assert_eq!(note("length"), "ok");
+        let pixels = fields.iter().find(|f| f.name == PIXELS_FIELD).unwrap();
+        assert_eq!((pixels.bytes.clone(), pixels.note.as_str()), (18..21, "1 LEDs"));
+
+        // Frames spanning several universes are left to the raw dump
+        let artnet = ArtNet { slots: 3, ..Default::default() };
+        assert!(artnet.describe_frame(&artnet.build_frame(&[1, 2, 3, 4, 5, 6], 3), 3).is_empty());
+    }
+}
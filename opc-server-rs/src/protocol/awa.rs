@@ -1,13 +1,24 @@
+use super::{check, count_note, FrameField, PIXELS_FIELD};
+
 /// Build AWA protocol frame (HyperSerialPico format)
 pub fn build_awa_frame(pixel_data: &[u8], stride: usize) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(6 + pixel_data.len() + 3);
+    build_awa_frame_into(pixel_data, stride, &mut frame);
+    frame
+}
+
+/// Build AWA protocol frame into `frame`, reusing its allocation
+pub fn build_awa_frame_into(pixel_data: &[u8], stride: usize, frame: &mut Vec<u8>) {
     let led_count = pixel_data.len() / stride;
     
     // AWA header: 'Awa' + LED count high + LED count low + CRC
-    let count_hi = ((led_count - 1) >> 8) as u8 & 0xFF;
-    let count_lo = (led_count - 1) as u8 & 0xFF;
+    let count_minus_one = led_count.saturating_sub(1);
+    let count_hi = (count_minus_one >> 8) as u8;
+    let count_lo = count_minus_one as u8;
     let crc = (count_hi ^ count_lo) ^ 0x55;
     
-    let mut frame = Vec::with_capacity(6 + pixel_data.len() + 3);
+    frame.clear();
+    frame.reserve(6 + pixel_data.len() + 3);
     
     // Header
     frame.extend_from_slice(&[0x41, 0x77, 0x61]); // 'Awa'
@@ -18,17 +29,69 @@ pub fn build_awa_frame(pixel_data: &[u8], stride: usize) -> Vec<u8> {
     // Pixel data
     frame.extend_from_slice(pixel_data);
     
-    // Calculate Fletcher checksums (matches HyperSerialPico implementation)
-    let mut fletcher1: u16 = 0;
-    let mut fletcher2: u16 = 0;
-    let mut fletcher_ext: u16 = 0;
-    let mut position: u16 = 0;
-    
-    for &byte in pixel_data {
-        fletcher1 = (fletcher1 + byte as u16) % 255;
-        fletcher2 = (fletcher2 + fletcher1) % 255;
-        fletcher_ext = (fletcher_ext + ((byte as u16) ^ position)) % 255;
-        position += 1;
+    let (fletcher1, fletcher2, fletcher_ext) = fletcher_checksums(pixel_data);
+    
+    // Checksums
+    frame.push(fletcher1);
+    frame.push(fletcher2);
+    frame.push(fletcher_ext);
+}
+
+/// Fields of an AWA frame, checking its header CRC and Fletcher checksums
+pub fn describe_awa_frame(frame: &[u8], stride: usize) -> Vec<FrameField> {
+    // Header, trailer, and no pixels
+    if frame.len() < 9 {
+        return Vec::new();
+    }
+    let pixels = 6..frame.len() - 3;
+    let announced = ((frame[3] as usize) << 8 | frame[4] as usize) + 1;
+    let carried = pixels.len() / stride;
+    let crc = frame[3] ^ frame[4] ^ 0x55;
+    let (fletcher1, fletcher2, fletcher_ext) = fletcher_checksums(&frame[pixels.clone()]);
+    
+    vec![
+        FrameField { name: "magic", bytes: 0..3, note: check(&frame[..3] == b"Awa", "Awa") },
+        FrameField { name: "count", bytes: 3..5, note: count_note(announced, carried) },
+        FrameField { name: "crc", bytes: 5..6, note: check(frame[5] == crc, &format!("{:02x}", crc)) },
+        FrameField { name: PIXELS_FIELD, bytes: pixels.clone(), note: format!("{} LEDs", carried) },
+        FrameField {
+            name: "fletcher",
+            bytes: pixels.end..frame.len(),
+            note: check(frame[pixels.end..] == [fletcher1, fletcher2, fletcher_ext],
+                        &format!("{:02x} {:02x} {:02x}", fletcher1, fletcher2, fletcher_ext)),
+        },
+    ]
+}
+
+/// Bytes summed between modulo reductions, small enough that the u32 sums cannot overflow
+const FLETCHER_BLOCK: usize = 1024;
+
+/// Fletcher checksums over the pixel data (matches HyperSerialPico implementation)
+///
+/// The firmware reduces modulo 255 after every byte; since the sums are linear the
+/// reduction is deferred to the end of each block, which removes a division per byte.
+/// Position is a wrapping 16-bit counter in the firmware.
+pub(super) fn fletcher_checksums(pixel_data: &[u8]) -> (u8, u8, u8) {
+    let mut fletcher1: u32 = 0;
+    let mut fletcher2: u32 = 0;
+    let mut fletcher_ext: u32 = 0;
+    
+    for (block_index, block) in pixel_data.chunks(FLETCHER_BLOCK).enumerate() {
+        for &byte in block {
+            fletcher1 += byte as u32;
+            fletcher2 += fletcher1;
+        }
+        
+        // Independent of the running sums, so this loop can be vectorized
+        let base = block_index * FLETCHER_BLOCK;
+        fletcher_ext += block.iter()
+            .enumerate()
+            .map(|(i, &byte)| (byte as u32) ^ ((base + i) as u16 as u32))
+            .sum::<u32>();
+        
+        fletcher1 %= 255;
+        fletcher2 %= 255;
+        fletcher_ext %= 255;
     }
     
     // Special case: if fletcher_ext is 0x41 ('A'), use 0xaa instead
@@ -36,10 +99,81 @@ pub fn build_awa_frame(pixel_data: &[u8], stride: usize) -> Vec<u8> {
         fletcher_ext = 0xaa;
     }
     
-    // Checksums
-    frame.push(fletcher1 as u8);
-    frame.push(fletcher2 as u8);
-    frame.push(fletcher_ext as u8);
-    
-    frame
+    (fletcher1 as u8, fletcher2 as u8, fletcher_ext as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte-at-a-time reference, reducing after every byte like the firmware
+    fn fletcher_scalar(pixel_data: &[u8]) -> (u8, u8, u8) {
+        let mut fletcher1: u32 = 0;
+        let mut fletcher2: u32 = 0;
+        let mut fletcher_ext: u32 = 0;
+        for (position, &byte) in pixel_data.iter().enumerate() {
+            fletcher1 = (fletcher1 + byte as u32) % 255;
+            fletcher2 = (fletcher2 + fletcher1) % 255;
+            fletcher_ext = (fletcher_ext + ((byte as u32) ^ (position as u16 as u32))) % 255;
+        }
+        if fletcher_ext == 0x41 {
+            fletcher_ext = 0xaa;
+        }
+        (fletcher1 as u8, fletcher2 as u8, fletcher_ext as u8)
+    }
+
+    #[test]
+    fn test_fletcher_matches_scalar() {
+        // Covers partial blocks and the 16-bit position wraparound (max OPC message size)
+        for len in [0, 1, 3, 450, 1023, 1024, 1025, 3000, 65535] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 % 251) as u8).collect();
+            assert_eq!(fletcher_checksums(&data), fletcher_scalar(&data), "length {}", len);
+        }
+
+        let white = vec![255u8; 65535];
+        assert_eq!(fletcher_checksums(&white), fletcher_scalar(&white));
+    }
+
+    /// Name, pixel data, stride, header bytes after 'Awa', and the fletcher1,
+    /// fletcher2 and fletcher_ext trailer
+    type GoldenFrame = (&'static str, fn() -> Vec<u8>, usize, [u8; 3], [u8; 3]);
+
+    /// Known-good frames, computed independently with HyperSerialPico's per-byte receive loop
+    const GOLDEN_FRAMES: &[GoldenFrame] = &[
+        ("one black LED", || vec![0, 0, 0], 3, [0x00, 0x00, 0x55], [0x00, 0x00, 0x03]),
+        ("one red LED", || vec![255, 0, 0], 3, [0x00, 0x00, 0x55], [0x00, 0x00, 0x03]),
+        // fletcher_ext comes to 0x41 ('A') and is sent as 0xaa
+        ("fletcher_ext 0x41", || vec![0x3e, 0, 0], 3, [0x00, 0x00, 0x55], [0x3e, 0xba, 0xaa]),
+        // Only fletcher_ext is special-cased; fletcher1 may be 0x41
+        ("two RGBW LEDs", || vec![10, 20, 30, 5, 0, 0, 0, 255], 4, [0x00, 0x01, 0x54], [0x41, 0xaa, 0x49]),
+        ("300 LED ramp", || (0..900).map(|i| i as u8).collect(), 3, [0x01, 0x2b, 0x7f], [0xe7, 0x29, 0x90]),
+        ("1000 white RGBW LEDs", || vec![255; 4000], 4, [0x03, 0xe7, 0xb1], [0x00, 0x00, 0xf0]),
+    ];
+
+    #[test]
+    fn test_describe_checks_header_and_checksums() {
+        let mut frame = build_awa_frame(&[0x3e, 0, 0, 1, 2, 3], 3);
+        let fields = describe_awa_frame(&frame, 3);
+        let names: Vec<_> = fields.iter().map(|f| f.name).collect();
+        assert_eq!(names, ["magic", "count", "crc", "pixels", "fletcher"]);
+        assert!(fields.iter().all(|f| f.note == "ok" || f.note == "2 LEDs"), "{:?}", fields);
+        assert_eq!(fields[3].bytes, 6..12);
+
+        // A corrupted pixel shows up in the checksums
+        frame[6] ^= 1;
+        assert!(describe_awa_frame(&frame, 3)[4].note.starts_with("BAD"));
+        assert!(describe_awa_frame(&frame[..8], 3).is_empty());
+    }
+
+    #[test]
+    fn test_golden_frames() {
+        for &(name, pixels, stride, header, checksums) in GOLDEN_FRAMES {
+            let pixels = pixels();
+            let mut expected = b"Awa".to_vec();
+            expected.extend_from_slice(&header);
+            expected.extend_from_slice(&pixels);
+            expected.extend_from_slice(&checksums);
+            assert_eq!(build_awa_frame(&pixels, stride), expected, "{}", name);
+        }
+    }
 }
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::udp::{self, UdpPort};
+use super::{check, FrameField, Protocol, PIXELS_FIELD};
+use crate::config::OutputConfig;
+
+/// UDP port DDP receivers listen on
+pub const DDP_PORT: u16 = 4048;
+
+/// Flags, sequence, data type, destination, offset and length
+const HEADER_LEN: usize = 10;
+
+/// Most pixel bytes per packet, as xLights and the controllers use (480 RGB pixels)
+const MAX_DATA: usize = 1440;
+
+const VERSION_1: u8 = 0x40;
+
+/// Set on the last packet of a frame: the receiver shows everything sent so far
+const PUSH: u8 = 0x01;
+
+/// Destination of pixel data on every DDP device
+const DISPLAY: u8 = 1;
+
+/// DDP (Distributed Display Protocol) output, as spoken by ESPixelStick, Falcon
+/// controllers and WLED
+///
+/// Each frame is split into packets of up to 1440 bytes carrying their byte offset
+/// into the output; the last one has the push flag. Packets carry a 4-bit sequence
+/// number, 1 to 15.
+#[derive(Default)]
+pub struct Ddp {
+    sequence: AtomicU8,
+}
+
+/// Length of the DDP packet starting `frame`
+pub fn ddp_packet_len(frame: &[u8]) -> Option<usize> {
+    let length = frame.get(8..10)?;
+    Some(HEADER_LEN + u16::from_be_bytes([length[0], length[1]]) as usize)
+}
+
+/// DDP data type of 8-bit pixels with `stride` bytes: RGB, RGBW or undefined
+fn data_type(stride: usize) -> u8 {
+    match stride {
+        3 => 0x0b,
+        4 => 0x1b,
+        _ => 0x00,
+    }
+}
+
+impl Protocol for Ddp {
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        let sequence = self.sequence.load(Ordering::Relaxed) % 15 + 1;
+        self.sequence.store(sequence, Ordering::Relaxed);
+        // Whole pixels per packet
+        let packet_data = MAX_DATA / stride * stride;
+        let packets = pixels.len().div_ceil(packet_data);
+
+        frame.clear();
+        for (i, data) in pixels.chunks(packet_data).enumerate() {
+            let offset = i * packet_data;
+            frame.push(if i + 1 == packets { VERSION_1 | PUSH } else { VERSION_1 });
+            frame.push(sequence);
+            frame.push(data_type(stride));
+            frame.push(DISPLAY);
+            frame.extend_from_slice(&(offset as u32).to_be_bytes());
+            frame.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            frame.extend_from_slice(data);
+        }
+    }
+
+    /// `port` is the controller's address, `host` or `host:port`
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let target = udp::resolve(&config.port, DDP_PORT)?;
+        Ok(Box::new(UdpPort::open(&config.port, vec![target], ddp_packet_len)?))
+    }
+
+    /// Fields of a frame that fits one packet; longer frames are shown as raw bytes
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        if frame.len() < HEADER_LEN || ddp_packet_len(frame) != Some(frame.len()) {
+            return Vec::new();
+        }
+        let length = frame.len() - HEADER_LEN;
+        vec![
+            FrameField { name: "flags", bytes: 0..1, note: check(frame[0] == VERSION_1 | PUSH, "version 1, push") },
+            FrameField { name: "sequence", bytes: 1..2, note: check((1..=15).contains(&frame[1]), &frame[1].to_string()) },
+            FrameField { name: "type", bytes: 2..3, note: check(frame[2] == data_type(stride), &format!("{:02x}", data_type(stride))) },
+            FrameField { name: "id", bytes: 3..4, note: check(frame[3] == DISPLAY, "display") },
+            FrameField { name: "offset", bytes: 4..8, note: check(frame[4..8] == [0; 4], "0") },
+            FrameField { name: "length", bytes: 8..10, note: format!("{} bytes", length) },
+            FrameField { name: PIXELS_FIELD, bytes: HEADER_LEN..HEADER_LEN + length / stride * stride,
+                         note: format!("{} LEDs", length / stride) },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_split_at_max_data_with_push_on_last() {
+        let ddp = Ddp::default();
+        // 500 RGB pixels: 480 in the first packet, 20 in the second
+        let frame = ddp.build_frame(&[5; 1500], 3);
+        assert_eq!(frame.len(), 2 * HEADER_LEN + 1500);
+        assert_eq!(&frame[..HEADER_LEN], &[0x40, 1, 0x0b, 1, 0, 0, 0, 0, 0x05, 0xa0]);
+        let second = ddp_packet_len(&frame).unwrap();
+        assert_eq!(&frame[second..second + HEADER_LEN], &[0x41, 1, 0x0b, 1, 0, 0, 0x05, 0xa0, 0, 60]);
+        assert!(ddp.describe_frame(&frame, 3).is_empty());
+
+        // Sequence numbers run from 1 to 15
+        for _ in 0..14 {
+            ddp.build_frame(&[0; 3], 3);
+        }
+        let frame = ddp.build_frame(&[1, 2, 3], 3);
+        assert_eq!(frame[1], 1);
+        let fields = ddp.describe_frame(&frame, 3);
+        assert!(fields.iter().all(|f| !f.note.contains("BAD")), "{:?}", fields);
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::Result;
+use std::sync::Mutex;
+
+use super::awa::fletcher_checksums;
+use super::{check, FrameField, Protocol};
+use crate::config::OutputConfig;
+
+const MAGIC: &[u8; 3] = b"Dlt";
+
+/// Magic, flags, sequence, LED count, bytes per pixel and record count
+const HEADER_LEN: usize = 10;
+
+/// Set in the flags of a frame carrying every pixel
+const KEYFRAME: u8 = 0x01;
+
+/// Set in an RLE packet header for a run of one repeated pixel
+const RUN: u8 = 0x80;
+
+/// Most pixels one RLE packet covers
+const MAX_PACKET: usize = 128;
+
+/// Unchanged pixels between two changed ranges that are resent rather than starting a
+/// new record (a record header costs 4 bytes)
+const MAX_GAP: usize = 2;
+
+/// Frames between keyframes (`keyframe_interval`)
+const DEFAULT_KEYFRAME_INTERVAL: u32 = 30;
+
+/// What the receiver was last sent
+#[derive(Default)]
+struct Reference {
+    pixels: Vec<u8>,
+    sequence: u8,
+    since_keyframe: u32,
+}
+
+/// Delta-encoded serial protocol for slow links: only the pixel ranges that changed
+/// since the previous frame are sent, run-length encoded, with a full keyframe every
+/// `keyframe_interval` frames
+///
+/// The wire format and what the firmware must do is specified in DELTA_PROTOCOL.md.
+#[derive(Default)]
+pub struct Delta {
+    keyframe_interval: u32,
+    reference: Mutex<Reference>,
+}
+
+/// The header's LED count and record starts are 16 bits
+pub fn check_delta(config: &OutputConfig) -> Result<()> {
+    if config.led_count > u16::MAX as usize {
+        anyhow::bail!("the delta protocol carries at most {} LEDs", u16::MAX);
+    }
+    Ok(())
+}
+
+/// Append RLE packets for `pixels` to `frame`
+fn encode_rle(pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+    let count = pixels.len() / stride;
+    let pixel = |i: usize| &pixels[i * stride..(i + 1) * stride];
+    let mut i = 0;
+    let mut literal_start = 0;
+    while i < count {
+        let mut run = 1;
+        while i + run < count && run < MAX_PACKET && pixel(i + run) == pixel(i) {
+            run += 1;
+        }
+        // A run of two already takes fewer bytes than two literal pixels
+        if run < 2 && i - literal_start < MAX_PACKET {
+            i += 1;
+            continue;
+        }
+        push_literal(pixels, stride, literal_start, i, frame);
+        if run >= 2 {
+            frame.push(RUN | (run - 1) as u8);
+            frame.extend_from_slice(pixel(i));
+            i += run;
+        }
+        literal_start = i;
+    }
+    push_literal(pixels, stride, literal_start, count, frame);
+}
+
+/// Append a literal packet for pixels `start..end` (at most `MAX_PACKET` of them)
+fn push_literal(pixels: &[u8], stride: usize, start: usize, end: usize, frame: &mut Vec<u8>) {
+    if end > start {
+        frame.push((end - start - 1) as u8);
+        frame.extend_from_slice(&pixels[start * stride..end * stride]);
+    }
+}
+
+/// Pixel ranges of `pixels` that differ from `previous`, with short gaps merged
+fn changed_ranges(pixels: &[u8], previous: &[u8], stride: usize) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (i, (new, old)) in pixels.chunks_exact(stride).zip(previous.chunks_exact(stride)).enumerate() {
+        if new == old {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if i - *end <= MAX_GAP => *end = i + 1,
+            _ => ranges.push((i, i + 1)),
+        }
+    }
+    ranges
+}
+
+/// Append one record: start pixel, pixel count and the RLE packets covering them
+fn push_record(pixels: &[u8], stride: usize, (start, end): (usize, usize), frame: &mut Vec<u8>) {
+    frame.extend_from_slice(&(start as u16).to_be_bytes());
+    frame.extend_from_slice(&((end - start) as u16).to_be_bytes());
+    encode_rle(&pixels[start * stride..end * stride], stride, frame);
+}
+
+impl Protocol for Delta {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.keyframe_interval = config.keyframe_interval.unwrap_or(DEFAULT_KEYFRAME_INTERVAL).max(1);
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        let mut reference = self.reference.lock().unwrap();
+        let count = pixels.len() / stride;
+        let pixels = &pixels[..count * stride];
+        reference.sequence = reference.sequence.wrapping_add(1);
+        let due = reference.since_keyframe + 1 >= self.keyframe_interval.max(1);
+
+        frame.clear();
+        frame.extend_from_slice(MAGIC);
+        frame.extend_from_slice(&[0, reference.sequence]);
+        frame.extend_from_slice(&(count as u16).to_be_bytes());
+        frame.push(stride as u8);
+        frame.extend_from_slice(&[0, 0]);
+
+        let mut keyframe = due || reference.pixels.len() != pixels.len();
+        if !keyframe {
+            let ranges = changed_ranges(pixels, &reference.pixels, stride);
+            for &range in &ranges {
+                push_record(pixels, stride, range, frame);
+            }
+            frame[8..10].copy_from_slice(&(ranges.len() as u16).to_be_bytes());
+            // A frame that changes nearly everything is cheaper as a keyframe
+            keyframe = frame.len() - HEADER_LEN > 4 + pixels.len();
+        }
+        if keyframe {
+            frame.truncate(HEADER_LEN);
+            frame[3] = KEYFRAME;
+            frame[8..10].copy_from_slice(&1u16.to_be_bytes());
+            push_record(pixels, stride, (0, count), frame);
+            reference.since_keyframe = 0;
+        } else {
+            reference.since_keyframe += 1;
+        }
+
+        let (fletcher1, fletcher2, fletcher_ext) = fletcher_checksums(&frame[3..]);
+        frame.extend_from_slice(&[fletcher1, fletcher2, fletcher_ext]);
+        reference.pixels.clear();
+        reference.pixels.extend_from_slice(pixels);
+    }
+
+    /// A keyframe of pixels that never repeat: one record of literal packets only
+    fn max_frame_len(&self, count: usize, stride: usize) -> usize {
+        HEADER_LEN + 4 + count.div_ceil(MAX_PACKET) + count * stride + 3
+    }
+
+    /// The receiver may not hold what was last built, so the next frame is a keyframe
+    fn frame_abandoned(&self) {
+        self.reference.lock().unwrap().pixels.clear();
+    }
+
+    fn describe_frame(&self, frame: &[u8], _stride: usize) -> Vec<FrameField> {
+        if frame.len() < HEADER_LEN + 3 {
+            return Vec::new();
+        }
+        let body = HEADER_LEN..frame.len() - 3;
+        let count = u16::from_be_bytes([frame[5], frame[6]]);
+        let records = u16::from_be_bytes([frame[8], frame[9]]);
+        let (fletcher1, fletcher2, fletcher_ext) = fletcher_checksums(&frame[3..body.end]);
+        vec![
+            FrameField { name: "magic", bytes: 0..3, note: check(&frame[..3] == MAGIC, "Dlt") },
+            FrameField { name: "flags", bytes: 3..4,
+                         note: if frame[3] & KEYFRAME != 0 { "keyframe".to_string() } else { "delta".to_string() } },
+            FrameField { name: "sequence", bytes: 4..5, note: frame[4].to_string() },
+            FrameField { name: "count", bytes: 5..7, note: format!("{} LEDs", count) },
+            FrameField { name: "stride", bytes: 7..8, note: format!("{} bytes per pixel", frame[7]) },
+            FrameField { name: "records", bytes: 8..10, note: format!("{} ranges", records) },
+            FrameField { name: "data", bytes: body.clone(), note: format!("{} bytes", body.len()) },
+            FrameField {
+                name: "fletcher",
+                bytes: body.end..frame.len(),
+                note: check(frame[body.end..] == [fletcher1, fletcher2, fletcher_ext],
+                            &format!("{:02x} {:02x} {:02x}", fletcher1, fletcher2, fletcher_ext)),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_then_changed_ranges() {
+        let delta = Delta { keyframe_interval: 3, ..Default::default() };
+        let mut pixels = vec![0u8; 10 * 3];
+        pixels[27..30].copy_from_slice(&[1, 2, 3]);
+
+        // First frame: a keyframe of 9 black pixels run-length encoded, then one literal
+        let frame = delta.build_frame(&pixels, 3);
+        assert_eq!(&frame[..HEADER_LEN], b"Dlt\x01\x01\x00\x0a\x03\x00\x01");
+        assert_eq!(&frame[HEADER_LEN..frame.len() - 3], &[0, 0, 0, 10, 0x88, 0, 0, 0, 0x00, 1, 2, 3]);
+        assert!(delta.describe_frame(&frame, 3).iter().all(|f| !f.note.contains("BAD")));
+
+        // Then only pixels 2 and 4 (with the unchanged 3 between them) are sent
+        pixels[6] = 9;
+        pixels[12] = 9;
+        let frame = delta.build_frame(&pixels, 3);
+        assert_eq!(&frame[3..HEADER_LEN], &[0, 2, 0, 10, 3, 0, 1]);
+        assert_eq!(&frame[HEADER_LEN..frame.len() - 3], &[0, 2, 0, 3, 2, 9, 0, 0, 0, 0, 0, 9, 0, 0]);
+
+        // An unchanged frame has no records; three frames after the last keyframe comes the next
+        let frame = delta.build_frame(&pixels, 3);
+        assert_eq!(frame.len(), HEADER_LEN + 3);
+        assert_eq!(delta.build_frame(&pixels, 3)[3], KEYFRAME);
+    }
+
+    #[test]
+    fn test_max_frame_len_is_uncompressed_keyframe() {
+        let delta = Delta::default();
+        let pixels: Vec<u8> = (0..200 * 3).map(|i| (i % 251) as u8).collect();
+        let frame = delta.build_frame(&pixels, 3);
+        assert_eq!(frame[3], KEYFRAME);
+        assert_eq!(delta.max_frame_len(200, 3), frame.len());
+    }
+
+    #[test]
+    fn test_led_count_fits_header() {
+        let config = |led_count: usize| -> OutputConfig {
+            serde_json::from_value(serde_json::json!({
+                "port": "/dev/ttyUSB0", "protocol": "delta", "baud_rate": 2000000,
+                "opc_channel": 0, "led_count": led_count,
+            })).unwrap()
+        };
+        assert!(check_delta(&config(65535)).is_ok());
+        assert!(check_delta(&config(65536)).is_err());
+    }
+
+    #[test]
+    fn test_abandoned_frame_forces_keyframe() {
+        let delta = Delta { keyframe_interval: 30, ..Default::default() };
+        let pixels = vec![5u8; 4 * 3];
+        assert_eq!(delta.build_frame(&pixels, 3)[3], KEYFRAME);
+        assert_eq!(delta.build_frame(&pixels, 3)[3], 0);
+
+        delta.frame_abandoned();
+        assert_eq!(delta.build_frame(&pixels, 3)[3], KEYFRAME);
+    }
+}
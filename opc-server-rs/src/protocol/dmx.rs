@@ -0,0 +1,286 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use super::Protocol;
+use crate::config::OutputConfig;
+use crate::null_serial::delegate_serial_port;
+use crate::output::Output;
+use crate::pixel_format;
+
+/// Slots in a DMX universe
+pub const UNIVERSE_SLOTS: usize = 512;
+
+/// DMX512 line rate; every slot is 11 bits (start bit, 8 data bits, 2 stop bits)
+pub const DMX_BAUD_RATE: u32 = 250_000;
+
+/// Start code of dimmer data; other codes carry text, RDM and the like
+const NULL_START_CODE: u8 = 0x00;
+
+/// Break before each packet; DMX512 needs at least 88µs, and sleeps overshoot anyway
+const BREAK: Duration = Duration::from_micros(100);
+
+/// Mark after break; at least 8µs
+const MARK_AFTER_BREAK: Duration = Duration::from_micros(12);
+
+/// Enttec DMX USB Pro message delimiters and the "Output Only Send DMX Packet" label
+const ENTTEC_START: u8 = 0x7e;
+const ENTTEC_END: u8 = 0xe7;
+const ENTTEC_SEND_DMX: u8 = 6;
+
+/// Fewest slots the Enttec Pro sends; shorter packets are padded with zeros
+const ENTTEC_MIN_SLOTS: usize = 24;
+
+/// Whether `protocol` names one of the DMX protocols
+pub fn is_dmx(protocol: &str) -> bool {
+    protocol == "dmx" || protocol == "enttec-pro"
+}
+
+/// What one DMX slot shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// Byte of the output's transformed pixel data
+    Byte(usize),
+    Fixed(u8),
+    Unused,
+}
+
+/// DMX slots of an output, from its `dmx_channels` table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DmxLayout {
+    /// Slot `i` is DMX address `i + 1`, up to the highest address in use
+    slots: Vec<Slot>,
+}
+
+impl DmxLayout {
+    /// The output's `dmx_channels`, or all of its pixel bytes from address 1 without a table
+    pub fn from_config(config: &OutputConfig) -> Result<Self> {
+        let format = config.pixel_format.as_deref().unwrap_or("RGB");
+        let stride = pixel_format::stride(config.pixel_format.as_deref());
+        if config.protocol == "dmx" && config.baud_rate != DMX_BAUD_RATE {
+            anyhow::bail!("DMX runs at {} baud, not {}", DMX_BAUD_RATE, config.baud_rate);
+        }
+
+        let Some(channels) = &config.dmx_channels else {
+            let bytes = config.led_count * stride;
+            if bytes > UNIVERSE_SLOTS {
+                anyhow::bail!("{} LEDs of {} bytes need {} DMX slots, more than a universe's {}; map them with dmx_channels",
+                              config.led_count, stride, bytes, UNIVERSE_SLOTS);
+            }
+            return Ok(DmxLayout { slots: (0..bytes).map(Slot::Byte).collect() });
+        };
+
+        let mut slots = Vec::new();
+        for channel in channels {
+            let address = channel.address as usize;
+            if !(1..=UNIVERSE_SLOTS).contains(&address) {
+                anyhow::bail!("dmx_channels: address {} is not between 1 and {}", address, UNIVERSE_SLOTS);
+            }
+            let slot = match (channel.pixel, &channel.color, channel.value) {
+                (Some(pixel), Some(color), None) => {
+                    if pixel >= config.led_count {
+                        anyhow::bail!("dmx_channels: address {} shows pixel {}, but the output has {} LEDs",
+                                      address, pixel, config.led_count);
+                    }
+                    let letter = color.chars().next().filter(|_| color.chars().count() == 1);
+                    let Some(index) = letter.and_then(|l| format.chars().position(|c| c.eq_ignore_ascii_case(&l))) else {
+                        anyhow::bail!("dmx_channels: address {}: color '{}' is not in pixel_format {}", address, color, format);
+                    };
+                    Slot::Byte(pixel * stride + index)
+                }
+                (None, None, Some(value)) => Slot::Fixed(value),
+                _ => anyhow::bail!("dmx_channels: address {} needs either \"pixel\" and \"color\", or \"value\"", address),
+            };
+            if slots.len() < address {
+                slots.resize(address, Slot::Unused);
+            }
+            if slots[address - 1] != Slot::Unused {
+                anyhow::bail!("dmx_channels: address {} is listed twice", address);
+            }
+            slots[address - 1] = slot;
+        }
+        Ok(DmxLayout { slots })
+    }
+
+    /// Levels of the slots for `pixels`; pixel bytes missing from a short frame are 0
+    fn fill(&self, pixels: &[u8], frame: &mut Vec<u8>) {
+        frame.extend(self.slots.iter().map(|slot| match *slot {
+            Slot::Byte(i) => pixels.get(i).copied().unwrap_or(0),
+            Slot::Fixed(value) => value,
+            Slot::Unused => 0,
+        }));
+    }
+}
+
+/// The output's layout, or the default one and why its own was rejected
+fn configure_layout(config: &OutputConfig) -> (DmxLayout, Option<String>) {
+    match DmxLayout::from_config(config) {
+        Ok(layout) => (layout, None),
+        Err(e) => (DmxLayout::default(), Some(format!("{:#}", e))),
+    }
+}
+
+/// DMX512 straight from a serial adapter, as an Enttec Open DMX (FTDI) is driven
+///
+/// The port sends a break before every packet; `connect` opens it at 8N2 whatever the
+/// output's `stop_bits` say.
+#[derive(Default)]
+pub struct OpenDmx {
+    layout: DmxLayout,
+    /// Why the output's `dmx_channels` were rejected; `connect` fails with it
+    invalid: Option<String>,
+}
+
+impl Protocol for OpenDmx {
+    fn configure(&mut self, config: &OutputConfig) {
+        (self.layout, self.invalid) = configure_layout(config);
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], _stride: usize, frame: &mut Vec<u8>) {
+        frame.clear();
+        frame.push(NULL_START_CODE);
+        self.layout.fill(pixels, frame);
+    }
+
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        if let Some(invalid) = &self.invalid {
+            anyhow::bail!("{}", invalid);
+        }
+        let mut config = config.clone();
+        config.stop_bits = Some(2);
+        Ok(Box::new(BreakPort::new(Output::open_standard_port(&config)?)))
+    }
+}
+
+/// DMX through an Enttec DMX USB Pro (or compatible), which times the packets itself
+#[derive(Default)]
+pub struct EnttecPro {
+    layout: DmxLayout,
+    /// Why the output's `dmx_channels` were rejected; `connect` fails with it
+    invalid: Option<String>,
+}
+
+impl Protocol for EnttecPro {
+    fn configure(&mut self, config: &OutputConfig) {
+        (self.layout, self.invalid) = configure_layout(config);
+    }
+
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        if let Some(invalid) = &self.invalid {
+            anyhow::bail!("{}", invalid);
+        }
+        Output::open_standard_port(config)
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], _stride: usize, frame: &mut Vec<u8>) {
+        frame.clear();
+        frame.extend_from_slice(&[ENTTEC_START, ENTTEC_SEND_DMX, 0, 0, NULL_START_CODE]);
+        self.layout.fill(pixels, frame);
+        frame.resize(frame.len().max(5 + ENTTEC_MIN_SLOTS), 0);
+        // The length counts the start code and the slots
+        let length = (frame.len() - 4) as u16;
+        frame[2..4].copy_from_slice(&length.to_le_bytes());
+        frame.push(ENTTEC_END);
+    }
+}
+
+/// Serial port wrapper that sends a DMX break and mark-after-break before each flushed packet
+pub struct BreakPort {
+    inner: Box<dyn SerialPort>,
+    /// Bytes written since the last flush
+    pending: Vec<u8>,
+}
+
+impl BreakPort {
+    pub fn new(inner: Box<dyn SerialPort>) -> Self {
+        BreakPort { inner, pending: Vec::new() }
+    }
+}
+
+impl Write for BreakPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return self.inner.flush();
+        }
+        // The previous packet must be on the wire before the line is held low
+        self.inner.flush()?;
+        self.inner.set_break().map_err(io::Error::from)?;
+        thread::sleep(BREAK);
+        self.inner.clear_break().map_err(io::Error::from)?;
+        thread::sleep(MARK_AFTER_BREAK);
+        let result = self.inner.write_all(&self.pending).and_then(|_| self.inner.flush());
+        self.pending.clear();
+        result
+    }
+}
+
+impl io::Read for BreakPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+delegate_serial_port!(BreakPort, inner);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(extra: serde_json::Value) -> OutputConfig {
+        let mut entry = serde_json::json!(OutputConfig {
+            protocol: "enttec-pro".to_string(),
+            baud_rate: 57600,
+            led_count: 2,
+            ..OutputConfig::test_default("/dev/ttyUSB0")
+        });
+        entry.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(entry).unwrap()
+    }
+
+    #[test]
+    fn test_dmx_channel_map() {
+        // Without a table the pixel bytes fill the universe from address 1
+        let mut enttec = EnttecPro::default();
+        enttec.configure(&output(serde_json::json!({})));
+        let frame = enttec.build_frame(&[1, 2, 3, 4, 5, 6], 3);
+        assert_eq!(&frame[..11], &[0x7e, 6, 25, 0, 0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(frame.len(), 5 + ENTTEC_MIN_SLOTS + 1);
+        assert_eq!(frame.last(), Some(&0xe7));
+
+        // A fixture at address 10: master dimmer, then the second pixel's green and red
+        let mapped = output(serde_json::json!({"protocol": "dmx", "baud_rate": 250000, "pixel_format": "GRB", "dmx_channels": [
+            {"address": 10, "value": 255},
+            {"address": 11, "pixel": 1, "color": "r"},
+            {"address": 12, "pixel": 1, "color": "G"},
+        ]}));
+        let mut dmx = OpenDmx::default();
+        dmx.configure(&mapped);
+        let frame = dmx.build_frame(&[1, 2, 3, 4, 5, 6], 3);
+        assert_eq!(frame.len(), 13);
+        assert_eq!(&frame[..1], &[0]);
+        assert_eq!(&frame[9..], &[0, 255, 5, 4]);
+
+        let invalid = |channels: serde_json::Value| DmxLayout::from_config(&output(serde_json::json!({"dmx_channels": channels}))).is_err();
+        assert!(invalid(serde_json::json!([{"address": 0, "value": 1}])));
+        assert!(invalid(serde_json::json!([{"address": 1, "pixel": 2, "color": "R"}])));
+        assert!(invalid(serde_json::json!([{"address": 1, "pixel": 0, "color": "W"}])));
+        assert!(invalid(serde_json::json!([{"address": 1, "value": 1}, {"address": 1, "value": 2}])));
+        assert!(invalid(serde_json::json!([{"address": 1, "pixel": 0, "color": "R", "value": 3}])));
+        assert!(DmxLayout::from_config(&output(serde_json::json!({"led_count": 200}))).is_err());
+        assert!(DmxLayout::from_config(&output(serde_json::json!({"protocol": "dmx"}))).is_err());
+
+        // An output that skipped the config check still fails to open with the reason
+        let mut enttec = EnttecPro::default();
+        let rejected = output(serde_json::json!({"led_count": 200}));
+        enttec.configure(&rejected);
+        let error = enttec.connect(&rejected, false, false).err().unwrap();
+        assert_eq!(format!("{:#}", error), format!("{:#}", DmxLayout::from_config(&rejected).unwrap_err()));
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use serialport::SerialPort;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::Duration;
+
+use super::Protocol;
+use crate::config::{HueConfig, OutputConfig};
+use crate::null_serial::{delegate_serial_port, NullSerial};
+
+/// Port of the bridge's DTLS entertainment stream
+const STREAM_PORT: u16 = 2100;
+
+/// Protocol name, version 1.0, sequence, reserved, RGB color space and reserved
+const HEADER: &[u8; 16] = b"HueStream\x01\x00\x00\x00\x00\x00\x00";
+
+/// The bridge forwards 25 updates a second to the lights; faster streams are dropped
+const MAX_FPS: f64 = 25.0;
+
+/// Time the bridge has to answer the REST calls starting and stopping the stream
+const HTTP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Philips Hue Entertainment output: each LED of the output is one light of an
+/// entertainment area
+///
+/// Opening the output activates streaming on the area over the bridge's REST API
+/// and starts the DTLS client (`hue.dtls_command`), which carries the HueStream
+/// messages written to its stdin to the bridge. Pixels are expected in RGB order.
+#[derive(Default)]
+pub struct Hue {
+    /// Bridge address and the output's `hue` settings, to stop streaming on teardown
+    bridge: String,
+    config: Option<HueConfig>,
+    /// Whether `connect` switched streaming on; dry runs and stand-in ports never do
+    streaming: bool,
+}
+
+/// A `hue` output needs its settings, one light per LED and a valid streaming key
+pub fn check(config: &OutputConfig) -> Result<()> {
+    let Some(hue) = &config.hue else {
+        anyhow::bail!("hue outputs need a hue section with the bridge username, clientkey, group and lights");
+    };
+    if hue.lights.len() != config.led_count {
+        anyhow::bail!("hue.lights lists {} lights for {} LEDs", hue.lights.len(), config.led_count);
+    }
+    if hue.clientkey.len() != 32 || !hue.clientkey.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("hue.clientkey must be 32 hex digits");
+    }
+    if config.pixel_format.as_deref().is_some_and(|format| format != "RGB") {
+        anyhow::bail!("hue outputs take RGB pixels; leave pixel_format unset");
+    }
+    Ok(())
+}
+
+/// Build the HueStream message setting `lights` to `pixels` into `frame`
+pub fn build_hue_frame_into(pixels: &[u8], stride: usize, lights: &[u16], frame: &mut Vec<u8>) {
+    frame.clear();
+    frame.extend_from_slice(HEADER);
+    for (light, rgb) in lights.iter().zip(pixels.chunks(stride)) {
+        frame.push(0x00);
+        frame.extend_from_slice(&light.to_be_bytes());
+        // 16-bit channels: 0xff becomes 0xffff
+        for &value in rgb.iter().take(3) {
+            frame.extend_from_slice(&(value as u16 * 257).to_be_bytes());
+        }
+    }
+}
+
+/// Switch streaming on the entertainment area on or off over the bridge's REST API
+fn set_streaming(bridge: &str, hue: &HueConfig, active: bool) -> Result<()> {
+    let body = format!("{{\"stream\":{{\"active\":{}}}}}", active);
+    let request = format!(
+        "PUT /api/{}/groups/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        hue.username, hue.group, bridge, body.len(), body,
+    );
+    let mut stream = TcpStream::connect((bridge, 80)).context(format!("Failed to reach Hue bridge {}", bridge))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).context(format!("No answer from Hue bridge {}", bridge))?;
+    // The bridge answers 200 with a list of successes or errors
+    if !response.contains("\"success\"") {
+        let body = response.split("\r\n\r\n").nth(1).unwrap_or(&response);
+        anyhow::bail!("Hue bridge {} refused to {} streaming: {}", bridge, if active { "start" } else { "stop" }, body.trim());
+    }
+    Ok(())
+}
+
+/// The DTLS client command with the output's settings filled in
+fn dtls_command(bridge: &str, hue: &HueConfig) -> Vec<String> {
+    let default = || vec![
+        "openssl", "s_client", "-quiet", "-dtls1_2", "-connect", "{host}",
+        "-psk_identity", "{username}", "-psk", "{clientkey}", "-cipher", "PSK-AES128-GCM-SHA256",
+    ].into_iter().map(str::to_string).collect();
+    hue.dtls_command.clone().unwrap_or_else(default).iter()
+        .map(|arg| arg.replace("{host}", &format!("{}:{}", bridge, STREAM_PORT))
+            .replace("{username}", &hue.username)
+            .replace("{clientkey}", &hue.clientkey))
+        .collect()
+}
+
+impl Protocol for Hue {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.bridge = config.port.clone();
+        self.config = config.hue.clone();
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        let lights = self.config.as_ref().map_or(&[][..], |hue| &hue.lights[..]);
+        build_hue_frame_into(pixels, stride, lights, frame);
+    }
+
+    /// `port` is the bridge's address
+    fn connect(&mut self, config: &OutputConfig, debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let hue = config.hue.as_ref().context("hue outputs need a hue section")?;
+        set_streaming(&config.port, hue, true)?;
+        self.streaming = true;
+        let command = dtls_command(&config.port, hue);
+        if debug {
+            println!("Streaming {} to Hue area {} through `{}`", config.label(), hue.group, command[0]);
+        }
+        let mut child = Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context(format!("Failed to start {}", command[0]))?;
+        let stdin = child.stdin.take().context("DTLS client has no stdin")?;
+        Ok(Box::new(HuePort { child, stdin, pending: Vec::new(), serial: NullSerial::new(&config.port, 0) }))
+    }
+
+    fn teardown(&mut self, _port: &mut dyn SerialPort, _restore: bool) -> io::Result<()> {
+        match &self.config {
+            Some(hue) if self.streaming => {
+                self.streaming = false;
+                set_streaming(&self.bridge, hue, false).map_err(|e| io::Error::other(format!("{:#}", e)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The lights return to their previous state once streaming stops
+    fn restores_device_state(&self) -> bool {
+        true
+    }
+
+    fn max_fps(&self) -> Option<f64> {
+        Some(MAX_FPS)
+    }
+}
+
+/// Stand-in serial port writing each frame to the DTLS client in one piece, so it
+/// becomes one datagram to the bridge
+struct HuePort {
+    child: Child,
+    stdin: ChildStdin,
+    /// Bytes written since the last flush (one HueStream message)
+    pending: Vec<u8>,
+    serial: NullSerial,
+}
+
+impl Drop for HuePort {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Write for HuePort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let message = std::mem::take(&mut self.pending);
+        if let Some(status) = self.child.try_wait()? {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, format!("DTLS client exited ({})", status)));
+        }
+        self.stdin.write_all(&message)?;
+        self.stdin.flush()
+    }
+}
+
+impl Read for HuePort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Hue outputs have no input"))
+    }
+}
+
+delegate_serial_port!(HuePort, serial);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_message_and_command() {
+        let mut frame = Vec::new();
+        build_hue_frame_into(&[255, 0, 128, 1, 2, 3], 3, &[7, 300], &mut frame);
+        assert_eq!(&frame[..16], b"HueStream\x01\x00\x00\x00\x00\x00\x00");
+        assert_eq!(&frame[16..25], &[0, 0, 7, 0xff, 0xff, 0, 0, 0x80, 0x80]);
+        assert_eq!(&frame[25..28], &[0, 1, 44]);
+        assert_eq!(frame.len(), 16 + 2 * 9);
+
+        let hue = HueConfig {
+            username: "user".to_string(),
+            clientkey: "0123456789abcdef0123456789abcdef".to_string(),
+            group: "1".to_string(),
+            lights: vec![7, 300],
+            dtls_command: None,
+        };
+        let command = dtls_command("192.168.1.2", &hue);
+        assert_eq!(command[..6], ["openssl", "s_client", "-quiet", "-dtls1_2", "-connect", "192.168.1.2:2100"]);
+        assert!(command.windows(2).any(|w| w == ["-psk", "0123456789abcdef0123456789abcdef"]));
+
+        // Without connect (dry run, mock ports) there is no stream for teardown to stop
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "192.0.2.1", "hardware_type": "mock", "protocol": "hue", "baud_rate": 115200,
+            "opc_channel": 0, "led_count": 2, "hue": hue,
+        })).unwrap();
+        let mut protocol = Hue::default();
+        protocol.configure(&config);
+        let mut port = crate::mock_serial::MockPort::new(&config);
+        assert!(protocol.teardown(&mut port, true).is_ok());
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::udp::{self, UdpPort};
+use super::Protocol;
+use crate::config::OutputConfig;
+
+/// UDP port LIFX devices listen on
+pub const LIFX_PORT: u16 = 56700;
+
+/// Frame, frame address and protocol headers
+const HEADER_LEN: usize = 36;
+
+/// Protocol number, with the addressable and tagged (all targets) bits
+const PROTOCOL: u16 = 1024 | 0x1000 | 0x2000;
+
+/// Identifies this server in the packets; any nonzero value
+const SOURCE: u32 = 0x4f50_4353;
+
+const SET_COLOR: u16 = 102;
+const SET_EXTENDED_COLOR_ZONES: u16 = 510;
+
+/// Zones one SetExtendedColorZones message carries, and so the most LEDs an output may have
+pub const MAX_ZONES: usize = 82;
+
+/// Color temperature sent with the colors; bulbs only use it for whites
+const KELVIN: u16 = 3500;
+
+/// LIFX asks for no more than 20 messages a second to each device
+const MAX_FPS: f64 = 20.0;
+
+/// LIFX LAN output: one bulb, or the zones of a multizone strip or beam, at `port`
+///
+/// A one-LED output sends SetColor; longer outputs send all their zones in one
+/// SetExtendedColorZones. Pixels are expected in RGB order.
+#[derive(Default)]
+pub struct Lifx {
+    sequence: AtomicU8,
+}
+
+/// An output drives a bulb, or at most `MAX_ZONES` zones, from RGB pixels
+pub fn check(config: &OutputConfig) -> Result<()> {
+    if config.led_count > MAX_ZONES {
+        anyhow::bail!("LIFX devices take at most {} zones per message, not {}", MAX_ZONES, config.led_count);
+    }
+    if config.pixel_format.as_deref().is_some_and(|format| format != "RGB") {
+        anyhow::bail!("LIFX outputs take RGB pixels; leave pixel_format unset");
+    }
+    Ok(())
+}
+
+/// Length of the LIFX message starting `frame`, from its size field
+fn packet_len(frame: &[u8]) -> Option<usize> {
+    let size = frame.get(..2)?;
+    Some(u16::from_le_bytes([size[0], size[1]]) as usize)
+}
+
+/// LIFX HSBK (hue, saturation, brightness, kelvin) of an RGB pixel
+fn hsbk(rgb: &[u8]) -> [u16; 4] {
+    let (r, g, b) = (rgb[0] as f32, rgb[1] as f32, rgb[2] as f32);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    [
+        (hue / 6.0 * 65535.0).round() as u16,
+        (saturation * 65535.0).round() as u16,
+        (max / 255.0 * 65535.0).round() as u16,
+        KELVIN,
+    ]
+}
+
+fn push_hsbk(frame: &mut Vec<u8>, rgb: &[u8]) {
+    for value in hsbk(rgb) {
+        frame.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl Protocol for Lifx {
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let zones = (pixels.len() / stride).min(MAX_ZONES);
+        let kind = if zones <= 1 { SET_COLOR } else { SET_EXTENDED_COLOR_ZONES };
+
+        frame.clear();
+        frame.reserve(HEADER_LEN + 8 + MAX_ZONES * 8);
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&PROTOCOL.to_le_bytes());
+        frame.extend_from_slice(&SOURCE.to_le_bytes());
+        // Every target, no replies wanted
+        frame.extend_from_slice(&[0; 14]);
+        frame.push(0);
+        frame.push(sequence);
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&kind.to_le_bytes());
+        frame.extend_from_slice(&[0; 2]);
+
+        if kind == SET_COLOR {
+            frame.push(0);
+            push_hsbk(frame, pixels.get(..3).unwrap_or(&[0; 3]));
+            // Duration: change at once
+            frame.extend_from_slice(&0u32.to_le_bytes());
+        } else {
+            frame.extend_from_slice(&0u32.to_le_bytes());
+            // Apply now, from zone 0
+            frame.push(1);
+            frame.extend_from_slice(&0u16.to_le_bytes());
+            frame.push(zones as u8);
+            for zone in 0..MAX_ZONES {
+                push_hsbk(frame, pixels.get(zone * stride..zone * stride + 3).unwrap_or(&[0; 3]));
+            }
+        }
+        let size = (frame.len() as u16).to_le_bytes();
+        frame[..2].copy_from_slice(&size);
+    }
+
+    /// `port` is the device's address, `host` or `host:port`
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let target = udp::resolve(&config.port, LIFX_PORT)?;
+        Ok(Box::new(UdpPort::open(&config.port, vec![target], packet_len)?))
+    }
+
+    fn max_fps(&self) -> Option<f64> {
+        Some(MAX_FPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_color_and_zones_messages() {
+        assert_eq!(hsbk(&[255, 0, 0]), [0, 65535, 65535, KELVIN]);
+        assert_eq!(hsbk(&[0, 0, 255])[0], 43690);
+        assert_eq!(hsbk(&[128, 128, 128])[..3], [0, 0, 32896]);
+
+        let lifx = Lifx::default();
+        let frame = lifx.build_frame(&[0, 255, 0], 3);
+        assert_eq!(frame.len(), HEADER_LEN + 13);
+        assert_eq!(packet_len(&frame), Some(frame.len()));
+        assert_eq!(&frame[2..4], &[0x00, 0x34]);
+        assert_eq!(&frame[32..34], &SET_COLOR.to_le_bytes());
+        assert_eq!(&frame[37..39], &21845u16.to_le_bytes());
+
+        let frame = lifx.build_frame(&[255; 30], 3);
+        assert_eq!(frame.len(), HEADER_LEN + 8 + MAX_ZONES * 8);
+        assert_eq!(frame[23], 1);
+        assert_eq!(&frame[32..34], &SET_EXTENDED_COLOR_ZONES.to_le_bytes());
+        assert_eq!(&frame[40..44], &[1, 0, 0, 10]);
+    }
+}
@@ -1,5 +1,263 @@
+mod ack;
+mod artnet;
 mod awa;
 mod adalight;
+mod ddp;
+mod delta;
+mod dmx;
+#[cfg(feature = "hue")]
+mod hue;
+#[cfg(feature = "lifx")]
+mod lifx;
+mod openrgb;
+mod pixelblaze;
+mod sacn;
+mod udp;
 
-pub use awa::build_awa_frame;
-pub use adalight::build_adalight_frame;
+use anyhow::Result;
+use serialport::SerialPort;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{OnceLock, RwLock};
+
+use crate::config::{AdalightCount, OutputConfig};
+use crate::output::Output;
+
+pub use ack::{wait_for_ack, AckMode, AckResult};
+pub use artnet::{artnet_packet_len, ArtNet, ARTNET_PORT};
+pub use awa::{build_awa_frame, build_awa_frame_into, describe_awa_frame};
+pub use adalight::{build_adalight_frame, build_adalight_frame_counted, build_adalight_frame_into,
+                   describe_adalight_frame, ADALIGHT_BAUD_RATES};
+pub use ddp::{ddp_packet_len, Ddp, DDP_PORT};
+pub use delta::{check_delta, Delta};
+#[cfg(feature = "hue")]
+pub use hue::{build_hue_frame_into, Hue};
+#[cfg(feature = "lifx")]
+pub use lifx::{Lifx, LIFX_PORT};
+pub use dmx::{is_dmx, BreakPort, DmxLayout, EnttecPro, OpenDmx, DMX_BAUD_RATE, UNIVERSE_SLOTS};
+pub use openrgb::{OpenRgb, OPENRGB_PORT};
+pub use pixelblaze::{build_pixelblaze_frame_into, check_pixelblaze, describe_pixelblaze_frame, Pixelblaze};
+pub use sacn::{multicast_group, sacn_packet_len, Sacn, SACN_PORT};
+pub use udp::{check_network, is_network, UdpPort};
+
+/// A region of a built frame and what it holds, for frame inspection
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameField {
+    pub name: &'static str,
+    pub bytes: Range<usize>,
+    /// Meaning of the bytes, e.g. `300 LEDs` or `ok`
+    pub note: String,
+}
+
+/// Name of the field holding the frame's pixel data
+pub const PIXELS_FIELD: &str = "pixels";
+
+/// `ok`, or what a field should have held
+fn check(ok: bool, expected: &str) -> String {
+    if ok { "ok".to_string() } else { format!("BAD, expected {}", expected) }
+}
+
+/// The LED count a header announces, flagged if the frame carries a different number
+fn count_note(announced: usize, carried: usize) -> String {
+    if announced == carried {
+        format!("{} LEDs", announced)
+    } else {
+        format!("{} LEDs, BAD: frame carries {}", announced, carried)
+    }
+}
+
+/// Wire protocol spoken to a serial device
+///
+/// The worker builds every frame through this trait; the hooks let protocols that
+/// need a handshake or a mode switch (WLED) manage the device themselves.
+pub trait Protocol: Send {
+    /// Build the frame for transformed pixel data into `frame`, reusing its allocation
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>);
+
+    /// Apply the output's protocol options; called once, right after the protocol is created
+    fn configure(&mut self, _config: &OutputConfig) {}
+
+    /// Build the frame for transformed pixel data
+    fn build_frame(&self, pixels: &[u8], stride: usize) -> Vec<u8> {
+        let mut frame = Vec::new();
+        self.build_frame_into(pixels, stride, &mut frame);
+        frame
+    }
+
+    /// Open the port, performing any handshake the device needs
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        Output::open_standard_port(config)
+    }
+
+    /// Called before frames are streamed; may run repeatedly and should be cheap when already prepared
+    fn prepare(&mut self, _port: &mut dyn SerialPort) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once when the output stops; `restore` asks the device to resume its own state
+    fn teardown(&mut self, _port: &mut dyn SerialPort, _restore: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the device can return to its own effect on shutdown (so it need not be blanked)
+    fn restores_device_state(&self) -> bool {
+        false
+    }
+
+    /// Fastest frame rate the device keeps up with, unless the output sets `max_fps`
+    fn max_fps(&self) -> Option<f64> {
+        None
+    }
+
+    /// Header, pixel and checksum fields of a frame this protocol built, with the checks
+    /// a device would make; empty if the protocol doesn't describe its frames
+    fn describe_frame(&self, _frame: &[u8], _stride: usize) -> Vec<FrameField> {
+        Vec::new()
+    }
+
+    /// Length of the longest frame this protocol builds for `count` pixels, which sets how
+    /// fast the line can carry frames; by default that of an all-black frame
+    fn max_frame_len(&self, count: usize, stride: usize) -> usize {
+        self.build_frame(&vec![0; count * stride], stride).len()
+    }
+
+    /// The last frame built did not reach the device (it missed the write deadline or
+    /// was rejected); protocols whose frames build on earlier ones start over
+    fn frame_abandoned(&self) {}
+}
+
+/// HyperSerial AWA protocol (Fletcher checksums)
+pub struct Awa;
+
+impl Protocol for Awa {
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        build_awa_frame_into(pixels, stride, frame);
+    }
+
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        describe_awa_frame(frame, stride)
+    }
+}
+
+/// Classic Adalight protocol
+#[derive(Default)]
+pub struct Adalight {
+    /// Convention for the header's LED count (`adalight_count`)
+    count: AdalightCount,
+}
+
+impl Protocol for Adalight {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.count = config.adalight_count.unwrap_or_default();
+    }
+    
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        build_adalight_frame_counted(pixels, stride, self.count, frame);
+    }
+    
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        describe_adalight_frame(frame, stride, self.count)
+    }
+    
+    /// With `handshake`, waits for the sketch's `Ada` greeting (probing baud rates)
+    fn connect(&mut self, config: &OutputConfig, debug: bool, ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        if config.handshake {
+            adalight::handshake(config, debug, ddebug)
+        } else {
+            Output::open_standard_port(config)
+        }
+    }
+}
+
+/// Creates a fresh protocol instance for one output
+pub type ProtocolFactory = fn() -> Box<dyn Protocol>;
+
+fn registry() -> &'static RwLock<HashMap<String, ProtocolFactory>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, ProtocolFactory>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut protocols: HashMap<String, ProtocolFactory> = HashMap::new();
+        protocols.insert("awa".into(), || Box::new(Awa));
+        protocols.insert("adalight".into(), || Box::<Adalight>::default());
+        protocols.insert("wled".into(), || Box::new(crate::wled::WledProtocol::new()));
+        protocols.insert("delta".into(), || Box::<Delta>::default());
+        protocols.insert("pixelblaze".into(), || Box::<Pixelblaze>::default());
+        protocols.insert("dmx".into(), || Box::<OpenDmx>::default());
+        protocols.insert("enttec-pro".into(), || Box::<EnttecPro>::default());
+        protocols.insert("artnet".into(), || Box::<ArtNet>::default());
+        protocols.insert("sacn".into(), || Box::<Sacn>::default());
+        protocols.insert("ddp".into(), || Box::<Ddp>::default());
+        protocols.insert("openrgb".into(), || Box::<OpenRgb>::default());
+        #[cfg(feature = "lifx")]
+        protocols.insert("lifx".into(), || Box::<Lifx>::default());
+        #[cfg(feature = "hue")]
+        protocols.insert("hue".into(), || Box::<Hue>::default());
+        RwLock::new(protocols)
+    })
+}
+
+/// Make a protocol available to outputs under `name` (replacing any existing one)
+pub fn register(name: &str, factory: ProtocolFactory) {
+    registry().write().unwrap().insert(name.to_string(), factory);
+}
+
+/// Names of all registered protocols, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = registry().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Create the protocol for an output
+///
+/// `hardware_type: "WLED"` selects the WLED protocol (AWA framing plus WLED's
+/// handshake and live mode) regardless of `protocol`.
+pub fn for_output(config: &OutputConfig) -> Result<Box<dyn Protocol>> {
+    let name = if config.is_wled() { "wled" } else { config.protocol.as_str() };
+    let factory = registry().read().unwrap().get(name).copied();
+    match factory {
+        Some(factory) => {
+            let mut protocol = factory();
+            protocol.configure(config);
+            Ok(protocol)
+        }
+        None => anyhow::bail!("Unknown protocol '{}' (available: {})", name, names().join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output_config(protocol: &str, hardware_type: Option<&str>) -> OutputConfig {
+        serde_json::from_value(serde_json::json!({
+            "port": "/dev/null",
+            "protocol": protocol,
+            "hardware_type": hardware_type,
+            "baud_rate": 115200,
+            "opc_channel": 0,
+            "led_count": 1,
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_registry_selects_protocol() {
+        let pixels = [1, 2, 3];
+        let awa = for_output(&output_config("awa", None)).unwrap();
+        assert_eq!(awa.build_frame(&pixels, 3), build_awa_frame(&pixels, 3));
+        assert!(!awa.restores_device_state());
+
+        let adalight = for_output(&output_config("adalight", None)).unwrap();
+        assert_eq!(adalight.build_frame(&pixels, 3), build_adalight_frame(&pixels, 3));
+
+        let mut raw_count = output_config("adalight", None);
+        raw_count.adalight_count = Some(AdalightCount::Actual);
+        assert_eq!(&for_output(&raw_count).unwrap().build_frame(&pixels, 3)[3..6], &[0x00, 0x01, 0x54]);
+
+        // WLED hardware speaks AWA frames with its own device handling
+        let wled = for_output(&output_config("awa", Some("WLED"))).unwrap();
+        assert_eq!(wled.build_frame(&pixels, 3), build_awa_frame(&pixels, 3));
+        assert!(wled.restores_device_state());
+
+        assert!(for_output(&output_config("no-such-protocol", None)).is_err());
+    }
+}
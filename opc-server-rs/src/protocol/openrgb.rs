@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use serialport::SerialPort;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::udp;
+use super::Protocol;
+use crate::config::OutputConfig;
+use crate::null_serial::{delegate_serial_port, NullSerial};
+
+/// TCP port of the OpenRGB SDK server
+pub const OPENRGB_PORT: u16 = 6742;
+
+const MAGIC: &[u8; 4] = b"ORGB";
+
+const SET_CLIENT_NAME: u32 = 50;
+const UPDATE_LEDS: u32 = 1050;
+const UPDATE_ZONE_LEDS: u32 = 1051;
+/// Switches the device to its direct (software-controlled) mode
+const SET_CUSTOM_MODE: u32 = 1100;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// OpenRGB SDK client output: the output's pixels set the LEDs of one device (or one
+/// zone of it) on an OpenRGB server, such as motherboard, RAM or keyboard lighting
+///
+/// Pixels are expected in RGB order; OpenRGB arranges them for the device.
+#[derive(Default)]
+pub struct OpenRgb {
+    device: u32,
+    zone: Option<u32>,
+}
+
+/// OpenRGB takes RGB colors, at most a u16 count of them per update
+pub fn check(config: &OutputConfig) -> Result<()> {
+    if config.pixel_format.as_deref().is_some_and(|format| format != "RGB") {
+        anyhow::bail!("OpenRGB outputs take RGB pixels; leave pixel_format unset");
+    }
+    if config.led_count > u16::MAX as usize {
+        anyhow::bail!("OpenRGB updates carry at most {} LEDs", u16::MAX);
+    }
+    Ok(())
+}
+
+/// Append an SDK packet for `device` with `body` to `frame`
+fn push_packet(frame: &mut Vec<u8>, device: u32, id: u32, body: impl FnOnce(&mut Vec<u8>)) {
+    let start = frame.len();
+    frame.extend_from_slice(MAGIC);
+    frame.extend_from_slice(&device.to_le_bytes());
+    frame.extend_from_slice(&id.to_le_bytes());
+    frame.extend_from_slice(&[0; 4]);
+    body(frame);
+    let size = (frame.len() - start - 16) as u32;
+    frame[start + 12..start + 16].copy_from_slice(&size.to_le_bytes());
+}
+
+impl Protocol for OpenRgb {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.device = config.openrgb_device.unwrap_or(0);
+        self.zone = config.openrgb_zone;
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        let count = pixels.len() / stride;
+        frame.clear();
+        let id = if self.zone.is_some() { UPDATE_ZONE_LEDS } else { UPDATE_LEDS };
+        push_packet(frame, self.device, id, |body| {
+            // The body starts with its own size
+            let size = 4 + self.zone.map_or(0, |_| 4) + 2 + 4 * count;
+            body.extend_from_slice(&(size as u32).to_le_bytes());
+            if let Some(zone) = self.zone {
+                body.extend_from_slice(&zone.to_le_bytes());
+            }
+            body.extend_from_slice(&(count as u16).to_le_bytes());
+            for pixel in pixels.chunks_exact(stride) {
+                body.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 0]);
+            }
+        });
+    }
+
+    /// `port` is the OpenRGB server's address, `host` or `host:port`; the device is put in
+    /// its direct mode, in which it shows the colors the server last sent
+    fn connect(&mut self, config: &OutputConfig, debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let addr = udp::resolve(&config.port, OPENRGB_PORT)?;
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .context(format!("Failed to connect to OpenRGB server {}", config.port))?;
+        stream.set_nodelay(true)?;
+
+        let mut hello = Vec::new();
+        push_packet(&mut hello, 0, SET_CLIENT_NAME, |body| {
+            body.extend_from_slice(format!("opc_server {}", config.id()).as_bytes());
+            body.push(0);
+        });
+        push_packet(&mut hello, self.device, SET_CUSTOM_MODE, |_| {});
+        stream.write_all(&hello).context(format!("Failed to set up OpenRGB device {}", self.device))?;
+        if debug {
+            println!("OpenRGB device {} on {} switched to direct mode", self.device, config.port);
+        }
+        Ok(Box::new(TcpPort { stream, write_timeout: None, serial: NullSerial::new(&config.port, 0) }))
+    }
+}
+
+/// Stand-in serial port writing to a TCP connection
+struct TcpPort {
+    stream: TcpStream,
+    /// Port timeout last applied to the stream
+    write_timeout: Option<Duration>,
+    serial: NullSerial,
+}
+
+impl Write for TcpPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = self.serial.timeout();
+        if self.write_timeout != Some(timeout) {
+            self.stream.set_write_timeout(Some(timeout).filter(|t| !t.is_zero()))?;
+            self.write_timeout = Some(timeout);
+        }
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Read for TcpPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "OpenRGB outputs have no input"))
+    }
+}
+
+delegate_serial_port!(TcpPort, serial);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_leds_packet() {
+        let mut openrgb = OpenRgb { device: 2, zone: None };
+        let frame = openrgb.build_frame(&[1, 2, 3, 4, 5, 6], 3);
+        assert_eq!(&frame[..16], b"ORGB\x02\x00\x00\x00\x1a\x04\x00\x00\x0e\x00\x00\x00");
+        assert_eq!(&frame[16..], &[14, 0, 0, 0, 2, 0, 1, 2, 3, 0, 4, 5, 6, 0]);
+
+        openrgb.zone = Some(1);
+        let frame = openrgb.build_frame(&[1, 2, 3], 3);
+        assert_eq!(&frame[8..12], &UPDATE_ZONE_LEDS.to_le_bytes());
+        assert_eq!(&frame[12..16], &14u32.to_le_bytes());
+        assert_eq!(&frame[16..], &[14, 0, 0, 0, 1, 0, 0, 0, 1, 0, 1, 2, 3, 0]);
+    }
+}
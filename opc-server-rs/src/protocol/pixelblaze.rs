@@ -0,0 +1,168 @@
+use anyhow::Result;
+
+use super::{check, count_note, FrameField, Protocol, PIXELS_FIELD};
+use crate::config::OutputConfig;
+use crate::pixel_format;
+
+/// Start of every Output Expander packet
+const MAGIC: &[u8; 4] = b"UPXL";
+
+/// Packet carrying one channel's WS2812 pixels, shown on the next draw
+const SET_CHANNEL_WS2812: u8 = 1;
+
+/// Packet latching the pixels sent to every channel
+const DRAW_ALL: u8 = 2;
+
+/// Channel of packets meant for every board on the line
+const ALL_CHANNELS: u8 = 0xff;
+
+/// Color order telling the expander the pixels are already in wire order
+/// (red, green, blue and white at indexes 0 to 3, two bits each)
+const WIRE_ORDER: u8 = 0b11_10_01_00;
+
+/// Magic, channel, packet type, bytes per pixel, color order and pixel count
+const HEADER_LEN: usize = 10;
+
+const CRC_LEN: usize = 4;
+
+/// Highest `expander_channel`: 8 boards, told apart by their address jumpers, of 8 channels each
+pub const MAX_CHANNEL: u8 = 63;
+
+/// Pixelblaze Output Expander protocol, as a Pixelblaze sends it to its expander boards
+///
+/// Each frame is a WS2812 packet for the output's `expander_channel` followed by a
+/// draw-all packet, both ending in a CRC-32. The pixels are sent in wire order, already
+/// arranged by `pixel_format`.
+#[derive(Default)]
+pub struct Pixelblaze {
+    channel: u8,
+}
+
+impl Protocol for Pixelblaze {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.channel = config.expander_channel.unwrap_or(0);
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        build_pixelblaze_frame_into(pixels, stride, self.channel, frame);
+    }
+
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        describe_pixelblaze_frame(frame, stride)
+    }
+}
+
+/// `expander_channel` must exist, and the expander only takes RGB and RGBW pixels
+pub fn check_pixelblaze(config: &OutputConfig) -> Result<()> {
+    if config.expander_channel.is_some_and(|channel| channel > MAX_CHANNEL) {
+        anyhow::bail!("expander_channel must be between 0 and {}", MAX_CHANNEL);
+    }
+    let stride = pixel_format::stride(config.pixel_format.as_deref());
+    if stride != 3 && stride != 4 {
+        anyhow::bail!("the Pixelblaze protocol needs 3 or 4 bytes per pixel, not {}", stride);
+    }
+    if config.led_count > u16::MAX as usize {
+        anyhow::bail!("the Pixelblaze protocol carries at most {} LEDs per channel", u16::MAX);
+    }
+    Ok(())
+}
+
+/// Build the packets showing `pixels` on expander `channel` into `frame`, reusing its allocation
+pub fn build_pixelblaze_frame_into(pixels: &[u8], stride: usize, channel: u8, frame: &mut Vec<u8>) {
+    let led_count = (pixels.len() / stride) as u16;
+
+    frame.clear();
+    frame.reserve(HEADER_LEN + pixels.len() + 2 * CRC_LEN + MAGIC.len() + 2);
+
+    frame.extend_from_slice(MAGIC);
+    frame.push(channel);
+    frame.push(SET_CHANNEL_WS2812);
+    frame.push(stride as u8);
+    frame.push(WIRE_ORDER);
+    frame.extend_from_slice(&led_count.to_le_bytes());
+    frame.extend_from_slice(pixels);
+    let crc = crc32(frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+
+    let draw = frame.len();
+    frame.extend_from_slice(MAGIC);
+    frame.push(ALL_CHANNELS);
+    frame.push(DRAW_ALL);
+    let crc = crc32(&frame[draw..]);
+    frame.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// Fields of a Pixelblaze frame, checking both packets' CRCs
+pub fn describe_pixelblaze_frame(frame: &[u8], stride: usize) -> Vec<FrameField> {
+    let draw_len = MAGIC.len() + 2 + CRC_LEN;
+    if frame.len() < HEADER_LEN + CRC_LEN + draw_len {
+        return Vec::new();
+    }
+    let pixels_end = frame.len() - draw_len - CRC_LEN;
+    let draw = frame.len() - draw_len;
+    let announced = u16::from_le_bytes([frame[8], frame[9]]) as usize;
+    let carried = (pixels_end - HEADER_LEN) / stride;
+    let crc_ok = |packet: std::ops::Range<usize>| {
+        let crc = crc32(&frame[packet.start..packet.end - CRC_LEN]);
+        check(frame[packet.end - CRC_LEN..packet.end] == crc.to_le_bytes(), &format!("{:08x}", crc))
+    };
+
+    vec![
+        FrameField { name: "magic", bytes: 0..4, note: check(&frame[..4] == MAGIC, "UPXL") },
+        FrameField { name: "channel", bytes: 4..5, note: format!("channel {}", frame[4]) },
+        FrameField { name: "type", bytes: 5..6, note: check(frame[5] == SET_CHANNEL_WS2812, "01 (WS2812)") },
+        FrameField { name: "elements", bytes: 6..7, note: check(frame[6] as usize == stride, &stride.to_string()) },
+        FrameField { name: "order", bytes: 7..8, note: check(frame[7] == WIRE_ORDER, &format!("{:02x}", WIRE_ORDER)) },
+        FrameField { name: "count", bytes: 8..10, note: count_note(announced, carried) },
+        FrameField { name: PIXELS_FIELD, bytes: HEADER_LEN..pixels_end, note: format!("{} LEDs", carried) },
+        FrameField { name: "crc", bytes: pixels_end..draw, note: crc_ok(0..draw) },
+        FrameField { name: "draw", bytes: draw..draw + 6, note: check(frame[draw..draw + 6] == [b'U', b'P', b'X', b'L', ALL_CHANNELS, DRAW_ALL], "UPXL ff 02") },
+        FrameField { name: "draw crc", bytes: draw + 6..frame.len(), note: crc_ok(draw..frame.len()) },
+    ]
+}
+
+/// CRC-32 as in zlib and Ethernet (reflected polynomial 0xEDB88320)
+fn crc32(bytes: &[u8]) -> u32 {
+    static TABLE: [u32; 256] = crc32_table();
+    !bytes.iter().fold(!0u32, |crc, &b| TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_is_channel_and_draw_packets() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let mut frame = Vec::new();
+        build_pixelblaze_frame_into(&[1, 2, 3, 4, 5, 6], 3, 9, &mut frame);
+        assert_eq!(&frame[..HEADER_LEN], b"UPXL\x09\x01\x03\xe4\x02\x00");
+        assert_eq!(&frame[HEADER_LEN..HEADER_LEN + 6], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(frame[16..20], crc32(&frame[..16]).to_le_bytes());
+        assert_eq!(&frame[20..26], b"UPXL\xff\x02");
+        assert_eq!(frame.len(), 30);
+
+        let fields = describe_pixelblaze_frame(&frame, 3);
+        assert!(fields.iter().all(|f| !f.note.contains("BAD")), "{:?}", fields);
+        assert_eq!(fields.iter().find(|f| f.name == PIXELS_FIELD).unwrap().bytes, 10..16);
+        frame[17] ^= 1;
+        assert!(describe_pixelblaze_frame(&frame, 3).iter().any(|f| f.name == "crc" && f.note.starts_with("BAD")));
+    }
+}
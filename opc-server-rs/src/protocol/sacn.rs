@@ -0,0 +1,198 @@
+use anyhow::Result;
+use serialport::SerialPort;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::udp::{self, UdpPort};
+use super::{check, FrameField, Protocol, PIXELS_FIELD};
+use crate::config::OutputConfig;
+
+/// UDP port sACN receivers listen on
+pub const SACN_PORT: u16 = 5568;
+
+/// Universes E1.31 allows for data
+pub const UNIVERSES: RangeInclusive<u16> = 1..=63999;
+
+/// `port` of outputs sending each universe to its multicast group
+pub const MULTICAST: &str = "multicast";
+
+const ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+
+const VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+const VECTOR_FRAMING_DATA: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+/// Root, framing and DMP layers up to the start code
+const HEADER_LEN: usize = 126;
+
+/// Where each layer's flags and length field sits
+const ROOT_LAYER: usize = 16;
+const FRAMING_LAYER: usize = 38;
+const DMP_LAYER: usize = 115;
+
+const SOURCE_NAME_LEN: usize = 64;
+
+/// Priority receivers merge sources by; 100 is the default
+const PRIORITY: u8 = 100;
+
+/// sACN (E1.31) output: the output's pixels packed into consecutive universes, one
+/// data packet each, sent to the receiver at `port` or multicast
+///
+/// Universes hold `universe_size` bytes; without it pixels are never split across
+/// universes, so an RGB universe carries 170 LEDs.
+#[derive(Default)]
+pub struct Sacn {
+    universe: u16,
+    /// Pixel bytes per universe
+    slots: usize,
+    /// Identifies this output to receivers; stable across restarts
+    cid: [u8; 16],
+    source_name: String,
+    sequence: AtomicU8,
+}
+
+/// First universe of an sACN output (default: 1)
+pub fn first_universe(config: &OutputConfig) -> u16 {
+    config.universe.unwrap_or(1)
+}
+
+/// Multicast group receivers of `universe` join
+pub fn multicast_group(universe: u16) -> Ipv4Addr {
+    Ipv4Addr::new(239, 255, (universe >> 8) as u8, universe as u8)
+}
+
+/// Length of the E1.31 packet starting `frame`, from the root layer's length
+pub fn sacn_packet_len(frame: &[u8]) -> Option<usize> {
+    let length = frame.get(ROOT_LAYER..ROOT_LAYER + 2)?;
+    Some(ROOT_LAYER + (u16::from_be_bytes([length[0], length[1]]) & 0x0fff) as usize)
+}
+
+/// Component identifier of the output `id`: two FNV-1a hashes of it
+fn cid(id: &str) -> [u8; 16] {
+    let fnv = |seed: u64| id.bytes().fold(seed, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+    let mut cid = [0u8; 16];
+    cid[..8].copy_from_slice(&fnv(0xcbf2_9ce4_8422_2325).to_be_bytes());
+    cid[8..].copy_from_slice(&fnv(0x6c62_272e_07bb_0142).to_be_bytes());
+    cid
+}
+
+/// Flags (always 0x7) and length of the layer starting at `layer` in a packet of `total` bytes
+fn flags_and_length(total: usize, layer: usize) -> [u8; 2] {
+    (0x7000 | (total - layer) as u16).to_be_bytes()
+}
+
+impl Protocol for Sacn {
+    fn configure(&mut self, config: &OutputConfig) {
+        self.universe = first_universe(config);
+        self.slots = udp::universe_slots(config);
+        self.cid = cid(config.id());
+        self.source_name = format!("opc_server {}", config.id());
+    }
+
+    fn build_frame_into(&self, pixels: &[u8], _stride: usize, frame: &mut Vec<u8>) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        let mut source_name = [0u8; SOURCE_NAME_LEN];
+        // Null-terminated
+        let name = &self.source_name.as_bytes()[..self.source_name.len().min(SOURCE_NAME_LEN - 1)];
+        source_name[..name.len()].copy_from_slice(name);
+
+        frame.clear();
+        for (i, slots) in pixels.chunks(self.slots).enumerate() {
+            let total = HEADER_LEN + slots.len();
+            frame.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+            frame.extend_from_slice(ACN_ID);
+            frame.extend_from_slice(&flags_and_length(total, ROOT_LAYER));
+            frame.extend_from_slice(&VECTOR_ROOT_DATA.to_be_bytes());
+            frame.extend_from_slice(&self.cid);
+
+            frame.extend_from_slice(&flags_and_length(total, FRAMING_LAYER));
+            frame.extend_from_slice(&VECTOR_FRAMING_DATA.to_be_bytes());
+            frame.extend_from_slice(&source_name);
+            frame.push(PRIORITY);
+            // No synchronization universe
+            frame.extend_from_slice(&[0, 0]);
+            frame.push(sequence);
+            frame.push(0);
+            frame.extend_from_slice(&self.universe.wrapping_add(i as u16).to_be_bytes());
+
+            frame.extend_from_slice(&flags_and_length(total, DMP_LAYER));
+            // Address type 0xa1, first address 0, increment 1, then the start code and slots
+            frame.extend_from_slice(&[VECTOR_DMP_SET_PROPERTY, 0xa1, 0x00, 0x00, 0x00, 0x01]);
+            frame.extend_from_slice(&(slots.len() as u16 + 1).to_be_bytes());
+            frame.push(0);
+            frame.extend_from_slice(slots);
+        }
+    }
+
+    /// `port` is the receiver's address, `host` or `host:port`, or `multicast` for
+    /// each universe's multicast group
+    fn connect(&mut self, config: &OutputConfig, _debug: bool, _ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let targets = if config.port == MULTICAST {
+            (0..udp::universe_count(config))
+                .map(|i| SocketAddr::from((multicast_group(self.universe + i as u16), SACN_PORT)))
+                .collect()
+        } else {
+            vec![udp::resolve(&config.port, SACN_PORT)?]
+        };
+        Ok(Box::new(UdpPort::open(&config.port, targets, sacn_packet_len)?))
+    }
+
+    /// Fields of a frame that fits one universe; longer frames are shown as raw bytes
+    fn describe_frame(&self, frame: &[u8], stride: usize) -> Vec<FrameField> {
+        if frame.len() < HEADER_LEN || sacn_packet_len(frame) != Some(frame.len()) {
+            return Vec::new();
+        }
+        let total = frame.len();
+        let slots = total - HEADER_LEN;
+        let length_ok = |layer: usize| check(frame[layer..layer + 2] == flags_and_length(total, layer),
+                                             &format!("{} bytes", total - layer));
+        let count = u16::from_be_bytes([frame[123], frame[124]]) as usize;
+        vec![
+            FrameField { name: "acn id", bytes: 4..16, note: check(&frame[4..16] == ACN_ID, "ASC-E1.17") },
+            FrameField { name: "root len", bytes: 16..18, note: length_ok(ROOT_LAYER) },
+            FrameField { name: "cid", bytes: 22..38, note: check(frame[22..38] == self.cid, "this output") },
+            FrameField { name: "frame len", bytes: 38..40, note: length_ok(FRAMING_LAYER) },
+            FrameField { name: "sequence", bytes: 111..112, note: frame[111].to_string() },
+            FrameField { name: "universe", bytes: 113..115,
+                         note: format!("universe {}", u16::from_be_bytes([frame[113], frame[114]])) },
+            FrameField { name: "dmp len", bytes: 115..117, note: length_ok(DMP_LAYER) },
+            FrameField { name: "count", bytes: 123..125, note: check(count == slots + 1, &format!("{} slots", slots)) },
+            FrameField { name: PIXELS_FIELD, bytes: HEADER_LEN..HEADER_LEN + slots / stride * stride,
+                         note: format!("{} LEDs", slots / stride) },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_packet_layout() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "multicast", "protocol": "sacn", "baud_rate": 1,
+            "opc_channel": 0, "led_count": 2, "universe": 7,
+        })).unwrap();
+        let mut sacn = Sacn::default();
+        sacn.configure(&config);
+
+        let frame = sacn.build_frame(&[1, 2, 3, 4, 5, 6], 3);
+        assert_eq!(frame.len(), HEADER_LEN + 6);
+        assert_eq!(sacn_packet_len(&frame), Some(frame.len()));
+        assert_eq!(&frame[..16], b"\x00\x10\x00\x00ASC-E1.17\x00\x00\x00");
+        assert_eq!(&frame[16..22], &[0x70, 116, 0, 0, 0, 4]);
+        assert_eq!(&frame[38..44], &[0x70, 94, 0, 0, 0, 2]);
+        assert!(frame[44..108].starts_with(b"opc_server multicast\0"));
+        assert_eq!(&frame[108..115], &[100, 0, 0, 1, 0, 0, 7]);
+        assert_eq!(&frame[115..126], &[0x70, 17, 0x02, 0xa1, 0, 0, 0, 1, 0, 7, 0]);
+        assert_eq!(&frame[126..], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(cid("desk"), cid("desk"));
+        assert_ne!(cid("desk"), cid("shelf"));
+
+        let fields = sacn.describe_frame(&frame, 3);
+        assert!(fields.iter().all(|f| !f.note.contains("BAD")), "{:?}", fields);
+        assert_eq!(multicast_group(7), Ipv4Addr::new(239, 255, 0, 7));
+        assert_eq!(multicast_group(300), Ipv4Addr::new(239, 255, 1, 44));
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+use super::{artnet, sacn, UNIVERSE_SLOTS};
+use crate::config::OutputConfig;
+use crate::null_serial::{delegate_serial_port, NullSerial};
+use crate::pixel_format;
+
+/// Length of the packet at the start of a built frame, or None if it is not one
+pub type PacketLen = fn(&[u8]) -> Option<usize>;
+
+/// Whether `protocol` sends its frames over the network instead of a serial port
+pub fn is_network(protocol: &str) -> bool {
+    matches!(protocol, "artnet" | "sacn" | "ddp" | "lifx" | "hue" | "openrgb")
+}
+
+/// Pixel bytes one universe carries: `universe_size`, or without it as many whole
+/// pixels as fit, so no pixel is split across two universes
+pub fn universe_slots(config: &OutputConfig) -> usize {
+    let stride = pixel_format::stride(config.pixel_format.as_deref());
+    config.universe_size.map_or(UNIVERSE_SLOTS / stride * stride, usize::from)
+}
+
+/// Universes the output's pixels fill
+pub fn universe_count(config: &OutputConfig) -> usize {
+    let stride = pixel_format::stride(config.pixel_format.as_deref());
+    (config.led_count * stride).div_ceil(universe_slots(config)).max(1)
+}
+
+/// The universes of a network output must all exist in its protocol
+pub fn check_network(config: &OutputConfig) -> Result<()> {
+    if config.is_autobaud() {
+        anyhow::bail!("network outputs have no baud rate to detect");
+    }
+    let (first, range) = match config.protocol.as_str() {
+        "artnet" => (artnet::first_universe(config), artnet::UNIVERSES),
+        "sacn" => (sacn::first_universe(config), sacn::UNIVERSES),
+        "openrgb" => return super::openrgb::check(config),
+        #[cfg(feature = "lifx")]
+        "lifx" => return super::lifx::check(config),
+        #[cfg(feature = "hue")]
+        "hue" => return super::hue::check(config),
+        _ => return Ok(()),
+    };
+    if config.universe_size.is_some_and(|size| size == 0 || size as usize > UNIVERSE_SLOTS) {
+        anyhow::bail!("universe_size must be between 1 and {}", UNIVERSE_SLOTS);
+    }
+    let last = first as usize + universe_count(config) - 1;
+    if !range.contains(&first) || last > *range.end() as usize {
+        anyhow::bail!("{} LEDs from universe {} need universes up to {}; {} universes run from {} to {}",
+                      config.led_count, first, last, config.protocol, range.start(), range.end());
+    }
+    Ok(())
+}
+
+/// Address of `target`, given as `host` or `host:port`
+pub fn resolve(target: &str, default_port: u16) -> Result<SocketAddr> {
+    if let Ok(addr) = target.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, default_port));
+    }
+    let addr = match target.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => target.to_socket_addrs(),
+        _ => (target, default_port).to_socket_addrs(),
+    };
+    addr.ok()
+        .and_then(|mut addrs| addrs.next())
+        .with_context(|| format!("Failed to resolve {}", target))
+}
+
+/// Stand-in serial port sending each frame as UDP datagrams
+///
+/// The bytes written between flushes are one protocol frame; the flush splits it into
+/// packets with `packet_len` and sends packet `i` to `targets[i]`, or to the last
+/// target once there are more packets than targets.
+pub struct UdpPort {
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>,
+    packet_len: PacketLen,
+    /// Bytes written since the last flush (one protocol frame)
+    pending: Vec<u8>,
+    serial: NullSerial,
+}
+
+impl UdpPort {
+    pub fn open(name: &str, targets: Vec<SocketAddr>, packet_len: PacketLen) -> Result<Self> {
+        let Some(first) = targets.first() else {
+            anyhow::bail!("{} has no address to send to", name);
+        };
+        let bind = if first.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = UdpSocket::bind(bind).context(format!("Failed to open a UDP socket for {}", name))?;
+        // Art-Net nodes are often reached through a broadcast address
+        socket.set_broadcast(true).context("Failed to enable UDP broadcast")?;
+        Ok(UdpPort {
+            socket,
+            targets,
+            packet_len,
+            pending: Vec::new(),
+            serial: NullSerial::new(name, 0),
+        })
+    }
+}
+
+impl io::Write for UdpPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let frame = std::mem::take(&mut self.pending);
+        let mut rest = &frame[..];
+        let mut packet = 0;
+        while !rest.is_empty() {
+            let len = (self.packet_len)(rest)
+                .filter(|&len| len > 0 && len <= rest.len())
+                .unwrap_or(rest.len());
+            let target = self.targets[packet.min(self.targets.len() - 1)];
+            self.socket.send_to(&rest[..len], target)?;
+            rest = &rest[len..];
+            packet += 1;
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for UdpPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "network outputs have no input"))
+    }
+}
+
+delegate_serial_port!(UdpPort, serial);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{artnet_packet_len, ArtNet, Protocol};
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frame_sent_as_one_datagram_per_universe() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut port = UdpPort::open("node", vec![receiver.local_addr().unwrap()], artnet_packet_len).unwrap();
+
+        // 200 RGB LEDs: 170 in the first universe, 30 in the second
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "node", "protocol": "artnet", "baud_rate": 1, "opc_channel": 0, "led_count": 200,
+        })).unwrap();
+        let mut artnet = ArtNet::default();
+        artnet.configure(&config);
+        let frame = artnet.build_frame(&[7; 600], 3);
+        port.write_all(&frame).unwrap();
+        port.flush().unwrap();
+
+        let mut buf = [0u8; 1024];
+        let first = receiver.recv(&mut buf).unwrap();
+        assert_eq!(first, 18 + 510);
+        assert_eq!(&buf[14..18], &[0, 0, 0x01, 0xfe]);
+        let second = receiver.recv(&mut buf).unwrap();
+        assert_eq!(second, 18 + 90);
+        assert_eq!(&buf[14..18], &[1, 0, 0, 90]);
+        assert_eq!(first + second, frame.len());
+
+        // Controllers counting 512 slots per universe take pixels split across two
+        let mut spanning = config.clone();
+        spanning.universe_size = Some(512);
+        assert_eq!((universe_slots(&config), universe_slots(&spanning)), (510, 512));
+        assert_eq!(universe_count(&spanning), 2);
+
+        assert_eq!(resolve("10.0.0.5", 6454).unwrap(), "10.0.0.5:6454".parse().unwrap());
+        assert_eq!(resolve("10.0.0.5:7000", 6454).unwrap(), "10.0.0.5:7000".parse().unwrap());
+    }
+}
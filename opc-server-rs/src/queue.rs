@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+/// What to do with a new frame when an output's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePolicy {
+    /// Discard the incoming frame (skip-ahead, the worker finishes what it has queued)
+    #[default]
+    DropNewest,
+    /// Discard the oldest queued frame to make room, so the freshest data is always sent
+    DropOldest,
+    /// Wait for room, applying backpressure to the source (no frames are lost)
+    Block,
+}
+
+struct Inner {
+    /// Frames with the time they reached the output
+    frames: VecDeque<(Vec<u8>, Instant)>,
+    closed: bool,
+}
+
+/// Bounded frame queue between the OPC server and an output worker
+pub struct FrameQueue {
+    inner: Mutex<Inner>,
+    /// Signalled when a frame is pushed or the queue is closed
+    not_empty: Condvar,
+    /// Signalled when a frame is popped or the queue is closed
+    not_full: Condvar,
+    capacity: usize,
+    policy: QueuePolicy,
+}
+
+impl FrameQueue {
+    pub fn new(capacity: usize, policy: QueuePolicy) -> Self {
+        FrameQueue {
+            inner: Mutex::new(Inner {
+                frames: VecDeque::with_capacity(capacity.max(1)),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    /// Queue a frame according to the queue policy
+    ///
+    /// Returns the frame that was dropped (the incoming one if the queue is closed),
+    /// so its buffer can be reused.
+    pub fn push(&self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        self.push_received(frame, Instant::now())
+    }
+
+    /// Queue a frame that reached the output at `received` (e.g. before a delay line)
+    pub fn push_received(&self, frame: Vec<u8>, received: Instant) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut dropped = None;
+
+        while !inner.closed && inner.frames.len() >= self.capacity {
+            match self.policy {
+                QueuePolicy::DropNewest => return Some(frame),
+                QueuePolicy::DropOldest => {
+                    dropped = inner.frames.pop_front().map(|(frame, _)| frame);
+                }
+                QueuePolicy::Block => {
+                    inner = self.not_full.wait(inner).unwrap();
+                }
+            }
+        }
+
+        if inner.closed {
+            return Some(frame);
+        }
+
+        inner.frames.push_back((frame, received));
+        self.not_empty.notify_one();
+        dropped
+    }
+
+    /// Take the oldest frame, waiting up to `timeout` for one to arrive
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        self.pop_received_timeout(timeout).map(|(frame, _)| frame)
+    }
+
+    /// Like `pop_timeout`, with the time the frame reached the output
+    pub fn pop_received_timeout(&self, timeout: Duration) -> Result<(Vec<u8>, Instant), RecvTimeoutError> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.frames.is_empty() && !inner.closed {
+            inner = self.not_empty.wait_timeout(inner, timeout).unwrap().0;
+        }
+
+        match inner.frames.pop_front() {
+            Some(frame) => {
+                self.not_full.notify_one();
+                Ok(frame)
+            }
+            None if inner.closed => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    /// Remove every queued frame but the newest, returning them so their buffers can be reused
+    pub fn skip_to_newest(&self) -> Vec<Vec<u8>> {
+        let mut inner = self.inner.lock().unwrap();
+        let stale = inner.frames.len().saturating_sub(1);
+        let skipped = inner.frames.drain(..stale).map(|(frame, _)| frame).collect();
+        self.not_full.notify_all();
+        skipped
+    }
+
+    /// Whether a push would have to drop or wait
+    pub fn is_full(&self) -> bool {
+        self.inner.lock().unwrap().frames.len() >= self.capacity
+    }
+
+    /// Close the queue, waking any blocked producer or consumer
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_newest_keeps_queued_frames() {
+        let queue = FrameQueue::new(1, QueuePolicy::DropNewest);
+        assert_eq!(queue.push(vec![1]), None);
+        assert_eq!(queue.push(vec![2]), Some(vec![2]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_skip_to_newest() {
+        let queue = FrameQueue::new(3, QueuePolicy::DropNewest);
+        for frame in 1..=3 {
+            queue.push(vec![frame]);
+        }
+        assert_eq!(queue.skip_to_newest(), vec![vec![1], vec![2]]);
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Ok(vec![3]));
+        assert!(queue.skip_to_newest().is_empty());
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_latest_frames() {
+        let queue = FrameQueue::new(2, QueuePolicy::DropOldest);
+        assert_eq!(queue.push(vec![1]), None);
+        assert_eq!(queue.push(vec![2]), None);
+        assert_eq!(queue.push(vec![3]), Some(vec![1]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Ok(vec![2]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Ok(vec![3]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Err(RecvTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn test_close_wakes_blocked_producer() {
+        let queue = std::sync::Arc::new(FrameQueue::new(1, QueuePolicy::Block));
+        assert_eq!(queue.push(vec![1]), None);
+
+        let producer = {
+            let queue = std::sync::Arc::clone(&queue);
+            std::thread::spawn(move || queue.push(vec![2]))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        queue.close();
+
+        assert_eq!(producer.join().unwrap(), Some(vec![2]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Ok(vec![1]));
+        assert_eq!(queue.pop_timeout(Duration::ZERO), Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// First bytes of every recording file
+const MAGIC: &[u8; 8] = b"OPCREC1\n";
+
+/// Longest sleep between checks of the running flag during replay
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// One recorded OPC message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMessage {
+    /// Time since the start of the recording
+    pub offset: Duration,
+    pub channel: u8,
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+/// Writes incoming OPC messages to a file with timestamps
+///
+/// File format: `OPCREC1\n`, then per message a little-endian u64 offset in
+/// microseconds followed by the OPC message as received (4-byte header + data).
+pub struct Recorder {
+    file: BufWriter<File>,
+    /// Time of the first recorded message, so recordings start without dead air
+    started: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path)
+            .context(format!("Failed to create recording {}", path))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(MAGIC)?;
+
+        Ok(Recorder {
+            file,
+            started: None,
+        })
+    }
+
+    pub fn record(&mut self, channel: u8, command: u8, data: &[u8]) -> Result<()> {
+        let offset = self.started.get_or_insert_with(Instant::now).elapsed().as_micros() as u64;
+        self.file.write_all(&offset.to_le_bytes())?;
+        self.file.write_all(&[channel, command])?;
+        self.file.write_all(&(data.len() as u16).to_be_bytes())?;
+        self.file.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Read every message from a recording
+pub fn load(path: &str) -> Result<Vec<RecordedMessage>> {
+    let file = File::open(path)
+        .context(format!("Failed to open recording {}", path))?;
+    read_messages(BufReader::new(file))
+        .context(format!("Invalid recording {}", path))
+}
+
+fn read_messages<R: Read>(mut reader: R) -> Result<Vec<RecordedMessage>> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        anyhow::bail!("Not an OPC recording");
+    }
+
+    let mut messages = Vec::new();
+    loop {
+        let mut header = [0u8; 12];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            // A recording cut off mid-message (e.g. on a crash) keeps what was complete
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut offset = [0u8; 8];
+        offset.copy_from_slice(&header[..8]);
+        let length = u16::from_be_bytes([header[10], header[11]]) as usize;
+
+        let mut data = vec![0u8; length];
+        if let Err(e) = reader.read_exact(&mut data) {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                break;
+            }
+            return Err(e.into());
+        }
+
+        messages.push(RecordedMessage {
+            offset: Duration::from_micros(u64::from_le_bytes(offset)),
+            channel: header[8],
+            command: header[9],
+            data,
+        });
+    }
+
+    Ok(messages)
+}
+
+/// Play messages back with their original timing (scaled by `speed`), calling
+/// `handle` for each one. Stops early when `running` is cleared.
+pub fn replay<F: FnMut(&RecordedMessage)>(
+    messages: &[RecordedMessage],
+    speed: f64,
+    looping: bool,
+    running: &AtomicBool,
+    mut handle: F,
+) {
+    loop {
+        let started = Instant::now();
+
+        for message in messages {
+            let due = message.offset.div_f64(speed);
+            while running.load(Ordering::Relaxed) {
+                let elapsed = started.elapsed();
+                if elapsed >= due {
+                    break;
+                }
+                thread::sleep((due - elapsed).min(MAX_SLEEP));
+            }
+
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+            handle(message);
+        }
+
+        if !looping || messages.is_empty() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_truncation() {
+        let path = std::env::temp_dir().join(format!("opc-record-test-{}.opcrec", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut recorder = Recorder::create(path).unwrap();
+        recorder.record(1, 0, &[255, 0, 0]).unwrap();
+        recorder.record(2, 0xFF, &[0, 1, 0, 1]).unwrap();
+        recorder.flush().unwrap();
+
+        let mut bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let messages = read_messages(&bytes[..]).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!((messages[0].channel, messages[0].command), (1, 0));
+        assert_eq!(messages[0].data, vec![255, 0, 0]);
+        assert_eq!(messages[1].data, vec![0, 1, 0, 1]);
+        assert!(messages[0].offset <= messages[1].offset);
+
+        // Drop the last byte: the incomplete message is ignored
+        bytes.pop();
+        assert_eq!(read_messages(&bytes[..]).unwrap().len(), 1);
+
+        assert!(read_messages(&b"not a recording"[..]).is_err());
+    }
+}
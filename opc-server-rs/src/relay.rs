@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::RelayConfig;
+use crate::net;
+use crate::opc_protocol;
+
+/// Messages buffered per downstream server; more are dropped, like a slow output
+const QUEUE_DEPTH: usize = 4;
+
+/// Wait between attempts to (re)connect to a downstream server
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters for one downstream server, shown in `/status`
+#[derive(Debug, Default)]
+pub struct RelayStats {
+    pub connected: AtomicBool,
+    pub messages_sent: AtomicU64,
+    /// Messages dropped because the server was unreachable or too slow
+    pub messages_dropped: AtomicU64,
+}
+
+/// Forwards received OPC messages to a downstream OPC server (`relay`)
+///
+/// Messages are handed to a background connection and never block the input; while
+/// the server is unreachable they are dropped and the connection is retried.
+pub struct Relay {
+    pub target: String,
+    channels: Option<Vec<u8>>,
+    sender: SyncSender<Vec<u8>>,
+    pub stats: Arc<RelayStats>,
+}
+
+impl Relay {
+    /// Start the connection thread; it ends when the relay is dropped
+    pub fn spawn(config: &RelayConfig, debug: bool) -> Relay {
+        let target = format!("{}:{}", config.host, config.port);
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_DEPTH);
+        let stats = Arc::new(RelayStats::default());
+
+        let worker = Worker {
+            config: config.clone(),
+            target: target.clone(),
+            stats: Arc::clone(&stats),
+            debug,
+        };
+        thread::spawn(move || worker.run(receiver));
+
+        Relay { target, channels: config.channels.clone(), sender, stats }
+    }
+
+    /// Queue one OPC message for the downstream server, if it wants the channel
+    pub fn forward(&self, channel: u8, command: u8, data: &[u8]) {
+        if let Some(channels) = &self.channels {
+            if !channels.contains(&channel) {
+                return;
+            }
+        }
+        let mut packet = Vec::with_capacity(opc_protocol::HEADER_SIZE + data.len());
+        if opc_protocol::encode(channel, command, data, &mut packet).is_err() {
+            return;
+        }
+
+        if !self.stats.connected.load(Ordering::Relaxed) {
+            self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(packet) {
+            self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Worker {
+    config: RelayConfig,
+    target: String,
+    stats: Arc<RelayStats>,
+    debug: bool,
+}
+
+impl Worker {
+    fn run(&self, receiver: Receiver<Vec<u8>>) {
+        let mut retry_at = Instant::now();
+        // Only the first failed attempt in a row is logged
+        let mut reported = false;
+        loop {
+            // Keep the queue empty while disconnected, so no stale frames are sent later
+            if Instant::now() < retry_at {
+                match receiver.recv_timeout(Duration::from_millis(100)) {
+                    Err(RecvTimeoutError::Disconnected) => return,
+                    _ => continue,
+                }
+            }
+
+            let mut stream = match self.connect() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if !reported {
+                        eprintln!("✗ Relay to {} unavailable: {:#} (retrying every {}s)",
+                                  self.target, e, RECONNECT_DELAY.as_secs());
+                        reported = true;
+                    }
+                    retry_at = Instant::now() + RECONNECT_DELAY;
+                    continue;
+                }
+            };
+            if self.debug {
+                println!("✓ Relaying to {}", self.target);
+            }
+            self.stats.connected.store(true, Ordering::Relaxed);
+            reported = false;
+
+            let result = loop {
+                let packet = match receiver.recv() {
+                    Ok(packet) => packet,
+                    Err(_) => return,
+                };
+                if let Err(e) = stream.write_all(&packet) {
+                    break e;
+                }
+                self.stats.messages_sent.fetch_add(1, Ordering::Relaxed);
+            };
+
+            self.stats.connected.store(false, Ordering::Relaxed);
+            eprintln!("✗ Relay to {} lost: {}", self.target, result);
+            retry_at = Instant::now() + RECONNECT_DELAY;
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let addr = net::resolve(&self.config.host, self.config.port)?;
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .context(format!("Failed to connect to {}", addr))?;
+        stream.set_nodelay(true)?;
+        // A downstream server that stops reading counts as lost
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+        if let Some(token) = &self.config.auth_token {
+            stream.write_all(format!("{}\n", token).as_bytes())?;
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn config(port: u16) -> RelayConfig {
+        RelayConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            channels: Some(vec![1]),
+            auth_token: Some("secret".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_forwards_wanted_channels() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let relay = Relay::spawn(&config(listener.local_addr().unwrap().port()), false);
+        let (mut stream, _) = listener.accept().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !relay.stats.connected.load(Ordering::Relaxed) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // The token comes first, then only the messages of the relayed channels
+        relay.forward(2, 0, &[9, 9, 9]);
+        relay.forward(1, 0, &[1, 2, 3]);
+        let mut received = [0u8; 14];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"secret\n\x01\x00\x00\x03\x01\x02\x03");
+        while relay.stats.messages_sent.load(Ordering::Relaxed) < 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(relay.stats.messages_sent.load(Ordering::Relaxed), 1);
+        assert_eq!(relay.stats.messages_dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_drops_while_unreachable() {
+        // Nothing listens on a port just released
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let relay = Relay::spawn(&config(port), false);
+        relay.forward(1, 0, &[1, 2, 3]);
+        assert!(!relay.stats.connected.load(Ordering::Relaxed));
+        assert_eq!(relay.stats.messages_dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::config::Resample;
+
+/// Stretch or shrink RGB pixel data to fill `dst`
+///
+/// `nearest` repeats or skips source pixels; `linear` blends neighbouring source
+/// pixels, with the first and last pixels of both ends lined up. Empty sources leave
+/// `dst` untouched.
+pub fn resample(dst: &mut [u8], src: &[u8], mode: Resample) {
+    let from = src.len() / 3;
+    let to = dst.len() / 3;
+    if from == 0 || to == 0 {
+        return;
+    }
+
+    for (i, pixel) in dst.chunks_exact_mut(3).enumerate() {
+        match mode {
+            Resample::Nearest => {
+                let j = (2 * i + 1) * from / (2 * to);
+                pixel.copy_from_slice(&src[j * 3..j * 3 + 3]);
+            }
+            Resample::Linear => {
+                // Position in source pixels, as a 16.16 fixed-point number
+                let position = if to > 1 { ((i * (from - 1)) << 16) / (to - 1) } else { 0 };
+                let j = position >> 16;
+                let k = (j + 1).min(from - 1);
+                let weight = (position & 0xffff) as u32;
+                for c in 0..3 {
+                    let a = src[j * 3 + c] as u32;
+                    let b = src[k * 3 + c] as u32;
+                    pixel[c] = ((a * (0x10000 - weight) + b * weight + 0x8000) >> 16) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample() {
+        let src = [0, 0, 0, 100, 200, 250];
+        let mut dst = vec![0u8; 9];
+
+        resample(&mut dst, &src, Resample::Linear);
+        assert_eq!(dst, vec![0, 0, 0, 50, 100, 125, 100, 200, 250]);
+
+        resample(&mut dst, &src, Resample::Nearest);
+        assert_eq!(dst, vec![0, 0, 0, 100, 200, 250, 100, 200, 250]);
+
+        // Shrinking 4 pixels to 2 takes the source pixel under the middle of each LED
+        let mut half = vec![0u8; 6];
+        resample(&mut half, &[1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4], Resample::Nearest);
+        assert_eq!(half, vec![2, 2, 2, 4, 4, 4]);
+
+        let mut untouched = vec![7u8; 3];
+        resample(&mut untouched, &[], Resample::Linear);
+        assert_eq!(untouched, vec![7, 7, 7]);
+    }
+}
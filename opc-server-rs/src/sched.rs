@@ -0,0 +1,102 @@
+use anyhow::Result;
+
+/// Realtime priorities accepted for `priority` (Linux SCHED_FIFO)
+pub const PRIORITY_RANGE: std::ops::RangeInclusive<u8> = 1..=99;
+
+/// Highest CPU number `cpu_affinity` can name (the size of a Linux CPU set)
+const MAX_CPUS: usize = 1024;
+
+/// `priority` and `cpu_affinity` must be in range
+pub fn check(priority: Option<u8>, cpus: Option<&[usize]>) -> Result<()> {
+    if let Some(priority) = priority {
+        if !PRIORITY_RANGE.contains(&priority) {
+            anyhow::bail!("priority must be between {} and {}", PRIORITY_RANGE.start(), PRIORITY_RANGE.end());
+        }
+    }
+    if let Some(cpus) = cpus {
+        if cpus.is_empty() {
+            anyhow::bail!("cpu_affinity must list at least one CPU");
+        }
+        if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= MAX_CPUS) {
+            anyhow::bail!("cpu_affinity: there is no CPU {}", cpu);
+        }
+    }
+    Ok(())
+}
+
+/// Give the calling thread a realtime priority and pin it to `cpus`, as configured
+///
+/// Threads it starts afterwards inherit both. Best effort: a failure (usually
+/// missing permission) is a warning and the thread runs with normal scheduling.
+pub fn apply(label: &str, priority: Option<u8>, cpus: Option<&[usize]>, debug: bool) {
+    if priority.is_none() && cpus.is_none() {
+        return;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(priority) = priority {
+            match sys::set_priority(priority) {
+                Ok(()) if debug => println!("✓ {} runs at realtime priority {}", label, priority),
+                Ok(()) => {}
+                Err(e) => eprintln!("Warning: Failed to set realtime priority {} for {}: {} \
+                                     (needs root, CAP_SYS_NICE or an rtprio limit)", priority, label, e),
+            }
+        }
+        if let Some(cpus) = cpus {
+            match sys::set_affinity(cpus) {
+                Ok(()) if debug => println!("✓ {} runs on CPUs {:?}", label, cpus),
+                Ok(()) => {}
+                Err(e) => eprintln!("Warning: Failed to pin {} to CPUs {:?}: {}", label, cpus, e),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = debug;
+        eprintln!("Warning: priority and cpu_affinity of {} are only supported on Linux, ignoring", label);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::mem;
+
+    pub fn set_priority(priority: u8) -> io::Result<()> {
+        let param = libc::sched_param { sched_priority: priority as libc::c_int };
+        // Returns the error number instead of setting errno
+        match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+            0 => Ok(()),
+            error => Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+
+    pub fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for &cpu in cpus {
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        // Thread id 0 is the calling thread
+        if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_priority_and_affinity() {
+        assert!(check(None, None).is_ok());
+        assert!(check(Some(50), Some(&[0, 3])).is_ok());
+        assert!(check(Some(0), None).is_err());
+        assert!(check(Some(100), None).is_err());
+        assert!(check(None, Some(&[])).is_err());
+        assert!(check(None, Some(&[MAX_CPUS])).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{ScheduleWindow, Weekday};
+use crate::opc_server::SharedState;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+
+/// How often the scheduler checks the clock
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Weekly on/off windows (`schedule`), as minutes since Monday 00:00 local time
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    /// Start and length of every window occurrence in the week
+    spans: Vec<(u32, u32)>,
+}
+
+impl Schedule {
+    /// Parse the configured windows, rejecting malformed times
+    pub fn from_config(windows: &[ScheduleWindow]) -> Result<Self> {
+        let mut spans = Vec::new();
+        for (i, window) in windows.iter().enumerate() {
+            let on = parse_time(&window.on).context(format!("schedule[{}].on", i))?;
+            let off = parse_time(&window.off).context(format!("schedule[{}].off", i))?;
+            // Equal times keep the lights on all day; an earlier `off` ends the next day
+            let length = match off.cmp(&on) {
+                std::cmp::Ordering::Equal => MINUTES_PER_DAY,
+                std::cmp::Ordering::Greater => off - on,
+                std::cmp::Ordering::Less => MINUTES_PER_DAY - on + off,
+            };
+            let days = match &window.days {
+                Some(days) if days.is_empty() => anyhow::bail!("schedule[{}].days is empty", i),
+                Some(days) => days.iter().map(|&d| day_index(d)).collect(),
+                None => (0..7).collect::<Vec<_>>(),
+            };
+            spans.extend(days.into_iter().map(|day| (day * MINUTES_PER_DAY + on, length)));
+        }
+        Ok(Schedule { spans })
+    }
+
+    /// Whether a window covers `minute` of the day `weekday` (0 = Monday)
+    pub fn is_active(&self, weekday: u32, minute: u32) -> bool {
+        let now = weekday * MINUTES_PER_DAY + minute;
+        self.spans.iter().any(|&(start, length)| (now + MINUTES_PER_WEEK - start) % MINUTES_PER_WEEK < length)
+    }
+
+    /// Whether a window covers the current local time
+    pub fn is_active_now(&self) -> bool {
+        let (weekday, minute) = local_time();
+        self.is_active(weekday, minute)
+    }
+}
+
+fn day_index(day: Weekday) -> u32 {
+    match day {
+        Weekday::Mon => 0,
+        Weekday::Tue => 1,
+        Weekday::Wed => 2,
+        Weekday::Thu => 3,
+        Weekday::Fri => 4,
+        Weekday::Sat => 5,
+        Weekday::Sun => 6,
+    }
+}
+
+/// Minutes since midnight of an "HH:MM" time
+fn parse_time(text: &str) -> Result<u32> {
+    let parsed = text.split_once(':').filter(|(_, minutes)| minutes.len() == 2).and_then(|(hours, minutes)| {
+        let hours: u32 = hours.parse().ok()?;
+        let minutes: u32 = minutes.parse().ok()?;
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    });
+    parsed.with_context(|| format!("expected a time as \"HH:MM\", got \"{}\"", text))
+}
+
+/// Manual control over the schedule from the control API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleMode {
+    /// Follow the configured windows
+    #[default]
+    Auto,
+    /// Keep the outputs lit
+    On,
+    /// Keep the outputs dark
+    Off,
+}
+
+impl ScheduleMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ScheduleMode::Auto),
+            "on" => Some(ScheduleMode::On),
+            "off" => Some(ScheduleMode::Off),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScheduleMode::Auto => "auto",
+            ScheduleMode::On => "on",
+            ScheduleMode::Off => "off",
+        }
+    }
+}
+
+/// The schedule and whether it currently lets the outputs light up
+#[derive(Debug)]
+pub struct ScheduleState {
+    schedule: Schedule,
+    mode: Mutex<ScheduleMode>,
+    active: AtomicBool,
+}
+
+impl ScheduleState {
+    pub fn new(schedule: Schedule) -> Self {
+        let active = schedule.is_active_now();
+        ScheduleState {
+            schedule,
+            mode: Mutex::new(ScheduleMode::Auto),
+            active: AtomicBool::new(active),
+        }
+    }
+
+    /// Whether outputs are lit and input is accepted right now
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn mode(&self) -> ScheduleMode {
+        *self.mode.lock().unwrap()
+    }
+
+    /// Override the windows (or follow them again); takes effect on the next `update`
+    pub fn set_mode(&self, mode: ScheduleMode) {
+        *self.mode.lock().unwrap() = mode;
+    }
+
+    /// Whether a window covers the current local time, ignoring any override
+    pub fn scheduled_now(&self) -> bool {
+        self.schedule.is_active_now()
+    }
+
+    fn wanted(&self) -> bool {
+        match self.mode() {
+            ScheduleMode::Auto => self.scheduled_now(),
+            ScheduleMode::On => true,
+            ScheduleMode::Off => false,
+        }
+    }
+}
+
+/// Switch the outputs on or off if the schedule or its override changed
+///
+/// Turning off blanks every output; turning on unblanks them again, including
+/// outputs blanked through the control API in the meantime.
+pub fn update(state: &SharedState, schedule: &ScheduleState, debug: bool) {
+    let active = schedule.wanted();
+    if schedule.active.swap(active, Ordering::Relaxed) == active {
+        return;
+    }
+    for output in state.outputs.read().unwrap().iter() {
+        output.set_blanked(!active);
+    }
+    if debug {
+        println!("✓ Schedule: outputs {}", if active { "on" } else { "off until the next window" });
+    }
+}
+
+/// Check the schedule on a background thread until the server stops
+pub fn spawn(state: SharedState, schedule: std::sync::Arc<ScheduleState>, debug: bool) {
+    // Outputs start lit, so a server started outside the windows goes dark right away
+    if !schedule.is_active() {
+        for output in state.outputs.read().unwrap().iter() {
+            output.set_blanked(true);
+        }
+        if debug {
+            println!("✓ Schedule: outputs off until the next window");
+        }
+    }
+
+    thread::spawn(move || {
+        while state.running.load(Ordering::Relaxed) {
+            thread::sleep(CHECK_INTERVAL);
+            update(&state, &schedule, debug);
+        }
+    });
+}
+
+/// Local day of the week (0 = Monday) and minute of the day
+#[cfg(unix)]
+fn local_time() -> (u32, u32) {
+    // SAFETY: localtime_r only writes to the tm it is given
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return utc_time();
+        }
+        // tm_wday counts from Sunday
+        (((tm.tm_wday + 6) % 7) as u32, (tm.tm_hour * 60 + tm.tm_min) as u32)
+    }
+}
+
+#[cfg(windows)]
+fn local_time() -> (u32, u32) {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemTime {
+        year: u16,
+        month: u16,
+        day_of_week: u16,
+        day: u16,
+        hour: u16,
+        minute: u16,
+        second: u16,
+        milliseconds: u16,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetLocalTime(time: *mut SystemTime);
+    }
+
+    let mut time = SystemTime::default();
+    // SAFETY: GetLocalTime fills in the SYSTEMTIME it is given
+    unsafe { GetLocalTime(&mut time) };
+    // wDayOfWeek counts from Sunday
+    (((time.day_of_week + 6) % 7) as u32, time.hour as u32 * 60 + time.minute as u32)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn local_time() -> (u32, u32) {
+    utc_time()
+}
+
+/// UTC day of the week and minute of the day, when local time is unavailable
+#[cfg_attr(windows, allow(dead_code))]
+fn utc_time() -> (u32, u32) {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let minutes = (secs / 60) as u32;
+    let days = minutes / MINUTES_PER_DAY;
+    // 1 January 1970 was a Thursday
+    ((days + 3) % 7, minutes % MINUTES_PER_DAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(on: &str, off: &str, days: Option<Vec<Weekday>>) -> ScheduleWindow {
+        ScheduleWindow { on: on.to_string(), off: off.to_string(), days }
+    }
+
+    #[test]
+    fn test_schedule_windows() {
+        let schedule = Schedule::from_config(&[
+            window("18:00", "23:30", None),
+            // Friday night runs past midnight into Saturday
+            window("22:00", "02:00", Some(vec![Weekday::Fri])),
+        ]).unwrap();
+
+        assert!(!schedule.is_active(0, 17 * 60 + 59));
+        assert!(schedule.is_active(0, 18 * 60));
+        assert!(!schedule.is_active(0, 23 * 60 + 30));
+        assert!(!schedule.is_active(3, 23 * 60 + 45));
+        assert!(schedule.is_active(4, 23 * 60 + 45));
+        assert!(schedule.is_active(5, 60));
+        assert!(!schedule.is_active(5, 2 * 60));
+
+        assert!(Schedule::from_config(&[window("24:00", "01:00", None)]).is_err());
+        assert!(Schedule::from_config(&[window("7pm", "23:00", None)]).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+
+/// Default time the script has to return each frame
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Wait before restarting a script that failed
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// An external program every frame of an output passes through (`script`)
+///
+/// The program reads raw RGB frames of exactly `led_count * 3` bytes from stdin and
+/// writes each one back, modified as it likes, to stdout. Any language works; the
+/// output's name and LED count are passed in `OPC_OUTPUT` and `OPC_LED_COUNT`.
+/// While the script is down, frames pass through unchanged.
+pub struct FrameScript {
+    command: Vec<String>,
+    /// Output name (or port) passed to the script
+    id: String,
+    label: String,
+    frame_len: usize,
+    timeout: Duration,
+    running: Option<Running>,
+    /// When to start the script again after a failure
+    retry_at: Option<Instant>,
+}
+
+struct Running {
+    child: Child,
+    /// Frames for the pipe thread to write to the script
+    requests: Sender<Vec<u8>>,
+    /// Frames the pipe thread read back
+    replies: Receiver<Vec<u8>>,
+}
+
+impl FrameScript {
+    /// Build the script stage for an output, if it sets `script`
+    pub fn from_config(config: &OutputConfig) -> Option<Self> {
+        config.script.as_ref().map(|script| FrameScript {
+            command: script.command.clone(),
+            id: config.id().to_string(),
+            label: config.label(),
+            frame_len: config.led_count * 3,
+            timeout: script.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT),
+            running: None,
+            retry_at: None,
+        })
+    }
+
+    /// Run one frame through the script, in place
+    ///
+    /// A script that fails, exits or misses the timeout is stopped and restarted later;
+    /// the frame is left as it was.
+    pub fn process(&mut self, data: &mut [u8]) {
+        if data.len() != self.frame_len {
+            return;
+        }
+        if self.running.is_none() {
+            if self.retry_at.is_some_and(|at| Instant::now() < at) {
+                return;
+            }
+            match self.start() {
+                Ok(running) => self.running = Some(running),
+                Err(e) => {
+                    eprintln!("✗ Script for {} failed to start: {:#}", self.label, e);
+                    self.retry_at = Some(Instant::now() + RESTART_DELAY);
+                    return;
+                }
+            }
+        }
+
+        if let Err(e) = self.exchange(data) {
+            eprintln!("✗ Script for {} failed: {:#} (restarting in {}s)", self.label, e, RESTART_DELAY.as_secs());
+            self.stop();
+            self.retry_at = Some(Instant::now() + RESTART_DELAY);
+        }
+    }
+
+    fn exchange(&mut self, data: &mut [u8]) -> Result<()> {
+        let running = self.running.as_mut().context("script is not running")?;
+        running.requests.send(data.to_vec()).context("script exited")?;
+        let frame = match running.replies.recv_timeout(self.timeout) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => anyhow::bail!("no frame back within {}ms", self.timeout.as_millis()),
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("script exited"),
+        };
+        data.copy_from_slice(&frame);
+        Ok(())
+    }
+
+    fn start(&self) -> Result<Running> {
+        let program = self.command.first().context("script command is empty")?;
+        let mut child = Command::new(program)
+            .args(&self.command[1..])
+            .env("OPC_OUTPUT", &self.id)
+            .env("OPC_LED_COUNT", (self.frame_len / 3).to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context(format!("Failed to start {}", program))?;
+        let mut stdin = child.stdin.take().context("script has no stdin")?;
+        let mut stdout = child.stdout.take().context("script has no stdout")?;
+
+        // Write and read on a separate thread so a stuck script can't stall the output;
+        // killing the script ends the thread on the broken pipes
+        let (requests, pending) = mpsc::channel::<Vec<u8>>();
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for mut frame in pending {
+                if stdin.write_all(&frame).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+                if stdout.read_exact(&mut frame).is_err() || sender.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Running { child, requests, replies })
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut running) = self.running.take() {
+            let _ = running.child.kill();
+            let _ = running.child.wait();
+        }
+    }
+}
+
+impl Drop for FrameScript {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::ScriptConfig;
+
+    fn script(command: &str, led_count: usize, timeout_ms: u64) -> FrameScript {
+        let config = OutputConfig {
+            led_count,
+            script: Some(ScriptConfig { command: vec!["sh".into(), "-c".into(), command.into()], timeout_ms: Some(timeout_ms) }),
+            ..OutputConfig::test_default("stub")
+        };
+        FrameScript::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_frames_round_trip_through_the_script() {
+        // Answers one frame early, so each reply is visibly the script's
+        let mut script = script(r"printf '\011\012\013'; cat", 1, 2000);
+        let mut frame = [1, 2, 3];
+        script.process(&mut frame);
+        assert_eq!(frame, [9, 10, 11]);
+
+        let mut frame = [4, 5, 6];
+        script.process(&mut frame);
+        assert_eq!(frame, [1, 2, 3]);
+        assert!(script.running.is_some());
+    }
+
+    #[test]
+    fn test_stuck_script_times_out() {
+        // A frame bigger than the pipe buffer, to a script that never reads it
+        let mut script = script("sleep 10", 100_000, 50);
+        let mut frame = vec![7u8; 300_000];
+        let started = Instant::now();
+        script.process(&mut frame);
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(frame.iter().all(|&b| b == 7));
+        assert!(script.running.is_none() && script.retry_at.is_some());
+    }
+
+    #[test]
+    fn test_exited_script_passes_frames_through() {
+        let mut script = script("exit 0", 1, 2000);
+        let mut frame = [1, 2, 3];
+        let started = Instant::now();
+        script.process(&mut frame);
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(frame, [1, 2, 3]);
+        assert!(script.running.is_none() && script.retry_at.is_some());
+
+        // Not restarted before the delay
+        script.process(&mut frame);
+        assert!(script.running.is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::Result;
+
+use crate::config::OutputConfig;
+
+/// Part of a shared serial port's frame, fed from a range of one OPC channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub opc_channel: u8,
+    pub opc_offset: usize,
+    pub led_count: usize,
+    /// Wrap around the end of the channel's data (`wrap: true`)
+    pub wrap: bool,
+    /// First pixel of this segment in the combined frame
+    pub start: usize,
+}
+
+impl Segment {
+    /// Whether a message of `len` bytes covers every pixel of this segment
+    pub fn is_covered_by(&self, len: usize) -> bool {
+        if self.wrap {
+            len >= 3
+        } else {
+            (self.opc_offset + self.led_count) * 3 <= len
+        }
+    }
+}
+
+/// Merge output entries that use the same serial port into one output
+///
+/// Firmwares such as multi-segment HyperSerialPico builds take one frame for all
+/// their strips, so the entries' pixels are concatenated in config order. The
+/// entries must agree on protocol, baud rate and pixel format; other settings are
+/// taken from the first entry. The combined output is named after the entries
+/// (`left+right`) when they all have names.
+pub fn combine(configs: &[OutputConfig]) -> Result<(OutputConfig, Vec<Segment>)> {
+    let Some(first) = configs.first() else {
+        anyhow::bail!("No outputs to combine");
+    };
+
+    for config in &configs[1..] {
+        if config.protocol != first.protocol
+            || config.baud_rate != first.baud_rate
+            || config.pixel_format != first.pixel_format
+        {
+            anyhow::bail!("Outputs sharing {} must use the same protocol, baud_rate and pixel_format",
+                          first.port);
+        }
+    }
+    if configs.iter().any(|c| c.resample.is_some() || c.matrix.is_some() || c.transforms.is_some()) {
+        anyhow::bail!("resample, matrix and transforms are not supported on outputs sharing {}", first.port);
+    }
+
+    let mut segments = Vec::with_capacity(configs.len());
+    let mut start = 0;
+    for config in configs {
+        segments.push(Segment {
+            opc_channel: config.opc_channel,
+            opc_offset: config.opc_offset,
+            led_count: config.led_count,
+            wrap: config.wrap,
+            start,
+        });
+        start += config.led_count;
+    }
+
+    let mut combined = first.clone();
+    combined.led_count = start;
+    combined.name = configs.iter()
+        .map(|c| c.name.clone())
+        .collect::<Option<Vec<_>>>()
+        .map(|names| names.join("+"));
+
+    Ok((combined, segments))
+}
+
+/// Copy the parts of an OPC message that belong to `segments` into the combined RGB frame
+///
+/// Pixels missing from a short message keep their previous values. Returns false if
+/// no segment listens to `channel`.
+pub fn place(frame: &mut [u8], segments: &[Segment], channel: u8, data: &[u8]) -> bool {
+    let mut matched = false;
+
+    for segment in segments.iter().filter(|s| s.opc_channel == channel) {
+        matched = true;
+
+        let dst_start = segment.start * 3;
+        let dst = &mut frame[dst_start..dst_start + segment.led_count * 3];
+        copy_pixels(dst, data, segment.opc_offset, segment.wrap);
+    }
+
+    matched
+}
+
+/// Copy pixels from an OPC message into `dst`, starting at pixel `offset`
+///
+/// Without `wrap`, copying stops at the end of the message. With it, pixels past the
+/// end are read from the start again, so a short ring of data fills a longer strip.
+/// Returns the number of pixels copied; the rest of `dst` is left untouched.
+pub fn copy_pixels(dst: &mut [u8], data: &[u8], offset: usize, wrap: bool) -> usize {
+    let available = data.len() / 3;
+    let wanted = dst.len() / 3;
+
+    if wrap && available > 0 {
+        for (i, pixel) in dst.chunks_exact_mut(3).enumerate() {
+            let src = (offset + i) % available * 3;
+            pixel.copy_from_slice(&data[src..src + 3]);
+        }
+        return wanted;
+    }
+
+    let count = available.saturating_sub(offset).min(wanted);
+    if count == 0 {
+        return 0;
+    }
+    dst[..count * 3].copy_from_slice(&data[offset * 3..(offset + count) * 3]);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(opc_channel: u8, opc_offset: usize, led_count: usize, start: usize) -> Segment {
+        Segment { opc_channel, opc_offset, led_count, wrap: false, start }
+    }
+
+    #[test]
+    fn test_place_fills_each_segment() {
+        let segments = vec![segment(1, 0, 2, 0), segment(2, 1, 1, 2)];
+        let mut frame = vec![0u8; 9];
+
+        assert!(place(&mut frame, &segments, 1, &[1, 1, 1, 2, 2, 2, 3, 3, 3]));
+        assert!(place(&mut frame, &segments, 2, &[9, 9, 9, 8, 8, 8]));
+        assert_eq!(frame, vec![1, 1, 1, 2, 2, 2, 8, 8, 8]);
+
+        // Short message: the rest of the segment keeps its previous pixels
+        assert!(place(&mut frame, &segments, 1, &[5, 5, 5]));
+        assert_eq!(frame, vec![5, 5, 5, 2, 2, 2, 8, 8, 8]);
+
+        assert!(!place(&mut frame, &segments, 3, &[7, 7, 7]));
+    }
+
+    #[test]
+    fn test_copy_pixels_wraps() {
+        let data = [1, 1, 1, 2, 2, 2, 3, 3, 3];
+        let mut dst = vec![0u8; 12];
+
+        assert_eq!(copy_pixels(&mut dst, &data, 1, false), 2);
+        assert_eq!(dst, vec![2, 2, 2, 3, 3, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(copy_pixels(&mut dst, &data, 5, false), 0);
+
+        // A 3-pixel source around a 4-pixel ring, starting at its second pixel
+        assert_eq!(copy_pixels(&mut dst, &data, 1, true), 4);
+        assert_eq!(dst, vec![2, 2, 2, 3, 3, 3, 1, 1, 1, 2, 2, 2]);
+        assert_eq!(copy_pixels(&mut dst, &[], 0, true), 0);
+
+        let ring = Segment { wrap: true, ..segment(0, 4, 8, 0) };
+        assert!(ring.is_covered_by(3));
+        assert!(!segment(0, 4, 8, 0).is_covered_by(33));
+        assert!(segment(0, 4, 8, 0).is_covered_by(36));
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use std::borrow::Cow;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serialport::{DataBits, SerialPort, SerialPortBuilder, SerialPortInfo, SerialPortType, StopBits};
+
+use crate::config::{FlowControl, OutputConfig, Parity};
+
+/// Time between port scans while waiting for devices at startup
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Prefix for ports named by USB identity, e.g. `usb:2e8a:000a` or `usb:2e8a:000a:E6614103E7`
+const USB_PREFIX: &str = "usb:";
+
+/// A USB serial device to look for instead of a fixed device path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbMatch {
+    pub vid: u16,
+    pub pid: u16,
+    /// Tells identical controllers apart; any serial number matches when unset
+    pub serial: Option<String>,
+}
+
+impl UsbMatch {
+    /// Parse a `usb:VID:PID[:SERIAL]` port name; Ok(None) for an ordinary device path
+    pub fn parse(port: &str) -> Result<Option<Self>, String> {
+        let Some(spec) = port.strip_prefix(USB_PREFIX) else {
+            return Ok(None);
+        };
+        let mut parts = spec.splitn(3, ':');
+        let mut id = |what| {
+            let part = parts.next().unwrap_or("");
+            u16::from_str_radix(part, 16)
+                .map_err(|_| format!("invalid USB {} '{}' in port {} (expected usb:VID:PID[:SERIAL] in hex)", what, part, port))
+        };
+        let vid = id("vendor id")?;
+        let pid = id("product id")?;
+        let serial = parts.next().filter(|s| !s.is_empty()).map(str::to_string);
+        Ok(Some(UsbMatch { vid, pid, serial }))
+    }
+
+    fn matches(&self, device: &Device) -> bool {
+        match &device.usb {
+            Some((vid, pid, serial)) => {
+                *vid == self.vid && *pid == self.pid
+                    && self.serial.as_ref().is_none_or(|want| serial.as_deref() == Some(want.as_str()))
+            }
+            None => false,
+        }
+    }
+}
+
+/// One enumerated serial port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub name: String,
+    /// Vendor id, product id and serial number of USB ports
+    pub usb: Option<(u16, u16, Option<String>)>,
+}
+
+impl From<SerialPortInfo> for Device {
+    fn from(info: SerialPortInfo) -> Self {
+        let usb = match info.port_type {
+            SerialPortType::UsbPort(usb) => Some((usb.vid, usb.pid, usb.serial_number)),
+            _ => None,
+        };
+        Device { name: info.port_name, usb }
+    }
+}
+
+/// Serial ports currently present, sorted by name
+pub fn devices() -> serialport::Result<Vec<Device>> {
+    let mut devices: Vec<Device> = serialport::available_ports()?.into_iter().map(Device::from).collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(devices)
+}
+
+/// Whether the device for a configured port is among `devices`
+///
+/// None for device paths the OS does not enumerate (pseudo terminals, some symlinks),
+/// whose presence can only be told by opening them.
+pub fn is_present(port: &str, devices: &[Device]) -> Option<bool> {
+    match UsbMatch::parse(port) {
+        Ok(Some(usb)) => Some(devices.iter().any(|d| usb.matches(d))),
+        Ok(None) => devices.iter().any(|d| d.name == port).then_some(true),
+        Err(_) => None,
+    }
+}
+
+/// Whether a configured port can be opened as far as a scan can tell: its device is
+/// enumerated, or, for paths the OS does not enumerate, the path exists
+fn is_available(port: &str, devices: &[Device]) -> bool {
+    is_present(port, devices).unwrap_or_else(|| UsbMatch::parse(port).is_ok() && std::path::Path::new(port).exists())
+}
+
+/// Wait up to `timeout` for the devices of `ports` to appear (`--wait-for-ports`)
+///
+/// For servers that start before USB enumeration has finished. Returns the ports still
+/// missing when the time is up; their outputs then fail to open as usual, or are retried
+/// with `output_retry_ms`.
+pub fn wait_for_ports(ports: &[&str], timeout: Duration, debug: bool) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let mut reported = false;
+    loop {
+        let devices = devices().unwrap_or_default();
+        let missing: Vec<String> = ports.iter()
+            .filter(|port| !is_available(port, &devices))
+            .map(|port| port.to_string())
+            .collect();
+        if missing.is_empty() {
+            if reported && debug {
+                println!("✓ All serial ports present");
+            }
+            return missing;
+        }
+        if Instant::now() >= deadline {
+            return missing;
+        }
+        if !reported && debug {
+            println!("Waiting up to {:.0}s for {}...", timeout.as_secs_f64(), missing.join(", "));
+        }
+        reported = true;
+        thread::sleep(WAIT_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+}
+
+/// Device path to open for a configured port, looking up `usb:` names among the present devices
+pub fn resolve(port: &str) -> serialport::Result<String> {
+    let usb = UsbMatch::parse(port)
+        .map_err(|e| serialport::Error::new(serialport::ErrorKind::InvalidInput, e))?;
+    let Some(usb) = usb else {
+        return Ok(device_path(port).into_owned());
+    };
+    devices()?.into_iter()
+        .find(|d| usb.matches(d))
+        .map(|d| device_path(&d.name).into_owned())
+        .ok_or_else(|| serialport::Error::new(serialport::ErrorKind::NoDevice, format!("no USB device matches {}", port)))
+}
+
+/// Name to hand to the OS for a configured port
+///
+/// Windows only opens `COM1`..`COM9` by their bare names; higher ports need the
+/// `\\.\COMxx` device namespace prefix. Names that already carry a prefix, and every
+/// name on other platforms, are passed through unchanged.
+pub fn device_path(port: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        windows_device_path(port)
+    } else {
+        Cow::Borrowed(port)
+    }
+}
+
+fn windows_device_path(port: &str) -> Cow<'_, str> {
+    let port = port.trim();
+    let is_com = port.len() > 3
+        && port[..3].eq_ignore_ascii_case("COM")
+        && port[3..].bytes().all(|b| b.is_ascii_digit());
+    if is_com {
+        Cow::Owned(format!(r"\\.\COM{}", &port[3..]))
+    } else {
+        Cow::Borrowed(port)
+    }
+}
+
+/// Character format and flow control of a configured port
+pub struct LineSettings {
+    pub data_bits: DataBits,
+    pub parity: serialport::Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: serialport::FlowControl,
+}
+
+/// Line settings for an output, 8N1 without flow control unless configured
+pub fn line_settings(config: &OutputConfig) -> Result<LineSettings> {
+    let data_bits = match config.data_bits.unwrap_or(8) {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        8 => DataBits::Eight,
+        bits => anyhow::bail!("data_bits must be 5-8, not {}", bits),
+    };
+    let stop_bits = match config.stop_bits.unwrap_or(1) {
+        1 => StopBits::One,
+        2 => StopBits::Two,
+        bits => anyhow::bail!("stop_bits must be 1 or 2, not {}", bits),
+    };
+    let parity = match config.parity.unwrap_or_default() {
+        Parity::None => serialport::Parity::None,
+        Parity::Odd => serialport::Parity::Odd,
+        Parity::Even => serialport::Parity::Even,
+    };
+    let flow_control = match config.flow_control.unwrap_or_default() {
+        FlowControl::None => serialport::FlowControl::None,
+        FlowControl::Software => serialport::FlowControl::Software,
+        FlowControl::Hardware => serialport::FlowControl::Hardware,
+    };
+    Ok(LineSettings { data_bits, parity, stop_bits, flow_control })
+}
+
+impl LineSettings {
+    pub fn apply(&self, builder: SerialPortBuilder) -> SerialPortBuilder {
+        builder
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(self.flow_control)
+    }
+}
+
+/// Set DTR and RTS to an output's configured levels (both raised by default)
+///
+/// With hardware flow control the driver owns RTS, so only DTR is set.
+pub fn set_control_lines(port: &mut dyn SerialPort, config: &OutputConfig) -> serialport::Result<()> {
+    port.write_data_terminal_ready(config.dtr.unwrap_or(true))?;
+    if config.flow_control != Some(FlowControl::Hardware) {
+        port.write_request_to_send(config.rts.unwrap_or(true))?;
+    }
+    Ok(())
+}
+
+/// Raise DTR and RTS the way pyserial does when it opens a port
+///
+/// Linux and macOS assert both lines on open, but Windows leaves them low. Adalight
+/// sketches rely on the DTR edge to reset the Arduino, and USB CDC firmwares (Pico,
+/// ESP32-S2/S3) do not send or accept data until DTR is set.
+pub fn raise_control_lines(port: &mut dyn SerialPort) -> serialport::Result<()> {
+    port.write_data_terminal_ready(true)?;
+    port.write_request_to_send(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_match() {
+        let pico = Device { name: "/dev/ttyACM0".to_string(), usb: Some((0x2e8a, 0x000a, Some("E661".to_string()))) };
+        let devices = [pico, Device { name: "/dev/ttyS0".to_string(), usb: None }];
+
+        assert_eq!(UsbMatch::parse("/dev/ttyUSB0"), Ok(None));
+        assert!(UsbMatch::parse("usb:2e8a").is_err());
+        assert_eq!(is_present("usb:2E8A:000a", &devices), Some(true));
+        assert_eq!(is_present("usb:2e8a:000a:E661", &devices), Some(true));
+        assert_eq!(is_present("usb:2e8a:000a:OTHER", &devices), Some(false));
+        assert_eq!(is_present("/dev/ttyS0", &devices), Some(true));
+        // Not enumerated: presence unknown rather than absent
+        assert_eq!(is_present("/dev/pts/3", &devices), None);
+
+        // ...which waiting for ports settles by looking for the path
+        let path = std::env::temp_dir().join(format!("opc_serial_test_{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        let present = path.to_str().unwrap();
+        assert!(is_available(present, &devices));
+        assert!(!is_available("usb:2e8a:000a:OTHER", &devices));
+        let started = Instant::now();
+        let missing = wait_for_ports(&[present, "/dev/opc-no-such-port"], Duration::from_millis(300), false);
+        assert_eq!(missing, vec!["/dev/opc-no-such-port".to_string()]);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_line_settings() {
+        let output = |settings: serde_json::Value| -> OutputConfig {
+            let mut entry = serde_json::json!({
+                "port": "/dev/ttyUSB0", "protocol": "adalight", "baud_rate": 115200, "opc_channel": 0, "led_count": 10,
+            });
+            entry.as_object_mut().unwrap().extend(settings.as_object().unwrap().clone());
+            serde_json::from_value(entry).unwrap()
+        };
+
+        let default = line_settings(&output(serde_json::json!({}))).unwrap();
+        assert_eq!((default.data_bits, default.parity, default.stop_bits), (DataBits::Eight, serialport::Parity::None, StopBits::One));
+
+        let custom = line_settings(&output(serde_json::json!({
+            "data_bits": 7, "parity": "even", "stop_bits": 2, "flow_control": "hardware",
+        }))).unwrap();
+        assert_eq!((custom.data_bits, custom.parity, custom.stop_bits), (DataBits::Seven, serialport::Parity::Even, StopBits::Two));
+        assert_eq!(custom.flow_control, serialport::FlowControl::Hardware);
+
+        assert!(line_settings(&output(serde_json::json!({"data_bits": 9}))).is_err());
+        assert!(line_settings(&output(serde_json::json!({"stop_bits": 0}))).is_err());
+    }
+
+    #[test]
+    fn test_windows_device_path() {
+        assert_eq!(windows_device_path("COM4"), r"\\.\COM4");
+        assert_eq!(windows_device_path("com12"), r"\\.\COM12");
+        assert_eq!(windows_device_path(r"\\.\COM12"), r"\\.\COM12");
+        assert_eq!(windows_device_path("COM"), "COM");
+        assert_eq!(windows_device_path("/dev/ttyUSB0"), "/dev/ttyUSB0");
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use std::ffi::c_void;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+
+// advapi32 service API (winsvc.h); declared here since the crate has no Windows bindings
+const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+const SERVICE_STOPPED: u32 = 1;
+const SERVICE_STOP_PENDING: u32 = 3;
+const SERVICE_RUNNING: u32 = 4;
+const SERVICE_ACCEPT_STOP: u32 = 0x1;
+const SERVICE_ACCEPT_SHUTDOWN: u32 = 0x4;
+const SERVICE_CONTROL_STOP: u32 = 1;
+const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+const NO_ERROR: u32 = 0;
+const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+const ERROR_FAILED_SERVICE_CONTROLLER_CONNECT: i32 = 1063;
+
+/// Time the SCM is told a stop may take, enough for fades and blanking to finish
+const STOP_WAIT_HINT_MS: u32 = 10_000;
+
+#[repr(C)]
+struct ServiceStatus {
+    service_type: u32,
+    current_state: u32,
+    controls_accepted: u32,
+    win32_exit_code: u32,
+    service_specific_exit_code: u32,
+    check_point: u32,
+    wait_hint: u32,
+}
+
+type ServiceMain = unsafe extern "system" fn(argc: u32, argv: *mut *mut u16);
+type HandlerEx = unsafe extern "system" fn(control: u32, event_type: u32, event_data: *mut c_void, context: *mut c_void) -> u32;
+
+#[repr(C)]
+struct ServiceTableEntry {
+    name: *mut u16,
+    main: Option<ServiceMain>,
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn StartServiceCtrlDispatcherW(table: *const ServiceTableEntry) -> i32;
+    fn RegisterServiceCtrlHandlerExW(name: *const u16, handler: HandlerEx, context: *mut c_void) -> isize;
+    fn SetServiceStatus(handle: isize, status: *const ServiceStatus) -> i32;
+}
+
+/// The server's running flag, cleared by stop requests
+static RUNNING: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+/// Status handle from `RegisterServiceCtrlHandlerExW` (0 until the service started)
+static STATUS_HANDLE: AtomicIsize = AtomicIsize::new(0);
+/// Progress counter of the pending state being reported
+static CHECK_POINT: AtomicU32 = AtomicU32::new(0);
+/// Tells the service's main function to report the service stopped and return
+static STOPPED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+/// Where the service's main function reports that the service is running
+static STARTED: Mutex<Option<mpsc::Sender<Result<()>>>> = Mutex::new(None);
+
+/// The server running as a Windows service (`--service`)
+///
+/// Dropping it, once the server has shut down, reports the service stopped.
+pub struct Service {
+    dispatcher: Option<thread::JoinHandle<()>>,
+}
+
+/// Connect to the Service Control Manager, which started this process, and have its
+/// stop and shutdown requests clear `running`
+///
+/// The server then shuts down as it does on Ctrl-C. Fails when the process was not
+/// started as a service (run `sc start` instead of starting it by hand).
+pub fn start(running: Arc<AtomicBool>) -> Result<Service> {
+    if RUNNING.set(running).is_err() {
+        anyhow::bail!("The service was started already");
+    }
+    let (started_tx, started) = mpsc::channel();
+    *STARTED.lock().unwrap() = Some(started_tx.clone());
+
+    // Blocks until the service reports it stopped, calling `service_main` on a thread of its own
+    let dispatcher = thread::spawn(move || {
+        // The name is not checked for a service in a process of its own
+        let mut name = [0u16];
+        let table = [
+            ServiceTableEntry { name: name.as_mut_ptr(), main: Some(service_main) },
+            ServiceTableEntry { name: std::ptr::null_mut(), main: None },
+        ];
+        if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+            let e = io::Error::last_os_error();
+            let e = if e.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT) {
+                anyhow::anyhow!("--service needs the process to be started by the Service Control Manager (sc start)")
+            } else {
+                anyhow::Error::from(e).context("Failed to connect to the Service Control Manager")
+            };
+            let _ = started_tx.send(Err(e));
+        }
+    });
+
+    started.recv().context("The service dispatcher ended before the service started")??;
+    Ok(Service { dispatcher: Some(dispatcher) })
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let (stopped, signal) = &STOPPED;
+        *stopped.lock().unwrap() = true;
+        signal.notify_all();
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+    }
+}
+
+/// Called by the dispatcher once the SCM starts the service
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut *mut u16) {
+    let name = [0u16];
+    let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), control_handler, std::ptr::null_mut());
+    let started = STARTED.lock().unwrap().take();
+    if handle == 0 {
+        let e = anyhow::Error::from(io::Error::last_os_error()).context("Failed to register the service control handler");
+        if let Some(started) = started {
+            let _ = started.send(Err(e));
+        }
+        return;
+    }
+    STATUS_HANDLE.store(handle, Ordering::Relaxed);
+    report(SERVICE_RUNNING);
+    if let Some(started) = started {
+        let _ = started.send(Ok(()));
+    }
+
+    // The server runs on the main thread; wait for it to finish shutting down
+    let (stopped, signal) = &STOPPED;
+    let mut done = stopped.lock().unwrap();
+    while !*done {
+        done = signal.wait(done).unwrap();
+    }
+    drop(done);
+    report(SERVICE_STOPPED);
+}
+
+/// Called by the dispatcher for each control request the SCM sends
+unsafe extern "system" fn control_handler(control: u32, _event_type: u32, _event_data: *mut c_void, _context: *mut c_void) -> u32 {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            if let Some(running) = RUNNING.get() {
+                running.store(false, Ordering::Relaxed);
+            }
+            report(SERVICE_STOP_PENDING);
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+/// Tell the SCM the service's state
+fn report(state: u32) {
+    let handle = STATUS_HANDLE.load(Ordering::Relaxed);
+    if handle == 0 {
+        return;
+    }
+    let pending = state == SERVICE_STOP_PENDING;
+    let status = ServiceStatus {
+        service_type: SERVICE_WIN32_OWN_PROCESS,
+        current_state: state,
+        // No new requests are taken while stopping
+        controls_accepted: if state == SERVICE_RUNNING { SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN } else { 0 },
+        win32_exit_code: NO_ERROR,
+        service_specific_exit_code: 0,
+        check_point: if pending { CHECK_POINT.fetch_add(1, Ordering::Relaxed) + 1 } else { 0 },
+        wait_hint: if pending { STOP_WAIT_HINT_MS } else { 0 },
+    };
+    if unsafe { SetServiceStatus(handle, &status) } == 0 {
+        eprintln!("Warning: Failed to report the service state: {}", io::Error::last_os_error());
+    }
+}
@@ -0,0 +1,97 @@
+use std::io;
+
+use crate::opc_server::SharedState;
+
+/// Print a full state snapshot (`stats::snapshot`) whenever the process receives SIGUSR1
+///
+/// `kill -USR1 <pid>` works without `--debug` and without the control API.
+#[cfg(unix)]
+pub fn dump_on_sigusr1(state: SharedState) -> io::Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::stats;
+
+    /// Set by the handler; only an atomic store is safe inside a signal handler
+    static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_sigusr1(_: libc::c_int) {
+        DUMP_REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    install(libc::SIGUSR1, on_sigusr1)?;
+    watch(state, &DUMP_REQUESTED, |state| println!("{}", stats::snapshot(state)));
+    Ok(())
+}
+
+/// Inspect the next `inspect::DEFAULT_FRAMES` frames of every output whenever the process receives SIGUSR2
+///
+/// Prints the file each output's capture goes to; see `inspect::Capture`.
+#[cfg(unix)]
+pub fn inspect_on_sigusr2(state: SharedState) -> io::Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::inspect;
+
+    static INSPECT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_sigusr2(_: libc::c_int) {
+        INSPECT_REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    install(libc::SIGUSR2, on_sigusr2)?;
+    watch(state, &INSPECT_REQUESTED, |state| {
+        for output in state.outputs.read().unwrap().iter() {
+            match output.inspect(inspect::DEFAULT_FRAMES, false, state.quiet) {
+                Ok(path) if !state.quiet => println!("✓ Inspecting {} frames of {} to {}",
+                                                     inspect::DEFAULT_FRAMES, output.config().id(), path.display()),
+                Ok(_) => {}
+                Err(e) => eprintln!("✗ Could not inspect {}: {:#}", output.config().id(), e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(unix)]
+fn install(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        // Restart interrupted reads/accepts instead of failing them with EINTR
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Run `action` on a background thread each time a handler sets `requested`, until the server stops
+#[cfg(unix)]
+fn watch(state: SharedState, requested: &'static std::sync::atomic::AtomicBool, action: impl Fn(&SharedState) + Send + 'static) {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    thread::spawn(move || {
+        while state.running.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+            if requested.swap(false, Ordering::Relaxed) {
+                action(&state);
+            }
+        }
+    });
+}
+
+/// Signals are unix-only; elsewhere use the control API's `/status`
+#[cfg(not(unix))]
+pub fn dump_on_sigusr1(_state: SharedState) -> io::Result<()> {
+    Ok(())
+}
+
+/// Signals are unix-only; elsewhere use the control API's `/inspect`
+#[cfg(not(unix))]
+pub fn inspect_on_sigusr2(_state: SharedState) -> io::Result<()> {
+    Ok(())
+}
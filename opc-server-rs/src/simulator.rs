@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::OutputConfig;
+use crate::null_serial::{delegate_serial_port, NullSerial};
+
+/// Terminal row handed to the next simulator output (1-based)
+static NEXT_ROW: AtomicUsize = AtomicUsize::new(1);
+
+/// Minimum time between terminal redraws of one output
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Terminal width used when `COLUMNS` is not set
+const DEFAULT_COLUMNS: usize = 80;
+
+/// Stand-in serial port that renders frames in the terminal instead of sending them
+///
+/// Frames are decoded from the protocol bytes the worker writes (AWA or Adalight),
+/// converted back to RGB according to the output's pixel format, and drawn as ANSI
+/// 24-bit colored blocks, one terminal row per output. Strips wider than the
+/// terminal are downsampled.
+pub struct SimulatorPort {
+    label: String,
+    pixel_format: Option<String>,
+    row: usize,
+    columns: usize,
+    /// Bytes written since the last flush (one protocol frame)
+    pending: Vec<u8>,
+    last_draw: Option<Instant>,
+    serial: NullSerial,
+}
+
+impl SimulatorPort {
+    pub fn new(config: &OutputConfig) -> Self {
+        let columns = std::env::var("COLUMNS").ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_COLUMNS);
+
+        SimulatorPort {
+            label: config.id().to_string(),
+            pixel_format: config.pixel_format.clone(),
+            row: NEXT_ROW.fetch_add(1, Ordering::Relaxed),
+            columns,
+            pending: Vec::new(),
+            last_draw: None,
+            serial: NullSerial::new(config.id(), 0),
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let pixels = decode_frame(&self.pending, self.pixel_format.as_deref());
+        self.pending.clear();
+
+        let Some(pixels) = pixels else {
+            return Ok(());
+        };
+
+        // Blank frames (shutdown, pause) are always drawn; others at most every REDRAW_INTERVAL
+        let is_blank = pixels.iter().all(|p| *p == [0, 0, 0]);
+        if !is_blank && self.last_draw.is_some_and(|t| t.elapsed() < REDRAW_INTERVAL) {
+            return Ok(());
+        }
+        self.last_draw = Some(Instant::now());
+
+        let width = self.columns.saturating_sub(self.label.len() + 1).max(1);
+        let mut line = format!("\x1b[{};1H\x1b[2K{} ", self.row, self.label);
+        for [r, g, b] in downsample(&pixels, width) {
+            line.push_str(&format!("\x1b[38;2;{};{};{}m█", r, g, b));
+        }
+        line.push_str("\x1b[0m");
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(line.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Decode an AWA or Adalight frame into RGB pixels
+fn decode_frame(frame: &[u8], pixel_format: Option<&str>) -> Option<Vec<[u8; 3]>> {
+    if frame.len() < 6 {
+        return None;
+    }
+
+    let payload = match &frame[..3] {
+        b"Awa" => &frame[6..frame.len().saturating_sub(3).max(6)],
+        b"Ada" => &frame[6..],
+        _ => return None,
+    };
+
+    let pixels = match pixel_format {
+        Some("RGBW") | Some("GRBW") => payload.chunks_exact(4)
+            .map(|p| {
+                let (r, g) = if pixel_format == Some("GRBW") { (p[1], p[0]) } else { (p[0], p[1]) };
+                [r.saturating_add(p[3]), g.saturating_add(p[3]), p[2].saturating_add(p[3])]
+            })
+            .collect(),
+        Some("GRB") => payload.chunks_exact(3).map(|p| [p[1], p[0], p[2]]).collect(),
+        Some("BGR") => payload.chunks_exact(3).map(|p| [p[2], p[1], p[0]]).collect(),
+        _ => payload.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+    };
+
+    Some(pixels)
+}
+
+/// Average neighbouring pixels so the strip fits in `width` columns
+fn downsample(pixels: &[[u8; 3]], width: usize) -> Vec<[u8; 3]> {
+    if pixels.len() <= width {
+        return pixels.to_vec();
+    }
+
+    (0..width).map(|column| {
+        let start = column * pixels.len() / width;
+        let end = ((column + 1) * pixels.len() / width).max(start + 1);
+        let group = &pixels[start..end];
+        let mut sum = [0u32; 3];
+        for pixel in group {
+            for (s, &c) in sum.iter_mut().zip(pixel) {
+                *s += c as u32;
+            }
+        }
+        sum.map(|s| (s / group.len() as u32) as u8)
+    }).collect()
+}
+
+impl Write for SimulatorPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.draw()
+    }
+}
+
+impl io::Read for SimulatorPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "simulator has no input"))
+    }
+}
+
+delegate_serial_port!(SimulatorPort, serial);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel_format::transform_pixels;
+    use crate::protocol::{build_adalight_frame, build_awa_frame};
+
+    #[test]
+    fn test_decode_round_trips_pixel_formats() {
+        let rgb = vec![255, 0, 0, 10, 20, 30];
+        for format in [None, Some("GRB"), Some("BGR"), Some("RGBW"), Some("GRBW")] {
+            let stride = if matches!(format, Some("RGBW") | Some("GRBW")) { 4 } else { 3 };
+            let transformed = transform_pixels(rgb.clone(), format);
+            for frame in [build_awa_frame(&transformed, stride), build_adalight_frame(&transformed, stride)] {
+                assert_eq!(decode_frame(&frame, format), Some(vec![[255, 0, 0], [10, 20, 30]]), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_downsample_averages() {
+        let pixels = vec![[0, 0, 0], [255, 255, 255], [100, 100, 100], [100, 100, 100]];
+        assert_eq!(downsample(&pixels, 2), vec![[127, 127, 127], [100, 100, 100]]);
+        assert_eq!(downsample(&pixels, 10), pixels);
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::OutputConfig;
+use crate::null_serial::{delegate_serial_port, NullSerial};
+
+/// Dry-run mode, set once at startup: `Some(None)` discards frames, `Some(Some(dir))`
+/// also writes them to files in `dir`
+static DRY_RUN: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Files already opened by this process; the first open truncates, reopens append
+fn opened() -> &'static Mutex<HashSet<PathBuf>> {
+    static OPENED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    OPENED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Replace every output's device with a sink (`--dry-run`), optionally writing the
+/// frames to `<dir>/<output id>.bin`
+pub fn enable(dir: Option<PathBuf>) -> Result<()> {
+    if let Some(dir) = &dir {
+        std::fs::create_dir_all(dir)
+            .context(format!("Failed to create dry-run directory {}", dir.display()))?;
+    }
+    DRY_RUN.set(dir).map_err(|_| anyhow::anyhow!("dry-run mode is already set"))
+}
+
+/// Whether outputs open sinks instead of their devices
+pub fn enabled() -> bool {
+    DRY_RUN.get().is_some()
+}
+
+/// File name for an output id: anything but letters, digits, `-` and `.` becomes `_`
+fn file_name(id: &str) -> String {
+    let name: String = id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    format!("{}.bin", name.trim_start_matches('.'))
+}
+
+/// Stand-in serial port for `--dry-run` that accepts every frame without a device
+///
+/// The bytes written between flushes are one protocol frame. With a dry-run directory
+/// each frame is appended to the output's `.bin` file, so what the device would have
+/// received can be compared or replayed; without one it is dropped.
+pub struct SinkPort {
+    /// Bytes written since the last flush (one protocol frame)
+    pending: Vec<u8>,
+    file: Option<File>,
+    serial: NullSerial,
+}
+
+impl SinkPort {
+    pub fn open(config: &OutputConfig) -> Result<Self> {
+        let dir = DRY_RUN.get().and_then(|dir| dir.as_deref());
+        let file = match dir {
+            Some(dir) => Some(open_file(&dir.join(file_name(config.id())))?),
+            None => None,
+        };
+        Ok(SinkPort {
+            pending: Vec::new(),
+            file,
+            serial: NullSerial::new(&config.port, config.baud_rate),
+        })
+    }
+}
+
+fn open_file(path: &Path) -> Result<File> {
+    let first = opened().lock().unwrap().insert(path.to_path_buf());
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!first)
+        .truncate(first)
+        .open(path)
+        .context(format!("Failed to open dry-run file {}", path.display()))
+}
+
+impl Write for SinkPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_some() {
+            self.pending.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.write_all(&self.pending)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for SinkPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "dry-run port has no input"))
+    }
+}
+
+delegate_serial_port!(SinkPort, serial);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_appended_to_output_file() {
+        let path = std::env::temp_dir().join(format!("opc-sink-test-{}.bin", std::process::id()));
+        std::fs::write(&path, b"stale").unwrap();
+        assert_eq!(file_name("/dev/ttyUSB0"), "_dev_ttyUSB0.bin");
+        assert_eq!(file_name("10.0.0.5:4048"), "10.0.0.5_4048.bin");
+
+        // The first open truncates what an earlier run left; a reopen keeps appending
+        let mut port = SinkPort { pending: Vec::new(), file: Some(open_file(&path).unwrap()),
+                                  serial: NullSerial::new("test", 115200) };
+        port.write_all(&[1, 2]).unwrap();
+        port.write_all(&[3]).unwrap();
+        port.flush().unwrap();
+        port.file = Some(open_file(&path).unwrap());
+        port.write_all(&[4]).unwrap();
+        port.flush().unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), vec![1, 2, 3, 4]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
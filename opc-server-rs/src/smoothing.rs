@@ -0,0 +1,111 @@
+use crate::config::OutputConfig;
+
+/// Temporal smoothing for one output (`smoothing`)
+///
+/// Each frame is blended into a running average of the previous ones, an
+/// exponential moving average like Hyperion's smoothing: with a strength of 0.8 a
+/// new color is 20% of the way there after one frame and about 90% after ten. This
+/// hides the frame-to-frame noise of screen and audio capture sources.
+///
+/// As in Hyperion, the average keeps moving on its own clock: a source that sends only
+/// when something changes gets the worker stepping toward its last frame (`unsettled`)
+/// until the strip shows it.
+#[derive(Debug, Clone)]
+pub struct Smoothing {
+    /// Share of the average kept in every frame, 0.0 (off) to just below 1.0
+    strength: f32,
+    /// Running average per channel, kept unrounded so slow fades still reach their target
+    average: Vec<f32>,
+    /// The last frame applied, which the average is heading for
+    target: Vec<u8>,
+}
+
+impl Smoothing {
+    /// Build the filter for an output, if it sets `smoothing`
+    pub fn from_config(config: &OutputConfig) -> Option<Self> {
+        config.smoothing.filter(|&strength| strength > 0.0).map(|strength| Smoothing {
+            strength,
+            average: Vec::new(),
+            target: Vec::new(),
+        })
+    }
+
+    /// Forget the average, so the next frame is shown as is
+    pub fn reset(&mut self) {
+        self.average.clear();
+    }
+
+    /// Replace pixel data in-place with its running average
+    pub fn apply(&mut self, data: &mut [u8]) {
+        self.target.clear();
+        self.target.extend_from_slice(data);
+        // A new stream, or one whose length changed, starts from its first frame
+        if self.average.len() != data.len() {
+            self.average = data.iter().map(|&v| v as f32).collect();
+            return;
+        }
+        let weight = 1.0 - self.strength;
+        for (average, value) in self.average.iter_mut().zip(data.iter_mut()) {
+            *average += (*value as f32 - *average) * weight;
+            *value = average.round() as u8;
+        }
+    }
+
+    /// The last frame applied, while the average still rounds to something else
+    pub fn unsettled(&self) -> Option<&[u8]> {
+        let settled = self.average.iter().zip(&self.target)
+            .all(|(average, &target)| average.round() as u8 == target);
+        (!settled).then_some(&self.target[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_follows_and_settles() {
+        let mut smoothing = Smoothing { strength: 0.5, average: Vec::new(), target: Vec::new() };
+        let mut data = vec![0, 200];
+        smoothing.apply(&mut data);
+        assert_eq!(data, vec![0, 200]);
+
+        let mut data = vec![100, 0];
+        smoothing.apply(&mut data);
+        assert_eq!(data, vec![50, 100]);
+
+        // Holding a color converges on it exactly
+        for _ in 0..20 {
+            data = vec![100, 0];
+            smoothing.apply(&mut data);
+        }
+        assert_eq!(data, vec![100, 0]);
+
+        smoothing.reset();
+        let mut data = vec![255, 255];
+        smoothing.apply(&mut data);
+        assert_eq!(data, vec![255, 255]);
+    }
+
+    #[test]
+    fn test_one_shot_change_settles_without_new_frames() {
+        let mut smoothing = Smoothing { strength: 0.8, average: Vec::new(), target: Vec::new() };
+        smoothing.apply(&mut [0, 0]);
+        assert!(smoothing.unsettled().is_none());
+
+        // One changed frame only gets 20% of the way there
+        let mut data = vec![200, 50];
+        smoothing.apply(&mut data);
+        assert_eq!(data, vec![40, 10]);
+
+        // Stepping toward the last frame reaches it, then stops
+        let mut steps = 0;
+        while let Some(target) = smoothing.unsettled() {
+            data = target.to_vec();
+            smoothing.apply(&mut data);
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert_eq!(data, vec![200, 50]);
+    }
+}
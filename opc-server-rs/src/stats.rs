@@ -0,0 +1,355 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::health::Health;
+use crate::opc_server::SharedState;
+
+/// How the periodic statistics are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsFormat {
+    /// One `[Stats] ...` line per interval
+    #[default]
+    Text,
+    /// One JSON object per line, for jq or telegraf
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(format!("unknown stats format '{}' (expected text or json)", s)),
+        }
+    }
+}
+
+/// Statistics for one interval
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    /// Unix time in seconds
+    pub timestamp: f64,
+    pub interval_secs: f64,
+    pub received_fps: f64,
+    pub frames_received: u64,
+    pub frames_throttled: u64,
+    pub messages_rejected: u64,
+    pub frames_preempted: u64,
+    pub outputs: Vec<OutputStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputStats {
+    pub id: String,
+    pub port: String,
+    pub connected: bool,
+    pub fps: f64,
+    pub bytes_per_sec: f64,
+    /// None when nothing was written during the interval
+    pub write_avg_ms: Option<f64>,
+    pub write_p99_ms: Option<f64>,
+    /// Time from frames reaching the output to their flush completing; None when
+    /// nothing was written during the interval
+    pub latency_p50_ms: Option<f64>,
+    pub latency_p90_ms: Option<f64>,
+    pub latency_p99_ms: Option<f64>,
+    pub latency_max_ms: Option<f64>,
+    pub frames_sent: u64,
+    pub frames_dropped: u64,
+    pub frames_deduped: u64,
+    pub frames_late: u64,
+    /// Average pause between chunks per frame written (`chunk_size` outputs); None when
+    /// nothing was paced during the interval
+    pub pacing_avg_ms: Option<f64>,
+    pub errors: u64,
+    /// Judged against the `health` error budget and reconnect limit
+    pub health: Health,
+}
+
+impl StatsReport {
+    /// The human-oriented `[Stats]` line
+    pub fn to_text(&self) -> String {
+        let mut line = format!("[Stats] Received: {:.1} fps", self.received_fps);
+        for (count, what) in [
+            (self.frames_throttled, "throttled"),
+            (self.messages_rejected, "rejected"),
+            (self.frames_preempted, "preempted"),
+        ] {
+            if count > 0 {
+                line.push_str(&format!(" ({} {})", count, what));
+            }
+        }
+
+        for output in &self.outputs {
+            line.push_str(&format!(", {}: {:.1} fps {:.1} KB/s", output.id, output.fps, output.bytes_per_sec / 1024.0));
+            if let (Some(avg), Some(p99)) = (output.write_avg_ms, output.write_p99_ms) {
+                line.push_str(&format!(" write {:.1}ms avg/{:.1}ms p99", avg, p99));
+            }
+            if let Some(pacing) = output.pacing_avg_ms {
+                line.push_str(&format!(" paced {:.1}ms/frame", pacing));
+            }
+            if output.frames_dropped > 0 {
+                line.push_str(&format!(" ({} dropped)", output.frames_dropped));
+            }
+            if output.frames_late > 0 {
+                line.push_str(&format!(" ({} late)", output.frames_late));
+            }
+            if output.frames_deduped > 0 {
+                line.push_str(&format!(" ({} unchanged)", output.frames_deduped));
+            }
+            if output.health != Health::Healthy {
+                line.push_str(&format!(" [{}]", output.health.name()));
+            }
+        }
+        line
+    }
+
+    /// One `[Latency] ...` line per output that wrote frames during the interval (`--latency`)
+    pub fn latency_lines(&self) -> Vec<String> {
+        self.outputs.iter().filter_map(|output| {
+            let (Some(p50), Some(p90), Some(p99), Some(max)) =
+                (output.latency_p50_ms, output.latency_p90_ms, output.latency_p99_ms, output.latency_max_ms) else {
+                return None;
+            };
+            let mut line = format!("[Latency] {}: {:.1}ms p50, {:.1}ms p90, {:.1}ms p99, {:.1}ms max",
+                                   output.id, p50, p90, p99, max);
+            if let Some(write) = output.write_avg_ms {
+                line.push_str(&format!(" (write {:.1}ms avg)", write));
+            }
+            Some(line)
+        }).collect()
+    }
+
+    pub fn render(&self, format: StatsFormat) -> String {
+        match format {
+            StatsFormat::Text => self.to_text(),
+            StatsFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+/// Turns the running counters into per-interval rates
+#[derive(Default)]
+pub struct StatsCollector {
+    last_received: u64,
+    /// Frames and bytes sent and pacing time, keyed by output id so counters survive a
+    /// config reload
+    last_sent: HashMap<String, (u64, u64, u64)>,
+}
+
+impl StatsCollector {
+    pub fn collect(&mut self, state: &SharedState, interval: Duration) -> StatsReport {
+        let secs = interval.as_secs_f64();
+        let received = state.frames_received.load(Ordering::Relaxed);
+        let received_fps = received.saturating_sub(self.last_received) as f64 / secs;
+        self.last_received = received;
+
+        let now = Instant::now();
+        let outputs = state.outputs.read().unwrap().iter().map(|output| {
+            let config = output.config();
+            let output_state = output.state();
+            let frames = output.frames_sent();
+            let bytes = output_state.bytes_sent.load(Ordering::Relaxed);
+            let pacing = output_state.pacing_us.load(Ordering::Relaxed);
+            let (last_frames, last_bytes, last_pacing) = self.last_sent
+                .insert(config.id().to_string(), (frames, bytes, pacing))
+                .unwrap_or((0, 0, 0));
+            let frames_written = frames.saturating_sub(last_frames);
+            let paced = pacing.saturating_sub(last_pacing);
+            let latency = output_state.write_latency.lock().unwrap().take_summary();
+            let frame_latency = output_state.frame_latency.lock().unwrap().take_summary();
+            let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+            let connected = output_state.connected.load(Ordering::Relaxed);
+            let errors = output_state.errors.load(Ordering::Relaxed);
+            let frames_late = output_state.frames_late.load(Ordering::Relaxed);
+            let health = state.health.evaluate(config.id(), connected, frames, errors + frames_late, now);
+
+            OutputStats {
+                id: config.id().to_string(),
+                port: config.port.clone(),
+                connected,
+                fps: frames_written as f64 / secs,
+                bytes_per_sec: bytes.saturating_sub(last_bytes) as f64 / secs,
+                write_avg_ms: latency.map(|l| ms(l.average)),
+                write_p99_ms: latency.map(|l| ms(l.p99)),
+                latency_p50_ms: frame_latency.map(|l| ms(l.p50)),
+                latency_p90_ms: frame_latency.map(|l| ms(l.p90)),
+                latency_p99_ms: frame_latency.map(|l| ms(l.p99)),
+                latency_max_ms: frame_latency.map(|l| ms(l.max)),
+                frames_sent: frames,
+                frames_dropped: output_state.frames_dropped.load(Ordering::Relaxed),
+                frames_deduped: output_state.frames_deduped.load(Ordering::Relaxed),
+                frames_late,
+                pacing_avg_ms: (paced > 0 && frames_written > 0).then(|| paced as f64 / 1000.0 / frames_written as f64),
+                errors,
+                health: health.health,
+            }
+        }).collect();
+
+        StatsReport {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            interval_secs: secs,
+            received_fps,
+            frames_received: received,
+            frames_throttled: state.frames_throttled.load(Ordering::Relaxed),
+            messages_rejected: state.messages_rejected.load(Ordering::Relaxed),
+            frames_preempted: state.frames_preempted.load(Ordering::Relaxed),
+            outputs,
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch, for `last_*_ms` timestamps (0 means never)
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Human-readable duration, e.g. `1h 02m 03s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}.{}s", secs, duration.subsec_millis() / 100),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+/// Age of a `last_*_ms` timestamp
+fn format_age(timestamp_ms: u64, now_ms: u64) -> String {
+    if timestamp_ms == 0 {
+        return "never".to_string();
+    }
+    format!("{} ago", format_duration(Duration::from_millis(now_ms.saturating_sub(timestamp_ms))))
+}
+
+/// Full picture of the server (uptime, clients, every output's counters and health)
+pub fn snapshot(state: &SharedState) -> String {
+    let now = now_ms();
+    let mut lines = vec![format!(
+        "[Dump] Uptime {}, {} messages received (last {}), {} throttled, {} rejected, {} preempted",
+        format_duration(state.started.elapsed()),
+        state.frames_received.load(Ordering::Relaxed),
+        format_age(state.last_message_ms.load(Ordering::Relaxed), now),
+        state.frames_throttled.load(Ordering::Relaxed),
+        state.messages_rejected.load(Ordering::Relaxed),
+        state.frames_preempted.load(Ordering::Relaxed),
+    )];
+
+    let clients = state.clients.lock().unwrap();
+    lines.push(format!("[Dump] {} client(s) connected", clients.len()));
+    for client in clients.iter() {
+        let connected_for = SystemTime::now().duration_since(client.connected_at).unwrap_or_default();
+        lines.push(format!("[Dump]   {} (for {})", client.addr, format_duration(connected_for)));
+    }
+    drop(clients);
+
+    for output in state.outputs.read().unwrap().iter() {
+        let output_state = output.state();
+        let connected = output_state.connected.load(Ordering::Relaxed);
+        lines.push(format!(
+            "[Dump] Output {}: {}, {}{}, brightness {:.2}, {} frames, {} bytes, {} dropped, {} errors, last write {}",
+            output.config().label(),
+            if connected { "connected" } else { "DISCONNECTED" },
+            if output.is_enabled() { "enabled" } else { "paused" },
+            if output.is_blanked() { ", blanked" } else { "" },
+            output.brightness(),
+            output_state.frames_sent.load(Ordering::Relaxed),
+            output_state.bytes_sent.load(Ordering::Relaxed),
+            output_state.frames_dropped.load(Ordering::Relaxed),
+            output_state.errors.load(Ordering::Relaxed),
+            format_age(output_state.last_write_ms.load(Ordering::Relaxed), now),
+        ));
+        for (port, mirror) in output.mirrors() {
+            lines.push(format!(
+                "[Dump]   mirror {}: {}, {} frames, {} errors",
+                port,
+                if mirror.connected.load(Ordering::Relaxed) { "connected" } else { "DISCONNECTED" },
+                mirror.frames_sent.load(Ordering::Relaxed),
+                mirror.errors.load(Ordering::Relaxed),
+            ));
+        }
+    }
+
+    for pending in state.pending.lock().unwrap().entries() {
+        lines.push(format!(
+            "[Dump] Output {}: PENDING, {} failed attempt(s), last error: {}",
+            pending.label(),
+            pending.attempts,
+            pending.last_error,
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(health: Health) -> StatsReport {
+        StatsReport {
+            timestamp: 1700000000.0,
+            interval_secs: 5.0,
+            received_fps: 60.0,
+            frames_received: 300,
+            frames_throttled: 0,
+            messages_rejected: 2,
+            frames_preempted: 0,
+            outputs: vec![OutputStats {
+                id: "desk".to_string(),
+                port: "/dev/ttyUSB0".to_string(),
+                connected: true,
+                fps: 59.8,
+                bytes_per_sec: 55705.6,
+                write_avg_ms: Some(8.14),
+                write_p99_ms: Some(9.3),
+                latency_p50_ms: Some(12.04),
+                latency_p90_ms: Some(16.5),
+                latency_p99_ms: Some(24.0),
+                latency_max_ms: Some(31.25),
+                frames_sent: 299,
+                frames_dropped: 1,
+                frames_deduped: 0,
+                frames_late: 0,
+                pacing_avg_ms: None,
+                errors: 0,
+                health,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let report = report(Health::Healthy);
+
+        assert_eq!(report.render(StatsFormat::Text),
+                   "[Stats] Received: 60.0 fps (2 rejected), desk: 59.8 fps 54.4 KB/s write 8.1ms avg/9.3ms p99 (1 dropped)");
+        assert_eq!(report.latency_lines(),
+                   vec!["[Latency] desk: 12.0ms p50, 16.5ms p90, 24.0ms p99, 31.2ms max (write 8.1ms avg)"]);
+
+        let json: serde_json::Value = serde_json::from_str(&report.render(StatsFormat::Json)).unwrap();
+        assert_eq!(json["outputs"][0]["id"], "desk");
+        assert_eq!(json["outputs"][0]["frames_dropped"], 1);
+        assert_eq!(json["outputs"][0]["health"], "healthy");
+        assert_eq!(json["received_fps"], 60.0);
+        assert_eq!("JSON".parse::<StatsFormat>(), Ok(StatsFormat::Json));
+    }
+
+    #[test]
+    fn test_report_marks_unhealthy_outputs() {
+        assert!(report(Health::Degraded).render(StatsFormat::Text).ends_with("(1 dropped) [degraded]"));
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(0, 5000), "never");
+        assert_eq!(format_age(4750, 5000), "0.2s ago");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_duration(Duration::from_secs(75)), "1m 15s");
+    }
+}
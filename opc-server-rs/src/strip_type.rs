@@ -0,0 +1,87 @@
+use anyhow::Result;
+
+use crate::color::ColorCorrection;
+use crate::config::OutputConfig;
+
+/// Defaults an output's `strip_type` stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StripPreset {
+    pub name: &'static str,
+    /// Color order the chips expect
+    pub pixel_format: &'static str,
+    /// Color correction curve exponent
+    pub gamma: f32,
+    /// R, G, B scale evening out the LEDs' unequal brightness, so white looks white
+    pub whitepoint: [f32; 3],
+    /// Current of one channel of one LED at full brightness
+    pub milliamps_per_channel: [f32; 3],
+}
+
+/// Gamma of the presets: Fadecandy's default curve
+const GAMMA: f32 = 2.5;
+
+/// FastLED's `TypicalSMD5050` correction (255, 176, 240): 5050 LEDs run blue-green
+const SMD5050: [f32; 3] = [1.0, 0.69, 0.94];
+
+/// Presets by name; the chips' datasheets and FastLED's defaults
+pub const PRESETS: &[StripPreset] = &[
+    StripPreset { name: "ws2811", pixel_format: "RGB", gamma: GAMMA, whitepoint: SMD5050, milliamps_per_channel: [20.0; 3] },
+    StripPreset { name: "ws2812b", pixel_format: "GRB", gamma: GAMMA, whitepoint: SMD5050, milliamps_per_channel: [20.0; 3] },
+    StripPreset { name: "ws2813", pixel_format: "GRB", gamma: GAMMA, whitepoint: SMD5050, milliamps_per_channel: [20.0; 3] },
+    // 12V: one chip drives its LEDs in series at a lower current
+    StripPreset { name: "ws2815", pixel_format: "GRB", gamma: GAMMA, whitepoint: SMD5050, milliamps_per_channel: [15.0; 3] },
+    StripPreset { name: "sk6812", pixel_format: "GRB", gamma: GAMMA, whitepoint: SMD5050, milliamps_per_channel: [20.0; 3] },
+    StripPreset { name: "sk6812-rgbw", pixel_format: "GRBW", gamma: GAMMA, whitepoint: [1.0; 3], milliamps_per_channel: [20.0; 3] },
+    StripPreset { name: "apa102", pixel_format: "BGR", gamma: GAMMA, whitepoint: [1.0; 3], milliamps_per_channel: [20.0; 3] },
+    StripPreset { name: "sk9822", pixel_format: "BGR", gamma: GAMMA, whitepoint: [1.0; 3], milliamps_per_channel: [20.0; 3] },
+    // 12V RGBW with a programmable constant current; 12mA is the usual setting
+    StripPreset { name: "tm1814", pixel_format: "RGBW", gamma: GAMMA, whitepoint: [1.0; 3], milliamps_per_channel: [12.0; 3] },
+];
+
+/// The preset called `name`, ignoring case
+pub fn find(name: &str) -> Option<&'static StripPreset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+/// Fill in the fields of `config` its `strip_type` provides and it leaves unset
+///
+/// Explicitly set fields win: a `pixel_format`, `color` or `milliamps_per_channel`
+/// in the output is kept as is.
+pub fn apply(config: &mut OutputConfig) -> Result<()> {
+    let Some(name) = &config.strip_type else {
+        return Ok(());
+    };
+    let Some(preset) = find(name) else {
+        let names: Vec<&str> = PRESETS.iter().map(|p| p.name).collect();
+        anyhow::bail!("{}: unknown strip_type '{}' (available: {})", config.label(), name, names.join(", "));
+    };
+    config.pixel_format.get_or_insert_with(|| preset.pixel_format.to_string());
+    config.color.get_or_insert_with(|| ColorCorrection {
+        gamma: preset.gamma,
+        whitepoint: preset.whitepoint,
+        ..Default::default()
+    });
+    config.milliamps_per_channel.get_or_insert(preset.milliamps_per_channel);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_fills_unset_fields_only() {
+        let mut config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "/dev/ttyUSB0", "protocol": "awa", "baud_rate": 2000000,
+            "opc_channel": 0, "led_count": 10, "strip_type": "WS2815", "pixel_format": "RGB",
+        })).unwrap();
+        apply(&mut config).unwrap();
+        assert_eq!(config.pixel_format.as_deref(), Some("RGB"));
+        assert_eq!(config.milliamps_per_channel, Some([15.0; 3]));
+        let color = config.color.as_ref().unwrap();
+        assert_eq!((color.gamma, color.whitepoint), (2.5, SMD5050));
+
+        config.strip_type = Some("ws9999".to_string());
+        assert!(apply(&mut config).unwrap_err().to_string().contains("available: ws2811, ws2812b"));
+    }
+}
@@ -0,0 +1,290 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+use crate::config::{Config, OutputConfig};
+use crate::opc_server::SharedState;
+use crate::pixel_format;
+use crate::protocol;
+
+/// Serial line bits per byte at 8N1: start, 8 data, stop
+const BITS_PER_BYTE: f64 = 10.0;
+
+/// A listener or capture feeding pixels to the outputs
+#[derive(Debug, Clone, Serialize)]
+pub struct Input {
+    pub kind: &'static str,
+    pub address: String,
+    /// OPC channel it draws on; None when its clients pick the channel
+    pub channel: Option<u8>,
+}
+
+/// Outputs fed from one OPC channel
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelSummary {
+    pub channel: u8,
+    pub outputs: Vec<String>,
+    pub led_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputSummary {
+    pub id: String,
+    pub port: String,
+    pub protocol: String,
+    pub led_count: usize,
+    pub pixel_format: String,
+    pub baud_rate: u32,
+    /// Frames per second the baud rate allows for a full frame (none for network protocols)
+    pub max_fps: Option<f64>,
+    pub ok: bool,
+    /// Why the output is not open or no longer writing
+    pub error: Option<String>,
+}
+
+/// What the server takes input from and drives, printed at startup and served at `/summary`
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub inputs: Vec<Input>,
+    pub channels: Vec<ChannelSummary>,
+    pub outputs: Vec<OutputSummary>,
+}
+
+/// Inputs `config` enables; they are bound at startup and only change on restart
+pub fn inputs(config: &Config) -> Vec<Input> {
+    let opc_host = &config.opc.host;
+    let mut inputs = Vec::new();
+    if config.opc.enabled {
+        inputs.push(Input { kind: "OPC", address: format!("{}:{}", opc_host, config.opc.port), channel: None });
+    }
+    if let Some(port) = config.opc.websocket_port {
+        inputs.push(Input { kind: "OPC WebSocket", address: format!("ws://{}:{}", opc_host, port), channel: None });
+    }
+    if let Some(fc) = &config.fadecandy {
+        let host = fc.host.as_deref().unwrap_or(opc_host);
+        inputs.push(Input { kind: "Fadecandy", address: format!("ws://{}:{}", host, fc.websocket_port), channel: None });
+    }
+    if let Some(hyperion) = &config.hyperion {
+        let host = hyperion.host.as_deref().unwrap_or(opc_host);
+        inputs.push(Input {
+            kind: "Hyperion",
+            address: format!("{}:{}", host, hyperion.port),
+            channel: Some(hyperion.opc_channel),
+        });
+    }
+    if let Some(ambilight) = &config.ambilight {
+        inputs.push(Input {
+            kind: "Ambilight",
+            address: ambilight.display.clone().unwrap_or_else(|| "default display".to_string()),
+            channel: Some(ambilight.opc_channel),
+        });
+    }
+    if let Some(audio) = &config.audio {
+        for effect in &audio.effects {
+            inputs.push(Input {
+                kind: "Audio",
+                address: audio.device.clone().unwrap_or_else(|| "default input".to_string()),
+                channel: Some(effect.opc_channel),
+            });
+        }
+    }
+    if let Some(control) = &config.control {
+        inputs.push(Input { kind: "Control API", address: format!("http://{}:{}", control.host, control.port), channel: None });
+    }
+    inputs
+}
+
+/// Highest frame rate `config`'s baud rate allows, from the length of a full frame in its
+/// protocol, or the output's `max_fps` limit if that is lower
+pub fn max_fps(config: &OutputConfig) -> Option<f64> {
+    let protocol = protocol::for_output(config).ok()?;
+    let limit = config.max_fps.or(protocol.max_fps());
+    let stride = pixel_format::stride(config.pixel_format.as_deref());
+    let frame_len = protocol.max_frame_len(config.led_count, stride);
+    if protocol::is_network(&config.protocol) || frame_len == 0 || config.baud_rate == 0 {
+        return limit;
+    }
+    // Paced outputs pause between the chunks of every frame
+    let pauses = config.chunk_size.filter(|&size| size > 0).map_or(0, |size| frame_len.div_ceil(size) - 1);
+    let pacing = pauses as f64 * config.inter_chunk_delay_us.unwrap_or(0) as f64 / 1e6;
+    let line_fps = 1.0 / (frame_len as f64 * BITS_PER_BYTE / config.baud_rate as f64 + pacing);
+    Some(limit.map_or(line_fps, |limit| limit.min(line_fps)))
+}
+
+fn output_summary(config: &OutputConfig, ok: bool, error: Option<String>) -> OutputSummary {
+    OutputSummary {
+        id: config.id().to_string(),
+        port: config.port.clone(),
+        protocol: config.protocol.clone(),
+        led_count: config.led_count,
+        pixel_format: config.pixel_format.clone().unwrap_or_else(|| "RGB".to_string()),
+        baud_rate: config.baud_rate,
+        max_fps: max_fps(config),
+        ok,
+        error,
+    }
+}
+
+impl Summary {
+    pub fn collect(state: &SharedState) -> Self {
+        let mut outputs = Vec::new();
+        let mut channels: BTreeMap<u8, ChannelSummary> = BTreeMap::new();
+        let mut add_channel = |config: &OutputConfig| {
+            let channel = channels.entry(config.opc_channel).or_insert_with(|| ChannelSummary {
+                channel: config.opc_channel,
+                outputs: Vec::new(),
+                led_count: 0,
+            });
+            channel.outputs.push(config.id().to_string());
+            channel.led_count += config.led_count;
+        };
+
+        for output in state.outputs.read().unwrap().iter() {
+            let error = (!output.is_connected()).then(|| "port stopped writing".to_string());
+            outputs.push(output_summary(output.config(), output.is_connected(), error));
+            output.entries().iter().for_each(&mut add_channel);
+        }
+        for pending in state.pending.lock().unwrap().entries() {
+            let ids: Vec<&str> = pending.group.iter().map(|c| c.id()).collect();
+            let mut summary = output_summary(&pending.group[0], false, Some(pending.last_error.clone()));
+            summary.id = ids.join("+");
+            summary.led_count = pending.group.iter().map(|c| c.led_count).sum();
+            outputs.push(summary);
+            pending.group.iter().for_each(&mut add_channel);
+        }
+
+        Summary {
+            inputs: state.inputs.to_vec(),
+            channels: channels.into_values().collect(),
+            outputs,
+        }
+    }
+
+    /// Tables of inputs, channels and outputs; `color` marks OK and FAIL in green and red
+    pub fn render(&self, color: bool) -> String {
+        let mut text = String::new();
+
+        let inputs: Vec<Vec<String>> = self.inputs.iter().map(|input| vec![
+            input.kind.to_string(),
+            input.address.clone(),
+            input.channel.map_or("any".to_string(), |c| c.to_string()),
+        ]).collect();
+        text.push_str(&table(&["INPUT", "ADDRESS", "CHANNEL"], &inputs));
+
+        let channels: Vec<Vec<String>> = self.channels.iter().map(|channel| vec![
+            channel.channel.to_string(),
+            channel.led_count.to_string(),
+            channel.outputs.join(", "),
+        ]).collect();
+        text.push('\n');
+        text.push_str(&table(&["CHANNEL", "LEDS", "OUTPUTS"], &channels));
+
+        let outputs: Vec<Vec<String>> = self.outputs.iter().map(|output| vec![
+            String::new(),
+            output.id.clone(),
+            output.port.clone(),
+            output.protocol.clone(),
+            output.led_count.to_string(),
+            output.pixel_format.clone(),
+            output.baud_rate.to_string(),
+            output.max_fps.map_or("-".to_string(), |fps| format!("{:.1}", fps)),
+            output.error.clone().unwrap_or_default(),
+        ]).collect();
+        text.push('\n');
+        let mut lines: Vec<String> = table(&["", "OUTPUT", "PORT", "PROTOCOL", "LEDS", "FORMAT", "BAUD", "MAX FPS", ""], &outputs)
+            .lines().map(str::to_string).collect();
+        // The status column is blank above, so the coloring doesn't upset the widths
+        for (line, output) in lines.iter_mut().skip(1).zip(&self.outputs) {
+            let (mark, code) = if output.ok { ("OK  ", "32") } else { ("FAIL", "31") };
+            let mark = if color { format!("\x1b[{}m{}\x1b[0m", code, mark) } else { mark.to_string() };
+            *line = format!("{}{}", mark, &line[4..]);
+        }
+        for line in lines {
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Whether the startup summary is colored: stdout is a terminal and `NO_COLOR` is unset
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Left-aligned columns two spaces apart, under a header row
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Room for the status marks, which are filled in afterwards
+    if header[0].is_empty() {
+        widths[0] = 4;
+    }
+
+    let mut text = String::new();
+    for row in std::iter::once(header.iter().map(|h| h.to_string()).collect::<Vec<_>>()).chain(rows.iter().cloned()) {
+        let line: Vec<String> = row.iter().zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        text.push_str(line.join("  ").trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(ok: bool, error: Option<&str>) -> OutputSummary {
+        OutputSummary {
+            id: if ok { "desk".to_string() } else { "shelf".to_string() },
+            port: "/dev/ttyUSB0".to_string(),
+            protocol: "awa".to_string(),
+            led_count: 100,
+            pixel_format: "GRB".to_string(),
+            baud_rate: 2_000_000,
+            max_fps: Some(648.5),
+            ok,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_render_marks_outputs() {
+        let summary = Summary {
+            inputs: vec![Input { kind: "OPC", address: "0.0.0.0:7890".to_string(), channel: None }],
+            channels: vec![ChannelSummary { channel: 0, outputs: vec!["desk".to_string()], led_count: 100 }],
+            outputs: vec![output(true, None), output(false, Some("No such file or directory"))],
+        };
+        let text = summary.render(false);
+        assert!(text.contains("INPUT  ADDRESS       CHANNEL\nOPC    0.0.0.0:7890  any\n"), "{}", text);
+        assert!(text.contains("CHANNEL  LEDS  OUTPUTS\n0        100   desk\n"), "{}", text);
+        assert!(text.contains("OK    desk    /dev/ttyUSB0  awa       100   GRB     2000000  648.5\n"), "{}", text);
+        assert!(text.contains("FAIL  shelf   /dev/ttyUSB0  awa       100   GRB     2000000  648.5    No such file"), "{}", text);
+        assert!(summary.render(true).contains("\x1b[31mFAIL\x1b[0m  shelf"));
+    }
+
+    #[test]
+    fn test_max_fps_from_frame_length() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "/dev/ttyUSB0", "protocol": "adalight", "baud_rate": 115200,
+            "opc_channel": 0, "led_count": 100,
+        })).unwrap();
+        // 6 header bytes and 300 pixel bytes, 10 bits each
+        let fps = max_fps(&config).unwrap();
+        assert!((fps - 115200.0 / 10.0 / 306.0).abs() < 0.01, "{}", fps);
+
+        // Delta is sized by its uncompressed keyframe, not by a black frame's few bytes
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "/dev/ttyUSB0", "protocol": "delta", "baud_rate": 115200,
+            "opc_channel": 0, "led_count": 100,
+        })).unwrap();
+        let fps = max_fps(&config).unwrap();
+        assert!((fps - 115200.0 / 10.0 / 318.0).abs() < 0.01, "{}", fps);
+    }
+}
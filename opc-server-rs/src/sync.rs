@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Longest a worker waits for the other outputs of its round before sending anyway
+pub const SYNC_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// One OPC message being rendered by every output on its channel
+struct Round {
+    generation: u64,
+    expected: usize,
+    arrived: usize,
+    started: Instant,
+}
+
+impl Round {
+    fn is_released(&self) -> bool {
+        self.arrived >= self.expected || self.started.elapsed() >= SYNC_TIMEOUT
+    }
+}
+
+/// Barrier that releases the frames of one OPC message to all its outputs at once
+///
+/// The server starts a round per channel when it hands a message to the outputs on
+/// that channel. Each worker prepares its protocol frame, then waits in
+/// `arrive_and_wait` until every output of the round is ready, so all strips start
+/// transmitting together. While a round is in flight, new messages for the channel
+/// are skipped, keeping skip-ahead consistent across the outputs instead of letting
+/// each queue drop different frames.
+#[derive(Default)]
+pub struct SyncGate {
+    rounds: Mutex<HashMap<u8, Round>>,
+    /// Signalled when a round is released
+    released: Condvar,
+}
+
+impl SyncGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a round of `expected` outputs on `channel`
+    ///
+    /// Returns false (and starts nothing) if the previous round is still in flight;
+    /// the caller should then skip the message.
+    pub fn begin(&self, channel: u8, expected: usize) -> bool {
+        let mut rounds = self.rounds.lock().unwrap();
+        let generation = match rounds.get(&channel) {
+            Some(round) if !round.is_released() => return false,
+            Some(round) => round.generation + 1,
+            None => 0,
+        };
+
+        rounds.insert(channel, Round {
+            generation,
+            expected,
+            arrived: 0,
+            started: Instant::now(),
+        });
+        // Wake workers still waiting on a round that timed out
+        self.released.notify_all();
+        true
+    }
+
+    /// Current round on `channel`; workers read this right after taking a frame
+    pub fn generation(&self, channel: u8) -> u64 {
+        self.rounds.lock().unwrap().get(&channel).map(|r| r.generation).unwrap_or(0)
+    }
+
+    /// Mark one output of the round as ready without waiting (e.g. it discarded its frame)
+    pub fn arrive(&self, channel: u8, generation: u64) {
+        let mut rounds = self.rounds.lock().unwrap();
+        if let Some(round) = rounds.get_mut(&channel).filter(|r| r.generation == generation) {
+            round.arrived += 1;
+            if round.arrived >= round.expected {
+                self.released.notify_all();
+            }
+        }
+    }
+
+    /// Mark one output of the round as ready and wait until the round is released
+    ///
+    /// Gives up after `timeout` so a stalled output cannot hold the others back.
+    pub fn arrive_and_wait(&self, channel: u8, generation: u64, timeout: Duration) {
+        self.arrive(channel, generation);
+
+        let deadline = Instant::now() + timeout;
+        let mut rounds = self.rounds.lock().unwrap();
+        loop {
+            match rounds.get(&channel) {
+                Some(round) if round.generation == generation && !round.is_released() => {}
+                _ => return,
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            rounds = self.released.wait_timeout(rounds, deadline - now).unwrap().0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_round_skips_until_all_arrive() {
+        let gate = SyncGate::new();
+        assert!(gate.begin(0, 2));
+        let generation = gate.generation(0);
+
+        // Other channels have their own rounds
+        assert!(gate.begin(1, 1));
+
+        assert!(!gate.begin(0, 2));
+        gate.arrive(0, generation);
+        assert!(!gate.begin(0, 2));
+        gate.arrive(0, generation);
+        assert!(gate.begin(0, 2));
+        assert_eq!(gate.generation(0), generation + 1);
+    }
+
+    #[test]
+    fn test_workers_released_together() {
+        let gate = Arc::new(SyncGate::new());
+        assert!(gate.begin(0, 2));
+
+        let waiter = {
+            let gate = Arc::clone(&gate);
+            thread::spawn(move || {
+                let started = Instant::now();
+                gate.arrive_and_wait(0, 0, Duration::from_secs(5));
+                started.elapsed()
+            })
+        };
+
+        thread::sleep(Duration::from_millis(30));
+        gate.arrive_and_wait(0, 0, Duration::from_secs(5));
+        let waited = waiter.join().unwrap();
+        assert!(waited >= Duration::from_millis(30) && waited < Duration::from_secs(5));
+    }
+}
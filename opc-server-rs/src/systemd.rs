@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::stats;
+
+/// Send a state change (`READY=1`, `STOPPING=1`, `WATCHDOG=1`) to systemd
+///
+/// Does nothing unless the server runs in a `Type=notify` unit (`NOTIFY_SOCKET` is set).
+pub fn notify(message: &str) {
+    #[cfg(unix)]
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(e) = send(&socket, message) {
+            eprintln!("Warning: Failed to notify systemd ({}): {}", message, e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = message;
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, message: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    let path = socket.as_bytes();
+    // A leading '@' names a socket in Linux's abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        sender.send_to_addr(message.as_bytes(), &addr)?;
+        return Ok(());
+    }
+    sender.send_to(message.as_bytes(), std::ffi::OsStr::from_bytes(path))?;
+    Ok(())
+}
+
+/// Watchdog timeout requested by the unit (`WatchdogSec=`), if it applies to this process
+fn watchdog_timeout(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.trim().parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+    let usec: u64 = usec?.trim().parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Ping the systemd watchdog while the server loop keeps updating `heartbeat_ms`
+///
+/// Pings go out at half the timeout; once the loop has not checked in for a whole
+/// timeout the pings stop, so systemd restarts the hung server.
+pub fn spawn_watchdog(heartbeat_ms: Arc<AtomicU64>, running: Arc<AtomicBool>, debug: bool) {
+    let timeout = watchdog_timeout(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    );
+    let Some(timeout) = timeout else {
+        return;
+    };
+    if debug {
+        println!("✓ systemd watchdog enabled ({:?})", timeout);
+    }
+
+    thread::spawn(move || {
+        let mut warned = false;
+        while running.load(Ordering::Relaxed) {
+            thread::sleep(timeout / 2);
+            let silent_ms = stats::now_ms().saturating_sub(heartbeat_ms.load(Ordering::Relaxed));
+            if silent_ms < timeout.as_millis() as u64 {
+                notify("WATCHDOG=1");
+                warned = false;
+            } else if !warned {
+                eprintln!("✗ Server loop unresponsive for {}ms, no longer pinging the systemd watchdog", silent_ms);
+                warned = true;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_timeout() {
+        assert_eq!(watchdog_timeout(Some("10000000"), None, 42), Some(Duration::from_secs(10)));
+        assert_eq!(watchdog_timeout(Some("10000000"), Some("42"), 42), Some(Duration::from_secs(10)));
+        // Meant for another process (e.g. a wrapper script's parent)
+        assert_eq!(watchdog_timeout(Some("10000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_timeout(Some("0"), None, 42), None);
+        assert_eq!(watchdog_timeout(None, None, 42), None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::arbiter::Source;
+use crate::opc_server::SharedState;
+
+/// SysEx system id of this server's own extensions ("OS"); OPC has no registry of ids,
+/// so this one just stays clear of Fadecandy's 0x0001
+pub const SYSTEM_ID: u16 = 0x4f53;
+
+/// Timed pixels: presentation time as u64 microseconds since the Unix epoch, then RGB pixels
+pub const SYSEX_TIMED_PIXELS: u16 = 0x0001;
+
+/// Frames timed further ahead are dropped; the sender's clock is probably off
+pub const MAX_AHEAD: Duration = Duration::from_secs(10);
+
+/// Most frames waiting at once; more are dropped
+pub const MAX_QUEUED: usize = 1024;
+
+/// How often the release thread checks the running flag while nothing is due
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// Wall-clock time in microseconds since the Unix epoch
+pub fn now_us() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+/// Data of an OPC SysEx message (command 0xFF) showing `pixels` at `time_us`
+pub fn encode(time_us: u64, pixels: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(12 + pixels.len());
+    data.extend_from_slice(&SYSTEM_ID.to_be_bytes());
+    data.extend_from_slice(&SYSEX_TIMED_PIXELS.to_be_bytes());
+    data.extend_from_slice(&time_us.to_be_bytes());
+    data.extend_from_slice(pixels);
+    data
+}
+
+/// Presentation time and pixels of a timed-pixels SysEx; None for any other SysEx
+pub fn decode(data: &[u8]) -> Option<(u64, &[u8])> {
+    if data.len() < 12 || data[..2] != SYSTEM_ID.to_be_bytes() || data[2..4] != SYSEX_TIMED_PIXELS.to_be_bytes() {
+        return None;
+    }
+    let time_us = u64::from_be_bytes(data[4..12].try_into().ok()?);
+    Some((time_us, &data[12..]))
+}
+
+struct TimedFrame {
+    source: Source,
+    channel: u8,
+    pixels: Vec<u8>,
+}
+
+/// Counters shown in `/status`
+#[derive(Debug, Default)]
+pub struct TimedStats {
+    pub scheduled: AtomicU64,
+    /// Frames whose time had already passed on arrival; shown right away
+    pub late: AtomicU64,
+    /// Frames timed too far ahead, or arriving with the queue full
+    pub dropped: AtomicU64,
+}
+
+/// Frames waiting for their presentation time (`opc.timed_frames`)
+///
+/// Times are wall-clock, so sender and server need synchronized clocks (NTP, or PTP
+/// for sub-millisecond agreement). A released frame goes through input arbitration
+/// and the schedule like any other frame.
+#[derive(Default)]
+pub struct TimedFrames {
+    /// By presentation time, then arrival order
+    queue: Mutex<BTreeMap<(u64, u64), TimedFrame>>,
+    /// Signalled when a frame is queued, which may be due before the one waited for
+    queued: Condvar,
+    next_id: AtomicU64,
+    pub stats: TimedStats,
+}
+
+impl TimedFrames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `pixels` for `channel` at `time_us`; false if the frame was dropped
+    pub fn schedule(&self, source: Source, channel: u8, time_us: u64, pixels: &[u8]) -> bool {
+        let now = now_us();
+        if time_us > now + MAX_AHEAD.as_micros() as u64 {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        if time_us < now {
+            self.stats.late.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= MAX_QUEUED {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        queue.insert((time_us, id), TimedFrame { source, channel, pixels: pixels.to_vec() });
+        self.stats.scheduled.fetch_add(1, Ordering::Relaxed);
+        self.queued.notify_one();
+        true
+    }
+
+    /// Frames waiting for their time
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Wait up to `timeout` for the next frame to come due, and take it
+    fn next_due(&self, timeout: Duration) -> Option<TimedFrame> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let now = now_us();
+            let until_due = match queue.first_entry() {
+                Some(entry) if entry.key().0 <= now => return Some(entry.remove()),
+                Some(entry) => Duration::from_micros(entry.key().0 - now),
+                None => Duration::MAX,
+            };
+            let wait = until_due.min(deadline.saturating_duration_since(Instant::now()));
+            if wait.is_zero() {
+                return None;
+            }
+            queue = self.queued.wait_timeout(queue, wait).unwrap().0;
+        }
+    }
+}
+
+/// Release frames to the outputs as they come due, until the server stops
+pub fn spawn(state: SharedState, timed: Arc<TimedFrames>) {
+    thread::spawn(move || {
+        while state.running.load(Ordering::Relaxed) {
+            if let Some(frame) = timed.next_due(IDLE_WAIT) {
+                state.show_pixels(frame.source, frame.channel, &frame.pixels);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_released_in_time_order() {
+        let data = encode(0x0102_0304_0506_0708, &[1, 2, 3]);
+        assert_eq!(&data[..4], &[0x4f, 0x53, 0x00, 0x01]);
+        assert_eq!(decode(&data), Some((0x0102_0304_0506_0708, &[1u8, 2, 3][..])));
+        // Fadecandy color correction is not a timed frame
+        assert_eq!(decode(&[0x00, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+
+        let timed = TimedFrames::new();
+        let now = now_us();
+        assert!(timed.schedule(Source::Opc, 1, now + 40_000, &[3]));
+        assert!(timed.schedule(Source::Opc, 1, now + 20_000, &[2]));
+        assert!(timed.schedule(Source::Opc, 2, now - 1_000, &[1]));
+        assert!(!timed.schedule(Source::Opc, 1, now + MAX_AHEAD.as_micros() as u64 + 1_000_000, &[4]));
+        assert_eq!(timed.queued(), 3);
+
+        let started = Instant::now();
+        let order: Vec<_> = (0..3).map(|_| {
+            let frame = timed.next_due(Duration::from_secs(1)).unwrap();
+            (frame.channel, frame.pixels)
+        }).collect();
+        assert_eq!(order, vec![(2, vec![1]), (1, vec![2]), (1, vec![3])]);
+        assert!(started.elapsed() >= Duration::from_millis(35));
+
+        assert!(timed.next_due(Duration::from_millis(10)).is_none());
+        assert_eq!(timed.stats.late.load(Ordering::Relaxed), 1);
+        assert_eq!(timed.stats.dropped.load(Ordering::Relaxed), 1);
+    }
+}
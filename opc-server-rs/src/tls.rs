@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use std::io::{self, Read};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::TlsConfig;
+
+/// Time `s_server` has to start listening for its connection
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check that the `openssl` command can be run
+pub fn check_openssl() -> Result<()> {
+    let status = Command::new("openssl")
+        .arg("version")
+        .stdout(Stdio::null())
+        .status()
+        .context("opc.tls needs OpenSSL's openssl command on the PATH")?;
+    if !status.success() {
+        anyhow::bail!("openssl version failed ({})", status);
+    }
+    Ok(())
+}
+
+/// One client's TLS connection (`opc.tls`), decrypted
+///
+/// The server links no TLS library. Like the Hue output's DTLS client, encryption is
+/// left to OpenSSL's `openssl` command: each connection gets its own `s_server` on a
+/// loopback port, the client's encrypted bytes are relayed to it, and the OPC data it
+/// decrypts comes back over a loopback socket that the listener reads like a plain
+/// client's.
+pub struct TlsSession {
+    /// OPC data the client sent, in plain text
+    pub stream: TcpStream,
+    child: Child,
+    /// Held open: `s_server` ends the connection when its stdin closes
+    _stdin: ChildStdin,
+    client: TcpStream,
+    relays: Vec<thread::JoinHandle<()>>,
+}
+
+impl TlsSession {
+    /// Start the TLS handshake with an accepted client
+    ///
+    /// Returns once `s_server` is relaying; the handshake itself is done by the time
+    /// the first OPC data can be read from `stream`. A client failing it (without a
+    /// certificate from `client_ca`, say) reads as one that closed the connection, with
+    /// OpenSSL's reason in the log.
+    pub fn accept(client: &TcpStream, config: &TlsConfig) -> Result<Self> {
+        // Accepted sockets inherit nonblocking mode on some platforms
+        client.set_nonblocking(false)?;
+
+        // s_server takes one connection on a free loopback port, then exits
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let mut command = Command::new("openssl");
+        command.args(["s_server", "-quiet", "-naccept", "1", "-cert", &config.cert, "-key", &config.key])
+            .arg("-accept")
+            .arg(format!("127.0.0.1:{}", port));
+        if let Some(ca) = &config.client_ca {
+            command.args(["-Verify", "1", "-verify_return_error", "-CAfile", ca]);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start openssl s_server")?;
+
+        match Self::relay(client, port, &mut child) {
+            Ok((stream, relays)) => {
+                let stdin = child.stdin.take().context("openssl s_server has no stdin")?;
+                Ok(TlsSession { stream, child, _stdin: stdin, client: client.try_clone()?, relays })
+            }
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// Connect `s_server` to the client and to a plain loopback socket for the listener
+    fn relay(client: &TcpStream, port: u16, child: &mut Child) -> Result<(TcpStream, Vec<thread::JoinHandle<()>>)> {
+        let stdout = child.stdout.take().context("openssl s_server has no stdout")?;
+
+        let started = Instant::now();
+        let server = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(server) => break server,
+                Err(e) => {
+                    if let Some(status) = child.try_wait()? {
+                        anyhow::bail!("openssl s_server exited ({})", status);
+                    }
+                    if started.elapsed() > START_TIMEOUT {
+                        return Err(e).context("openssl s_server did not start listening");
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+        };
+
+        // The listener's end of the decrypted data; check it is our own connection
+        let loopback = TcpListener::bind("127.0.0.1:0")?;
+        let relay_end = TcpStream::connect(loopback.local_addr()?)?;
+        let (stream, peer) = loopback.accept()?;
+        if peer != relay_end.local_addr()? {
+            anyhow::bail!("Unexpected connection {} to the TLS relay", peer);
+        }
+
+        // Small writes on loopback must not wait for delayed ACKs
+        server.set_nodelay(true)?;
+        relay_end.set_nodelay(true)?;
+
+        let relays = vec![
+            spawn_copy(client.try_clone()?, server.try_clone()?),
+            spawn_copy(server, client.try_clone()?),
+            spawn_copy(stdout, relay_end),
+        ];
+        Ok((stream, relays))
+    }
+}
+
+impl Drop for TlsSession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        // Wakes the relay still waiting for the client to send
+        let _ = self.client.shutdown(Shutdown::Both);
+        for relay in self.relays.drain(..) {
+            let _ = relay.join();
+        }
+    }
+}
+
+/// Copy `from` to `to` until either side closes, then pass the close on
+fn spawn_copy(mut from: impl Read + Send + 'static, mut to: TcpStream) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let _ = io::copy(&mut from, &mut to);
+        let _ = to.shutdown(Shutdown::Write);
+    })
+}
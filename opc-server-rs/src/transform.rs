@@ -0,0 +1,340 @@
+use anyhow::Result;
+use std::sync::Mutex;
+
+use crate::config::{OutputConfig, Resample, TransformConfig};
+use crate::{pixel_format, resample, segment};
+
+/// Largest pixel index or count a step may name: the pixels in the biggest OPC message
+pub const MAX_PIXELS: usize = u16::MAX as usize / 3;
+
+/// Check the `transforms` list of an output
+pub fn check(transforms: &[TransformConfig]) -> Result<()> {
+    for (i, transform) in transforms.iter().enumerate() {
+        match transform {
+            TransformConfig::Slice { offset, count } if *offset > MAX_PIXELS || count.is_some_and(|count| count > MAX_PIXELS) => {
+                anyhow::bail!("transforms[{}]: slice offset and count must be at most {}", i, MAX_PIXELS);
+            }
+            TransformConfig::Map { pixels } if pixels.is_empty() => {
+                anyhow::bail!("transforms[{}]: map needs at least one pixel", i);
+            }
+            TransformConfig::Map { pixels } if pixels.len() > MAX_PIXELS || pixels.iter().any(|&pixel| pixel >= MAX_PIXELS) => {
+                anyhow::bail!("transforms[{}]: map takes at most {} pixels, numbered below {}", i, MAX_PIXELS, MAX_PIXELS);
+            }
+            TransformConfig::Gamma { gamma } if !(*gamma > 0.0 && gamma.is_finite()) => {
+                anyhow::bail!("transforms[{}]: gamma must be positive", i);
+            }
+            TransformConfig::Brightness { level } if !(0.0..=1.0).contains(level) => {
+                anyhow::bail!("transforms[{}]: brightness level must be between 0.0 and 1.0", i);
+            }
+            TransformConfig::ColorOrder { order } if channel_order(order).is_none() => {
+                anyhow::bail!("transforms[{}]: color_order must be an arrangement of R, G and B, not '{}'", i, order);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Source channel (0 = R, 1 = G, 2 = B) of each channel in `order`, e.g. "GRB"
+fn channel_order(order: &str) -> Option<[usize; 3]> {
+    let mut result = [0; 3];
+    let mut seen = [false; 3];
+    if order.len() != 3 {
+        return None;
+    }
+    for (slot, c) in result.iter_mut().zip(order.chars()) {
+        let channel = "RGB".find(c.to_ascii_uppercase())?;
+        if std::mem::replace(&mut seen[channel], true) {
+            return None;
+        }
+        *slot = channel;
+    }
+    Some(result)
+}
+
+/// One compiled step of a chain
+#[derive(Debug, Clone)]
+enum Step {
+    Slice { offset: usize, count: Option<usize> },
+    Map(Vec<usize>),
+    Reverse,
+    /// Gamma and brightness steps in a row, folded into one table
+    Lut(Box<[u8; 256]>),
+    ColorOrder([usize; 3]),
+    /// The output's `opc_offset` and `wrap`: `count` pixels from `offset` on
+    Window { offset: usize, count: usize, wrap: bool },
+    /// The output's `resample`: `source` pixels from `offset` on, stretched to `count`
+    Resample { offset: usize, source: Option<usize>, count: usize, mode: Resample },
+    /// An RGBW `pixel_format`: 4-byte pixels with the colors in this order
+    White([usize; 3]),
+}
+
+/// How much of the span an output shows a message reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coverage {
+    /// Pixels the message supplied
+    pub supplied: usize,
+    /// Pixels it should have supplied
+    pub span: usize,
+}
+
+/// Steps an output's pixels go through, compiled once when the output opens
+///
+/// `for_output` compiles the output's `transforms` followed by its routing (`opc_offset`
+/// and `wrap`, or `resample`), run when a message arrives; `pixel_format` compiles the
+/// wire format, run by the worker after color correction and brightness. Steps that
+/// rebuild the data reuse the chain's scratch buffer.
+#[derive(Debug)]
+pub struct TransformChain {
+    steps: Vec<Step>,
+    scratch: Mutex<Vec<u8>>,
+}
+
+impl TransformChain {
+    /// A chain of just the `transforms` steps
+    pub fn new(transforms: &[TransformConfig]) -> Self {
+        let mut steps: Vec<Step> = Vec::with_capacity(transforms.len());
+        for transform in transforms {
+            let curve: Box<dyn Fn(f32) -> f32> = match transform {
+                TransformConfig::Slice { offset, count } => {
+                    steps.push(Step::Slice { offset: *offset, count: *count });
+                    continue;
+                }
+                TransformConfig::Map { pixels } => {
+                    steps.push(Step::Map(pixels.clone()));
+                    continue;
+                }
+                TransformConfig::Reverse => {
+                    steps.push(Step::Reverse);
+                    continue;
+                }
+                TransformConfig::ColorOrder { order } => {
+                    steps.push(Step::ColorOrder(channel_order(order).unwrap_or([0, 1, 2])));
+                    continue;
+                }
+                TransformConfig::Gamma { gamma } => {
+                    let gamma = *gamma;
+                    Box::new(move |v| v.powf(gamma))
+                }
+                TransformConfig::Brightness { level } => {
+                    let level = *level;
+                    Box::new(move |v| v * level)
+                }
+            };
+            let apply = |value: u8| (curve(value as f32 / 255.0) * 255.0).round().clamp(0.0, 255.0) as u8;
+            match steps.last_mut() {
+                Some(Step::Lut(lut)) => {
+                    for entry in lut.iter_mut() {
+                        *entry = apply(*entry);
+                    }
+                }
+                _ => {
+                    let mut lut = Box::new([0u8; 256]);
+                    for (i, entry) in lut.iter_mut().enumerate() {
+                        *entry = apply(i as u8);
+                    }
+                    steps.push(Step::Lut(lut));
+                }
+            }
+        }
+        TransformChain { steps, scratch: Mutex::new(Vec::new()) }
+    }
+
+    /// An output's `transforms`, then the step picking its pixels out of the result
+    pub fn for_output(config: &OutputConfig) -> Self {
+        let mut chain = TransformChain::new(config.transforms.as_deref().unwrap_or_default());
+        chain.steps.push(match config.resample {
+            Some(mode) => Step::Resample {
+                offset: config.opc_offset,
+                source: config.source_pixels,
+                count: config.led_count,
+                mode,
+            },
+            None => Step::Window { offset: config.opc_offset, count: config.led_count, wrap: config.wrap },
+        });
+        chain
+    }
+
+    /// The channel order and white channel of a `pixel_format` (none for RGB and unknown formats)
+    pub fn pixel_format(format: Option<&str>) -> Self {
+        let step = match format {
+            Some("GRB") => Some(Step::ColorOrder([1, 0, 2])),
+            Some("BGR") => Some(Step::ColorOrder([2, 1, 0])),
+            Some("RGBW") => Some(Step::White([0, 1, 2])),
+            Some("GRBW") => Some(Step::White([1, 0, 2])),
+            _ => None,
+        };
+        TransformChain { steps: step.into_iter().collect(), scratch: Mutex::new(Vec::new()) }
+    }
+
+    /// Run the chain on RGB pixel data into `out`
+    pub fn apply(&self, data: &[u8], out: &mut Vec<u8>) -> Coverage {
+        out.clear();
+        out.extend_from_slice(&data[..data.len() / 3 * 3]);
+        self.run(out)
+    }
+
+    /// Run the chain on RGB pixel data in place
+    ///
+    /// A `pixel_format` chain leaves a trailing partial pixel untouched, or drops it for RGBW.
+    pub fn run(&self, data: &mut Vec<u8>) -> Coverage {
+        let mut scratch = self.scratch.lock().unwrap();
+        let mut coverage = None;
+        for step in &self.steps {
+            match step {
+                Step::Slice { offset, count } => {
+                    let start = (offset * 3).min(data.len());
+                    let end = count.map_or(data.len(), |count| (start + count * 3).min(data.len()));
+                    data.copy_within(start..end, 0);
+                    data.truncate(end - start);
+                }
+                Step::Map(pixels) => {
+                    // Pixels the data doesn't reach are black
+                    scratch.clear();
+                    for &pixel in pixels {
+                        scratch.extend_from_slice(data.get(pixel * 3..pixel * 3 + 3).unwrap_or(&[0; 3]));
+                    }
+                    std::mem::swap(data, &mut *scratch);
+                }
+                Step::Reverse => {
+                    let count = data.len() / 3;
+                    for i in 0..count / 2 {
+                        for channel in 0..3 {
+                            data.swap(i * 3 + channel, (count - 1 - i) * 3 + channel);
+                        }
+                    }
+                }
+                Step::Lut(lut) => {
+                    for value in data.iter_mut() {
+                        *value = lut[*value as usize];
+                    }
+                }
+                Step::ColorOrder(order) => {
+                    for pixel in data.chunks_exact_mut(3) {
+                        let rgb = [pixel[0], pixel[1], pixel[2]];
+                        for (value, &channel) in pixel.iter_mut().zip(order) {
+                            *value = rgb[channel];
+                        }
+                    }
+                }
+                Step::Window { offset, count, wrap } => {
+                    scratch.clear();
+                    scratch.resize(count * 3, 0);
+                    let copied = segment::copy_pixels(&mut scratch, data, *offset, *wrap);
+                    scratch.truncate(copied * 3);
+                    std::mem::swap(data, &mut *scratch);
+                    coverage = Some(Coverage { supplied: copied, span: *count });
+                }
+                Step::Resample { offset, source, count, mode } => {
+                    let pixels = source.unwrap_or_else(|| (data.len() / 3).saturating_sub(*offset));
+                    scratch.clear();
+                    scratch.resize(pixels * 3, 0);
+                    let available = segment::copy_pixels(&mut scratch, data, *offset, false);
+                    // Source pixels missing from a short message are stretched as black
+                    data.clear();
+                    if available > 0 {
+                        data.resize(count * 3, 0);
+                        resample::resample(data, &scratch, *mode);
+                    }
+                    coverage = Some(Coverage { supplied: available, span: pixels.max(1) });
+                }
+                Step::White(order) => {
+                    pixel_format::extract_white(data, *order, &mut scratch);
+                    std::mem::swap(data, &mut *scratch);
+                }
+            }
+        }
+        let pixels = data.len() / 3;
+        coverage.unwrap_or(Coverage { supplied: pixels, span: pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_runs_steps_in_order() {
+        let transforms: Vec<TransformConfig> = serde_json::from_str(r#"[
+            {"type": "slice", "offset": 1, "count": 3},
+            {"type": "reverse"},
+            {"type": "brightness", "level": 0.5},
+            {"type": "gamma", "gamma": 1.0},
+            {"type": "color_order", "order": "BGR"},
+            {"type": "map", "pixels": [2, 0, 9]}
+        ]"#).unwrap();
+        check(&transforms).unwrap();
+        let chain = TransformChain::new(&transforms);
+        // Brightness and gamma share one table
+        assert_eq!(chain.steps.len(), 5);
+
+        let data = [0, 0, 0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 255, 255, 255];
+        let mut out = Vec::new();
+        chain.apply(&data, &mut out);
+        // Pixels 1-3 reversed, halved, as BGR, then the first sliced pixel, the last and black
+        assert_eq!(out, vec![15, 10, 5, 45, 40, 35, 0, 0, 0]);
+
+        let bad: Vec<TransformConfig> = serde_json::from_str(r#"[{"type": "color_order", "order": "RRB"}]"#).unwrap();
+        assert!(check(&bad).is_err());
+    }
+
+    #[test]
+    fn test_check_bounds_pixel_indices() {
+        for bad in [
+            format!(r#"[{{"type": "slice", "offset": {}}}]"#, usize::MAX),
+            format!(r#"[{{"type": "slice", "count": {}}}]"#, MAX_PIXELS + 1),
+            format!(r#"[{{"type": "map", "pixels": [0, {}]}}]"#, usize::MAX / 2),
+        ] {
+            let transforms: Vec<TransformConfig> = serde_json::from_str(&bad).unwrap();
+            assert!(check(&transforms).is_err(), "{}", bad);
+        }
+        let largest: Vec<TransformConfig> = serde_json::from_str(&format!(
+            r#"[{{"type": "slice", "offset": {0}, "count": {0}}}, {{"type": "map", "pixels": [{1}]}}]"#,
+            MAX_PIXELS, MAX_PIXELS - 1)).unwrap();
+        check(&largest).unwrap();
+    }
+
+    #[test]
+    fn test_output_fields_compile_into_the_chain() {
+        let data: Vec<u8> = (0..12).collect();
+        let mut out = Vec::new();
+
+        // opc_offset and wrap, after the transforms
+        let config = OutputConfig {
+            led_count: 3,
+            opc_offset: 1,
+            wrap: true,
+            transforms: Some(vec![TransformConfig::Reverse]),
+            ..OutputConfig::test_default("/dev/null")
+        };
+        let chain = TransformChain::for_output(&config);
+        assert_eq!(chain.apply(&data, &mut out), Coverage { supplied: 3, span: 3 });
+        assert_eq!(out, vec![6, 7, 8, 3, 4, 5, 0, 1, 2]);
+        // Without wrap a short message covers less than the span
+        let chain = TransformChain::for_output(&OutputConfig { led_count: 5, wrap: false, ..config.clone() });
+        assert_eq!(chain.apply(&data, &mut out), Coverage { supplied: 3, span: 5 });
+        assert_eq!(out, vec![6, 7, 8, 3, 4, 5, 0, 1, 2]);
+
+        // resample stretches the source pixels over the strip
+        let config = OutputConfig {
+            led_count: 4,
+            resample: Some(Resample::Nearest),
+            source_pixels: Some(3),
+            ..OutputConfig::test_default("/dev/null")
+        };
+        let chain = TransformChain::for_output(&config);
+        assert_eq!(chain.apply(&data[..6], &mut out), Coverage { supplied: 2, span: 3 });
+        assert_eq!(out.len(), 12);
+        assert_eq!(chain.apply(&[], &mut out), Coverage { supplied: 0, span: 3 });
+        assert!(out.is_empty());
+
+        // pixel_format, run in place
+        let mut pixels = vec![10, 20, 30, 40];
+        TransformChain::pixel_format(Some("BGR")).run(&mut pixels);
+        assert_eq!(pixels, vec![30, 20, 10, 40]);
+        TransformChain::pixel_format(Some("GRBW")).run(&mut pixels);
+        assert_eq!(pixels, vec![10, 20, 0, 10]);
+        TransformChain::pixel_format(Some("RGB")).run(&mut pixels);
+        assert_eq!(pixels, vec![10, 20, 0, 10]);
+    }
+}
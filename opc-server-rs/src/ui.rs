@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, LoadOptions};
+use crate::config_format::Format;
+
+/// Configuration page served at `/ui` (when `control.ui` is enabled)
+pub const PAGE: &str = include_str!("ui.html");
+
+/// The config file as written, before strips are expanded or overrides applied
+pub fn read_config(path: &str) -> Result<Value> {
+    if path.is_empty() {
+        anyhow::bail!("The server was not started from a config file");
+    }
+    let text = fs::read_to_string(path)
+        .context(format!("Failed to read config file {}", path))?;
+    Format::from_path(path).parse(&text)
+        .context(format!("Failed to parse config file {}", path))
+}
+
+/// Replace the config file with `config`, keeping the previous one as `<path>.bak`
+///
+/// The new config is checked the same way the server loads it, and the file is only
+/// replaced if it passes. Only JSON config files can be written; TOML and YAML files
+/// would lose their comments and layout.
+pub fn save_config(path: &str, config: &Value, options: &LoadOptions) -> Result<()> {
+    if path.is_empty() {
+        anyhow::bail!("The server was not started from a config file");
+    }
+    if !matches!(Format::from_path(path), Format::Json) {
+        anyhow::bail!("Config file {} is not JSON; only JSON config files can be saved from the UI", path);
+    }
+
+    let text = serde_json::to_string_pretty(config)? + "\n";
+    let temp = format!("{}.new", path);
+    fs::write(&temp, &text).context(format!("Failed to write {}", temp))?;
+    if let Err(e) = Config::load_with(&temp, options) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    if Path::new(path).exists() {
+        let backup = format!("{}.bak", path);
+        fs::copy(path, &backup).context(format!("Failed to back up config file to {}", backup))?;
+    }
+    fs::rename(&temp, path).context(format!("Failed to replace config file {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::config::OutputConfig;
+
+    #[test]
+    fn test_save_config() {
+        let dir = std::env::temp_dir().join(format!("opc_server_ui_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let path = path.to_str().unwrap();
+        let output = json!(OutputConfig::test_default("/dev/null"));
+        fs::write(path, "{}").unwrap();
+
+        let config = json!({"opc": {"host": "127.0.0.1", "port": 7890}, "outputs": [output]});
+        save_config(path, &config, &LoadOptions::default()).unwrap();
+        assert_eq!(read_config(path).unwrap(), config);
+        assert_eq!(fs::read_to_string(format!("{}.bak", path)).unwrap(), "{}");
+
+        // An invalid config leaves the file alone
+        let mut invalid = config.clone();
+        invalid["outputs"][0]["led_count"] = json!("many");
+        assert!(save_config(path, &invalid, &LoadOptions::default()).is_err());
+        assert_eq!(read_config(path).unwrap(), config);
+        assert!(!Path::new(&format!("{}.new", path)).exists());
+
+        assert!(save_config(dir.join("config.yaml").to_str().unwrap(), &config, &LoadOptions::default()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use crate::http::Request;
+
+/// GUID appended to the client key when computing Sec-WebSocket-Accept (RFC 6455)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message accepted from a client
+const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024; // 4MB
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// A complete message received from a WebSocket client
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+/// Server side of a WebSocket connection
+pub struct WebSocket {
+    stream: TcpStream,
+    /// Raw bytes received but not yet parsed into frames
+    buffer: Vec<u8>,
+    /// Payload and opcode of a fragmented message in progress
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+/// Check whether an HTTP request asks for a WebSocket upgrade
+pub fn is_upgrade_request(request: &Request) -> bool {
+    request.header("upgrade")
+        .map(|v| v.eq_ignore_ascii_case("websocket"))
+        .unwrap_or(false)
+}
+
+impl WebSocket {
+    /// Complete the opening handshake for an upgrade request
+    pub fn accept(mut stream: TcpStream, request: &Request) -> Result<Self> {
+        let key = request.header("sec-websocket-key")
+            .context("Missing Sec-WebSocket-Key header")?;
+
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+
+        Ok(WebSocket {
+            stream,
+            buffer: Vec::new(),
+            fragments: None,
+        })
+    }
+
+    /// Read the next complete message
+    ///
+    /// Returns `Ok(None)` if the socket read timed out before a full message arrived,
+    /// so callers can poll a running flag between reads.
+    pub fn read_message(&mut self) -> Result<Option<Message>> {
+        loop {
+            if let Some(message) = self.parse_buffered()? {
+                return Ok(Some(message));
+            }
+
+            let mut read_buf = [0u8; 8192];
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => return Ok(Some(Message::Close)),
+                Ok(n) => self.buffer.extend_from_slice(&read_buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    return Ok(None);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Send a text message
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /// Send a binary message
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.send_frame(OPCODE_BINARY, data)
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode); // FIN + opcode
+
+        // Server-to-client frames are never masked
+        match payload.len() {
+            n if n < 126 => frame.push(n as u8),
+            n if n <= 0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(127);
+                frame.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Parse complete frames from the buffer, returning the first complete message
+    fn parse_buffered(&mut self) -> Result<Option<Message>> {
+        while let Some((fin, opcode, payload)) = self.parse_frame()? {
+            match opcode {
+                OPCODE_PING => self.send_frame(OPCODE_PONG, &payload)?,
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    let _ = self.send_frame(OPCODE_CLOSE, &[]);
+                    return Ok(Some(Message::Close));
+                }
+                OPCODE_CONTINUATION => {
+                    let (first_opcode, mut data) = self.fragments.take()
+                        .context("Continuation frame without a message in progress")?;
+                    data.extend_from_slice(&payload);
+                    if data.len() > MAX_MESSAGE_SIZE {
+                        anyhow::bail!("WebSocket message too large");
+                    }
+                    if fin {
+                        return to_message(first_opcode, data).map(Some);
+                    }
+                    self.fragments = Some((first_opcode, data));
+                }
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if fin {
+                        return to_message(opcode, payload).map(Some);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                other => anyhow::bail!("Unsupported WebSocket opcode 0x{:x}", other),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Remove one complete frame from the buffer, if available
+    fn parse_frame(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>> {
+        let buf = &self.buffer;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let fin = buf[0] & 0x80 != 0;
+        let opcode = buf[0] & 0x0F;
+        let masked = buf[1] & 0x80 != 0;
+
+        let (length, mut pos) = match buf[1] & 0x7F {
+            126 => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                (u16::from_be_bytes([buf[2], buf[3]]) as usize, 4)
+            }
+            127 => {
+                if buf.len() < 10 {
+                    return Ok(None);
+                }
+                let mut len_bytes = [0u8; 8];
+                len_bytes.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(len_bytes) as usize, 10)
+            }
+            n => (n as usize, 2),
+        };
+
+        if length > MAX_MESSAGE_SIZE {
+            anyhow::bail!("WebSocket frame too large ({} bytes)", length);
+        }
+
+        let mask = if masked {
+            if buf.len() < pos + 4 {
+                return Ok(None);
+            }
+            let key = [buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]];
+            pos += 4;
+            Some(key)
+        } else {
+            None
+        };
+
+        if buf.len() < pos + length {
+            return Ok(None);
+        }
+
+        let mut payload: Vec<u8> = self.buffer.drain(..pos + length).skip(pos).collect();
+        if let Some(key) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= key[i % 4];
+            }
+        }
+
+        Ok(Some((fin, opcode, payload)))
+    }
+}
+
+fn to_message(opcode: u8, payload: Vec<u8>) -> Result<Message> {
+    if opcode == OPCODE_TEXT {
+        Ok(Message::Text(String::from_utf8(payload).context("Invalid UTF-8 in text message")?))
+    } else {
+        Ok(Message::Binary(payload))
+    }
+}
+
+/// Compute the Sec-WebSocket-Accept value for a client key
+fn accept_key(key: &str) -> String {
+    let mut input = key.trim().as_bytes().to_vec();
+    input.extend_from_slice(HANDSHAKE_GUID.as_bytes());
+    base64_encode(&sha1(&input))
+}
+
+/// SHA-1 digest (only used for the WebSocket handshake)
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard base64 encoding with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        out.push(ALPHABET[(n >> 18) as usize & 0x3F] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 0x3F] as char } else { '=' });
+        out.push(if chunk.len() > 2 { ALPHABET[n as usize & 0x3F] as char } else { '=' });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha1_known_vector() {
+        let digest = sha1(b"abc");
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn test_base64_padding() {
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn test_rfc6455_accept_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+}
@@ -0,0 +1,494 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use serialport::SerialPort;
+
+use crate::autobaud;
+use crate::config::OutputConfig;
+use crate::output::Output;
+use crate::protocol::{build_awa_frame_into, Protocol};
+use crate::serial;
+
+/// All supported WLED baud rates in priority order
+pub const WLED_BAUD_RATES: &[u32] = &[
+    115200,   // Default WLED speed
+    230400,
+    460800,
+    500000,
+    576000,
+    921600,
+    1000000,
+    1500000,
+    2000000,
+];
+
+/// How long to wait for a complete JSON reply to the version query
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// Device information reported by WLED in reply to `{"v":true}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WledInfo {
+    pub version: Option<String>,
+    pub name: Option<String>,
+    pub led_count: Option<usize>,
+    /// Device state before streaming started, restored when live mode is released
+    pub was_on: Option<bool>,
+    pub brightness: Option<u8>,
+    pub preset: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct WledResponse {
+    state: Option<WledResponseState>,
+    info: Option<WledResponseInfo>,
+}
+
+#[derive(Deserialize)]
+struct WledResponseState {
+    on: Option<bool>,
+    bri: Option<u8>,
+    ps: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct WledResponseInfo {
+    ver: Option<String>,
+    name: Option<String>,
+    leds: Option<WledResponseLeds>,
+}
+
+#[derive(Deserialize)]
+struct WledResponseLeds {
+    count: Option<usize>,
+}
+
+impl WledInfo {
+    /// Parse the JSON state/info reply from a WLED device
+    pub fn parse(response: &str) -> Result<Self> {
+        // Devices may emit debug text before the JSON object
+        let start = response.find('{').context("No JSON object in response")?;
+        let parsed: WledResponse = serde_json::from_str(response[start..].trim_end())
+            .context("Invalid JSON in WLED response")?;
+
+        let info = parsed.info.context("WLED response has no \"info\" section")?;
+
+        let state = parsed.state;
+
+        Ok(WledInfo {
+            version: info.ver,
+            name: info.name,
+            led_count: info.leds.and_then(|l| l.count),
+            was_on: state.as_ref().and_then(|s| s.on),
+            brightness: state.as_ref().and_then(|s| s.bri),
+            preset: state.as_ref().and_then(|s| s.ps),
+        })
+    }
+
+    /// Warn if the configured LED count doesn't match what the device reports
+    pub fn check_led_count(&self, config: &OutputConfig) {
+        if let Some(device_count) = self.led_count {
+            if device_count != config.led_count {
+                eprintln!("Warning: {} is configured for {} LEDs but WLED device reports {}",
+                         config.label(), config.led_count, device_count);
+            }
+        }
+    }
+}
+
+/// Explicit WLED realtime ("live") mode management for a streaming output
+///
+/// Live mode is entered with `{"live":true}` before the first frame is streamed, and on
+/// release the device leaves live mode and gets its previous on/brightness/preset state back.
+pub struct LiveMode {
+    info: WledInfo,
+    live: bool,
+}
+
+impl LiveMode {
+    pub fn new(info: WledInfo) -> Self {
+        LiveMode { info, live: false }
+    }
+
+    /// Enter live mode if not already streaming
+    pub fn ensure_live(&mut self, port: &mut dyn SerialPort) -> std::io::Result<()> {
+        if !self.live {
+            send_json(port, &serde_json::json!({"live": true}))?;
+            self.live = true;
+        }
+        Ok(())
+    }
+
+    /// Leave live mode, either restoring the state the device had before streaming
+    /// or turning it off
+    pub fn release(&mut self, port: &mut dyn SerialPort, restore: bool) -> std::io::Result<()> {
+        if !self.live {
+            return Ok(());
+        }
+
+        let command = if restore {
+            self.release_command()
+        } else {
+            serde_json::json!({"live": false, "on": false})
+        };
+        send_json(port, &command)?;
+        self.live = false;
+        Ok(())
+    }
+
+    fn release_command(&self) -> serde_json::Value {
+        let mut command = serde_json::json!({"live": false});
+        if let Some(on) = self.info.was_on {
+            command["on"] = on.into();
+        }
+        if let Some(bri) = self.info.brightness {
+            command["bri"] = bri.into();
+        }
+        // WLED reports -1 (or 0) when no preset is active
+        if let Some(preset) = self.info.preset.filter(|&p| p > 0) {
+            command["ps"] = preset.into();
+        }
+        command
+    }
+}
+
+/// WLED over serial: AWA frames, with baud detection on connect and live mode while streaming
+pub struct WledProtocol {
+    /// Set once connected
+    live_mode: Option<LiveMode>,
+}
+
+impl WledProtocol {
+    pub fn new() -> Self {
+        WledProtocol { live_mode: None }
+    }
+}
+
+impl Default for WledProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Protocol for WledProtocol {
+    fn build_frame_into(&self, pixels: &[u8], stride: usize, frame: &mut Vec<u8>) {
+        build_awa_frame_into(pixels, stride, frame);
+    }
+
+    fn connect(&mut self, config: &OutputConfig, debug: bool, ddebug: bool) -> Result<Box<dyn SerialPort>> {
+        let (port, info) = connect(config, debug, ddebug)?;
+        self.live_mode = Some(LiveMode::new(info));
+        Ok(port)
+    }
+
+    fn prepare(&mut self, port: &mut dyn SerialPort) -> std::io::Result<()> {
+        match self.live_mode.as_mut() {
+            Some(live_mode) => live_mode.ensure_live(port),
+            None => Ok(()),
+        }
+    }
+
+    fn teardown(&mut self, port: &mut dyn SerialPort, restore: bool) -> std::io::Result<()> {
+        match self.live_mode.as_mut() {
+            Some(live_mode) => live_mode.release(port, restore),
+            None => Ok(()),
+        }
+    }
+
+    fn restores_device_state(&self) -> bool {
+        true
+    }
+}
+
+/// Send a single JSON API command over serial
+fn send_json(port: &mut dyn SerialPort, command: &serde_json::Value) -> std::io::Result<()> {
+    let mut line = command.to_string().into_bytes();
+    line.push(b'\n');
+    port.write_all(&line)?;
+    port.flush()
+}
+
+/// Detect a WLED device, switch it to the configured baud rate and open the port for LED data
+///
+/// Used both on startup and when reconnecting to a device.
+pub fn connect(config: &OutputConfig, debug: bool, ddebug: bool) -> Result<(Box<dyn SerialPort>, WledInfo)> {
+    if debug {
+        println!("Detecting WLED device on {}...", config.label());
+    }
+
+    let (detected_baud, info) = detect(config, debug, ddebug)?;
+
+    if debug {
+        println!("✓ WLED {} (version {}, {} LEDs) on {}",
+                 info.name.as_deref().unwrap_or("device"),
+                 info.version.as_deref().unwrap_or("unknown"),
+                 info.led_count.map(|c| c.to_string()).unwrap_or_else(|| "?".to_string()),
+                 config.port);
+    }
+
+    info.check_led_count(config);
+
+    let port = if detected_baud != config.baud_rate {
+        switch_baud(config, detected_baud, debug, ddebug)?
+    } else {
+        // Already at correct baud, just open normally
+        Output::open_standard_port(config)?
+    };
+
+    Ok((port, info))
+}
+
+/// Probe baud rates until the device answers the version query
+fn detect(config: &OutputConfig, debug: bool, ddebug: bool) -> Result<(u32, WledInfo)> {
+    let baud_rates_to_try = config.baud_candidates(WLED_BAUD_RATES);
+
+    let (baud, info) = autobaud::scan(&config.label(), &baud_rates_to_try, ddebug, |baud| handshake(&config.port, baud, ddebug))
+        .context(format!("Failed to detect WLED device on {}", config.port))?;
+    if debug {
+        println!("✓ WLED device detected at {} baud on {}", baud, config.label());
+    }
+    Ok((baud, info))
+}
+
+/// Query a WLED device at a specific baud rate and parse its info reply
+pub fn handshake(port_name: &str, baud: u32, ddebug: bool) -> Result<WledInfo> {
+    let mut port = open_at(port_name, baud, Duration::from_millis(100))
+        .context("Failed to open port")?;
+
+    // Set DTR/RTS
+    if let Err(e) = serial::raise_control_lines(port.as_mut()) {
+        if ddebug {
+            eprintln!("Warning: Failed to set DTR/RTS: {}", e);
+        }
+    }
+
+    // Give device time to initialize
+    thread::sleep(Duration::from_millis(150));
+
+    // Clear any pending data aggressively
+    port.clear(serialport::ClearBuffer::All).ok();
+    thread::sleep(Duration::from_millis(50));
+
+    // Send WLED version query
+    let query = b"{\"v\":true}\n";
+    port.write_all(query).context("Failed to write query")?;
+    port.flush().context("Failed to flush")?;
+
+    let result = read_json_reply(port.as_mut())
+        .and_then(|response| {
+            if ddebug {
+                eprintln!("[DEBUG {}] WLED response: {}", port_name, response);
+            }
+            WledInfo::parse(&response)
+        });
+
+    // Close and let the device settle before the port is reopened
+    drop(port);
+    thread::sleep(Duration::from_millis(if result.is_ok() { 100 } else { 200 }));
+
+    result
+}
+
+/// Read from the port until a complete JSON object has arrived or the timeout expires
+fn read_json_reply(port: &mut dyn SerialPort) -> Result<String> {
+    let deadline = Instant::now() + RESPONSE_TIMEOUT;
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while Instant::now() < deadline {
+        match port.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(n) => {
+                response.extend_from_slice(&buffer[..n]);
+                if is_complete_json(&response) {
+                    return Ok(String::from_utf8_lossy(&response).into_owned());
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e).context("Failed to read response"),
+        }
+    }
+
+    if response.is_empty() {
+        anyhow::bail!("No response received");
+    }
+    anyhow::bail!("Incomplete response: {}", String::from_utf8_lossy(&response))
+}
+
+/// Check whether the buffer contains a balanced top-level JSON object
+fn is_complete_json(data: &[u8]) -> bool {
+    let mut depth = 0i32;
+    let mut started = false;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &b in data {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' if started => in_string = true,
+            b'{' => {
+                depth += 1;
+                started = true;
+            }
+            b'}' if started => {
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Command the device to change speed, then reopen the port at the configured baud rate
+fn switch_baud(config: &OutputConfig, detected_baud: u32, debug: bool, ddebug: bool) -> Result<Box<dyn SerialPort>> {
+    if debug {
+        println!("Switching {} from {} to {} baud...", config.label(), detected_baud, config.baud_rate);
+    }
+
+    let mut port = open_at(&config.port, detected_baud, Duration::from_millis(500))
+        .context(format!("Failed to reopen {} at detected baud", config.label()))?;
+
+    // Set DTR/RTS
+    if let Err(e) = serial::raise_control_lines(port.as_mut()) {
+        eprintln!("Warning: Failed to set DTR/RTS on {}: {}", config.label(), e);
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    // Send baud change command based on target rate
+    let baud_byte = baud_change_byte(config.baud_rate)
+        .context(format!("Unsupported WLED baud rate: {}", config.baud_rate))?;
+
+    port.write_all(&[baud_byte])
+        .context("Failed to send baud change command")?;
+    port.flush().context("Failed to flush baud change command")?;
+
+    // Wait for confirmation
+    thread::sleep(Duration::from_millis(200));
+
+    // Try to read confirmation (optional, may not always work)
+    let mut buf = vec![0u8; 100];
+    if let Ok(n) = port.read(&mut buf) {
+        if ddebug {
+            let response = String::from_utf8_lossy(&buf[..n]);
+            eprintln!("[DEBUG {}] Baud change response: {}", config.label(), response);
+        }
+    }
+
+    // Close and reopen at new baud rate
+    drop(port);
+    thread::sleep(Duration::from_millis(100));
+
+    let mut port = open_at(&config.port, config.baud_rate, Duration::from_millis(1000))
+        .context(format!("Failed to reopen {} at new baud", config.label()))?;
+
+    if let Err(e) = serial::raise_control_lines(port.as_mut()) {
+        eprintln!("Warning: Failed to set DTR/RTS on {}: {}", config.label(), e);
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    if debug {
+        println!("✓ WLED device on {} now running at {} baud", config.label(), config.baud_rate);
+    }
+
+    Ok(port)
+}
+
+/// Open a port 8N1 with no flow control
+fn open_at(port_name: &str, baud: u32, timeout: Duration) -> serialport::Result<Box<dyn SerialPort>> {
+    serialport::new(serial::resolve(port_name)?, baud)
+        .data_bits(serialport::DataBits::Eight)
+        .parity(serialport::Parity::None)
+        .stop_bits(serialport::StopBits::One)
+        .flow_control(serialport::FlowControl::None)
+        .timeout(timeout)
+        .open()
+}
+
+/// Get the baud change byte for a given baud rate
+fn baud_change_byte(baud: u32) -> Option<u8> {
+    match baud {
+        115200 => Some(0xB0),
+        230400 => Some(0xB1),
+        460800 => Some(0xB2),
+        500000 => Some(0xB3),
+        576000 => Some(0xB4),
+        921600 => Some(0xB5),
+        1000000 => Some(0xB6),
+        1500000 => Some(0xB7),
+        2000000 => Some(0xB8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info_response() {
+        let response = r#"{"state":{"on":true,"bri":128},"info":{"ver":"0.14.0","leds":{"count":144,"rgbw":false},"name":"Desk"}}"#;
+        let info = WledInfo::parse(response).unwrap();
+        assert_eq!(info.version.as_deref(), Some("0.14.0"));
+        assert_eq!(info.name.as_deref(), Some("Desk"));
+        assert_eq!(info.led_count, Some(144));
+    }
+
+    #[test]
+    fn test_parse_previous_state() {
+        let response = r#"{"state":{"on":true,"bri":77,"ps":3},"info":{"ver":"0.14.0"}}"#;
+        let info = WledInfo::parse(response).unwrap();
+        assert_eq!(info.was_on, Some(true));
+        assert_eq!(info.brightness, Some(77));
+        assert_eq!(info.preset, Some(3));
+    }
+
+    #[test]
+    fn test_release_restores_previous_state() {
+        let info = WledInfo {
+            was_on: Some(true),
+            brightness: Some(77),
+            preset: Some(3),
+            ..Default::default()
+        };
+        let command = LiveMode::new(info).release_command();
+        assert_eq!(command, serde_json::json!({"live": false, "on": true, "bri": 77, "ps": 3}));
+
+        let no_preset = WledInfo { preset: Some(-1), ..Default::default() };
+        assert_eq!(LiveMode::new(no_preset).release_command(), serde_json::json!({"live": false}));
+    }
+
+    #[test]
+    fn test_parse_skips_leading_noise() {
+        let response = "boot\r\n{\"info\":{\"ver\":\"0.13.3\"}}\n";
+        let info = WledInfo::parse(response).unwrap();
+        assert_eq!(info.version.as_deref(), Some("0.13.3"));
+        assert_eq!(info.led_count, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_non_json() {
+        assert!(WledInfo::parse("ver 0.14").is_err());
+        assert!(WledInfo::parse("{\"state\":{}}").is_err());
+    }
+
+    #[test]
+    fn test_complete_json_detection() {
+        assert!(!is_complete_json(b"{\"info\":{\"ver\":\"0.1"));
+        assert!(!is_complete_json(b"{\"name\":\"a}b\""));
+        assert!(is_complete_json(b"{\"name\":\"a}b\"}"));
+        assert!(is_complete_json(b"noise {\"a\":{}}"));
+    }
+}
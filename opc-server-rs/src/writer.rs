@@ -0,0 +1,227 @@
+use serialport::SerialPort;
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+use crate::null_serial::delegate_serial_port;
+use crate::output::{self, OutputState};
+
+/// Frame handed from the worker to the writer thread
+#[derive(Default)]
+struct Handoff {
+    /// Finished frame waiting to be written, with the arrival of the OPC message behind
+    /// it if the write goes into the output's stats
+    frame: Option<(Vec<u8>, Option<Instant>)>,
+    /// Set while the writer thread is writing a frame
+    busy: bool,
+    /// Error of the last write, reported by the worker's next write or flush
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+struct Shared {
+    port: Mutex<Box<dyn SerialPort>>,
+    handoff: Mutex<Handoff>,
+    changed: Condvar,
+    /// Arrival of the frame being written to the port, set through `FrameStamp`
+    received: Mutex<Option<Instant>>,
+    state: Arc<OutputState>,
+}
+
+impl Shared {
+    /// Wait until the writer thread has written everything handed to it
+    fn idle(&self) -> MutexGuard<'_, Handoff> {
+        let mut handoff = self.handoff.lock().unwrap();
+        while handoff.frame.is_some() || handoff.busy {
+            handoff = self.changed.wait(handoff).unwrap();
+        }
+        handoff
+    }
+
+    /// Like `idle`, reporting the error of a frame written in the meantime
+    fn wait_idle(&self) -> io::Result<MutexGuard<'_, Handoff>> {
+        let mut handoff = self.idle();
+        match handoff.error.take() {
+            Some(e) => Err(e),
+            None => Ok(handoff),
+        }
+    }
+}
+
+/// Serial port wrapper that writes each flushed frame from a thread of its own (`writer_thread`)
+///
+/// The output's worker keeps doing the pixel work and framing; the flush that ends a
+/// frame hands it over and returns, so the next frame is prepared while this one is on
+/// the wire, and transform time does not show up as gaps between frames. The flush
+/// waits only while the previous frame is still being written. Frames marked with the
+/// `FrameStamp` have their write and latency recorded in the output's stats by the
+/// writer thread, once they are on the wire. A failed write is reported by the next
+/// write or flush. Reads and settings wait for the writer first, so acknowledgements
+/// are read after the frame they answer.
+pub struct ThreadedPort {
+    shared: Arc<Shared>,
+    /// Bytes written since the last flush
+    pending: Vec<u8>,
+    writer_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ThreadedPort {
+    pub fn new(inner: Box<dyn SerialPort>, state: Arc<OutputState>) -> Self {
+        let shared = Arc::new(Shared {
+            port: Mutex::new(inner),
+            handoff: Mutex::new(Handoff::default()),
+            changed: Condvar::new(),
+            received: Mutex::new(None),
+            state,
+        });
+        let writer_shared = Arc::clone(&shared);
+        let writer_handle = thread::spawn(move || write_frames(&writer_shared));
+        ThreadedPort {
+            shared,
+            pending: Vec::new(),
+            writer_handle: Some(writer_handle),
+        }
+    }
+
+    /// Handle for the worker to mark its frames, which stays usable once the port is boxed
+    pub fn stamp(&self) -> FrameStamp {
+        FrameStamp { shared: Arc::clone(&self.shared) }
+    }
+
+    /// The wrapped port, once the writer thread is done with it
+    /// (errors of frames still in flight surface on the next write or flush instead)
+    fn port(&self) -> MutexGuard<'_, Box<dyn SerialPort>> {
+        drop(self.shared.idle());
+        self.shared.port.lock().unwrap()
+    }
+}
+
+/// Marks the frame about to be flushed to a `ThreadedPort` as an output frame
+#[derive(Clone)]
+pub struct FrameStamp {
+    shared: Arc<Shared>,
+}
+
+impl FrameStamp {
+    /// The next flushed frame is for the OPC message that arrived at `received`; its
+    /// write time and latency are recorded when it has been written
+    pub fn received(&self, received: Instant) {
+        *self.shared.received.lock().unwrap() = Some(received);
+    }
+}
+
+fn write_frames(shared: &Shared) {
+    let mut handoff = shared.handoff.lock().unwrap();
+    loop {
+        let Some((frame, received)) = handoff.frame.take() else {
+            if handoff.closed {
+                return;
+            }
+            handoff = shared.changed.wait(handoff).unwrap();
+            continue;
+        };
+        handoff.busy = true;
+        drop(handoff);
+
+        let mut port = shared.port.lock().unwrap();
+        let write_started = Instant::now();
+        let result = port.write_all(&frame).and_then(|_| port.flush());
+        drop(port);
+        if let (Ok(_), Some(received)) = (&result, received) {
+            let flushed = Instant::now();
+            output::record_write(&shared.state, frame.len(), flushed - write_started, flushed - received);
+        }
+
+        handoff = shared.handoff.lock().unwrap();
+        handoff.busy = false;
+        if let Err(e) = result {
+            handoff.error.get_or_insert(e);
+        }
+        shared.changed.notify_all();
+    }
+}
+
+impl Drop for ThreadedPort {
+    fn drop(&mut self) {
+        // The writer finishes the frame it was handed (e.g. the final blank) first
+        self.shared.handoff.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(handle) = self.writer_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Write for ThreadedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(e) = self.shared.handoff.lock().unwrap().error.take() {
+            return Err(e);
+        }
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut handoff = self.shared.wait_idle()?;
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let received = self.shared.received.lock().unwrap().take();
+        handoff.frame = Some((std::mem::take(&mut self.pending), received));
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl io::Read for ThreadedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port().read(buf)
+    }
+}
+
+delegate_serial_port!(ThreadedPort, port());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use crate::config::OutputConfig;
+    use crate::mock_serial::{self, MockPort};
+
+    #[test]
+    fn test_flush_returns_while_frame_is_written() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "port": "mock-threaded", "hardware_type": "mock", "protocol": "awa",
+            "baud_rate": 9600, "opc_channel": 0, "led_count": 30,
+        })).unwrap();
+        let state = Arc::new(OutputState::new(255));
+        let mut port = ThreadedPort::new(Box::new(MockPort::new(&config)), Arc::clone(&state));
+        let stamp = port.stamp();
+
+        // 96 bytes at 9600 baud take about 100ms on the wire
+        let started = Instant::now();
+        stamp.received(started);
+        port.write_all(&[1; 96]).unwrap();
+        port.flush().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert_eq!(state.frames_sent.load(Ordering::Relaxed), 0);
+
+        // The next flush waits for the first frame, then hands over the second
+        port.write_all(&[2; 96]).unwrap();
+        port.flush().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(90));
+
+        drop(port);
+        assert_eq!(mock_serial::frames("mock-threaded"), vec![vec![1; 96], vec![2; 96]]);
+
+        // Only the stamped frame is counted, with the time it took on the wire
+        assert_eq!(state.frames_sent.load(Ordering::Relaxed), 1);
+        assert_eq!(state.bytes_sent.load(Ordering::Relaxed), 96);
+        let write = state.write_latency.lock().unwrap().take_summary().unwrap();
+        assert!(write.count == 1 && write.max >= Duration::from_millis(90));
+        assert!(state.frame_latency.lock().unwrap().take_summary().unwrap().max >= write.max);
+    }
+}
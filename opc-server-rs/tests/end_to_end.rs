@@ -0,0 +1,303 @@
+//! End-to-end tests: OPC over TCP in, exact serial bytes out
+//!
+//! Each test starts an `OpcServer` on a free local port with `hardware_type: "mock"`
+//! outputs, which record every frame in memory instead of opening a serial port.
+
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use opc_server::config::Config;
+use opc_server::mock_serial;
+use opc_server::opc_server::OpcServer;
+use opc_server::timed;
+
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running server and the address its OPC clients connect to
+struct TestServer {
+    addr: String,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    handle: Option<thread::JoinHandle<OpcServer>>,
+}
+
+impl TestServer {
+    fn start(outputs: serde_json::Value) -> TestServer {
+        Self::start_with(outputs, json!({}))
+    }
+
+    /// Start with extra settings for the `opc` section
+    fn start_with(outputs: serde_json::Value, opc: serde_json::Value) -> TestServer {
+        // Let the OS pick a free port; it's released just before the server binds it
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut config = json!({
+            "opc": { "host": "127.0.0.1", "port": port },
+            "outputs": outputs,
+            "output_retry_ms": 0,
+        });
+        config["opc"].as_object_mut().unwrap().extend(opc.as_object().unwrap().clone());
+        let config: Config = serde_json::from_value(config).unwrap();
+
+        let server = OpcServer::new(config, String::new(), false, false).unwrap();
+        let running = server.get_running_flag();
+        let handle = thread::spawn(move || {
+            server.run().unwrap();
+            server
+        });
+        TestServer { addr: format!("127.0.0.1:{}", port), running, handle: Some(handle) }
+    }
+
+    fn connect(&self) -> TcpStream {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(&self.addr) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("server did not start listening on {}", self.addr);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            if let Ok(mut server) = handle.join() {
+                server.shutdown();
+            }
+        }
+    }
+}
+
+fn output(port: &str, protocol: &str, channel: u8, led_count: usize) -> serde_json::Value {
+    json!({
+        "port": port,
+        "hardware_type": "mock",
+        "protocol": protocol,
+        "baud_rate": 115200,
+        "opc_channel": channel,
+        "led_count": led_count,
+    })
+}
+
+fn send(stream: &mut TcpStream, channel: u8, data: &[u8]) {
+    send_command(stream, channel, 0, data);
+}
+
+fn send_command(stream: &mut TcpStream, channel: u8, command: u8, data: &[u8]) {
+    let mut message = vec![channel, command];
+    message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    message.extend_from_slice(data);
+    stream.write_all(&message).unwrap();
+}
+
+/// Whether the server closes `stream` within the frame timeout
+fn closed_by_server(stream: &mut TcpStream) -> bool {
+    stream.set_read_timeout(Some(FRAME_TIMEOUT)).unwrap();
+    // A reset is a close too; only a timeout means the connection is still open
+    !matches!(stream.read(&mut [0u8; 1]), Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+                                                  || e.kind() == std::io::ErrorKind::TimedOut)
+}
+
+/// The next frame `port` writes after the ones already seen
+fn next_frame(port: &str, seen: usize) -> Vec<u8> {
+    let frames = mock_serial::wait_for_frames(port, seen + 1, FRAME_TIMEOUT);
+    assert!(frames.len() > seen, "no frame written to {}", port);
+    frames[seen].clone()
+}
+
+#[test]
+fn test_awa_frame_bytes() {
+    let port = "mock-awa";
+    let server = TestServer::start(json!([output(port, "awa", 0, 2)]));
+    let mut client = server.connect();
+
+    send(&mut client, 0, &[255, 0, 0, 0, 255, 0]);
+    let frame = next_frame(port, mock_serial::frames(port).len());
+
+    assert_eq!(frame, vec![
+        b'A', b'w', b'a', 0x00, 0x01, 0x54, // header: LED count - 1, then hi ^ lo ^ 0x55
+        255, 0, 0, 0, 255, 0,
+        0x00, 0x00, 0x07, // Fletcher checksums
+    ]);
+}
+
+#[test]
+fn test_adalight_pixel_format() {
+    let grb = "mock-adalight-grb";
+    let rgbw = "mock-adalight-rgbw";
+    let mut grb_output = output(grb, "adalight", 1, 2);
+    grb_output["pixel_format"] = json!("GRB");
+    let mut rgbw_output = output(rgbw, "adalight", 2, 1);
+    rgbw_output["pixel_format"] = json!("RGBW");
+    let server = TestServer::start(json!([grb_output, rgbw_output]));
+    let mut client = server.connect();
+
+    let (grb_seen, rgbw_seen) = (mock_serial::frames(grb).len(), mock_serial::frames(rgbw).len());
+    send(&mut client, 1, &[10, 20, 30, 40, 50, 60]);
+    send(&mut client, 2, &[200, 100, 50]);
+
+    assert_eq!(next_frame(grb, grb_seen), vec![b'A', b'd', b'a', 0x00, 0x01, 0x54, 20, 10, 30, 50, 40, 60]);
+    // White is taken out of the colors
+    assert_eq!(next_frame(rgbw, rgbw_seen), vec![b'A', b'd', b'a', 0x00, 0x00, 0x55, 150, 50, 0, 50]);
+}
+
+#[test]
+fn test_channel_routing_and_offset() {
+    let first = "mock-route-first";
+    let second = "mock-route-second";
+    let mut second_output = output(second, "adalight", 3, 1);
+    second_output["opc_offset"] = json!(1);
+    let server = TestServer::start(json!([output(first, "adalight", 3, 1), second_output]));
+    let mut client = server.connect();
+
+    let (first_seen, second_seen) = (mock_serial::frames(first).len(), mock_serial::frames(second).len());
+    // Each output takes its own slice of the channel
+    send(&mut client, 3, &[1, 2, 3, 4, 5, 6]);
+    assert_eq!(&next_frame(first, first_seen)[6..], &[1, 2, 3]);
+    assert_eq!(&next_frame(second, second_seen)[6..], &[4, 5, 6]);
+
+    // Another channel reaches neither output
+    send(&mut client, 4, &[9, 9, 9, 9, 9, 9]);
+    send(&mut client, 3, &[7, 7, 7, 8, 8, 8]);
+    assert_eq!(&next_frame(first, first_seen + 1)[6..], &[7, 7, 7]);
+    assert_eq!(&next_frame(second, second_seen + 1)[6..], &[8, 8, 8]);
+}
+
+#[test]
+fn test_sysex_color_correction() {
+    let port = "mock-sysex";
+    let server = TestServer::start(json!([output(port, "adalight", 0, 1)]));
+    let mut client = server.connect();
+
+    // Fadecandy's set-color-correction SysEx, without the fadecandy section
+    let mut sysex = vec![0x00, 0x01, 0x00, 0x01];
+    sysex.extend_from_slice(br#"{"gamma": 1.0, "whitepoint": [0.0, 1.0, 1.0]}"#);
+    send_command(&mut client, 0, 0xff, &sysex);
+
+    let seen = mock_serial::frames(port).len();
+    send(&mut client, 0, &[200, 100, 50]);
+    assert_eq!(&next_frame(port, seen)[6..], &[0, 100, 50]);
+}
+
+#[test]
+fn test_timed_frame_waits_for_its_time() {
+    let port = "mock-timed";
+    let server = TestServer::start_with(json!([output(port, "adalight", 0, 1)]), json!({"timed_frames": true}));
+    let mut client = server.connect();
+
+    let seen = mock_serial::frames(port).len();
+    let due = Instant::now() + Duration::from_millis(300);
+    send_command(&mut client, 0, 0xff, &timed::encode(timed::now_us() + 300_000, &[9, 8, 7]));
+    // An untimed frame goes out first
+    send(&mut client, 0, &[1, 2, 3]);
+
+    assert_eq!(&next_frame(port, seen)[6..], &[1, 2, 3]);
+    assert_eq!(&next_frame(port, seen + 1)[6..], &[9, 8, 7]);
+    assert!(Instant::now() >= due);
+}
+
+#[test]
+fn test_backpressure_keeps_every_frame() {
+    let port = "mock-backpressure";
+    // About 100ms on the wire per frame, so the client gets far ahead
+    let mut slow = output(port, "adalight", 0, 30);
+    slow["baud_rate"] = json!(9600);
+    let server = TestServer::start_with(json!([slow]), json!({"overflow": "backpressure"}));
+    let mut client = server.connect();
+
+    let seen = mock_serial::frames(port).len();
+    for value in 1..=5 {
+        send(&mut client, 0, &[value; 90]);
+    }
+
+    let frames = mock_serial::wait_for_frames(port, seen + 5, FRAME_TIMEOUT);
+    let values: Vec<u8> = frames[seen..].iter().map(|frame| frame[6]).collect();
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_client_limit_and_idle_timeout() {
+    let port = "mock-clients";
+    let server = TestServer::start_with(json!([output(port, "adalight", 0, 1)]),
+                                        json!({"max_clients": 1, "idle_timeout_ms": 300}));
+    let mut first = server.connect();
+    let seen = mock_serial::frames(port).len();
+    send(&mut first, 0, &[1, 2, 3]);
+    assert_eq!(&next_frame(port, seen)[6..], &[1, 2, 3]);
+
+    // The limit turns a second client away while the first is connected
+    let mut second = server.connect();
+    assert!(closed_by_server(&mut second));
+
+    // The first client goes quiet and is dropped, which makes room again
+    let started = Instant::now();
+    assert!(closed_by_server(&mut first));
+    assert!(started.elapsed() < FRAME_TIMEOUT);
+
+    let mut third = server.connect();
+    send(&mut third, 0, &[4, 5, 6]);
+    assert_eq!(&next_frame(port, seen + 1)[6..], &[4, 5, 6]);
+}
+
+/// Client speaking TLS to `addr` through `openssl s_client`, sending what is written to its stdin
+fn tls_client(addr: &str, args: &[&std::path::Path]) -> Child {
+    Command::new("openssl")
+        .args(["s_client", "-quiet", "-connect", addr])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+#[test]
+fn test_tls_listener_with_client_certificates() {
+    if Command::new("openssl").arg("version").output().is_err() {
+        eprintln!("skipped: needs OpenSSL's openssl command");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("opc-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let status = Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes",
+               "-subj", "/CN=localhost", "-days", "1", "-keyout"])
+        .arg(&key).arg("-out").arg(&cert)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // The self-signed certificate is both the server's and the only accepted client's
+    let port = "mock-tls";
+    let server = TestServer::start_with(json!([output(port, "adalight", 0, 1)]),
+                                        json!({"tls": {"cert": cert, "key": key, "client_ca": cert}}));
+    drop(server.connect());
+    let seen = mock_serial::frames(port).len();
+
+    let mut client = tls_client(&server.addr, &["-cert".as_ref(), &cert, "-key".as_ref(), &key]);
+    client.stdin.as_mut().unwrap().write_all(&[0, 0, 0, 3, 1, 2, 3]).unwrap();
+    assert_eq!(&next_frame(port, seen)[6..], &[1, 2, 3]);
+    client.kill().unwrap();
+    client.wait().unwrap();
+
+    // A client without a certificate fails the handshake; what it sends is never shown
+    let mut anonymous = tls_client(&server.addr, &[]);
+    let _ = anonymous.stdin.as_mut().unwrap().write_all(&[0, 0, 0, 3, 4, 5, 6]);
+    let started = Instant::now();
+    while anonymous.try_wait().unwrap().is_none() && started.elapsed() < FRAME_TIMEOUT {
+        thread::sleep(Duration::from_millis(20));
+    }
+    let _ = anonymous.kill();
+    let _ = anonymous.wait();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(mock_serial::frames(port).len(), seen + 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}